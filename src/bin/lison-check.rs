@@ -0,0 +1,117 @@
+use std::env;
+use std::fs;
+
+use lison::image::*;
+
+struct CheckConfig {
+    input: String,
+    palette: Option<String>,
+    tolerance: f64
+}
+
+enum Config {
+    Help,
+    Check(CheckConfig)
+}
+
+fn parse_args(mut args: &[String]) -> Result<Config, String> {
+    let mut palette = None;
+    let mut tolerance = 0.0;
+
+    while !args.is_empty() {
+        let arg = &args[0];
+
+        match arg.as_str() {
+            "-h" | "--help" => {
+                return Ok(Config::Help);
+            },
+            "--palette" => {
+                if args.len() == 1 {
+                    return Err(String::from("missing operand after '--palette'."));
+                }
+
+                palette = Some(args[1].clone());
+                args = &args[2..];
+            },
+            "--tolerance" => {
+                if args.len() == 1 {
+                    return Err(String::from("missing operand after '--tolerance'."));
+                }
+
+                tolerance = args[1]
+                    .parse()
+                    .map_err(|_| String::from("invalid tolerance value."))?;
+                args = &args[2..];
+            },
+            option if option.starts_with("-") => {
+                return Err(format!("unknown option '{}'.", option));
+            },
+            _ => {
+                break;
+            }
+        }
+    }
+
+    if args.is_empty() {
+        return Err(String::from("missing operand."));
+    } else if args.len() > 1 {
+        return Err(String::from("too many operands."));
+    }
+
+    let input = args[0].clone();
+
+    Ok(Config::Check(CheckConfig { input, palette, tolerance }))
+}
+
+const HELP_MESSAGE: &str = r#"usage: lison-check [-h] [--palette file] [--tolerance num] input
+options:
+  -h                : print help message.
+  --palette <file>  : JSON array of allowed colors.
+  --tolerance <num> : maximum difference of a color component from the palette."#;
+
+fn main() -> Result<(), String> {
+    let args: Vec<String> = env::args().collect();
+    let conf = parse_args(&args[1..])?;
+
+    match conf {
+        Config::Help => {
+            eprintln!("{}", HELP_MESSAGE);
+        },
+        Config::Check(conf) => {
+            let image_str = fs::read_to_string(&conf.input)
+                .map_err(|_| format!("failed to read '{}'.", &conf.input))?;
+
+            let image: Image = serde_json::from_str(&image_str)
+                .map_err(|_| format!("failed to parse '{}'.", &conf.input))?;
+
+            image.validate()
+                .map_err(|e| format!("'{}': {}.", &conf.input, e))?;
+
+            for warning in image.warnings() {
+                eprintln!("warning: {}.", warning);
+            }
+
+            if let Some(palette) = &conf.palette {
+                let palette_str = fs::read_to_string(palette)
+                    .map_err(|_| format!("failed to read '{}'.", palette))?;
+
+                let allowed: Vec<Color> = serde_json::from_str(&palette_str)
+                    .map_err(|_| format!("failed to parse '{}'.", palette))?;
+
+                let off_palette = image.check_palette(&allowed, conf.tolerance);
+
+                for color in off_palette.iter() {
+                    let color_str = serde_json::to_string(color)
+                        .map_err(|_| String::from("failed to format a color."))?;
+                    eprintln!("off-palette color: {}", color_str);
+                }
+
+                if !off_palette.is_empty() {
+                    return Err(format!("'{}' uses {} off-palette color(s).", &conf.input, off_palette.len()));
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
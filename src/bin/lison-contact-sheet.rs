@@ -0,0 +1,212 @@
+use std::env;
+use std::fs;
+
+use lison::image::*;
+use lison::render::*;
+
+struct SheetConfig {
+    inputs: Vec<String>,
+    output: String,
+    cols: Option<usize>,
+    cell: i32,
+    labels: bool
+}
+
+enum Config {
+    Help,
+    Sheet(SheetConfig)
+}
+
+fn parse_args(mut args: &[String]) -> Result<Config, String> {
+    let mut output = String::from("contact-sheet.png");
+    let mut cols = None;
+    let mut cell = 128;
+    let mut labels = false;
+
+    while !args.is_empty() {
+        let arg = &args[0];
+
+        match arg.as_str() {
+            "-h" | "--help" => {
+                return Ok(Config::Help);
+            },
+            "-o" => {
+                if args.len() == 1 {
+                    return Err(String::from("missing operand after '-o'."));
+                }
+
+                output = args[1].clone();
+                args = &args[2..];
+            },
+            "--cols" => {
+                if args.len() == 1 {
+                    return Err(String::from("missing operand after '--cols'."));
+                }
+
+                let value: usize = args[1]
+                    .parse()
+                    .map_err(|_| String::from("invalid cols value."))?;
+
+                if value == 0 {
+                    return Err(String::from("invalid cols value."));
+                }
+
+                cols = Some(value);
+                args = &args[2..];
+            },
+            "--cell" => {
+                if args.len() == 1 {
+                    return Err(String::from("missing operand after '--cell'."));
+                }
+
+                cell = args[1]
+                    .parse()
+                    .ok()
+                    .filter(|cell| *cell > 0)
+                    .ok_or_else(|| String::from("invalid cell value."))?;
+                args = &args[2..];
+            },
+            "--labels" => {
+                labels = true;
+                args = &args[1..];
+            },
+            option if option.starts_with("-") => {
+                return Err(format!("unknown option '{}'.", option));
+            },
+            _ => {
+                break;
+            }
+        }
+    }
+
+    if args.is_empty() {
+        return Err(String::from("missing operand."));
+    }
+
+    let inputs = args.to_vec();
+
+    Ok(Config::Sheet(SheetConfig { inputs, output, cols, cell, labels }))
+}
+
+const HELP_MESSAGE: &str = r#"usage: lison-contact-sheet [-h] [-o output] [--cols num] [--cell px] [--labels] input...
+options:
+  -h           : print help message.
+  -o <file>    : output file name.
+  --cols <num> : number of columns, the square root of the number of inputs by default.
+  --cell <px>  : width and height of each cell in pixels.
+  --labels     : print the file name under each image."#;
+
+const LABEL_HEIGHT: i32 = 16;
+
+fn grid_size(count: usize, cols: Option<usize>) -> (usize, usize) {
+    let cols = cols.unwrap_or_else(|| (count as f64).sqrt().ceil() as usize).max(1);
+    let rows = count.div_ceil(cols);
+
+    (cols, rows)
+}
+
+/// Renders the images into a grid of `cell`-sized squares, scaling each one to fit its cell.
+fn contact_sheet(images: &[Image], labels: Option<&[String]>, cols: usize, cell: i32) -> Result<cairo::ImageSurface, String> {
+    let (cols, rows) = grid_size(images.len(), Some(cols));
+    let cell_height = if labels.is_some() { cell + LABEL_HEIGHT } else { cell };
+
+    let sheet_size = |count: usize, size: i32| i32::try_from(count).ok()
+        .and_then(|count| count.checked_mul(size))
+        .ok_or_else(|| String::from("bad sheet dimension."));
+
+    let surface = cairo::ImageSurface::create(cairo::Format::ARgb32, sheet_size(cols, cell)?, sheet_size(rows, cell_height)?)
+        .map_err(|_| String::from("surface creation failed."))?;
+
+    let context = cairo::Context::new(&surface)
+        .map_err(|_| String::from("context creation failed."))?;
+
+    for (i, image) in images.iter().enumerate() {
+        let x = f64::from(cell) * (i % cols) as f64;
+        let y = f64::from(cell_height) * (i / cols) as f64;
+
+        let (width, height) = pixel_size(image, 96.0, 1.0)
+            .ok_or_else(|| String::from("bad image dimension."))?;
+        let scale = (f64::from(cell) / f64::from(width)).min(f64::from(cell) / f64::from(height));
+        let offset_x = (f64::from(cell) - f64::from(width) * scale) / 2.0;
+        let offset_y = (f64::from(cell) - f64::from(height) * scale) / 2.0;
+
+        context.save().map_err(|_| String::from("rendering operation failed."))?;
+        context.rectangle(x, y, f64::from(cell), f64::from(cell));
+        context.clip();
+        context.translate(x + offset_x, y + offset_y);
+        render(&context, image, 96.0, scale)
+            .map_err(|_| String::from("rendering operation failed."))?;
+        context.restore().map_err(|_| String::from("rendering operation failed."))?;
+
+        if let Some(labels) = labels {
+            context.set_source_rgb(0.0, 0.0, 0.0);
+            context.set_font_size(f64::from(LABEL_HEIGHT) * 0.75);
+            context.move_to(x + 2.0, y + f64::from(cell_height) - f64::from(LABEL_HEIGHT) / 4.0);
+            context.show_text(&labels[i])
+                .map_err(|_| String::from("rendering operation failed."))?;
+        }
+    }
+
+    drop(context);
+
+    Ok(surface)
+}
+
+fn main() -> Result<(), String> {
+    let args: Vec<String> = env::args().collect();
+    let conf = parse_args(&args[1..])?;
+
+    match conf {
+        Config::Help => {
+            eprintln!("{}", HELP_MESSAGE);
+        },
+        Config::Sheet(conf) => {
+            let mut images: Vec<Image> = Vec::new();
+
+            for input in conf.inputs.iter() {
+                let image_str = fs::read_to_string(input)
+                    .map_err(|_| format!("failed to read '{}'.", input))?;
+
+                let image: Image = serde_json::from_str(&image_str)
+                    .map_err(|_| format!("failed to parse '{}'.", input))?;
+
+                images.push(image);
+            }
+
+            let (cols, _) = grid_size(images.len(), conf.cols);
+            let labels = if conf.labels { Some(conf.inputs.as_slice()) } else { None };
+            let surface = contact_sheet(&images, labels, cols, conf.cell)?;
+
+            let mut output_file = fs::File::create(&conf.output)
+                .map_err(|_| format!("failed to create '{}'.", &conf.output))?;
+
+            surface.write_to_png(&mut output_file)
+                .map_err(|_| format!("failed to write to '{}'.", &conf.output))?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_contact_sheet() {
+        let image: Image = serde_json::from_str(include_str!("../../samples/region.lison")).unwrap();
+        let images = vec![image.clone(), image.clone(), image.clone(), image];
+
+        let (cols, rows) = grid_size(images.len(), None);
+        assert_eq!((2, 2), (cols, rows));
+
+        let sheet = contact_sheet(&images, None, cols, 50).unwrap();
+        assert_eq!(100, sheet.width());
+        assert_eq!(100, sheet.height());
+
+        let labels: Vec<String> = (0..4).map(|i| format!("{}.lison", i)).collect();
+        let sheet = contact_sheet(&images, Some(&labels), 3, 50).unwrap();
+        assert_eq!(150, sheet.width());
+        assert_eq!(2 * (50 + LABEL_HEIGHT), sheet.height());
+    }
+}
@@ -0,0 +1,192 @@
+use std::env;
+use std::path::Path;
+
+use serde::Serialize;
+
+use lison::image::*;
+
+struct StatConfig {
+    input: String,
+    json: bool
+}
+
+enum Config {
+    Help,
+    Stat(StatConfig)
+}
+
+fn parse_args(mut args: &[String]) -> Result<Config, String> {
+    let mut json = false;
+
+    while !args.is_empty() {
+        let arg = &args[0];
+
+        match arg.as_str() {
+            "-h" | "--help" => {
+                return Ok(Config::Help);
+            },
+            "--json" => {
+                json = true;
+                args = &args[1..];
+            },
+            STDIN => {
+                break;
+            },
+            option if option.starts_with("-") => {
+                return Err(format!("unknown option '{}'.", option));
+            },
+            _ => {
+                break;
+            }
+        }
+    }
+
+    if args.is_empty() {
+        return Err(String::from("missing operand."));
+    } else if args.len() > 1 {
+        return Err(String::from("too many operands."));
+    }
+
+    let input = args[0].clone();
+
+    Ok(Config::Stat(StatConfig { input, json }))
+}
+
+const HELP_MESSAGE: &str = r#"usage: lison-stat [-h] [--json] input
+options:
+  -h     : print help message.
+  --json : print the statistics as a JSON object."#;
+
+/// Reads the image from `input`.
+fn read_input(input: &str) -> Result<Image, String> {
+    read_image(Path::new(input))
+        .map_err(|e| format!("failed to read '{}': {}.", input, e))
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct Stat {
+    pens: usize,
+    brushes: usize,
+    shapes: usize,
+    flattened_shapes: usize,
+    curves: usize,
+    regions: usize,
+    segments: usize,
+    bounding_box: Option<(Point, Point)>
+}
+
+impl Stat {
+    fn new(image: &Image) -> Stat {
+        let stats = image.stats();
+
+        Stat {
+            pens: stats.pens,
+            brushes: stats.brushes,
+            shapes: stats.shapes,
+            flattened_shapes: image.iter_flattened().count(),
+            curves: stats.curves,
+            regions: stats.regions,
+            segments: stats.segments,
+            bounding_box: image.bounding_box()
+        }
+    }
+
+    /// Formats the statistics as `key: value` lines. The bounding box is given by the
+    /// coordinates of its minimum and maximum corners, or `none` for an empty image.
+    fn to_text(&self) -> String {
+        let bounding_box = match self.bounding_box {
+            Some((min, max)) => format!("{} {} {} {}", min.x, min.y, max.x, max.y),
+            None => String::from("none")
+        };
+
+        format!(
+            "pens: {}\nbrushes: {}\nshapes: {}\nflattened-shapes: {}\ncurves: {}\nregions: {}\nsegments: {}\nbounding-box: {}",
+            self.pens, self.brushes, self.shapes, self.flattened_shapes, self.curves, self.regions, self.segments, bounding_box
+        )
+    }
+}
+
+fn main() -> Result<(), String> {
+    let args: Vec<String> = env::args().collect();
+    let conf = parse_args(&args[1..])?;
+
+    match conf {
+        Config::Help => {
+            eprintln!("{}", HELP_MESSAGE);
+        },
+        Config::Stat(conf) => {
+            let image = read_input(&conf.input)?;
+
+            let stat = Stat::new(&image);
+
+            if conf.json {
+                let stat_str = serde_json::to_string(&stat)
+                    .map_err(|_| String::from("failed to format the statistics."))?;
+                println!("{}", stat_str);
+            } else {
+                println!("{}", stat.to_text());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stat() {
+        let image_str = r#"{
+  "width": 100,
+  "height": 100,
+  "unit-per-inch": 72,
+  "pens": [{ "pattern": { "type": "monochrome", "color": [0, 0, 0] }, "width": 2 }],
+  "brushes": [],
+  "shapes": [
+    { "type": "curve", "pen": 0, "data": [[10, 10], ["L", [20, 10]], ["L", [20, 20]]] },
+    { "type": "group", "content": [
+      { "type": "region", "pen": 0, "data": [[[30, 30], ["L", [40, 30]], ["L", [40, 50]]]] },
+      { "type": "group", "content": [] }
+    ] }
+  ]
+}"#;
+        let image: Image = serde_json::from_str(image_str).unwrap();
+        let stat = Stat::new(&image);
+
+        assert_eq!(
+            "pens: 1\nbrushes: 0\nshapes: 2\nflattened-shapes: 2\ncurves: 1\nregions: 1\nsegments: 4\nbounding-box: 9 9 41 51",
+            stat.to_text()
+        );
+        assert_eq!(
+            r#"{"pens":1,"brushes":0,"shapes":2,"flattened-shapes":2,"curves":1,"regions":1,"segments":4,"bounding-box":[[9.0,9.0],[41.0,51.0]]}"#,
+            serde_json::to_string(&stat).unwrap()
+        );
+
+        let empty = Stat::new(&Image::new(10.0, 10.0, 72.0));
+        assert!(empty.to_text().ends_with("bounding-box: none"));
+        assert!(serde_json::to_string(&empty).unwrap().ends_with(r#""bounding-box":null}"#));
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let args = |args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|arg| String::from(*arg)).collect();
+            parse_args(&args)
+        };
+
+        match args(&["--json", "a.lison"]).unwrap() {
+            Config::Stat(conf) => {
+                assert!(conf.json);
+                assert_eq!("a.lison", conf.input);
+            },
+            Config::Help => panic!("expected a stat config")
+        }
+
+        assert!(matches!(args(&["-"]).unwrap(), Config::Stat(conf) if conf.input == STDIN && !conf.json));
+        assert!(args(&[]).is_err());
+        assert!(args(&["a.lison", "b.lison"]).is_err());
+    }
+}
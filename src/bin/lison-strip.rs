@@ -1,12 +1,17 @@
 
+use std::collections::HashMap;
 use std::env;
 use std::fs;
+use std::path::Path;
 
 use lison::image::*;
 
 struct StripConfig {
     input: String,
-    output: String
+    output: String,
+    redact: Option<Color>,
+    relaxed: bool,
+    gzip: bool
 }
 
 enum Config {
@@ -14,52 +19,255 @@ enum Config {
     Strip(StripConfig)
 }
 
-fn parse_args(args: &[String]) -> Result<Config, String> {
-    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
-        return Ok(Config::Help);
+fn parse_args(mut args: &[String]) -> Result<Config, String> {
+    let mut output = String::new();
+    let mut redact = None;
+    let mut relaxed = false;
+    let mut gzip = false;
+    let mut operands = Vec::new();
+
+    while !args.is_empty() {
+        let arg = &args[0];
+
+        match arg.as_str() {
+            "-h" | "--help" => {
+                return Ok(Config::Help);
+            },
+            "-o" => {
+                if args.len() == 1 {
+                    return Err(String::from("missing operand after '-o'."));
+                }
+
+                output = args[1].clone();
+                args = &args[2..];
+            },
+            "--redact" => {
+                if args.len() == 1 {
+                    return Err(String::from("missing operand after '--redact'."));
+                }
+
+                redact = Some(serde_json::from_str(&args[1])
+                    .map_err(|_| String::from("invalid color value."))?);
+                args = &args[2..];
+            },
+            "--relaxed" => {
+                relaxed = true;
+                args = &args[1..];
+            },
+            "--gzip" => {
+                gzip = true;
+                args = &args[1..];
+            },
+            option if option != STDIN && option.starts_with("-") => {
+                return Err(format!("unknown option '{}'.", option));
+            },
+            operand => {
+                operands.push(String::from(operand));
+                args = &args[1..];
+            }
+        }
     }
 
-    if args.len() == 1 {
-        let input = args[0].clone();
-        let output = format!("stripped-{}", input);
-        Ok(Config::Strip(StripConfig { input, output }))
-    } else if args.len() == 3 && args[0] == "-o" {
-        let input = args[2].clone();
-        let output = args[1].clone();
-        Ok(Config::Strip(StripConfig { input, output }))
-    } else {
-        Err(String::from("invalid arguments."))
+    if operands.is_empty() {
+        return Err(String::from("missing operand."));
+    } else if operands.len() > 1 {
+        return Err(String::from("too many operands."));
+    }
+
+    let input = operands.remove(0);
+
+    if output.is_empty() {
+        if input == STDIN {
+            return Err(String::from("'-o' is required when reading from the standard input."));
+        }
+
+        output = format!("stripped-{}", input);
+
+        if gzip && !is_gzip_path(Path::new(&output)) {
+            output.push_str(".gz");
+        }
     }
+
+    Ok(Config::Strip(StripConfig { input, output, redact, relaxed, gzip }))
 }
 
-const HELP_MESSAGE: &str = r#"usage: lison-strip [-h] [-o output] input
+const HELP_MESSAGE: &str = r#"usage: lison-strip [-h] [-o output] [--redact color] [--relaxed] [--gzip] input
 options:
-  -h        : print help message.
-  -o <file> : output file name."#;
-
-fn flatten_shape(shapes: &mut Vec<Shape>, shape: &Shape) {
-    match shape {
-        Shape::Group(group) => {
-            for child in group.content.iter() {
-                flatten_shape(shapes, child);
+  -h                : print help message.
+  -o <file>         : output file name.
+  --redact <color>  : replace all patterns with the color, e.g. '[0.5, 0.5, 0.5]'.
+  --relaxed         : accept JSON5, e.g. comments and trailing commas.
+  --gzip            : compress the output, as is done when its name ends with .gz."#;
+
+/// Reads the image from `input`, as JSON5 if `relaxed` is set.
+fn read_input(input: &str, relaxed: bool) -> Result<Image, String> {
+    let path = Path::new(input);
+    let image = if relaxed { read_image_relaxed(path) } else { read_image(path) };
+
+    image.map_err(|e| format!("failed to read '{}': {}.", input, e))
+}
+
+/// Fails, leaving the image as it is, if a group has a transform other than a translation.
+fn strip_image(image: &mut Image) -> Result<(), String> {
+    let untranslatable = image.iter_shapes()
+        .any(|shape| matches!(shape, Shape::Group(group) if group.transform.is_some_and(|t| !is_translation(&t))));
+
+    if untranslatable {
+        return Err(String::from("groups transformed by other than a translation cannot be flattened."));
+    }
+
+    image.editor = None;
+
+    let dropped = image.iter_shapes()
+        .any(|shape| matches!(shape, Shape::Group(group) if group.opacity.is_some() || group.operator.is_some()));
+
+    if dropped {
+        eprintln!("warning: the opacity and operators of groups are dropped.");
+    }
+
+    flatten_groups(image);
+    gc_resources(image);
+
+    Ok(())
+}
+
+fn is_translation(transform: &[f64; 6]) -> bool {
+    transform[..4] == [1.0, 0.0, 0.0, 1.0]
+}
+
+/// Replaces the groups with the curves and regions inside them, moved by the translations of
+/// the groups. The pattern origin of a region with a brush moves along, and a pen whose
+/// pattern has a position is copied for each translation it is used under.
+fn flatten_groups(image: &mut Image) {
+    let origin = Point { x: 0.0, y: 0.0 };
+    let mut translated_pens = HashMap::new();
+    let mut shapes = Vec::new();
+    let mut stack: Vec<(Shape, Point)> = std::mem::take(&mut image.shapes)
+        .into_iter()
+        .rev()
+        .map(|shape| (shape, origin))
+        .collect();
+
+    while let Some((shape, offset)) = stack.pop() {
+        let translate = |p: Point| Point { x: p.x + offset.x, y: p.y + offset.y };
+
+        match shape {
+            Shape::Group(group) => {
+                let offset = group.transform.map_or(offset, |t| translate(Point { x: t[4], y: t[5] }));
+                stack.extend(group.content.into_iter().rev().map(|child| (child, offset)));
+            },
+            shape if offset == origin => shapes.push(shape),
+            Shape::Curve(mut curve) => {
+                curve.data.map_points(translate);
+                curve.pen = translated_pen(&mut image.pens, &mut translated_pens, curve.pen, offset);
+                shapes.push(Shape::Curve(curve));
+            },
+            Shape::Region(mut region) => {
+                for data in region.data.iter_mut() {
+                    data.map_points(translate);
+                }
+
+                if region.brush.is_some() {
+                    region.pattern_origin = Some(translate(region.pattern_origin.unwrap_or(origin)));
+                }
+
+                region.pen = region.pen.map(|pen| translated_pen(&mut image.pens, &mut translated_pens, pen, offset));
+                shapes.push(Shape::Region(region));
             }
-        },
-        _ => {
-            shapes.push(shape.clone());
         }
     }
+
+    image.shapes = shapes;
 }
 
-fn strip_image(image: &mut Image) {
-    image.editor = None;
+/// Returns the index of a pen like `pen` whose pattern is moved by `offset`, which is `pen`
+/// itself for a monochrome pattern. Copies are appended to `pens` once per pen and offset.
+fn translated_pen(pens: &mut Vec<Pen>, copies: &mut HashMap<(usize, u64, u64), usize>, pen: usize, offset: Point) -> usize {
+    if matches!(pens[pen].pattern, Pattern::Monochrome(_)) {
+        return pen;
+    }
+
+    *copies.entry((pen, offset.x.to_bits(), offset.y.to_bits())).or_insert_with(|| {
+        let translate = |p: Point| Point { x: p.x + offset.x, y: p.y + offset.y };
+        let pattern = match &pens[pen].pattern {
+            // A bitmap without an origin lies at the origin, which has to move as well.
+            Pattern::Image(pat) => Pattern::Image(ImagePattern {
+                origin: Some(translate(pat.origin.unwrap_or(Point { x: 0.0, y: 0.0 }))),
+                ..pat.clone()
+            }),
+            pattern => pattern.map_points(translate)
+        };
+
+        pens.push(Pen { pattern, ..pens[pen].clone() });
+        pens.len() - 1
+    })
+}
+
+/// Removes the pens and brushes that no shape refers to and renumbers the references to the
+/// rest, which keep their order.
+///
+/// Panics if a shape refers to a pen or a brush that does not exist.
+fn gc_resources(image: &mut Image) {
+    let mut pen_used = vec![false; image.pens.len()];
+    let mut brush_used = vec![false; image.brushes.len()];
 
-    let mut shapes: Vec<Shape> = Vec::new();
+    for shape in image.iter_flattened() {
+        match shape {
+            Shape::Group(_) => {},
+            Shape::Curve(curve) => {
+                pen_used[curve.pen] = true;
+            },
+            Shape::Region(region) => {
+                if let Some(pen) = region.pen {
+                    pen_used[pen] = true;
+                }
 
-    for shape in image.shapes.iter() {
-        flatten_shape(&mut shapes, shape);
+                if let Some(brush) = region.brush {
+                    brush_used[brush] = true;
+                }
+            }
+        }
     }
 
-    image.shapes = shapes;
+    let pen_map = retain_used(&mut image.pens, &pen_used);
+    let brush_map = retain_used(&mut image.brushes, &brush_used);
+    let mut stack: Vec<&mut Shape> = image.shapes.iter_mut().collect();
+
+    while let Some(shape) = stack.pop() {
+        match shape {
+            Shape::Group(group) => {
+                stack.extend(group.content.iter_mut());
+            },
+            Shape::Curve(curve) => {
+                curve.pen = pen_map[curve.pen];
+            },
+            Shape::Region(region) => {
+                region.pen = region.pen.map(|pen| pen_map[pen]);
+                region.brush = region.brush.map(|brush| brush_map[brush]);
+            }
+        }
+    }
+}
+
+/// Removes the items not marked as used and returns the new index of each old one. Indices
+/// of removed items are meaningless.
+fn retain_used<T>(items: &mut Vec<T>, used: &[bool]) -> Vec<usize> {
+    let map = used.iter()
+        .scan(0, |next, &used| {
+            let index = *next;
+
+            if used {
+                *next += 1;
+            }
+
+            Some(index)
+        })
+        .collect();
+
+    let mut used = used.iter();
+    items.retain(|_| *used.next().unwrap());
+
+    map
 }
 
 fn main() -> Result<(), String> {
@@ -71,21 +279,181 @@ fn main() -> Result<(), String> {
             eprintln!("{}", HELP_MESSAGE);
         },
         Config::Strip(conf) => {
-            let image_str = fs::read_to_string(&conf.input)
-                .or_else(|_| Err(format!("failed to read '{}'.", &conf.input)))?;
+            let mut image = read_input(&conf.input, conf.relaxed)?;
+
+            image.validate()
+                .map_err(|e| format!("'{}': {}.", &conf.input, e))?;
+
+            strip_image(&mut image)
+                .map_err(|e| format!("'{}': {}", &conf.input, e))?;
 
-            let mut image: Image = serde_json::from_str(&image_str)
-                .or_else(|_| Err(format!("failed to parse '{}'.", &conf.input)))?;
+            if let Some(color) = conf.redact {
+                image.redact(color);
+            }
 
-            strip_image(&mut image);
+            let mut stripped_image = serde_json::to_vec(&image)
+                .map_err(|_| String::from("failed to strip the image."))?;
 
-            let stripped_image_str = serde_json::to_string(&image)
-                .or_else(|_| Err(String::from("failed to strip the image.")))?;
+            if conf.gzip || is_gzip_path(Path::new(&conf.output)) {
+                stripped_image = compress(&stripped_image)
+                    .map_err(|_| String::from("failed to compress the image."))?;
+            }
 
-            fs::write(&conf.output, &stripped_image_str)
-                .or_else(|_| Err(format!("failed to write to '{}'.", &conf.output)))?;
+            fs::write(&conf.output, &stripped_image)
+                .map_err(|_| format!("failed to write to '{}'.", &conf.output))?;
         }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strip_config(args: &[&str]) -> Result<StripConfig, String> {
+        let args: Vec<String> = args.iter().map(|arg| String::from(*arg)).collect();
+
+        match parse_args(&args)? {
+            Config::Strip(conf) => Ok(conf),
+            Config::Help => panic!("expected a strip config")
+        }
+    }
+
+    #[test]
+    fn test_stdin_input() {
+        let conf = strip_config(&["-o", "out.lison", "-"]).unwrap();
+        assert_eq!(STDIN, conf.input);
+        assert_eq!("out.lison", conf.output);
+
+        let conf = strip_config(&["-", "-o", "out.lison"]).unwrap();
+        assert_eq!(STDIN, conf.input);
+        assert_eq!("out.lison", conf.output);
+
+        assert!(strip_config(&["-"]).is_err());
+        assert!(strip_config(&["a.lison", "b.lison"]).is_err());
+        assert_eq!("stripped-a.lison", strip_config(&["a.lison"]).unwrap().output);
+    }
+
+    #[test]
+    fn test_gzip() {
+        assert!(!strip_config(&["a.lison"]).unwrap().gzip);
+        assert_eq!("stripped-a.lison.gz", strip_config(&["--gzip", "a.lison"]).unwrap().output);
+        assert_eq!("stripped-a.lison.gz", strip_config(&["--gzip", "a.lison.gz"]).unwrap().output);
+        assert_eq!("out.lison", strip_config(&["--gzip", "-o", "out.lison", "a.lison"]).unwrap().output);
+
+        let input = env::temp_dir().join(format!("lison-strip-gzip-{}.lison.gz", std::process::id()));
+        let image = Image::new(10.0, 10.0, 72.0);
+        write_image(&input, &image).unwrap();
+
+        assert_eq!(image, read_input(input.to_str().unwrap(), false).unwrap());
+
+        fs::remove_file(&input).unwrap();
+    }
+
+    #[test]
+    fn test_strip_image() {
+        let curve = Shape::curve(0, CurveData { start: Point { x: 0.0, y: 0.0 }, segments: vec![] });
+        let mut image = Image::new(10.0, 10.0, 72.0)
+            .with_shape(Shape::group(vec![Shape::region(None, None, vec![]), Shape::group(vec![curve])]))
+            .with_shape(Shape::region(None, None, vec![]));
+        image.editor = Some(String::from("editor"));
+        image.add_pen(Pen::new(Pattern::Monochrome(MonochromePattern { color: Color { red: 0.0, green: 0.0, blue: 0.0, alpha: 1.0 } }), 1.0));
+
+        strip_image(&mut image).unwrap();
+
+        assert_eq!(None, image.editor);
+        assert_eq!(3, image.shapes.len());
+        assert!(matches!(image.shapes[1], Shape::Curve(_)));
+    }
+
+    #[test]
+    fn test_strip_translated_group() {
+        use lison::render::{rasterize, RenderOptions};
+
+        let image_str = r#"{
+  "width": 40,
+  "height": 40,
+  "unit-per-inch": 96,
+  "pens": [
+    { "pattern": { "type": "linear-gradient", "point-1": [0, 0], "color-1": [1, 0, 0], "point-2": [10, 0], "color-2": [0, 0, 1] }, "width": 2 }
+  ],
+  "brushes": [
+    { "pattern": { "type": "linear-gradient", "point-1": [0, 0], "color-1": [0, 1, 0], "point-2": [10, 0], "color-2": [1, 0, 1] } }
+  ],
+  "shapes": [
+    { "type": "curve", "pen": 0, "data": [[2, 2], ["L", [12, 2]]] },
+    { "type": "group", "transform": [1, 0, 0, 1, 10, 5], "content": [
+      { "type": "group", "transform": [1, 0, 0, 1, 5, 5], "content": [
+        { "type": "region", "pen": 0, "brush": 0, "data": [[[0, 0], ["L", [10, 0]], ["L", [10, 10]], ["L", [0, 10]]]] }
+      ] },
+      { "type": "curve", "pen": 0, "data": [[0, 20], ["A", 5, 5, 0, false, true, [10, 20]]] }
+    ] }
+  ]
+}"#;
+        let image: Image = serde_json::from_str(image_str).unwrap();
+        let mut stripped = image.clone();
+        strip_image(&mut stripped).unwrap();
+
+        assert_eq!(3, stripped.shapes.len());
+        assert_eq!(3, stripped.pens.len());
+
+        let render = |image: &Image| {
+            let (mut surface, _) = rasterize(image, 96.0, 1.0, &RenderOptions::default()).unwrap();
+            surface.data().unwrap().to_vec()
+        };
+        assert_eq!(render(&image), render(&stripped));
+
+        let mut rotated = image.clone();
+
+        if let Shape::Group(group) = &mut rotated.shapes[1] {
+            group.transform = Some([0.0, 1.0, -1.0, 0.0, 10.0, 5.0]);
+        }
+
+        assert!(strip_image(&mut rotated).is_err());
+        assert_eq!(2, rotated.shapes.len());
+    }
+
+    #[test]
+    fn test_gc_resources() {
+        let image_str = r#"{
+  "width": 10,
+  "height": 10,
+  "unit-per-inch": 72,
+  "pens": [
+    { "pattern": { "type": "monochrome", "color": [1, 0, 0] }, "width": 1 },
+    { "pattern": { "type": "monochrome", "color": [0, 1, 0] }, "width": 1 },
+    { "pattern": { "type": "monochrome", "color": [0, 0, 1] }, "width": 1 }
+  ],
+  "brushes": [
+    { "pattern": { "type": "monochrome", "color": [1, 1, 0] } },
+    { "pattern": { "type": "monochrome", "color": [0, 1, 1] } }
+  ],
+  "shapes": [
+    { "type": "group", "content": [
+      { "type": "curve", "pen": 2, "data": [[0, 0]] },
+      { "type": "region", "pen": 2, "brush": 1, "data": [] }
+    ] },
+    { "type": "curve", "pen": 0, "data": [[0, 0]] }
+  ]
+}"#;
+        let mut image: Image = serde_json::from_str(image_str).unwrap();
+        let used_pens = [image.pens[0].clone(), image.pens[2].clone()];
+        let used_brush = image.brushes[1].clone();
+
+        strip_image(&mut image).unwrap();
+
+        assert_eq!(&used_pens[..], &image.pens[..]);
+        assert_eq!(vec![used_brush], image.brushes);
+        assert!(image.validate().is_ok());
+
+        match (&image.shapes[0], &image.shapes[1], &image.shapes[2]) {
+            (Shape::Curve(curve1), Shape::Region(region), Shape::Curve(curve2)) => {
+                assert_eq!(1, curve1.pen);
+                assert_eq!((Some(1), Some(0)), (region.pen, region.brush));
+                assert_eq!(0, curve2.pen);
+            },
+            _ => panic!("unexpected shape types")
+        }
+    }
+}
@@ -0,0 +1,268 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use lison::image::*;
+use lison::render::*;
+
+struct TilesConfig {
+    input: String,
+    output: String,
+    resolution: f64,
+    tile_size: i32
+}
+
+enum Config {
+    Help,
+    Tiles(TilesConfig)
+}
+
+fn parse_args(mut args: &[String]) -> Result<Config, String> {
+    let mut output = String::from("tiles");
+    let mut resolution = 96.0;
+    let mut tile_size = 256;
+
+    while !args.is_empty() {
+        let arg = &args[0];
+
+        match arg.as_str() {
+            "-h" | "--help" => {
+                return Ok(Config::Help);
+            },
+            "-o" => {
+                if args.len() == 1 {
+                    return Err(String::from("missing operand after '-o'."));
+                }
+
+                output = args[1].clone();
+                args = &args[2..];
+            },
+            "-r" => {
+                if args.len() == 1 {
+                    return Err(String::from("missing operand after '-r'."));
+                }
+
+                resolution = args[1]
+                    .parse()
+                    .ok()
+                    .filter(|resolution: &f64| *resolution > 0.0 && resolution.is_finite())
+                    .ok_or_else(|| String::from("invalid resolution value."))?;
+                args = &args[2..];
+            },
+            "--tile-size" => {
+                if args.len() == 1 {
+                    return Err(String::from("missing operand after '--tile-size'."));
+                }
+
+                tile_size = args[1]
+                    .parse()
+                    .ok()
+                    .filter(|size| *size > 0)
+                    .ok_or_else(|| String::from("invalid tile size value."))?;
+                args = &args[2..];
+            },
+            option if option.starts_with("-") => {
+                return Err(format!("unknown option '{}'.", option));
+            },
+            _ => {
+                break;
+            }
+        }
+    }
+
+    if args.is_empty() {
+        return Err(String::from("missing operand."));
+    } else if args.len() > 1 {
+        return Err(String::from("too many operands."));
+    }
+
+    let input = args[0].clone();
+
+    Ok(Config::Tiles(TilesConfig { input, output, resolution, tile_size }))
+}
+
+const HELP_MESSAGE: &str = r#"usage: lison-tiles [-h] [-o directory] [-r resolution] [--tile-size px] input
+options:
+  -h               : print help message.
+  -o <directory>   : output directory.
+  -r <num>         : resolution in ppi of the most detailed level.
+  --tile-size <px> : width and height of each tile in pixels."#;
+
+/// One zoom level of the pyramid.
+#[derive(Clone, Copy, Debug)]
+struct Level {
+    scale: f64,
+    width: i32,
+    height: i32,
+    cols: i32,
+    rows: i32
+}
+
+/// Returns the zoom levels from the full resolution at `ppi`, each half the size of the
+/// previous one, down to the first that fits in a single tile. Each side is at least one
+/// pixel, so a long, thin image keeps halving along its long side only.
+fn pyramid(image: &Image, ppi: f64, tile_size: i32) -> Result<Vec<Level>, String> {
+    let mut levels = Vec::new();
+    let mut scale = 1.0;
+
+    loop {
+        let pixels = |length: f64| {
+            let pixels = (length * ppi / image.unit_per_inch * scale).round();
+            (pixels.is_finite() && pixels <= i32::MAX.into()).then(|| pixels.max(1.0) as i32)
+        };
+        let (width, height) = pixels(image.width).zip(pixels(image.height))
+            .ok_or_else(|| String::from("bad image dimension."))?;
+        let (cols, rows) = ((width + tile_size - 1) / tile_size, (height + tile_size - 1) / tile_size);
+        levels.push(Level { scale, width, height, cols, rows });
+
+        if cols == 1 && rows == 1 {
+            return Ok(levels);
+        }
+
+        scale /= 2.0;
+    }
+}
+
+/// Renders the tile at `col` and `row` of `level`. Tiles on the right and bottom edges are
+/// cut to the size of the level.
+fn render_tile(image: &Image, ppi: f64, tile_size: i32, level: &Level, col: i32, row: i32) -> Result<cairo::ImageSurface, String> {
+    let (x, y) = (col * tile_size, row * tile_size);
+    let width = tile_size.min(level.width - x);
+    let height = tile_size.min(level.height - y);
+
+    let surface = cairo::ImageSurface::create(cairo::Format::ARgb32, width, height)
+        .map_err(|_| String::from("surface creation failed."))?;
+
+    let context = cairo::Context::new(&surface)
+        .map_err(|_| String::from("context creation failed."))?;
+
+    let pixels_per_unit = ppi / image.unit_per_inch * level.scale;
+    let origin = Point { x: f64::from(x) / pixels_per_unit, y: f64::from(y) / pixels_per_unit };
+
+    render_crop(&context, image, ppi, level.scale, origin)
+        .map_err(|_| String::from("rendering operation failed."))?;
+
+    drop(context);
+
+    Ok(surface)
+}
+
+fn manifest_json(levels: &[Level], tile_size: i32) -> serde_json::Value {
+    let levels: Vec<serde_json::Value> = levels.iter()
+        .map(|level| serde_json::json!({
+            "width": level.width,
+            "height": level.height,
+            "columns": level.cols,
+            "rows": level.rows
+        }))
+        .collect();
+
+    serde_json::json!({
+        "tile-size": tile_size,
+        "format": "png",
+        "levels": levels
+    })
+}
+
+/// Writes each tile to `<output>/<level>/<col>_<row>.png` and the levels to
+/// `<output>/manifest.json`. Level 0 has the full resolution.
+fn write_tiles(image: &Image, ppi: f64, tile_size: i32, output: &Path) -> Result<(), String> {
+    let levels = pyramid(image, ppi, tile_size)?;
+
+    for (index, level) in levels.iter().enumerate() {
+        let dir = output.join(index.to_string());
+
+        fs::create_dir_all(&dir)
+            .map_err(|_| format!("failed to create '{}'.", dir.display()))?;
+
+        for row in 0..level.rows {
+            for col in 0..level.cols {
+                let surface = render_tile(image, ppi, tile_size, level, col, row)?;
+                let path = dir.join(format!("{}_{}.png", col, row));
+
+                let mut file = fs::File::create(&path)
+                    .map_err(|_| format!("failed to create '{}'.", path.display()))?;
+
+                surface.write_to_png(&mut file)
+                    .map_err(|_| format!("failed to write to '{}'.", path.display()))?;
+            }
+        }
+    }
+
+    let manifest = output.join("manifest.json");
+
+    fs::write(&manifest, manifest_json(&levels, tile_size).to_string())
+        .map_err(|_| format!("failed to write to '{}'.", manifest.display()))
+}
+
+fn main() -> Result<(), String> {
+    let args: Vec<String> = env::args().collect();
+    let conf = parse_args(&args[1..])?;
+
+    match conf {
+        Config::Help => {
+            eprintln!("{}", HELP_MESSAGE);
+        },
+        Config::Tiles(conf) => {
+            let image_str = fs::read_to_string(&conf.input)
+                .map_err(|_| format!("failed to read '{}'.", &conf.input))?;
+
+            let image: Image = serde_json::from_str(&image_str)
+                .map_err(|_| format!("failed to parse '{}'.", &conf.input))?;
+
+            write_tiles(&image, conf.resolution, conf.tile_size, Path::new(&conf.output))?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pyramid() {
+        // 100 units at 72 units per inch is 800 pixels at 576 ppi.
+        let image: Image = serde_json::from_str(include_str!("../../samples/region.lison")).unwrap();
+
+        let levels = pyramid(&image, 576.0, 256).unwrap();
+        assert_eq!(3, levels.len());
+        assert_eq!((800, 800, 4, 4), (levels[0].width, levels[0].height, levels[0].cols, levels[0].rows));
+        assert_eq!((400, 400, 2, 2), (levels[1].width, levels[1].height, levels[1].cols, levels[1].rows));
+        assert_eq!((200, 200, 1, 1), (levels[2].width, levels[2].height, levels[2].cols, levels[2].rows));
+
+        let edge = render_tile(&image, 576.0, 256, &levels[0], 3, 1).unwrap();
+        assert_eq!(32, edge.width());
+        assert_eq!(256, edge.height());
+
+        let output = env::temp_dir().join(format!("lison-tiles-{}", std::process::id()));
+        write_tiles(&image, 576.0, 256, &output).unwrap();
+
+        assert_eq!(16, fs::read_dir(output.join("0")).unwrap().count());
+        assert_eq!(1, fs::read_dir(output.join("2")).unwrap().count());
+
+        let manifest: serde_json::Value = serde_json::from_str(&fs::read_to_string(output.join("manifest.json")).unwrap()).unwrap();
+        assert_eq!(3, manifest["levels"].as_array().unwrap().len());
+        assert_eq!(4, manifest["levels"][0]["columns"]);
+
+        fs::remove_dir_all(&output).unwrap();
+
+        // The short side of a thin image stays one pixel tall at the smaller levels.
+        let thin: Image = serde_json::from_str(r#"{ "width": 1000, "height": 1, "unit-per-inch": 96, "pens": [], "brushes": [], "shapes": [] }"#).unwrap();
+        let levels = pyramid(&thin, 96.0, 256).unwrap();
+        assert_eq!(3, levels.len());
+        assert_eq!((250, 1, 1, 1), (levels[2].width, levels[2].height, levels[2].cols, levels[2].rows));
+    }
+
+    #[test]
+    fn test_parse_args() {
+        for resolution in ["0", "-96", "inf", "NaN"] {
+            let args: Vec<String> = ["-r", resolution, "a.lison"].iter().map(|s| s.to_string()).collect();
+            assert!(parse_args(&args).is_err());
+        }
+
+        let args: Vec<String> = ["-r", "48", "a.lison"].iter().map(|s| s.to_string()).collect();
+        assert!(matches!(parse_args(&args), Ok(Config::Tiles(TilesConfig { resolution: 48.0, .. }))));
+    }
+}
@@ -1,6 +1,8 @@
 
 use std::env;
 use std::fs;
+use std::io::Write;
+use std::path::Path;
 
 use lison::image::*;
 use lison::render::*;
@@ -8,8 +10,24 @@ use lison::render::*;
 struct ConvertConfig {
     input: String,
     output: String,
+    format: Backend,
     resolution: f64,
-    scale: f64
+    scale: f64,
+    profile: bool,
+    info: bool,
+    quality: Option<Quality>,
+    antialias: Option<cairo::Antialias>,
+    expand_canvas: bool,
+    crop: bool,
+    margin: f64,
+    tile: Option<(i32, i32)>,
+    alpha_mask: bool,
+    max_pixels: Option<u64>,
+    clip: bool,
+    icc: Option<String>,
+    debug_index: bool,
+    relaxed: bool,
+    background: Option<Color>
 }
 
 enum Config {
@@ -19,8 +37,25 @@ enum Config {
 
 fn parse_args(mut args: &[String]) -> Result<Config, String> {
     let mut output = String::new();
-    let mut resolution = 96.0;
+    let mut format = None;
+    let mut resolution = DEFAULT_RESOLUTION;
     let mut scale = 1.0;
+    let mut profile = false;
+    let mut info = false;
+    let mut quality = None;
+    let mut antialias = None;
+    let mut expand_canvas = false;
+    let mut crop = false;
+    let mut margin = None;
+    let mut tile = None;
+    let mut alpha_mask = false;
+    let mut max_pixels = None;
+    let mut clip = false;
+    let mut icc = None;
+    let mut debug_index = false;
+    let mut relaxed = false;
+    let mut background = None;
+    let mut operands = Vec::new();
 
     while !args.is_empty() {
         let arg = &args[0];
@@ -37,6 +72,19 @@ fn parse_args(mut args: &[String]) -> Result<Config, String> {
                 output = args[1].clone();
                 args = &args[2..];
             },
+            "-f" => {
+                if args.len() == 1 {
+                    return Err(String::from("missing operand after '-f'."));
+                }
+
+                format = match args[1].as_str() {
+                    "png" => Some(Backend::Png),
+                    "svg" => Some(Backend::Svg),
+                    "pdf" => Some(Backend::Pdf),
+                    _ => return Err(String::from("invalid format value."))
+                };
+                args = &args[2..];
+            },
             "-r" => {
                 if args.len() == 1 {
                     return Err(String::from("missing operand after '-r'."));
@@ -44,7 +92,7 @@ fn parse_args(mut args: &[String]) -> Result<Config, String> {
 
                 resolution = args[1]
                     .parse()
-                    .or_else(|_| Err(String::from("invalid resolution value.")))?;
+                    .map_err(|_| String::from("invalid resolution value."))?;
                 args = &args[2..];
             },
             "-s" => {
@@ -54,39 +102,431 @@ fn parse_args(mut args: &[String]) -> Result<Config, String> {
 
                 scale = args[1]
                     .parse()
-                    .or_else(|_| Err(String::from("invalid scale value.")))?;
+                    .map_err(|_| String::from("invalid scale value."))?;
+                args = &args[2..];
+            },
+            "--profile" => {
+                profile = true;
+                args = &args[1..];
+            },
+            "--info" => {
+                info = true;
+                args = &args[1..];
+            },
+            "--quality" => {
+                if args.len() == 1 {
+                    return Err(String::from("missing operand after '--quality'."));
+                }
+
+                quality = match args[1].as_str() {
+                    "fast" => Some(Quality::Fast),
+                    "balanced" => Some(Quality::Balanced),
+                    "best" => Some(Quality::Best),
+                    _ => return Err(String::from("invalid quality value."))
+                };
+                args = &args[2..];
+            },
+            "--antialias" => {
+                if args.len() == 1 {
+                    return Err(String::from("missing operand after '--antialias'."));
+                }
+
+                antialias = match args[1].as_str() {
+                    "none" => Some(cairo::Antialias::None),
+                    "fast" => Some(cairo::Antialias::Fast),
+                    "best" => Some(cairo::Antialias::Best),
+                    _ => return Err(String::from("invalid antialias value."))
+                };
+                args = &args[2..];
+            },
+            "--expand-canvas" => {
+                expand_canvas = true;
+                args = &args[1..];
+            },
+            "--crop" => {
+                crop = true;
+                args = &args[1..];
+            },
+            "--relaxed" => {
+                relaxed = true;
+                args = &args[1..];
+            },
+            "--margin" => {
+                if args.len() == 1 {
+                    return Err(String::from("missing operand after '--margin'."));
+                }
+
+                margin = Some(args[1]
+                    .parse()
+                    .ok()
+                    .filter(|margin: &f64| *margin >= 0.0)
+                    .ok_or_else(|| String::from("invalid margin value."))?);
+                args = &args[2..];
+            },
+            "--tile" => {
+                if args.len() == 1 {
+                    return Err(String::from("missing operand after '--tile'."));
+                }
+
+                tile = Some(parse_tile(&args[1]).ok_or_else(|| String::from("invalid tile value."))?);
+                args = &args[2..];
+            },
+            "--alpha-mask" => {
+                alpha_mask = true;
+                args = &args[1..];
+            },
+            "--clip" => {
+                clip = true;
+                args = &args[1..];
+            },
+            "--debug-index" => {
+                debug_index = true;
+                args = &args[1..];
+            },
+            "--icc" => {
+                if args.len() == 1 {
+                    return Err(String::from("missing operand after '--icc'."));
+                }
+
+                icc = Some(args[1].clone());
+                args = &args[2..];
+            },
+            "--background" => {
+                if args.len() == 1 {
+                    return Err(String::from("missing operand after '--background'."));
+                }
+
+                background = Some(Color::parse(&args[1])
+                    .ok_or_else(|| String::from("invalid background value."))?);
+                args = &args[2..];
+            },
+            "--max-pixels" => {
+                if args.len() == 1 {
+                    return Err(String::from("missing operand after '--max-pixels'."));
+                }
+
+                max_pixels = Some(args[1]
+                    .parse()
+                    .map_err(|_| String::from("invalid max pixels value."))?);
                 args = &args[2..];
             },
-            option if option.starts_with("-") => {
+            option if option != STDIN && option.starts_with("-") => {
                 return Err(format!("unknown option '{}'.", option));
             },
-            _ => {
-                break;
+            operand => {
+                operands.push(String::from(operand));
+                args = &args[1..];
             }
         }
     }
 
-    if args.is_empty() {
+    if operands.is_empty() {
         return Err(String::from("missing operand."));
-    } else if args.len() > 1 {
+    } else if operands.len() > 1 {
         return Err(String::from("too many operands."));
     }
 
-    let input = args[0].clone();
+    if crop && expand_canvas {
+        return Err(String::from("'--crop' and '--expand-canvas' cannot be used together."));
+    }
+
+    if margin.is_some() && !crop {
+        return Err(String::from("'--margin' requires '--crop'."));
+    }
+
+    let margin = margin.unwrap_or(0.0);
+
+    let input = operands.remove(0);
+
+    if input == STDIN {
+        if output.is_empty() {
+            return Err(String::from("'-o' is required when reading from the standard input."));
+        } else if output == STDOUT {
+            return Err(String::from("the input and the output cannot both be the standard streams."));
+        }
+    }
+
+    let format = format.unwrap_or_else(|| output_format(&output));
 
     if output.is_empty() {
-        output = format!("{}.png", &input);
+        output = default_output(&input, format);
+    }
+
+    Ok(Config::Convert(ConvertConfig { input, output, format, resolution, scale, profile, info, quality, antialias, expand_canvas, crop, margin, tile, alpha_mask, max_pixels, clip, icc, debug_index, relaxed, background }))
+}
+
+/// Parses `COLSxROWS`, e.g. `3x2`.
+fn parse_tile(value: &str) -> Option<(i32, i32)> {
+    let (cols, rows) = value.split_once('x')?;
+    let cols: i32 = cols.parse().ok()?;
+    let rows: i32 = rows.parse().ok()?;
+
+    if cols <= 0 || rows <= 0 {
+        return None;
     }
 
-    Ok(Config::Convert(ConvertConfig { input, output, resolution, scale }))
+    Some((cols, rows))
 }
 
-const HELP_MESSAGE: &str = r#"usage: lison-to-png [-h] [-o output] [-r resolution] [-s scale] input
+const LISON_EXTENSIONS: [&str; 2] = [".lison", ".json"];
+
+/// Replaces a known LISON extension of `input` with that of `format`, or appends it
+/// otherwise.
+fn default_output(input: &str, format: Backend) -> String {
+    let stem = LISON_EXTENSIONS.iter()
+        .find_map(|ext| input.strip_suffix(ext).filter(|stem| !stem.is_empty() && !stem.ends_with('/')))
+        .unwrap_or(input);
+
+    format!("{}.{}", stem, format.name())
+}
+
+/// Chooses the format from the extension of `output`, PNG unless it is `.svg` or `.pdf`.
+fn output_format(output: &str) -> Backend {
+    let extension = Path::new(output).extension().and_then(|ext| ext.to_str());
+
+    match extension.map(str::to_ascii_lowercase).as_deref() {
+        Some("svg") => Backend::Svg,
+        Some("pdf") => Backend::Pdf,
+        _ => Backend::Png
+    }
+}
+
+const DEFAULT_RESOLUTION: f64 = 96.0;
+
+const HELP_MESSAGE: &str = r#"usage: lison-to-png [-h] [-o output] [-f format] [-r resolution] [-s scale] [--profile] [--info] [--quality preset] [--antialias mode] [--expand-canvas] [--crop] [--margin units] [--tile grid] [--alpha-mask] [--max-pixels num] [--clip] [--icc file] [--debug-index] [--relaxed] [--background color] input
 options:
-  -h        : print help message.
-  -o <file> : output file name.
-  -r <num>  : resolution in ppi.
-  -s <num>  : scale ratio."#;
+  -h                   : print help message.
+  -o <file>            : output file name; - writes to the standard output.
+  -f <format>          : output format, one of png, svg and pdf; by default taken from
+                         the output file name.
+  -r <num>             : resolution in ppi; ignored for svg and pdf.
+  -s <num>             : scale ratio.
+  --profile            : print the slowest top-level shapes.
+  --info               : print image statistics as JSON instead of converting.
+  --quality <preset>   : rendering quality, one of fast, balanced and best.
+  --antialias <mode>   : antialiasing, one of none, fast and best; overrides --quality.
+  --expand-canvas      : grow the output to include shapes outside the canvas.
+  --crop               : trim the output to the bounding box of the shapes.
+  --margin <units>     : margin around the bounding box with --crop, in image units.
+  --tile <grid>        : repeat the image in a grid given as COLSxROWS, e.g. 2x2.
+  --alpha-mask         : write only the alpha channel as a grayscale image.
+  --max-pixels <num>   : refuse to render more pixels, counting supersampling.
+  --clip               : clip shapes to the canvas, even with --expand-canvas.
+  --icc <file>         : embed an ICC profile; colors are taken to be in its space.
+  --debug-index        : label each top-level shape with its index.
+  --relaxed            : accept JSON5, e.g. comments and trailing commas.
+  --background <color> : paint the output with a CSS color name or a hex color
+                         such as '#ffffff' before drawing."#;
+
+const PROFILE_COUNT: usize = 10;
+
+fn render_options(conf: &ConvertConfig, image: &Image) -> RenderOptions {
+    let options = conf.quality.map_or_else(RenderOptions::default, Quality::options);
+    let viewport = if conf.crop {
+        image.bounding_box().map(|(min, max)| (
+            Point { x: min.x - conf.margin, y: min.y - conf.margin },
+            Point { x: max.x + conf.margin, y: max.y + conf.margin }
+        ))
+    } else if conf.expand_canvas {
+        Some(image.expanded_canvas())
+    } else {
+        None
+    };
+
+    RenderOptions { profile: conf.profile, viewport, max_pixels: conf.max_pixels, clip_to_canvas: conf.clip, debug_index: conf.debug_index, background: conf.background, antialias: conf.antialias.or(options.antialias), ..options }
+}
+
+/// Fails if `--crop` is given for an image without content to fit the canvas to.
+fn check_crop(conf: &ConvertConfig, image: &Image) -> Result<(), String> {
+    if conf.crop && image.bounding_box().is_none() {
+        return Err(String::from("nothing to crop."));
+    }
+
+    Ok(())
+}
+
+/// Repeats `surface` `cols` times horizontally and `rows` times vertically. Each tile is an
+/// identical copy, so gradients restart in every tile.
+fn tile_surface(surface: &cairo::ImageSurface, cols: i32, rows: i32) -> Result<cairo::ImageSurface, String> {
+    let width = surface.width().checked_mul(cols);
+    let height = surface.height().checked_mul(rows);
+    let (Some(width), Some(height)) = (width, height) else {
+        return Err(String::from("bad image dimension."));
+    };
+
+    let tiled = cairo::ImageSurface::create(cairo::Format::ARgb32, width, height)
+        .map_err(|_| String::from("surface creation failed."))?;
+
+    let context = cairo::Context::new(&tiled)
+        .map_err(|_| String::from("context creation failed."))?;
+
+    let pattern = cairo::SurfacePattern::create(surface);
+    pattern.set_extend(cairo::Extend::Repeat);
+    context.set_source(&pattern)
+        .map_err(|_| String::from("rendering operation failed."))?;
+    context.paint()
+        .map_err(|_| String::from("rendering operation failed."))?;
+    drop(context);
+
+    Ok(tiled)
+}
+
+/// Copies the alpha channel of an ARGB32 surface into an A8 surface, which cairo writes as a
+/// grayscale PNG.
+fn alpha_mask(surface: &mut cairo::ImageSurface) -> Result<cairo::ImageSurface, String> {
+    let (width, height) = (surface.width(), surface.height());
+    let stride = surface.stride() as usize;
+
+    let mut mask = cairo::ImageSurface::create(cairo::Format::A8, width, height)
+        .map_err(|_| String::from("surface creation failed."))?;
+    let mask_stride = mask.stride() as usize;
+
+    {
+        let data = surface.data()
+            .map_err(|_| String::from("failed to access the surface."))?;
+        let mut mask_data = mask.data()
+            .map_err(|_| String::from("failed to access the surface."))?;
+
+        for y in 0..height as usize {
+            for x in 0..width as usize {
+                let offset = y * stride + x * 4;
+                let pixel = u32::from_ne_bytes(data[offset..offset + 4].try_into().unwrap());
+                mask_data[y * mask_stride + x] = (pixel >> 24) as u8;
+            }
+        }
+    }
+
+    Ok(mask)
+}
+
+/// Inserts an `iCCP` chunk holding `profile` right after the `IHDR` chunk of a PNG file.
+/// Colors are not converted; the profile only tags the pixels as being in its color space.
+fn embed_icc_profile(png: &[u8], name: &str, profile: &[u8]) -> Result<Vec<u8>, String> {
+    const SIGNATURE_LENGTH: usize = 8;
+    const IHDR_END: usize = SIGNATURE_LENGTH + 4 + 4 + 13 + 4;
+
+    if png.len() < IHDR_END || &png[SIGNATURE_LENGTH + 4..SIGNATURE_LENGTH + 8] != b"IHDR" {
+        return Err(String::from("bad PNG data."));
+    }
+
+    let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(profile)
+        .map_err(|_| String::from("failed to compress the ICC profile."))?;
+    let compressed = encoder.finish()
+        .map_err(|_| String::from("failed to compress the ICC profile."))?;
+
+    // Profile name, null separator and compression method 0 (zlib).
+    let mut data = Vec::with_capacity(name.len() + 2 + compressed.len());
+    data.extend_from_slice(name.as_bytes());
+    data.extend_from_slice(&[0, 0]);
+    data.extend_from_slice(&compressed);
+
+    let length = u32::try_from(data.len())
+        .map_err(|_| String::from("ICC profile is too large."))?;
+
+    let mut crc = flate2::Crc::new();
+    crc.update(b"iCCP");
+    crc.update(&data);
+
+    let mut output = Vec::with_capacity(png.len() + data.len() + 12);
+    output.extend_from_slice(&png[..IHDR_END]);
+    output.extend_from_slice(&length.to_be_bytes());
+    output.extend_from_slice(b"iCCP");
+    output.extend_from_slice(&data);
+    output.extend_from_slice(&crc.sum().to_be_bytes());
+    output.extend_from_slice(&png[IHDR_END..]);
+
+    Ok(output)
+}
+
+/// Returns a profile name for the `iCCP` chunk from the file name of the profile. PNG allows
+/// 1 to 79 printable Latin-1 characters.
+fn icc_profile_name(path: &str) -> String {
+    let stem = Path::new(path).file_stem().and_then(|stem| stem.to_str()).unwrap_or_default();
+    let name: String = stem.chars()
+        .filter(|c| c.is_ascii_graphic() || *c == ' ')
+        .take(79)
+        .collect();
+    let name = name.trim();
+
+    if name.is_empty() { String::from("ICC profile") } else { String::from(name) }
+}
+
+fn info_json(image: &Image, conf: &ConvertConfig) -> Result<serde_json::Value, String> {
+    let (width, height) = pixel_size(image, conf.resolution, conf.scale)
+        .ok_or_else(|| String::from("bad image dimension."))?;
+
+    Ok(serde_json::json!({
+        "width": image.width,
+        "height": image.height,
+        "unit-per-inch": image.unit_per_inch,
+        "pixel-width": width,
+        "pixel-height": height,
+        "stats": image.stats(),
+        "bounding-box": image.bounding_box().map(|(min, max)| [min, max]),
+        "colors": image.colors()
+    }))
+}
+
+/// Renders the image as an SVG or PDF document of its physical size times the scale, so the
+/// resolution is ignored. Options that post-process the pixels are rejected.
+///
+/// Without the `svg` and `pdf` features, `image` is unused and an error is returned.
+#[cfg_attr(not(any(feature = "svg", feature = "pdf")), allow(unused_variables))]
+fn write_vector(image: &Image, conf: &ConvertConfig) -> Result<(), String> {
+    if conf.tile.is_some() || conf.alpha_mask || conf.icc.is_some() {
+        return Err(format!("--tile, --alpha-mask and --icc are not supported for {} output.", conf.format.name()));
+    }
+
+    if conf.resolution != DEFAULT_RESOLUTION {
+        eprintln!("warning: -r is ignored for {} output.", conf.format.name());
+    }
+
+    check_backend(conf.format)
+        .map_err(|e| format!("{}.", e))?;
+
+    let document: Result<Vec<u8>, RenderError> = match conf.format {
+        #[cfg(feature = "svg")]
+        Backend::Svg => render_svg(image, DEFAULT_RESOLUTION, conf.scale, &render_options(conf, image), Vec::new()).map(|(svg, _)| svg),
+        #[cfg(feature = "pdf")]
+        Backend::Pdf => render_pdf(image, conf.scale, &render_options(conf, image), Vec::new()).map(|(pdf, _)| pdf),
+        _ => Err(RenderError::BackendUnavailable(conf.format.name()))
+    };
+
+    let document = document.map_err(|e| match e {
+        RenderError::TooManyPixels { .. } | RenderError::BackendUnavailable(_)
+            | RenderError::PenOutOfRange { .. } | RenderError::BrushOutOfRange { .. }
+            | RenderError::ImageData(_) | RenderError::ImagePng(_) => format!("{}.", e),
+        _ => String::from("rendering operation failed.")
+    })?;
+
+    create_output(&conf.output)?
+        .write_all(&document)
+        .map_err(|_| format!("failed to write to '{}'.", &conf.output))
+}
+
+/// Output name that stands for the standard output.
+const STDOUT: &str = "-";
+
+/// Reads the image from `input`, as JSON5 if `relaxed` is set.
+fn read_input(input: &str, relaxed: bool) -> Result<Image, String> {
+    let path = Path::new(input);
+    let image = if relaxed { read_image_relaxed(path) } else { read_image(path) };
+
+    image.map_err(|e| format!("failed to read '{}': {}.", input, e))
+}
+
+/// Opens the output file, or the standard output if `output` is `STDOUT`.
+fn create_output(output: &str) -> Result<Box<dyn Write>, String> {
+    if output == STDOUT {
+        return Ok(Box::new(std::io::stdout().lock()));
+    }
+
+    let file = fs::File::create(output)
+        .map_err(|_| format!("failed to create '{}'.", output))?;
+
+    Ok(Box::new(file))
+}
 
 fn main() -> Result<(), String> {
     let args: Vec<String> = env::args().collect();
@@ -97,38 +537,409 @@ fn main() -> Result<(), String> {
             eprintln!("{}", HELP_MESSAGE);
         },
         Config::Convert(conf) => {
-            let image_str = fs::read_to_string(&conf.input)
-                .or_else(|_| Err(format!("failed to read '{}'.", &conf.input)))?;
+            let image = read_input(&conf.input, conf.relaxed)?;
 
-            let image: Image = serde_json::from_str(&image_str)
-                .or_else(|_| Err(format!("failed to parse '{}'.", &conf.input)))?;
+            if conf.info {
+                println!("{}", info_json(&image, &conf)?);
+                return Ok(());
+            }
 
-            let width = (image.width * conf.resolution / image.unit_per_inch * conf.scale).round();
-            let height = (image.height * conf.resolution / image.unit_per_inch * conf.scale).round();
+            image.validate()
+                .map_err(|e| format!("'{}': {}.", &conf.input, e))?;
 
-            if width <= 0.0 || width > i32::MAX.into() || height <= 0.0 || height > i32::MAX.into() {
+            if pixel_size(&image, conf.resolution, conf.scale).is_none() {
                 return Err(String::from("bad image dimension."));
             }
 
-            let width = width as i32;
-            let height = height as i32;
+            check_crop(&conf, &image)?;
+
+            if conf.format != Backend::Png {
+                return write_vector(&image, &conf);
+            }
+
+            let (mut surface, report) = rasterize(&image, conf.resolution, conf.scale, &render_options(&conf, &image))
+                .map_err(|e| match e {
+                    RenderError::TooManyPixels { .. } | RenderError::PenOutOfRange { .. }
+                        | RenderError::BrushOutOfRange { .. } | RenderError::ImageData(_)
+                        | RenderError::ImagePng(_) => format!("{}.", e),
+                    _ => String::from("rendering operation failed.")
+                })?;
+
+            if let Some((cols, rows)) = conf.tile {
+                let width = surface.width().saturating_mul(cols);
+                let height = surface.height().saturating_mul(rows);
+                check_pixel_count(width, height, conf.max_pixels)
+                    .map_err(|e| format!("{}.", e))?;
+
+                surface = tile_surface(&surface, cols, rows)?;
+            }
+
+            if conf.alpha_mask {
+                surface = alpha_mask(&mut surface)?;
+            }
 
-            let surface = cairo::ImageSurface::create(cairo::Format::ARgb32, width, height)
-                .or_else(|_| Err(String::from("surface creation failed.")))?;
+            if conf.profile {
+                let mut timings = report.timings;
+                timings.sort_by_key(|(_, time)| std::cmp::Reverse(*time));
 
-            let context = cairo::Context::new(&surface)
-                .or_else(|_| Err(String::from("context creation failed.")))?;
+                for (index, time) in timings.iter().take(PROFILE_COUNT) {
+                    eprintln!("shape {}: {:.3} ms", index, time.as_secs_f64() * 1000.0);
+                }
+            }
 
-            render(&context, &image, conf.resolution, conf.scale)
-                .or_else(|_| Err(String::from("rendering operation failed.")))?;
+            let mut output_file = create_output(&conf.output)?;
 
-            let mut output_file = fs::File::create(&conf.output)
-                .or_else(|_| Err(format!("failed to create '{}'.", &conf.output)))?;
+            if let Some(icc) = &conf.icc {
+                let profile = fs::read(icc)
+                    .map_err(|_| format!("failed to read '{}'.", icc))?;
 
-            surface.write_to_png(&mut output_file)
-                .or_else(|_| Err(format!("failed to write to '{}'.", &conf.output)))?;
+                let mut png = Vec::new();
+                surface.write_to_png(&mut png)
+                    .map_err(|_| String::from("failed to encode the image."))?;
+
+                let png = embed_icc_profile(&png, &icc_profile_name(icc), &profile)?;
+                output_file.write_all(&png)
+                    .map_err(|_| format!("failed to write to '{}'.", &conf.output))?;
+            } else {
+                surface.write_to_png(&mut output_file)
+                    .map_err(|_| format!("failed to write to '{}'.", &conf.output))?;
+            }
         }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn convert_config(args: &[&str]) -> ConvertConfig {
+        let args: Vec<String> = args.iter().map(|arg| String::from(*arg)).collect();
+
+        match parse_args(&args) {
+            Ok(Config::Convert(conf)) => conf,
+            _ => panic!("expected a convert config")
+        }
+    }
+
+    #[test]
+    fn test_info() {
+        let conf = convert_config(&["--info", "-r", "144", "region.lison"]);
+        assert!(conf.info);
+
+        let image: Image = serde_json::from_str(include_str!("../../samples/region.lison")).unwrap();
+        let info = info_json(&image, &conf).unwrap();
+
+        assert_eq!(200, info["pixel-width"]);
+        assert_eq!(200, info["pixel-height"]);
+        assert_eq!(2, info["stats"]["shapes"]);
+        assert_eq!(2, info["stats"]["regions"]);
+        assert_eq!(0, info["stats"]["curves"]);
+        assert_eq!(3, info["colors"].as_array().unwrap().len());
+        assert_eq!(serde_json::json!([[5.0, 4.0], [96.0, 96.0]]), info["bounding-box"]);
+    }
+
+    #[test]
+    fn test_default_output() {
+        assert_eq!("drawing.png", convert_config(&["drawing.lison"]).output);
+        assert_eq!("dir/drawing.png", convert_config(&["dir/drawing.json"]).output);
+        assert_eq!("drawing.txt.png", convert_config(&["drawing.txt"]).output);
+        assert_eq!(".lison.png", convert_config(&[".lison"]).output);
+        assert_eq!("out.png", convert_config(&["-o", "out.png", "drawing.lison"]).output);
+    }
+
+    #[test]
+    fn test_stdout_output() {
+        let conf = convert_config(&["-o", "-", "drawing.lison"]);
+        assert_eq!(STDOUT, conf.output);
+        assert_eq!(Backend::Png, conf.format);
+
+        let conf = convert_config(&["-o", "-", "-f", "svg", "drawing.lison"]);
+        assert_eq!(STDOUT, conf.output);
+        assert_eq!(Backend::Svg, conf.format);
+    }
+
+    #[test]
+    fn test_relaxed() {
+        let conf = convert_config(&["--relaxed", "a.lison"]);
+        assert!(conf.relaxed);
+        assert!(!convert_config(&["a.lison"]).relaxed);
+
+        let input = env::temp_dir().join(format!("lison-to-png-relaxed-{}.lison", std::process::id()));
+        fs::write(&input, "{ width: 10, height: 10, 'unit-per-inch': 96, pens: [], brushes: [], shapes: [], }").unwrap();
+        let input = input.to_str().unwrap();
+        assert!(read_input(input, false).is_err());
+        assert_eq!(cfg!(feature = "json5"), read_input(input, true).is_ok());
+        fs::remove_file(input).unwrap();
+    }
+
+    #[test]
+    fn test_antialias() {
+        assert!(convert_config(&["a.lison"]).antialias.is_none());
+        assert_eq!(Some(cairo::Antialias::None), convert_config(&["--antialias", "none", "a.lison"]).antialias);
+        assert_eq!(Some(cairo::Antialias::Best), convert_config(&["--antialias", "best", "a.lison"]).antialias);
+
+        let args: Vec<String> = ["--antialias", "gray", "a.lison"].iter().map(|arg| String::from(*arg)).collect();
+        assert!(parse_args(&args).is_err());
+
+        let image_str = r#"{
+  "width": 20,
+  "height": 20,
+  "unit-per-inch": 96,
+  "pens": [],
+  "brushes": [
+    { "pattern": { "type": "monochrome", "color": [0, 0, 0] } }
+  ],
+  "shapes": [
+    { "type": "region", "brush": 0, "data": [[[0, 0], ["L", [20, 0]], ["L", [0, 20]]]] }
+  ]
+}"#;
+        let image: Image = serde_json::from_str(image_str).unwrap();
+        let diagonal_alpha = |conf: &ConvertConfig| {
+            let (mut surface, _) = rasterize(&image, conf.resolution, conf.scale, &render_options(conf, &image)).unwrap();
+            let offset = 9 * surface.stride() as usize + 10 * 4;
+            surface.data().unwrap()[offset..offset + 4].iter().copied().max().unwrap()
+        };
+
+        // The diagonal edge passes through the pixel, which antialiasing covers partially.
+        let alpha = diagonal_alpha(&convert_config(&["a.lison"]));
+        assert!(alpha > 0 && alpha < 255);
+        assert!([0, 255].contains(&diagonal_alpha(&convert_config(&["--antialias", "none", "a.lison"]))));
+
+        // The option overrides the antialiasing of the preset.
+        let conf = convert_config(&["--quality", "fast", "--antialias", "none", "a.lison"]);
+        let options = render_options(&conf, &image);
+        assert_eq!(Some(cairo::Antialias::None), options.antialias);
+        assert_eq!(Some(0.5), options.tolerance);
+    }
+
+    #[test]
+    fn test_background() {
+        assert!(convert_config(&["a.lison"]).background.is_none());
+
+        let conf = convert_config(&["--background", "#ffffff", "a.lison"]);
+        assert_eq!(Some(Color { red: 1.0, green: 1.0, blue: 1.0, alpha: 1.0 }), conf.background);
+        assert_eq!(Color::parse("white"), convert_config(&["--background", "white", "a.lison"]).background);
+
+        let args: Vec<String> = ["--background", "[1, 1, 1]", "a.lison"].iter().map(|arg| String::from(*arg)).collect();
+        assert!(parse_args(&args).is_err());
+    }
+
+    #[test]
+    fn test_stdin_input() {
+        let conf = convert_config(&["-o", "out.png", "-"]);
+        assert_eq!(STDIN, conf.input);
+        assert_eq!("out.png", conf.output);
+
+        let conf = convert_config(&["-r", "48", "-o", "out.png", "-"]);
+        assert_eq!(STDIN, conf.input);
+        assert_eq!(48.0, conf.resolution);
+
+        let args = |args: &[&str]| args.iter().map(|arg| String::from(*arg)).collect::<Vec<String>>();
+        let conf = convert_config(&["-", "-o", "a.png"]);
+        assert_eq!(STDIN, conf.input);
+        assert_eq!("a.png", conf.output);
+
+        let conf = convert_config(&["a.lison", "-r", "48"]);
+        assert_eq!("a.lison", conf.input);
+        assert_eq!(48.0, conf.resolution);
+
+        assert!(parse_args(&args(&["-"])).is_err());
+        assert!(parse_args(&args(&["a.lison", "-o", "a.png", "b.lison"])).is_err());
+        assert!(parse_args(&args(&["-o", "-", "-"])).is_err());
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eq!(Backend::Png, convert_config(&["drawing.lison"]).format);
+        assert_eq!(Backend::Svg, convert_config(&["-o", "drawing.svg", "drawing.lison"]).format);
+        assert_eq!(Backend::Pdf, convert_config(&["-o", "dir/drawing.PDF", "drawing.lison"]).format);
+        assert_eq!(Backend::Png, convert_config(&["-o", "pdf", "drawing.lison"]).format);
+
+        let conf = convert_config(&["-f", "pdf", "drawing.lison"]);
+        assert_eq!(Backend::Pdf, conf.format);
+        assert_eq!("drawing.pdf", conf.output);
+
+        let conf = convert_config(&["-o", "out.png", "-f", "svg", "drawing.lison"]);
+        assert_eq!(Backend::Svg, conf.format);
+        assert_eq!("out.png", conf.output);
+    }
+
+    #[test]
+    fn test_tile() {
+        let conf = convert_config(&["--tile", "2x2", "-r", "48", "region.lison"]);
+        assert_eq!(Some((2, 2)), conf.tile);
+        assert!(parse_tile("2x0").is_none());
+        assert!(parse_tile("2").is_none());
+
+        let image: Image = serde_json::from_str(include_str!("../../samples/region.lison")).unwrap();
+        let (mut surface, _) = rasterize(&image, conf.resolution, conf.scale, &render_options(&conf, &image)).unwrap();
+        let (width, height) = (surface.width() as usize, surface.height() as usize);
+        let stride = surface.stride() as usize;
+        let original = surface.data().unwrap().to_vec();
+
+        let mut tiled = tile_surface(&surface, 2, 2).unwrap();
+        assert_eq!(2 * width, tiled.width() as usize);
+        assert_eq!(2 * height, tiled.height() as usize);
+
+        let tiled_stride = tiled.stride() as usize;
+        let data = tiled.data().unwrap();
+
+        for (x, y) in [(width / 2, height / 2), (width / 3, height / 4), (width - 1, height - 1)] {
+            let expect = &original[y * stride + x * 4..][..4];
+
+            for (tx, ty) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                let offset = (y + ty * height) * tiled_stride + (x + tx * width) * 4;
+                assert_eq!(expect, &data[offset..offset + 4]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_alpha_mask() {
+        let image_str = r#"{
+  "width": 10,
+  "height": 10,
+  "unit-per-inch": 96,
+  "pens": [],
+  "brushes": [
+    { "pattern": { "type": "monochrome", "color": [1, 0, 0, 0.5] } }
+  ],
+  "shapes": [
+    { "type": "region", "brush": 0, "data": [[[0, 0], ["L", [5, 0]], ["L", [5, 10]], ["L", [0, 10]]]] }
+  ]
+}"#;
+        let image: Image = serde_json::from_str(image_str).unwrap();
+
+        let conf = convert_config(&["--alpha-mask", "mask.lison"]);
+        assert!(conf.alpha_mask);
+
+        let (mut surface, _) = rasterize(&image, conf.resolution, conf.scale, &render_options(&conf, &image)).unwrap();
+        let mut mask = alpha_mask(&mut surface).unwrap();
+        assert_eq!(cairo::Format::A8, mask.format());
+
+        let stride = mask.stride() as usize;
+        let data = mask.data().unwrap();
+        assert_eq!(128, data[5 * stride + 2]);
+        assert_eq!(0, data[5 * stride + 7]);
+    }
+
+    #[test]
+    fn test_icc_profile() {
+        use std::io::Read;
+
+        let conf = convert_config(&["--icc", "profiles/Display P3.icc", "a.lison"]);
+        assert_eq!(Some(String::from("profiles/Display P3.icc")), conf.icc);
+        assert_eq!("Display P3", icc_profile_name("profiles/Display P3.icc"));
+
+        let image: Image = serde_json::from_str(include_str!("../../samples/region.lison")).unwrap();
+        let (surface, _) = rasterize(&image, 96.0, 1.0, &RenderOptions::default()).unwrap();
+        let mut png = Vec::new();
+        surface.write_to_png(&mut png).unwrap();
+
+        let profile = b"not really an ICC profile";
+        let tagged = embed_icc_profile(&png, "Display P3", profile).unwrap();
+
+        let mut offset = 8;
+        let mut found = None;
+
+        while offset < tagged.len() {
+            let length = u32::from_be_bytes(tagged[offset..offset + 4].try_into().unwrap()) as usize;
+            let chunk_type = &tagged[offset + 4..offset + 8];
+            let data = &tagged[offset + 8..offset + 8 + length];
+
+            if chunk_type == b"iCCP" {
+                found = Some(data.to_vec());
+            }
+
+            offset += length + 12;
+        }
+
+        let data = found.expect("no iCCP chunk");
+        let separator = data.iter().position(|b| *b == 0).unwrap();
+        assert_eq!(b"Display P3", &data[..separator]);
+        assert_eq!(0, data[separator + 1]);
+
+        let mut decompressed = Vec::new();
+        flate2::read::ZlibDecoder::new(&data[separator + 2..]).read_to_end(&mut decompressed).unwrap();
+        assert_eq!(profile.as_slice(), decompressed.as_slice());
+
+        let decoded = cairo::ImageSurface::create_from_png(&mut tagged.as_slice()).unwrap();
+        assert_eq!(surface.width(), decoded.width());
+    }
+
+    #[test]
+    fn test_quality() {
+        let image: Image = serde_json::from_str(include_str!("../../samples/region.lison")).unwrap();
+
+        let fast = render_options(&convert_config(&["--quality", "fast", "--profile", "a.lison"]), &image);
+        let best = render_options(&convert_config(&["--quality", "best", "a.lison"]), &image);
+        assert!(fast.tolerance.unwrap() > best.tolerance.unwrap());
+        assert!(fast.supersample < best.supersample);
+        assert!(fast.profile);
+
+        let default = render_options(&convert_config(&["a.lison"]), &image);
+        assert!(default.antialias.is_none());
+        assert!(default.tolerance.is_none());
+    }
+
+    #[test]
+    fn test_crop() {
+        let image: Image = serde_json::from_str(include_str!("../../samples/region.lison")).unwrap();
+
+        let conf = convert_config(&["--crop", "--margin", "2", "a.lison"]);
+        assert!(conf.crop);
+        assert_eq!(2.0, conf.margin);
+        let (min, max) = render_options(&conf, &image).viewport.unwrap();
+        let (content_min, content_max) = image.bounding_box().unwrap();
+        assert!(Point { x: content_min.x - 2.0, y: content_min.y - 2.0 } == min);
+        assert!(Point { x: content_max.x + 2.0, y: content_max.y + 2.0 } == max);
+        assert!(check_crop(&conf, &image).is_ok());
+
+        let args = |args: &[&str]| args.iter().map(|arg| String::from(*arg)).collect::<Vec<String>>();
+        assert!(parse_args(&args(&["--margin", "-1", "a.lison"])).is_err());
+        assert!(parse_args(&args(&["--crop", "--expand-canvas", "a.lison"])).is_err());
+        assert!(parse_args(&args(&["--margin", "2", "a.lison"])).is_err());
+
+        let empty = Image::new(10.0, 10.0, 96.0);
+        assert_eq!(Err(String::from("nothing to crop.")), check_crop(&conf, &empty));
+        assert!(check_crop(&convert_config(&["a.lison"]), &empty).is_ok());
+    }
+
+    #[test]
+    fn test_expand_canvas() {
+        let image: Image = serde_json::from_str(include_str!("../../samples/region.lison")).unwrap();
+
+        let expanded = render_options(&convert_config(&["--expand-canvas", "a.lison"]), &image);
+        assert!(expanded.viewport.is_some());
+        assert!(render_options(&convert_config(&["a.lison"]), &image).viewport.is_none());
+    }
+
+    #[test]
+    fn test_max_pixels() {
+        let image: Image = serde_json::from_str(include_str!("../../samples/region.lison")).unwrap();
+
+        let limited = render_options(&convert_config(&["--max-pixels", "1000", "a.lison"]), &image);
+        assert_eq!(Some(1000), limited.max_pixels);
+        assert!(rasterize(&image, 96.0, 1.0, &limited).is_err());
+    }
+
+    #[test]
+    fn test_clip() {
+        let image: Image = serde_json::from_str(include_str!("../../samples/region.lison")).unwrap();
+
+        let clipped = render_options(&convert_config(&["--clip", "a.lison"]), &image);
+        assert!(clipped.clip_to_canvas);
+        assert!(!render_options(&convert_config(&["a.lison"]), &image).clip_to_canvas);
+    }
+
+    #[test]
+    fn test_debug_index() {
+        let image: Image = serde_json::from_str(include_str!("../../samples/region.lison")).unwrap();
+
+        let labelled = render_options(&convert_config(&["--debug-index", "a.lison"]), &image);
+        assert!(labelled.debug_index);
+        assert!(!render_options(&convert_config(&["a.lison"]), &image).debug_index);
+    }
+}
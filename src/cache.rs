@@ -0,0 +1,176 @@
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, VecDeque};
+use std::hash::BuildHasher;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct CacheKey {
+    content_hash: u64,
+    content_len: usize,
+    ppi: u64,
+    scale: u64
+}
+
+impl CacheKey {
+    fn new(hasher: &RandomState, content: &[u8], ppi: f64, scale: f64) -> CacheKey {
+        CacheKey {
+            content_hash: hasher.hash_one(content),
+            content_len: content.len(),
+            ppi: ppi.to_bits(),
+            scale: scale.to_bits()
+        }
+    }
+}
+
+struct CacheEntry {
+    /// The source the bytes were rendered from, compared on every hit since different
+    /// sources may have the same key.
+    content: Vec<u8>,
+    bytes: Vec<u8>
+}
+
+/// In-memory LRU cache of rendered images keyed by source content, resolution and scale.
+pub struct RenderCache {
+    capacity: usize,
+    /// Hashes the content with keys chosen per cache, so that collisions cannot be predicted.
+    hasher: RandomState,
+    entries: HashMap<CacheKey, CacheEntry>,
+    order: VecDeque<CacheKey>
+}
+
+impl RenderCache {
+    pub fn new(capacity: usize) -> RenderCache {
+        RenderCache {
+            capacity: capacity.max(1),
+            hasher: RandomState::new(),
+            entries: HashMap::new(),
+            order: VecDeque::new()
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+    }
+
+    /// Returns the cached bytes for `(content, ppi, scale)`, calling `render` only on a miss.
+    /// An entry for other content with the same key is replaced.
+    pub fn get_or_render<F, E>(&mut self, content: &[u8], ppi: f64, scale: f64, render: F) -> Result<&[u8], E>
+    where
+        F: FnOnce() -> Result<Vec<u8>, E>
+    {
+        let key = CacheKey::new(&self.hasher, content, ppi, scale);
+
+        let cached = self.entries.get(&key).map(|entry| entry.content == content);
+
+        if cached != Some(true) {
+            let bytes = render()?;
+
+            while cached.is_none() && self.entries.len() >= self.capacity {
+                match self.order.pop_front() {
+                    Some(oldest) => { self.entries.remove(&oldest); },
+                    None => break
+                }
+            }
+
+            self.entries.insert(key, CacheEntry { content: content.to_vec(), bytes });
+        }
+
+        if let Some(pos) = self.order.iter().position(|k| *k == key) {
+            self.order.remove(pos);
+        }
+        self.order.push_back(key);
+
+        Ok(&self.entries[&key].bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    #[test]
+    fn test_get_or_render_hit() {
+        let count = Cell::new(0);
+        let render = || -> Result<Vec<u8>, ()> {
+            count.set(count.get() + 1);
+            Ok(vec![1, 2, 3])
+        };
+
+        let mut cache = RenderCache::new(4);
+        let content = br#"{"width":1}"#;
+
+        assert_eq!(&[1, 2, 3], cache.get_or_render(content, 96.0, 1.0, render).unwrap());
+        assert_eq!(&[1, 2, 3], cache.get_or_render(content, 96.0, 1.0, render).unwrap());
+        assert_eq!(1, count.get());
+        assert_eq!(1, cache.len());
+
+        cache.get_or_render(content, 96.0, 2.0, render).unwrap();
+        cache.get_or_render(content, 72.0, 1.0, render).unwrap();
+        cache.get_or_render(br#"{"width":2}"#, 96.0, 1.0, render).unwrap();
+        assert_eq!(4, count.get());
+        assert_eq!(4, cache.len());
+    }
+
+    #[test]
+    fn test_get_or_render_eviction() {
+        let count = Cell::new(0);
+        let render = || -> Result<Vec<u8>, ()> {
+            count.set(count.get() + 1);
+            Ok(vec![count.get()])
+        };
+
+        let mut cache = RenderCache::new(2);
+
+        cache.get_or_render(b"a", 96.0, 1.0, render).unwrap();
+        cache.get_or_render(b"b", 96.0, 1.0, render).unwrap();
+        cache.get_or_render(b"a", 96.0, 1.0, render).unwrap();
+        cache.get_or_render(b"c", 96.0, 1.0, render).unwrap();
+        assert_eq!(3, count.get());
+        assert_eq!(2, cache.len());
+
+        // "b" was the least recently used entry and has been evicted.
+        cache.get_or_render(b"a", 96.0, 1.0, render).unwrap();
+        assert_eq!(3, count.get());
+        cache.get_or_render(b"b", 96.0, 1.0, render).unwrap();
+        assert_eq!(4, count.get());
+    }
+
+    #[test]
+    fn test_get_or_render_collision() {
+        let count = Cell::new(0);
+        let render = || -> Result<Vec<u8>, ()> {
+            count.set(count.get() + 1);
+            Ok(vec![count.get()])
+        };
+
+        let mut cache = RenderCache::new(2);
+        cache.get_or_render(b"a", 96.0, 1.0, render).unwrap();
+
+        // Pretend that the entry was rendered from other content with the same key.
+        let key = CacheKey::new(&cache.hasher, b"a", 96.0, 1.0);
+        cache.entries.get_mut(&key).unwrap().content = b"b".to_vec();
+
+        assert_eq!(&[2], cache.get_or_render(b"a", 96.0, 1.0, render).unwrap());
+        assert_eq!(&[2], cache.get_or_render(b"a", 96.0, 1.0, render).unwrap());
+        assert_eq!(2, count.get());
+        assert_eq!(1, cache.len());
+        assert_eq!(1, cache.order.len());
+    }
+
+    #[test]
+    fn test_get_or_render_error() {
+        let mut cache = RenderCache::new(2);
+        let result = cache.get_or_render(b"a", 96.0, 1.0, || Err("failed"));
+        assert_eq!(Err("failed"), result);
+        assert!(cache.is_empty());
+    }
+}
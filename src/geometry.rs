@@ -0,0 +1,209 @@
+//! Approximate comparison of geometric values.
+//!
+//! ```
+//! use lison::geometry::Relative;
+//! use lison::image::Point;
+//!
+//! let expect = Point { x: 100.0, y: 50.0 };
+//! let actual = Point { x: 101.0, y: 50.0 };
+//!
+//! assert!((actual.relative_error_from(&expect) - 0.01).abs() < 1e-9);
+//! ```
+
+use crate::image::*;
+
+pub trait Relative {
+    /// Returns the largest relative difference between the components of `self` and
+    /// `other`, using `other` as the reference. Values of different kinds, such as a line
+    /// and a Bezier segment, are infinitely far apart. Equal values are never apart, even when
+    /// the reference is zero, while NaN is infinitely far from everything, itself included.
+    fn relative_error_from(&self, other: &Self) -> f64;
+}
+
+impl Relative for f64 {
+    fn relative_error_from(&self, other: &f64) -> f64 {
+        if self == other {
+            // Avoids 0 / 0.
+            0.0
+        } else {
+            let error = ((self - other) / other).abs();
+
+            // A NaN would be dropped by `f64::max` when combining errors.
+            if error.is_nan() { f64::INFINITY } else { error }
+        }
+    }
+}
+
+impl Relative for Point {
+    fn relative_error_from(&self, other: &Point) -> f64 {
+        self.x.relative_error_from(&other.x)
+            .max(self.y.relative_error_from(&other.y))
+    }
+}
+
+impl Relative for Color {
+    fn relative_error_from(&self, other: &Color) -> f64 {
+        self.red.relative_error_from(&other.red)
+            .max(self.green.relative_error_from(&other.green))
+            .max(self.blue.relative_error_from(&other.blue))
+            .max(self.alpha.relative_error_from(&other.alpha))
+    }
+}
+
+const ORIGIN: Point = Point { x: 0.0, y: 0.0 };
+
+const IDENTITY: [f64; 6] = [1.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+
+impl<A: Relative, B: Relative> Relative for (A, B) {
+    fn relative_error_from(&self, other: &(A, B)) -> f64 {
+        self.0.relative_error_from(&other.0)
+            .max(self.1.relative_error_from(&other.1))
+    }
+}
+
+impl<T: Relative> Relative for Vec<T> {
+    fn relative_error_from(&self, other: &Vec<T>) -> f64 {
+        if self.len() != other.len() {
+            return f64::INFINITY;
+        }
+
+        self.iter()
+            .zip(other.iter())
+            .fold(0.0, |error, (a, b)| error.max(a.relative_error_from(b)))
+    }
+}
+
+impl Relative for Pattern {
+    fn relative_error_from(&self, other: &Pattern) -> f64 {
+        match self {
+            Pattern::Monochrome(mono1) =>
+                match other {
+                    Pattern::Monochrome(mono2) =>
+                        mono1.color.relative_error_from(&mono2.color),
+                    _ => f64::INFINITY
+                },
+            Pattern::LinearGradient(grad1) =>
+                match other {
+                    Pattern::LinearGradient(grad2) if grad1.interpolate == grad2.interpolate && grad1.extend.unwrap_or(Extend::Pad) == grad2.extend.unwrap_or(Extend::Pad) =>
+                        grad1.point_1.relative_error_from(&grad2.point_1)
+                        .max(grad1.point_2.relative_error_from(&grad2.point_2))
+                        .max(grad1.color_stops().relative_error_from(&grad2.color_stops())),
+                    _ => f64::INFINITY
+                },
+            Pattern::RadialGradient(grad1) =>
+                match other {
+                    Pattern::RadialGradient(grad2) if grad1.interpolate == grad2.interpolate && grad1.extend.unwrap_or(Extend::Pad) == grad2.extend.unwrap_or(Extend::Pad) =>
+                        grad1.center_1.relative_error_from(&grad2.center_1)
+                        .max(grad1.radius_1.relative_error_from(&grad2.radius_1))
+                        .max(grad1.center_2.relative_error_from(&grad2.center_2))
+                        .max(grad1.radius_2.relative_error_from(&grad2.radius_2))
+                        .max(grad1.aspect.unwrap_or(1.0).relative_error_from(&grad2.aspect.unwrap_or(1.0)))
+                        .max(grad1.color_stops().relative_error_from(&grad2.color_stops())),
+                    _ => f64::INFINITY
+                },
+            Pattern::ConicGradient(grad1) =>
+                match other {
+                    Pattern::ConicGradient(grad2) if grad1.interpolate == grad2.interpolate =>
+                        grad1.center.relative_error_from(&grad2.center)
+                        .max(grad1.start_angle.relative_error_from(&grad2.start_angle))
+                        .max(grad1.stops.relative_error_from(&grad2.stops)),
+                    _ => f64::INFINITY
+                },
+            Pattern::Image(image1) =>
+                match other {
+                    Pattern::Image(image2) if image1.data == image2.data && image1.extend == image2.extend =>
+                        image1.origin.unwrap_or(ORIGIN).relative_error_from(&image2.origin.unwrap_or(ORIGIN))
+                        .max(image1.pixel_size.unwrap_or(1.0).relative_error_from(&image2.pixel_size.unwrap_or(1.0))),
+                    _ => f64::INFINITY
+                }
+        }
+    }
+}
+
+impl Relative for Segment {
+    fn relative_error_from(&self, other: &Segment) -> f64 {
+        match self {
+            Segment::Line(line1) =>
+                match other {
+                    Segment::Line(line2) =>
+                        line1.point_2.relative_error_from(&line2.point_2),
+                    _ => f64::INFINITY
+                },
+            Segment::QuadraticBezier(bezier1) =>
+                match other {
+                    Segment::QuadraticBezier(bezier2) =>
+                        bezier1.point_2.relative_error_from(&bezier2.point_2)
+                        .max(bezier1.point_3.relative_error_from(&bezier2.point_3)),
+                    _ => f64::INFINITY
+                },
+            Segment::CubicBezier(bezier1) =>
+                match other {
+                    Segment::CubicBezier(bezier2) =>
+                        bezier1.point_2.relative_error_from(&bezier2.point_2)
+                        .max(bezier1.point_3.relative_error_from(&bezier2.point_3))
+                        .max(bezier1.point_4.relative_error_from(&bezier2.point_4)),
+                    _ => f64::INFINITY
+                },
+            Segment::SmoothQuadraticBezier(bezier1) =>
+                match other {
+                    Segment::SmoothQuadraticBezier(bezier2) =>
+                        bezier1.point_3.relative_error_from(&bezier2.point_3),
+                    _ => f64::INFINITY
+                },
+            Segment::SmoothCubicBezier(bezier1) =>
+                match other {
+                    Segment::SmoothCubicBezier(bezier2) =>
+                        bezier1.point_3.relative_error_from(&bezier2.point_3)
+                        .max(bezier1.point_4.relative_error_from(&bezier2.point_4)),
+                    _ => f64::INFINITY
+                },
+            Segment::Arc(arc1) =>
+                match other {
+                    Segment::Arc(arc2) if arc1.large_arc == arc2.large_arc && arc1.sweep == arc2.sweep =>
+                        arc1.radius_x.relative_error_from(&arc2.radius_x)
+                        .max(arc1.radius_y.relative_error_from(&arc2.radius_y))
+                        .max(arc1.rotation.relative_error_from(&arc2.rotation))
+                        .max(arc1.point_2.relative_error_from(&arc2.point_2)),
+                    _ => f64::INFINITY
+                }
+        }
+    }
+}
+
+impl Relative for CurveData {
+    fn relative_error_from(&self, other: &CurveData) -> f64 {
+        self.start.relative_error_from(&other.start)
+            .max(self.segments.relative_error_from(&other.segments))
+    }
+}
+
+impl Relative for Shape {
+    fn relative_error_from(&self, other: &Shape) -> f64 {
+        match self {
+            Shape::Group(group1) =>
+                match other {
+                    Shape::Group(group2) if group1.operator.unwrap_or(Operator::Over) == group2.operator.unwrap_or(Operator::Over) =>
+                        group1.opacity.unwrap_or(1.0).relative_error_from(&group2.opacity.unwrap_or(1.0))
+                        .max(group1.transform.unwrap_or(IDENTITY).to_vec().relative_error_from(&group2.transform.unwrap_or(IDENTITY).to_vec()))
+                        .max(group1.content.relative_error_from(&group2.content)),
+                    _ => f64::INFINITY
+                },
+            Shape::Curve(curve1) =>
+                match other {
+                    Shape::Curve(curve2) if curve1.pen == curve2.pen =>
+                        curve1.data.relative_error_from(&curve2.data),
+                    _ => f64::INFINITY
+                },
+            Shape::Region(region1) =>
+                match other {
+                    Shape::Region(region2) if region1.pen == region2.pen && region1.brush == region2.brush
+                        && region1.fill_rule.unwrap_or(FillRule::EvenOdd) == region2.fill_rule.unwrap_or(FillRule::EvenOdd) =>
+                        region1.fill_opacity.unwrap_or(1.0).relative_error_from(&region2.fill_opacity.unwrap_or(1.0))
+                        .max(region1.stroke_opacity.unwrap_or(1.0).relative_error_from(&region2.stroke_opacity.unwrap_or(1.0)))
+                        .max(region1.pattern_origin.unwrap_or(ORIGIN).relative_error_from(&region2.pattern_origin.unwrap_or(ORIGIN)))
+                        .max(region1.data.relative_error_from(&region2.data)),
+                    _ => f64::INFINITY
+                }
+        }
+    }
+}
@@ -1,10 +1,15 @@
 
+use std::collections::HashMap;
+use std::error;
 use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
 use serde::{Deserialize, Serialize};
-use serde::de::{Deserializer, SeqAccess, Visitor};
+use serde::de::{Deserializer, MapAccess, SeqAccess, Visitor};
 use serde::ser::{Serializer, SerializeSeq};
 
-#[derive(Deserialize, Serialize, Clone)]
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
 #[serde(rename_all = "kebab-case", deny_unknown_fields)]
 pub struct Image {
     pub width: f64,
@@ -12,12 +17,233 @@ pub struct Image {
     pub unit_per_inch: f64,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub editor: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_cap: Option<LineCap>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_join: Option<LineJoin>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alpha_mode: Option<AlphaMode>,
     pub pens: Vec<Pen>,
     pub brushes: Vec<Brush>,
     pub shapes: Vec<Shape>
 }
 
-#[derive(Clone, Copy)]
+#[derive(Debug)]
+pub enum LisonError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    PointerNotFound(String),
+    /// The string is not a base64 data URI holding an image. The message tells what is wrong.
+    InvalidDataUri(&'static str),
+    /// Embedded data is not valid base64.
+    InvalidBase64,
+    #[cfg(feature = "json5")]
+    Json5(json5::Error),
+    /// Relaxed parsing was requested, but the crate is built without the `json5` feature.
+    RelaxedUnavailable
+}
+
+impl fmt::Display for LisonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LisonError::Io(e) => write!(f, "I/O error: {}", e),
+            LisonError::Json(e) => write!(f, "JSON error: {}", e),
+            LisonError::PointerNotFound(pointer) => write!(f, "no value at '{}'", pointer),
+            LisonError::InvalidDataUri(message) => write!(f, "invalid data URI: {}", message),
+            LisonError::InvalidBase64 => write!(f, "invalid base64 data"),
+            #[cfg(feature = "json5")]
+            LisonError::Json5(e) => write!(f, "JSON5 error: {}", e),
+            LisonError::RelaxedUnavailable => write!(f, "relaxed parsing needs the json5 feature")
+        }
+    }
+}
+
+impl error::Error for LisonError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            LisonError::Io(e) => Some(e),
+            LisonError::Json(e) => Some(e),
+            #[cfg(feature = "json5")]
+            LisonError::Json5(e) => Some(e),
+            LisonError::PointerNotFound(_) | LisonError::InvalidDataUri(_) | LisonError::InvalidBase64
+                | LisonError::RelaxedUnavailable => None
+        }
+    }
+}
+
+impl From<io::Error> for LisonError {
+    fn from(e: io::Error) -> LisonError {
+        LisonError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for LisonError {
+    fn from(e: serde_json::Error) -> LisonError {
+        LisonError::Json(e)
+    }
+}
+
+#[cfg(feature = "json5")]
+impl From<json5::Error> for LisonError {
+    fn from(e: json5::Error) -> LisonError {
+        LisonError::Json5(e)
+    }
+}
+
+/// Deserializes an image from JSON5, which also allows comments, trailing commas, unquoted
+/// keys and single-quoted strings, for files written by hand.
+///
+/// Fails with `LisonError::RelaxedUnavailable` unless the `json5` feature is enabled.
+pub fn from_str_relaxed(s: &str) -> Result<Image, LisonError> {
+    #[cfg(feature = "json5")]
+    return Ok(json5::from_str(s)?);
+
+    #[cfg(not(feature = "json5"))]
+    {
+        let _ = s;
+        Err(LisonError::RelaxedUnavailable)
+    }
+}
+
+/// Deserializes the image found at the JSON Pointer `pointer` within `value`.
+pub fn from_value_at(value: &serde_json::Value, pointer: &str) -> Result<Image, LisonError> {
+    let image_value = value.pointer(pointer)
+        .ok_or_else(|| LisonError::PointerNotFound(String::from(pointer)))?;
+
+    Ok(Image::deserialize(image_value)?)
+}
+
+/// Deserializes an image from a `data:` URI such as `data:application/json;base64,...`.
+///
+/// The payload must be base64-encoded. It is gunzipped first when the media type is
+/// `application/gzip`.
+pub fn from_data_uri(uri: &str) -> Result<Image, LisonError> {
+    use base64::Engine;
+    use std::io::Read;
+
+    let rest = uri.strip_prefix("data:")
+        .ok_or(LisonError::InvalidDataUri("the scheme is not 'data'"))?;
+    let (header, payload) = rest.split_once(',')
+        .ok_or(LisonError::InvalidDataUri("missing ','"))?;
+
+    let mut params = header.split(';').map(str::trim);
+    let media_type = params.next().unwrap_or_default();
+
+    if !params.any(|param| param == "base64") {
+        return Err(LisonError::InvalidDataUri("the payload is not base64-encoded"));
+    }
+
+    let bytes = base64::engine::general_purpose::STANDARD.decode(payload.trim())
+        .map_err(|_| LisonError::InvalidDataUri("malformed base64"))?;
+
+    if media_type.eq_ignore_ascii_case("application/gzip") {
+        let mut json = Vec::new();
+        flate2::read::GzDecoder::new(bytes.as_slice()).read_to_end(&mut json)?;
+        Ok(serde_json::from_slice(&json)?)
+    } else {
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+}
+
+/// The first bytes of gzip data.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Returns `bytes` gunzipped if they start with the gzip magic number, or as they are.
+pub fn decompress(bytes: Vec<u8>) -> io::Result<Vec<u8>> {
+    use std::io::Read;
+
+    if !bytes.starts_with(&GZIP_MAGIC) {
+        return Ok(bytes);
+    }
+
+    let mut decompressed = Vec::new();
+    flate2::read::GzDecoder::new(bytes.as_slice()).read_to_end(&mut decompressed)?;
+    Ok(decompressed)
+}
+
+/// Returns `bytes` gzip-compressed.
+pub fn compress(bytes: &[u8]) -> io::Result<Vec<u8>> {
+    use std::io::Write;
+
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(bytes)?;
+    encoder.finish()
+}
+
+/// Returns whether the file name ends with `.gz`, as that of a compressed image does.
+pub fn is_gzip_path(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("gz"))
+}
+
+/// Path that `read_image` and `read_image_relaxed` take to mean the standard input.
+pub const STDIN: &str = "-";
+
+/// Reads the image at `path`, or from the standard input if `path` is `STDIN`, gunzipping it
+/// if it is gzip-compressed, whatever its name.
+pub fn read_image(path: &Path) -> Result<Image, LisonError> {
+    Ok(serde_json::from_slice(&read_bytes(path)?)?)
+}
+
+/// Like `read_image`, but parses the image as JSON5 like `from_str_relaxed`.
+pub fn read_image_relaxed(path: &Path) -> Result<Image, LisonError> {
+    let text = String::from_utf8(read_bytes(path)?)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    from_str_relaxed(&text)
+}
+
+fn read_bytes(path: &Path) -> io::Result<Vec<u8>> {
+    use std::io::Read;
+
+    if path != Path::new(STDIN) {
+        return decompress(fs::read(path)?);
+    }
+
+    let mut bytes = Vec::new();
+    io::stdin().read_to_end(&mut bytes)?;
+    decompress(bytes)
+}
+
+/// Writes `image` to `path`, gzip-compressed if the name ends with `.gz`.
+pub fn write_image(path: &Path, image: &Image) -> Result<(), LisonError> {
+    let json = serde_json::to_vec(image)?;
+    let bytes = if is_gzip_path(path) { compress(&json)? } else { json };
+
+    fs::write(path, bytes)?;
+    Ok(())
+}
+
+/// Reads the image at `input`, applies `f` to it and writes the result to `output`. Either
+/// may be gzip-compressed, as with `read_image` and `write_image`.
+pub fn process_file<F: FnOnce(&mut Image)>(input: &Path, output: &Path, f: F) -> Result<(), LisonError> {
+    let mut image = read_image(input)?;
+
+    f(&mut image);
+
+    write_image(output, &image)
+}
+
+/// Serializes `image` like `serde_json::to_string`, but with the keys of free-form objects
+/// such as `edit-annot` sorted, so that equal images give the same bytes even when
+/// `serde_json` preserves insertion order. Members of the format itself keep their declared
+/// order.
+pub fn to_string_sorted(image: &Image) -> serde_json::Result<String> {
+    fn sort_shapes(shapes: &mut [Shape]) {
+        for shape in shapes {
+            if let Shape::Group(group) = shape {
+                group.edit_annot.sort_all_objects();
+                sort_shapes(&mut group.content);
+            }
+        }
+    }
+
+    let mut image = image.clone();
+    sort_shapes(&mut image.shapes);
+
+    serde_json::to_string(&image)
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub struct Point {
     pub x: f64,
     pub y: f64
@@ -42,10 +268,57 @@ impl<'de> Visitor<'de> for PointVisitor {
             .ok_or_else(|| serde::de::Error::invalid_length(1, &self))?;
 
         match seq.next_element::<f64>()? {
-            None => Ok(Point { x, y }),
+            None => Ok(Point { x: check_finite(x, "coordinate")?, y: check_finite(y, "coordinate")? }),
             Some(_) => Err(serde::de::Error::invalid_length(2, &self))
         }
     }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Point, A::Error>
+    where
+        A: MapAccess<'de>
+    {
+        let mut x = None;
+        let mut y = None;
+
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "x" => {
+                    if x.is_some() {
+                        return Err(serde::de::Error::duplicate_field("x"));
+                    }
+                    x = Some(map.next_value::<f64>()?);
+                },
+                "y" => {
+                    if y.is_some() {
+                        return Err(serde::de::Error::duplicate_field("y"));
+                    }
+                    y = Some(map.next_value::<f64>()?);
+                },
+                other => {
+                    return Err(serde::de::Error::unknown_field(other, &["x", "y"]));
+                }
+            }
+        }
+
+        let x = x.ok_or_else(|| serde::de::Error::missing_field("x"))?;
+        let y = y.ok_or_else(|| serde::de::Error::missing_field("y"))?;
+
+        Ok(Point { x: check_finite(x, "coordinate")?, y: check_finite(y, "coordinate")? })
+    }
+}
+
+/// Rejects NaN and infinities, which some JSON encoders emit and which cairo turns into
+/// garbage paths.
+fn check_finite<E: serde::de::Error>(value: f64, what: &str) -> Result<f64, E> {
+    if value.is_finite() {
+        Ok(value)
+    } else {
+        Err(E::custom(format!("{} must be finite", what)))
+    }
+}
+
+fn deserialize_finite<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    check_finite(f64::deserialize(deserializer)?, "value")
 }
 
 impl<'de> Deserialize<'de> for Point {
@@ -53,7 +326,7 @@ impl<'de> Deserialize<'de> for Point {
     where
         D: Deserializer<'de>
     {
-        deserializer.deserialize_seq(PointVisitor)
+        deserializer.deserialize_any(PointVisitor)
     }
 }
 
@@ -69,7 +342,7 @@ impl Serialize for Point {
     }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub struct Color {
     pub red: f64,
     pub green: f64,
@@ -98,6 +371,10 @@ impl<'de> Visitor<'de> for ColorVisitor {
             .ok_or_else(|| serde::de::Error::invalid_length(2, &self))?;
         let alpha = seq.next_element::<f64>()?;
 
+        for value in [Some(red), Some(green), Some(blue), alpha].into_iter().flatten() {
+            check_finite(value, "color component")?;
+        }
+
         match alpha {
             None => Ok(Color { red, green, blue, alpha: 1.0 }),
             Some(alpha) => match seq.next_element::<f64>()? {
@@ -106,6 +383,235 @@ impl<'de> Visitor<'de> for ColorVisitor {
             }
         }
     }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Color, A::Error>
+    where
+        A: MapAccess<'de>
+    {
+        let key = map.next_key::<String>()?
+            .ok_or_else(|| serde::de::Error::missing_field("hsl"))?;
+
+        let count = match key.as_str() {
+            "hsl" => 3,
+            "hsla" => 4,
+            other => return Err(serde::de::Error::unknown_field(other, &["hsl", "hsla"]))
+        };
+
+        let components = map.next_value::<Vec<f64>>()?;
+
+        if components.len() != count {
+            return Err(serde::de::Error::invalid_length(components.len(), &self));
+        }
+
+        for value in components.iter() {
+            check_finite(*value, "color component")?;
+        }
+
+        for (value, what) in [(components[1], "saturation"), (components[2], "lightness")] {
+            if !(0.0..=1.0).contains(&value) {
+                return Err(serde::de::Error::custom(format!("{} {} is out of range [0, 1]", what, value)));
+            }
+        }
+
+        if let Some(other) = map.next_key::<String>()? {
+            return Err(serde::de::Error::custom(format!("unexpected member '{}' after '{}'", other, key)));
+        }
+
+        let alpha = components.get(3).copied().unwrap_or(1.0);
+        Ok(Color::from_hsl(components[0], components[1], components[2], alpha))
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Color, E>
+    where
+        E: serde::de::Error
+    {
+        Color::parse(v)
+            .ok_or_else(|| serde::de::Error::invalid_value(serde::de::Unexpected::Str(v), &"a CSS color name or a hex color such as \"#ff8800\""))
+    }
+}
+
+/// Looks up a CSS color keyword. Like in CSS, the case of the letters does not matter.
+fn named_color(name: &str) -> Option<Color> {
+    let hex = match name.to_ascii_lowercase().as_str() {
+        "aliceblue" => "#f0f8ff",
+        "antiquewhite" => "#faebd7",
+        "aqua" => "#00ffff",
+        "aquamarine" => "#7fffd4",
+        "azure" => "#f0ffff",
+        "beige" => "#f5f5dc",
+        "bisque" => "#ffe4c4",
+        "black" => "#000000",
+        "blanchedalmond" => "#ffebcd",
+        "blue" => "#0000ff",
+        "blueviolet" => "#8a2be2",
+        "brown" => "#a52a2a",
+        "burlywood" => "#deb887",
+        "cadetblue" => "#5f9ea0",
+        "chartreuse" => "#7fff00",
+        "chocolate" => "#d2691e",
+        "coral" => "#ff7f50",
+        "cornflowerblue" => "#6495ed",
+        "cornsilk" => "#fff8dc",
+        "crimson" => "#dc143c",
+        "cyan" => "#00ffff",
+        "darkblue" => "#00008b",
+        "darkcyan" => "#008b8b",
+        "darkgoldenrod" => "#b8860b",
+        "darkgray" => "#a9a9a9",
+        "darkgreen" => "#006400",
+        "darkgrey" => "#a9a9a9",
+        "darkkhaki" => "#bdb76b",
+        "darkmagenta" => "#8b008b",
+        "darkolivegreen" => "#556b2f",
+        "darkorange" => "#ff8c00",
+        "darkorchid" => "#9932cc",
+        "darkred" => "#8b0000",
+        "darksalmon" => "#e9967a",
+        "darkseagreen" => "#8fbc8f",
+        "darkslateblue" => "#483d8b",
+        "darkslategray" => "#2f4f4f",
+        "darkslategrey" => "#2f4f4f",
+        "darkturquoise" => "#00ced1",
+        "darkviolet" => "#9400d3",
+        "deeppink" => "#ff1493",
+        "deepskyblue" => "#00bfff",
+        "dimgray" => "#696969",
+        "dimgrey" => "#696969",
+        "dodgerblue" => "#1e90ff",
+        "firebrick" => "#b22222",
+        "floralwhite" => "#fffaf0",
+        "forestgreen" => "#228b22",
+        "fuchsia" => "#ff00ff",
+        "gainsboro" => "#dcdcdc",
+        "ghostwhite" => "#f8f8ff",
+        "gold" => "#ffd700",
+        "goldenrod" => "#daa520",
+        "gray" => "#808080",
+        "green" => "#008000",
+        "greenyellow" => "#adff2f",
+        "grey" => "#808080",
+        "honeydew" => "#f0fff0",
+        "hotpink" => "#ff69b4",
+        "indianred" => "#cd5c5c",
+        "indigo" => "#4b0082",
+        "ivory" => "#fffff0",
+        "khaki" => "#f0e68c",
+        "lavender" => "#e6e6fa",
+        "lavenderblush" => "#fff0f5",
+        "lawngreen" => "#7cfc00",
+        "lemonchiffon" => "#fffacd",
+        "lightblue" => "#add8e6",
+        "lightcoral" => "#f08080",
+        "lightcyan" => "#e0ffff",
+        "lightgoldenrodyellow" => "#fafad2",
+        "lightgray" => "#d3d3d3",
+        "lightgreen" => "#90ee90",
+        "lightgrey" => "#d3d3d3",
+        "lightpink" => "#ffb6c1",
+        "lightsalmon" => "#ffa07a",
+        "lightseagreen" => "#20b2aa",
+        "lightskyblue" => "#87cefa",
+        "lightslategray" => "#778899",
+        "lightslategrey" => "#778899",
+        "lightsteelblue" => "#b0c4de",
+        "lightyellow" => "#ffffe0",
+        "lime" => "#00ff00",
+        "limegreen" => "#32cd32",
+        "linen" => "#faf0e6",
+        "magenta" => "#ff00ff",
+        "maroon" => "#800000",
+        "mediumaquamarine" => "#66cdaa",
+        "mediumblue" => "#0000cd",
+        "mediumorchid" => "#ba55d3",
+        "mediumpurple" => "#9370db",
+        "mediumseagreen" => "#3cb371",
+        "mediumslateblue" => "#7b68ee",
+        "mediumspringgreen" => "#00fa9a",
+        "mediumturquoise" => "#48d1cc",
+        "mediumvioletred" => "#c71585",
+        "midnightblue" => "#191970",
+        "mintcream" => "#f5fffa",
+        "mistyrose" => "#ffe4e1",
+        "moccasin" => "#ffe4b5",
+        "navajowhite" => "#ffdead",
+        "navy" => "#000080",
+        "oldlace" => "#fdf5e6",
+        "olive" => "#808000",
+        "olivedrab" => "#6b8e23",
+        "orange" => "#ffa500",
+        "orangered" => "#ff4500",
+        "orchid" => "#da70d6",
+        "palegoldenrod" => "#eee8aa",
+        "palegreen" => "#98fb98",
+        "paleturquoise" => "#afeeee",
+        "palevioletred" => "#db7093",
+        "papayawhip" => "#ffefd5",
+        "peachpuff" => "#ffdab9",
+        "peru" => "#cd853f",
+        "pink" => "#ffc0cb",
+        "plum" => "#dda0dd",
+        "powderblue" => "#b0e0e6",
+        "purple" => "#800080",
+        "rebeccapurple" => "#663399",
+        "red" => "#ff0000",
+        "rosybrown" => "#bc8f8f",
+        "royalblue" => "#4169e1",
+        "saddlebrown" => "#8b4513",
+        "salmon" => "#fa8072",
+        "sandybrown" => "#f4a460",
+        "seagreen" => "#2e8b57",
+        "seashell" => "#fff5ee",
+        "sienna" => "#a0522d",
+        "silver" => "#c0c0c0",
+        "skyblue" => "#87ceeb",
+        "slateblue" => "#6a5acd",
+        "slategray" => "#708090",
+        "slategrey" => "#708090",
+        "snow" => "#fffafa",
+        "springgreen" => "#00ff7f",
+        "steelblue" => "#4682b4",
+        "tan" => "#d2b48c",
+        "teal" => "#008080",
+        "thistle" => "#d8bfd8",
+        "tomato" => "#ff6347",
+        "turquoise" => "#40e0d0",
+        "violet" => "#ee82ee",
+        "wheat" => "#f5deb3",
+        "white" => "#ffffff",
+        "whitesmoke" => "#f5f5f5",
+        "yellow" => "#ffff00",
+        "yellowgreen" => "#9acd32",
+        "transparent" => "#00000000",
+        _ => return None
+    };
+
+    parse_hex_color(hex)
+}
+
+/// Parses `#rgb`, `#rgba`, `#rrggbb` or `#rrggbbaa`.
+fn parse_hex_color(value: &str) -> Option<Color> {
+    let digits = value.strip_prefix('#')?;
+
+    if !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    let components: Vec<f64> = match digits.len() {
+        3 | 4 => digits.chars()
+            .map(|c| c.to_digit(16).map(|d| (d * 17) as f64 / 255.0))
+            .collect::<Option<_>>()?,
+        6 | 8 => (0..digits.len()).step_by(2)
+            .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).ok().map(|d| d as f64 / 255.0))
+            .collect::<Option<_>>()?,
+        _ => return None
+    };
+
+    Some(Color {
+        red: components[0],
+        green: components[1],
+        blue: components[2],
+        alpha: components.get(3).copied().unwrap_or(1.0)
+    })
 }
 
 impl<'de> Deserialize<'de> for Color {
@@ -113,7 +619,7 @@ impl<'de> Deserialize<'de> for Color {
     where
         D: Deserializer<'de>
     {
-        deserializer.deserialize_seq(ColorVisitor)
+        deserializer.deserialize_any(ColorVisitor)
     }
 }
 
@@ -133,917 +639,5879 @@ impl Serialize for Color {
     }
 }
 
-#[derive(Deserialize, Serialize, Clone, Copy)]
-#[serde(rename_all = "kebab-case", deny_unknown_fields)]
-pub struct MonochromePattern {
-    pub color: Color
-}
+impl Color {
+    /// Parses a CSS color name or a hex color such as `#ff8800`, as accepted in place of a
+    /// component array in an image.
+    pub fn parse(value: &str) -> Option<Color> {
+        named_color(value).or_else(|| parse_hex_color(value))
+    }
 
-#[derive(Deserialize, Serialize, Clone, Copy)]
-#[serde(rename_all = "kebab-case", deny_unknown_fields)]
-pub struct LinearGradientPattern {
-    pub point_1: Point,
-    pub color_1: Color,
-    pub point_2: Point,
-    pub color_2: Color
-}
+    /// Returns the color with each component clamped to [0, 1]. Out-of-range components are
+    /// accepted when reading an image; the renderer clamps them with this method.
+    pub fn clamped(self) -> Color {
+        Color {
+            red: self.red.clamp(0.0, 1.0),
+            green: self.green.clamp(0.0, 1.0),
+            blue: self.blue.clamp(0.0, 1.0),
+            alpha: self.alpha.clamp(0.0, 1.0)
+        }
+    }
 
-#[derive(Deserialize, Serialize, Clone, Copy)]
-#[serde(rename_all = "kebab-case", deny_unknown_fields)]
-pub struct RadialGradientPattern {
-    pub center_1: Point,
-    pub radius_1: f64,
-    pub color_1: Color,
-    pub center_2: Point,
-    pub radius_2: f64,
-    pub color_2: Color
-}
+    /// Divides the color components by alpha. A fully transparent color becomes transparent
+    /// black.
+    pub fn unpremultiply(self) -> Color {
+        if self.alpha <= 0.0 {
+            return Color { red: 0.0, green: 0.0, blue: 0.0, alpha: 0.0 };
+        }
 
-#[derive(Deserialize, Serialize, Clone, Copy)]
-#[serde(rename_all = "kebab-case", tag = "type")]
-pub enum Pattern {
-    Monochrome(MonochromePattern),
-    LinearGradient(LinearGradientPattern),
-    RadialGradient(RadialGradientPattern)
+        Color {
+            red: (self.red / self.alpha).min(1.0),
+            green: (self.green / self.alpha).min(1.0),
+            blue: (self.blue / self.alpha).min(1.0),
+            alpha: self.alpha
+        }
+    }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq)]
-pub enum LineCap {
-    Butt,
-    Round,
-    Square
+/// The color space in which gradients interpolate between their colors.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ColorInterp {
+    Rgb,
+    /// Interpolates hue along the shorter arc of the color wheel.
+    Hsl,
+    /// Interpolates hue along the longer arc of the color wheel.
+    HslLong
 }
 
-struct LineCapVisitor;
+struct ColorInterpVisitor;
 
-impl<'de> Visitor<'de> for LineCapVisitor {
-    type Value = LineCap;
+impl<'de> Visitor<'de> for ColorInterpVisitor {
+    type Value = ColorInterp;
 
     fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
-        formatter.write_str("line cap")
+        formatter.write_str("color interpolation")
     }
 
-    fn visit_str<E>(self, v: &str) -> Result<LineCap, E>
+    fn visit_str<E>(self, v: &str) -> Result<ColorInterp, E>
     where
         E: serde::de::Error
     {
         match v {
-            "butt" => Ok(LineCap::Butt),
-            "round" => Ok(LineCap::Round),
-            "square" => Ok(LineCap::Square),
-            other => Err(serde::de::Error::unknown_variant(other, &["butt", "round", "square"]))
+            "rgb" => Ok(ColorInterp::Rgb),
+            "hsl" => Ok(ColorInterp::Hsl),
+            "hsl-long" => Ok(ColorInterp::HslLong),
+            other => Err(serde::de::Error::unknown_variant(other, &["rgb", "hsl", "hsl-long"]))
         }
     }
 
-    fn visit_borrowed_str<E>(self, v: &'de str) -> Result<LineCap, E>
+    fn visit_borrowed_str<E>(self, v: &'de str) -> Result<ColorInterp, E>
     where
         E: serde::de::Error
     {
         match v {
-            "butt" => Ok(LineCap::Butt),
-            "round" => Ok(LineCap::Round),
-            "square" => Ok(LineCap::Square),
-            other => Err(serde::de::Error::unknown_variant(other, &["butt", "round", "square"]))
+            "rgb" => Ok(ColorInterp::Rgb),
+            "hsl" => Ok(ColorInterp::Hsl),
+            "hsl-long" => Ok(ColorInterp::HslLong),
+            other => Err(serde::de::Error::unknown_variant(other, &["rgb", "hsl", "hsl-long"]))
         }
     }
 
-    fn visit_string<E>(self, v: String) -> Result<LineCap, E>
+    fn visit_string<E>(self, v: String) -> Result<ColorInterp, E>
     where
         E: serde::de::Error
     {
         match v.as_str() {
-            "butt" => Ok(LineCap::Butt),
-            "round" => Ok(LineCap::Round),
-            "square" => Ok(LineCap::Square),
-            other => Err(serde::de::Error::unknown_variant(other, &["butt", "round", "square"]))
+            "rgb" => Ok(ColorInterp::Rgb),
+            "hsl" => Ok(ColorInterp::Hsl),
+            "hsl-long" => Ok(ColorInterp::HslLong),
+            other => Err(serde::de::Error::unknown_variant(other, &["rgb", "hsl", "hsl-long"]))
         }
     }
 }
 
-impl<'de> Deserialize<'de> for LineCap {
-    fn deserialize<D>(deserializer: D) -> Result<LineCap, D::Error>
+impl<'de> Deserialize<'de> for ColorInterp {
+    fn deserialize<D>(deserializer: D) -> Result<ColorInterp, D::Error>
     where
         D: Deserializer<'de>
     {
-        deserializer.deserialize_str(LineCapVisitor)
+        deserializer.deserialize_str(ColorInterpVisitor)
     }
 }
 
-impl Serialize for LineCap {
+impl Serialize for ColorInterp {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer
     {
         match self {
-            LineCap::Butt => serializer.serialize_str("butt"),
-            LineCap::Round => serializer.serialize_str("round"),
-            LineCap::Square => serializer.serialize_str("square"),
+            ColorInterp::Rgb => serializer.serialize_str("rgb"),
+            ColorInterp::Hsl => serializer.serialize_str("hsl"),
+            ColorInterp::HslLong => serializer.serialize_str("hsl-long"),
         }
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq)]
-pub enum LineJoin {
-    Miter,
-    Round,
-    Bevel
+impl Color {
+    /// Returns hue in degrees, saturation and lightness.
+    fn to_hsl(self) -> (f64, f64, f64) {
+        let max = self.red.max(self.green).max(self.blue);
+        let min = self.red.min(self.green).min(self.blue);
+        let chroma = max - min;
+        let lightness = (max + min) / 2.0;
+
+        if chroma == 0.0 {
+            return (0.0, 0.0, lightness);
+        }
+
+        let hue = if max == self.red {
+            ((self.green - self.blue) / chroma).rem_euclid(6.0)
+        } else if max == self.green {
+            (self.blue - self.red) / chroma + 2.0
+        } else {
+            (self.red - self.green) / chroma + 4.0
+        };
+        let saturation = chroma / (1.0 - (2.0 * lightness - 1.0).abs());
+
+        (hue * 60.0, saturation, lightness)
+    }
+
+    fn from_hsl(hue: f64, saturation: f64, lightness: f64, alpha: f64) -> Color {
+        let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+        let h = hue.rem_euclid(360.0) / 60.0;
+        let x = chroma * (1.0 - (h.rem_euclid(2.0) - 1.0).abs());
+
+        let (red, green, blue) = match h as u32 {
+            0 => (chroma, x, 0.0),
+            1 => (x, chroma, 0.0),
+            2 => (0.0, chroma, x),
+            3 => (0.0, x, chroma),
+            4 => (x, 0.0, chroma),
+            _ => (chroma, 0.0, x)
+        };
+        let m = lightness - chroma / 2.0;
+
+        Color { red: red + m, green: green + m, blue: blue + m, alpha }
+    }
+
+    /// Returns the color at `t` between `self` (0) and `other` (1). Alpha is always
+    /// interpolated linearly. In HSL, a gray end takes the hue of the other end.
+    pub fn interpolate(self, other: Color, t: f64, interp: ColorInterp) -> Color {
+        let lerp = |a: f64, b: f64| a + (b - a) * t;
+        let alpha = lerp(self.alpha, other.alpha);
+
+        if interp == ColorInterp::Rgb {
+            return Color {
+                red: lerp(self.red, other.red),
+                green: lerp(self.green, other.green),
+                blue: lerp(self.blue, other.blue),
+                alpha
+            };
+        }
+
+        let (mut h1, s1, l1) = self.to_hsl();
+        let (mut h2, s2, l2) = other.to_hsl();
+
+        if s1 == 0.0 {
+            h1 = h2;
+        } else if s2 == 0.0 {
+            h2 = h1;
+        }
+
+        let mut delta = (h2 - h1).rem_euclid(360.0);
+
+        if (interp == ColorInterp::Hsl) == (delta > 180.0) {
+            delta -= 360.0;
+        }
+
+        Color::from_hsl(h1 + delta * t, lerp(s1, s2), lerp(l1, l2), alpha)
+    }
 }
 
-struct LineJoinVisitor;
+/// How the color components of an image relate to alpha.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AlphaMode {
+    Straight,
+    Premultiplied
+}
 
-impl<'de> Visitor<'de> for LineJoinVisitor {
-    type Value = LineJoin;
+struct AlphaModeVisitor;
+
+impl<'de> Visitor<'de> for AlphaModeVisitor {
+    type Value = AlphaMode;
 
     fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
-        formatter.write_str("line join")
+        formatter.write_str("alpha mode")
     }
 
-    fn visit_str<E>(self, v: &str) -> Result<LineJoin, E>
+    fn visit_str<E>(self, v: &str) -> Result<AlphaMode, E>
     where
         E: serde::de::Error
     {
         match v {
-            "miter" => Ok(LineJoin::Miter),
-            "round" => Ok(LineJoin::Round),
-            "bevel" => Ok(LineJoin::Bevel),
-            other => Err(serde::de::Error::unknown_variant(other, &["miter", "round", "bevel"]))
+            "straight" => Ok(AlphaMode::Straight),
+            "premultiplied" => Ok(AlphaMode::Premultiplied),
+            other => Err(serde::de::Error::unknown_variant(other, &["straight", "premultiplied"]))
         }
     }
 
-    fn visit_borrowed_str<E>(self, v: &'de str) -> Result<LineJoin, E>
+    fn visit_borrowed_str<E>(self, v: &'de str) -> Result<AlphaMode, E>
     where
         E: serde::de::Error
     {
         match v {
-            "miter" => Ok(LineJoin::Miter),
-            "round" => Ok(LineJoin::Round),
-            "bevel" => Ok(LineJoin::Bevel),
-            other => Err(serde::de::Error::unknown_variant(other, &["miter", "round", "bevel"]))
+            "straight" => Ok(AlphaMode::Straight),
+            "premultiplied" => Ok(AlphaMode::Premultiplied),
+            other => Err(serde::de::Error::unknown_variant(other, &["straight", "premultiplied"]))
         }
     }
 
-    fn visit_string<E>(self, v: String) -> Result<LineJoin, E>
+    fn visit_string<E>(self, v: String) -> Result<AlphaMode, E>
     where
         E: serde::de::Error
     {
         match v.as_str() {
-            "miter" => Ok(LineJoin::Miter),
-            "round" => Ok(LineJoin::Round),
-            "bevel" => Ok(LineJoin::Bevel),
-            other => Err(serde::de::Error::unknown_variant(other, &["miter", "round", "bevel"]))
+            "straight" => Ok(AlphaMode::Straight),
+            "premultiplied" => Ok(AlphaMode::Premultiplied),
+            other => Err(serde::de::Error::unknown_variant(other, &["straight", "premultiplied"]))
         }
     }
 }
 
-impl<'de> Deserialize<'de> for LineJoin {
-    fn deserialize<D>(deserializer: D) -> Result<LineJoin, D::Error>
+impl<'de> Deserialize<'de> for AlphaMode {
+    fn deserialize<D>(deserializer: D) -> Result<AlphaMode, D::Error>
     where
         D: Deserializer<'de>
     {
-        deserializer.deserialize_str(LineJoinVisitor)
+        deserializer.deserialize_str(AlphaModeVisitor)
     }
 }
 
-impl Serialize for LineJoin {
+impl Serialize for AlphaMode {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer
     {
         match self {
-            LineJoin::Miter => serializer.serialize_str("miter"),
-            LineJoin::Round => serializer.serialize_str("round"),
-            LineJoin::Bevel => serializer.serialize_str("bevel"),
+            AlphaMode::Straight => serializer.serialize_str("straight"),
+            AlphaMode::Premultiplied => serializer.serialize_str("premultiplied"),
         }
     }
 }
 
-#[derive(Deserialize, Serialize, Clone, Copy)]
-#[serde(rename_all = "kebab-case", deny_unknown_fields)]
-pub struct Pen {
-    pub pattern: Pattern,
-    pub width: f64,
-    pub cap: LineCap,
-    pub join: LineJoin
-}
-
-#[derive(Deserialize, Serialize, Clone, Copy)]
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Debug)]
 #[serde(rename_all = "kebab-case", deny_unknown_fields)]
-pub struct Brush {
-    pub pattern: Pattern
+pub struct MonochromePattern {
+    pub color: Color
 }
 
-#[derive(Deserialize, Serialize, Clone)]
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
 #[serde(rename_all = "kebab-case", deny_unknown_fields)]
-pub struct GroupShape {
-    pub content: Vec<Shape>,
-    #[serde(skip_serializing_if = "serde_json::Value::is_null", default)]
-    pub edit_annot: serde_json::Value
+pub struct LinearGradientPattern {
+    pub point_1: Point,
+    pub color_1: Color,
+    pub point_2: Point,
+    pub color_2: Color,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interpolate: Option<ColorInterp>,
+    /// Additional stops between `color_1` at offset 0 and `color_2` at offset 1.
+    #[serde(skip_serializing_if = "Option::is_none", deserialize_with = "deserialize_optional_stops", default)]
+    pub stops: Option<Vec<(f64, Color)>>,
+    /// How the area beyond the end points is painted, `Extend::Pad` by default.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extend: Option<Extend>
 }
 
-#[derive(Deserialize, Serialize, Clone)]
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
 #[serde(rename_all = "kebab-case", deny_unknown_fields)]
-pub struct CurveShape {
-    pub pen: usize,
-    pub data: CurveData
+pub struct RadialGradientPattern {
+    pub center_1: Point,
+    #[serde(deserialize_with = "deserialize_finite")]
+    pub radius_1: f64,
+    pub color_1: Color,
+    pub center_2: Point,
+    #[serde(deserialize_with = "deserialize_finite")]
+    pub radius_2: f64,
+    pub color_2: Color,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interpolate: Option<ColorInterp>,
+    /// Additional stops between `color_1` at offset 0 and `color_2` at offset 1.
+    #[serde(skip_serializing_if = "Option::is_none", deserialize_with = "deserialize_optional_stops", default)]
+    pub stops: Option<Vec<(f64, Color)>>,
+    /// How the area beyond the end points is painted, `Extend::Pad` by default.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extend: Option<Extend>,
+    /// Ratio of the horizontal to the vertical extent of the circles, which makes them
+    /// ellipses stretched horizontally about `center_1`. 1 by default.
+    #[serde(skip_serializing_if = "Option::is_none", deserialize_with = "deserialize_aspect", default)]
+    pub aspect: Option<f64>
+}
+
+/// Checks that stop offsets are within [0, 1] and in ascending order.
+fn check_stops<E: serde::de::Error>(stops: &[(f64, Color)]) -> Result<(), E> {
+    if stops.windows(2).any(|pair| pair[0].0 > pair[1].0) {
+        return Err(E::custom("stop offsets must be in ascending order"));
+    }
+
+    match stops.iter().find(|(offset, _)| !(0.0..=1.0).contains(offset)) {
+        Some((offset, _)) => Err(E::custom(format!("stop offset {} is out of range [0, 1]", offset))),
+        None => Ok(())
+    }
+}
+
+fn deserialize_stops<'de, D>(deserializer: D) -> Result<Vec<(f64, Color)>, D::Error>
+where
+    D: Deserializer<'de>
+{
+    let stops = Vec::<(f64, Color)>::deserialize(deserializer)?;
+    check_stops(&stops)?;
+    Ok(stops)
 }
 
-#[derive(Deserialize, Serialize, Clone)]
+fn deserialize_optional_stops<'de, D>(deserializer: D) -> Result<Option<Vec<(f64, Color)>>, D::Error>
+where
+    D: Deserializer<'de>
+{
+    let stops = Option::<Vec<(f64, Color)>>::deserialize(deserializer)?;
+    check_stops(stops.as_deref().unwrap_or_default())?;
+    Ok(stops)
+}
+
+/// Returns all stops of a gradient, from `color_1` at offset 0 to `color_2` at offset 1.
+fn gradient_stops(color_1: Color, stops: &Option<Vec<(f64, Color)>>, color_2: Color) -> Vec<(f64, Color)> {
+    std::iter::once((0.0, color_1))
+        .chain(stops.iter().flatten().copied())
+        .chain(std::iter::once((1.0, color_2)))
+        .collect()
+}
+
+impl LinearGradientPattern {
+    /// Returns the offset and color of every stop, including the two end colors.
+    pub fn color_stops(&self) -> Vec<(f64, Color)> {
+        gradient_stops(self.color_1, &self.stops, self.color_2)
+    }
+}
+
+impl RadialGradientPattern {
+    /// Returns the offset and color of every stop, including the two end colors.
+    pub fn color_stops(&self) -> Vec<(f64, Color)> {
+        gradient_stops(self.color_1, &self.stops, self.color_2)
+    }
+}
+
+/// A gradient whose color changes with the angle around `center`.
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
 #[serde(rename_all = "kebab-case", deny_unknown_fields)]
-pub struct RegionShape {
+pub struct ConicGradientPattern {
+    pub center: Point,
+    /// Angle in degrees, from the positive x axis toward the positive y axis, at which offset
+    /// 0 lies. Offsets increase in the same direction, and offset 1 is a full turn.
+    #[serde(deserialize_with = "deserialize_finite")]
+    pub start_angle: f64,
+    #[serde(deserialize_with = "deserialize_stops")]
+    pub stops: Vec<(f64, Color)>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub pen: Option<usize>,
+    pub interpolate: Option<ColorInterp>
+}
+
+/// A bitmap texture embedded as a base64-encoded PNG.
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ImagePattern {
+    #[serde(deserialize_with = "deserialize_base64")]
+    pub data: String,
+    /// Position of the top-left corner of the bitmap. The origin by default.
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub brush: Option<usize>,
-    pub data: Vec<CurveData>
+    pub origin: Option<Point>,
+    /// Size of a bitmap pixel in image units, 1 by default.
+    #[serde(skip_serializing_if = "Option::is_none", deserialize_with = "deserialize_pixel_size", default)]
+    pub pixel_size: Option<f64>,
+    /// How the area outside the bitmap is painted, `Extend::Repeat` by default.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extend: Option<Extend>
 }
 
-#[derive(Deserialize, Serialize, Clone)]
-#[serde(rename_all = "kebab-case", tag = "type")]
-pub enum Shape {
-    Group(GroupShape),
-    Curve(CurveShape),
-    Region(RegionShape)
+impl ImagePattern {
+    /// Decodes the PNG file from `data`.
+    pub fn png(&self) -> Result<Vec<u8>, LisonError> {
+        use base64::Engine;
+
+        base64::engine::general_purpose::STANDARD.decode(&self.data)
+            .map_err(|_| LisonError::InvalidBase64)
+    }
 }
 
-#[derive(Clone, Copy)]
-pub struct LineSegment {
-    pub point_2: Point
+fn deserialize_optional_positive<'de, D>(deserializer: D, what: &str) -> Result<Option<f64>, D::Error>
+where
+    D: Deserializer<'de>
+{
+    match Option::<f64>::deserialize(deserializer)? {
+        Some(value) if !(value.is_finite() && value > 0.0) =>
+            Err(serde::de::Error::custom(format!("{} {} must be positive", what, value))),
+        value => Ok(value)
+    }
 }
 
-#[derive(Clone, Copy)]
-pub struct QuadraticBezierSegment {
-    pub point_2: Point,
-    pub point_3: Point
+fn deserialize_aspect<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<f64>, D::Error> {
+    deserialize_optional_positive(deserializer, "aspect")
 }
 
-#[derive(Clone, Copy)]
-pub struct CubicBezierSegment {
-    pub point_2: Point,
-    pub point_3: Point,
-    pub point_4: Point
+fn deserialize_pixel_size<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<f64>, D::Error> {
+    deserialize_optional_positive(deserializer, "pixel size")
 }
 
-#[derive(Clone, Copy)]
-pub enum Segment {
-    Line(LineSegment),
-    QuadraticBezier(QuadraticBezierSegment),
-    CubicBezier(CubicBezierSegment)
+fn deserialize_miter_limit<'de, D>(deserializer: D) -> Result<Option<f64>, D::Error>
+where
+    D: Deserializer<'de>
+{
+    match Option::<f64>::deserialize(deserializer)? {
+        Some(limit) if !(limit.is_finite() && limit >= 1.0) =>
+            Err(serde::de::Error::custom(format!("miter limit {} must be at least 1", limit))),
+        limit => Ok(limit)
+    }
 }
 
-struct SegmentVisitor;
+fn deserialize_base64<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>
+{
+    use base64::Engine;
 
-impl<'de> Visitor<'de> for SegmentVisitor {
-    type Value = Segment;
+    let data = String::deserialize(deserializer)?;
 
-    fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
-        formatter.write_str("segment")
+    match base64::engine::general_purpose::STANDARD.decode(&data) {
+        Ok(_) => Ok(data),
+        Err(e) => Err(serde::de::Error::custom(format!("invalid base64: {}", e)))
     }
+}
 
-    fn visit_seq<A>(self, mut seq: A) -> Result<Segment, A::Error>
-    where
-        A: SeqAccess<'de>
-    {
-        let tag = seq.next_element::<String>()?
-            .ok_or_else(|| serde::de::Error::invalid_length(0, &self))?;
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
+#[serde(rename_all = "kebab-case", tag = "type")]
+pub enum Pattern {
+    Monochrome(MonochromePattern),
+    LinearGradient(LinearGradientPattern),
+    RadialGradient(RadialGradientPattern),
+    ConicGradient(ConicGradientPattern),
+    Image(ImagePattern)
+}
 
-        match tag.as_str() {
-            "L" => {
-                let point_2 = seq.next_element::<Point>()?
-                    .ok_or_else(|| serde::de::Error::invalid_length(1, &self))?;
+impl Pattern {
+    /// Returns the pattern with `f` applied to each of its colors. The pixels of image
+    /// patterns are left as they are.
+    pub fn map_colors<F: Fn(Color) -> Color>(&self, f: F) -> Pattern {
+        let map_stops = |stops: &Option<Vec<(f64, Color)>>| stops.as_ref()
+            .map(|stops| stops.iter().map(|(offset, color)| (*offset, f(*color))).collect());
 
-                match seq.next_element::<Point>()? {
-                    None => Ok(Segment::Line(LineSegment { point_2 })),
-                    Some(_) => Err(serde::de::Error::invalid_length(2, &self))
-                }
-            },
-            "Q" => {
-                let point_2 = seq.next_element::<Point>()?
-                    .ok_or_else(|| serde::de::Error::invalid_length(1, &self))?;
-                let point_3 = seq.next_element::<Point>()?
-                    .ok_or_else(|| serde::de::Error::invalid_length(2, &self))?;
+        match self {
+            Pattern::Monochrome(p) => Pattern::Monochrome(MonochromePattern { color: f(p.color) }),
+            Pattern::LinearGradient(p) => Pattern::LinearGradient(LinearGradientPattern {
+                color_1: f(p.color_1),
+                color_2: f(p.color_2),
+                stops: map_stops(&p.stops),
+                ..p.clone()
+            }),
+            Pattern::RadialGradient(p) => Pattern::RadialGradient(RadialGradientPattern {
+                color_1: f(p.color_1),
+                color_2: f(p.color_2),
+                stops: map_stops(&p.stops),
+                ..p.clone()
+            }),
+            Pattern::ConicGradient(p) => Pattern::ConicGradient(ConicGradientPattern {
+                stops: p.stops.iter().map(|(offset, color)| (*offset, f(*color))).collect(),
+                ..p.clone()
+            }),
+            Pattern::Image(p) => Pattern::Image(p.clone())
+        }
+    }
 
-                match seq.next_element::<Point>()? {
-                    None => Ok(Segment::QuadraticBezier(QuadraticBezierSegment { point_2, point_3 })),
-                    Some(_) => Err(serde::de::Error::invalid_length(3, &self))
-                }
-            },
-            "C" => {
-                let point_2 = seq.next_element::<Point>()?
-                    .ok_or_else(|| serde::de::Error::invalid_length(1, &self))?;
-                let point_3 = seq.next_element::<Point>()?
-                    .ok_or_else(|| serde::de::Error::invalid_length(2, &self))?;
-                let point_4 = seq.next_element::<Point>()?
-                    .ok_or_else(|| serde::de::Error::invalid_length(3, &self))?;
+    /// Returns the color the pattern paints everywhere, if it paints a single one: the color
+    /// of a monochrome pattern, or that of a gradient whose stops all share it and which
+    /// covers the whole plane. A gradient with `Extend::None`, or a radial one whose circles
+    /// do not enclose each other, leaves some area unpainted and has no uniform color.
+    pub fn uniform_color(&self) -> Option<Color> {
+        let single = |stops: &[(f64, Color)]| {
+            let (_, first) = *stops.first()?;
+            stops.iter().all(|(_, color)| *color == first).then_some(first)
+        };
 
-                match seq.next_element::<Point>()? {
-                    None => Ok(Segment::CubicBezier(CubicBezierSegment { point_2, point_3, point_4 })),
-                    Some(_) => Err(serde::de::Error::invalid_length(4, &self))
-                }
-            },
-            other => Err(serde::de::Error::unknown_variant(other, &["L", "Q", "C"]))
+        match self {
+            Pattern::Monochrome(p) => Some(p.color),
+            Pattern::LinearGradient(p) if p.extend != Some(Extend::None) => single(&p.color_stops()),
+            Pattern::RadialGradient(p) if p.extend != Some(Extend::None)
+                && (p.center_2.x - p.center_1.x).hypot(p.center_2.y - p.center_1.y) <= (p.radius_2 - p.radius_1).abs() => single(&p.color_stops()),
+            Pattern::ConicGradient(p) => single(&p.stops),
+            _ => None
         }
     }
-}
 
-impl<'de> Deserialize<'de> for Segment {
-    fn deserialize<D>(deserializer: D) -> Result<Segment, D::Error>
-    where
-        D: Deserializer<'de>
-    {
-        deserializer.deserialize_seq(SegmentVisitor)
+    /// Returns the pattern with `f` applied to the end points or centers of a gradient.
+    pub fn map_points<F: FnMut(Point) -> Point>(&self, mut f: F) -> Pattern {
+        match self {
+            Pattern::Monochrome(p) => Pattern::Monochrome(*p),
+            Pattern::LinearGradient(p) => Pattern::LinearGradient(LinearGradientPattern {
+                point_1: f(p.point_1),
+                point_2: f(p.point_2),
+                ..p.clone()
+            }),
+            Pattern::RadialGradient(p) => Pattern::RadialGradient(RadialGradientPattern {
+                center_1: f(p.center_1),
+                center_2: f(p.center_2),
+                ..p.clone()
+            }),
+            Pattern::ConicGradient(p) => Pattern::ConicGradient(ConicGradientPattern {
+                center: f(p.center),
+                ..p.clone()
+            }),
+            Pattern::Image(p) => Pattern::Image(ImagePattern {
+                origin: p.origin.map(f),
+                ..p.clone()
+            })
+        }
     }
-}
 
-impl Serialize for Segment {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: Serializer
-    {
-        let mut seq = serializer.serialize_seq(None)?;
-        
+    pub fn colors(&self) -> Vec<Color> {
         match self {
-            Segment::Line(s) => {
-                seq.serialize_element("L")?;
-                seq.serialize_element(&s.point_2)?;
-            },
-            Segment::QuadraticBezier(s) => {
-                seq.serialize_element("Q")?;
-                seq.serialize_element(&s.point_2)?;
-                seq.serialize_element(&s.point_3)?;
-            },
-            Segment::CubicBezier(s) => {
-                seq.serialize_element("C")?;
-                seq.serialize_element(&s.point_2)?;
-                seq.serialize_element(&s.point_3)?;
-                seq.serialize_element(&s.point_4)?;
-            }
+            Pattern::Monochrome(pat) => vec![pat.color],
+            Pattern::LinearGradient(pat) => pat.color_stops().into_iter().map(|(_, color)| color).collect(),
+            Pattern::RadialGradient(pat) => pat.color_stops().into_iter().map(|(_, color)| color).collect(),
+            Pattern::ConicGradient(pat) => pat.stops.iter().map(|(_, color)| *color).collect(),
+            Pattern::Image(_) => Vec::new()
         }
-
-        seq.end()
     }
 }
 
-#[derive(Clone)]
-pub struct CurveData {
-    pub start: Point,
-    pub segments: Vec<Segment>
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LineCap {
+    Butt,
+    Round,
+    Square
 }
 
-struct CurveDataVisitor;
+struct LineCapVisitor;
 
-impl<'de> Visitor<'de> for CurveDataVisitor {
-    type Value = CurveData;
+impl<'de> Visitor<'de> for LineCapVisitor {
+    type Value = LineCap;
 
     fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
-        formatter.write_str("curve data")
+        formatter.write_str("line cap")
     }
 
-    fn visit_seq<A>(self, mut seq: A) -> Result<CurveData, A::Error>
+    fn visit_str<E>(self, v: &str) -> Result<LineCap, E>
     where
-        A: SeqAccess<'de>
+        E: serde::de::Error
     {
-        let start = seq.next_element::<Point>()?
-            .ok_or_else(|| serde::de::Error::invalid_length(0, &self))?;
-
-        let mut segments = vec![];
+        match v {
+            "butt" => Ok(LineCap::Butt),
+            "round" => Ok(LineCap::Round),
+            "square" => Ok(LineCap::Square),
+            other => Err(serde::de::Error::unknown_variant(other, &["butt", "round", "square"]))
+        }
+    }
 
-        while let Some(seg) = seq.next_element::<Segment>()? {
-            segments.push(seg);
+    fn visit_borrowed_str<E>(self, v: &'de str) -> Result<LineCap, E>
+    where
+        E: serde::de::Error
+    {
+        match v {
+            "butt" => Ok(LineCap::Butt),
+            "round" => Ok(LineCap::Round),
+            "square" => Ok(LineCap::Square),
+            other => Err(serde::de::Error::unknown_variant(other, &["butt", "round", "square"]))
         }
+    }
 
-        Ok(CurveData { start, segments })
+    fn visit_string<E>(self, v: String) -> Result<LineCap, E>
+    where
+        E: serde::de::Error
+    {
+        match v.as_str() {
+            "butt" => Ok(LineCap::Butt),
+            "round" => Ok(LineCap::Round),
+            "square" => Ok(LineCap::Square),
+            other => Err(serde::de::Error::unknown_variant(other, &["butt", "round", "square"]))
+        }
     }
 }
 
-impl<'de> Deserialize<'de> for CurveData {
-    fn deserialize<D>(deserializer: D) -> Result<CurveData, D::Error>
+impl<'de> Deserialize<'de> for LineCap {
+    fn deserialize<D>(deserializer: D) -> Result<LineCap, D::Error>
     where
         D: Deserializer<'de>
     {
-        deserializer.deserialize_seq(CurveDataVisitor)
+        deserializer.deserialize_str(LineCapVisitor)
     }
 }
 
-impl Serialize for CurveData {
+impl Serialize for LineCap {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer
     {
-        let mut seq = serializer.serialize_seq(None)?;
-        seq.serialize_element(&self.start)?;
-
-        for seg in self.segments.iter() {
-            seq.serialize_element(&seg)?;
+        match self {
+            LineCap::Butt => serializer.serialize_str("butt"),
+            LineCap::Round => serializer.serialize_str("round"),
+            LineCap::Square => serializer.serialize_str("square"),
         }
-
-        seq.end()
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// How a pattern paints the area outside its natural extent.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Extend {
+    None,
+    Repeat,
+    Reflect,
+    Pad
+}
 
-    trait Relative {
-        fn relative_error_from(&self, other: &Self) -> f64;
-    }
+struct ExtendVisitor;
 
-    impl Relative for f64 {
-        fn relative_error_from(&self, other: &f64) -> f64 {
-            (self - other) / other
-        }
+impl<'de> Visitor<'de> for ExtendVisitor {
+    type Value = Extend;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str("extend mode")
     }
 
-    impl Relative for Point {
-        fn relative_error_from(&self, other: &Point) -> f64 {
-            self.x.relative_error_from(&other.x)
-                .max(self.y.relative_error_from(&other.y))
+    fn visit_str<E>(self, v: &str) -> Result<Extend, E>
+    where
+        E: serde::de::Error
+    {
+        match v {
+            "none" => Ok(Extend::None),
+            "repeat" => Ok(Extend::Repeat),
+            "reflect" => Ok(Extend::Reflect),
+            "pad" => Ok(Extend::Pad),
+            other => Err(serde::de::Error::unknown_variant(other, &["none", "repeat", "reflect", "pad"]))
         }
     }
 
-    impl Relative for Color {
-        fn relative_error_from(&self, other: &Color) -> f64 {
-            self.red.relative_error_from(&other.red)
-                .max(self.green.relative_error_from(&other.green))
-                .max(self.blue.relative_error_from(&other.blue))
-                .max(self.alpha.relative_error_from(&other.alpha))
+    fn visit_borrowed_str<E>(self, v: &'de str) -> Result<Extend, E>
+    where
+        E: serde::de::Error
+    {
+        match v {
+            "none" => Ok(Extend::None),
+            "repeat" => Ok(Extend::Repeat),
+            "reflect" => Ok(Extend::Reflect),
+            "pad" => Ok(Extend::Pad),
+            other => Err(serde::de::Error::unknown_variant(other, &["none", "repeat", "reflect", "pad"]))
         }
     }
 
-    impl Relative for Pattern {
-        fn relative_error_from(&self, other: &Pattern) -> f64 {
-            match self {
-                Pattern::Monochrome(mono1) =>
-                    match other {
-                        Pattern::Monochrome(mono2) =>
-                            mono1.color.relative_error_from(&mono2.color),
-                        _ => f64::INFINITY
-                    },
-                Pattern::LinearGradient(grad1) =>
-                    match other {
-                        Pattern::LinearGradient(grad2) =>
-                            grad1.point_1.relative_error_from(&grad2.point_1)
-                            .max(grad1.color_1.relative_error_from(&grad2.color_1))
-                            .max(grad1.point_2.relative_error_from(&grad2.point_2))
-                            .max(grad1.color_2.relative_error_from(&grad2.color_2)) ,
-                        _ => f64::INFINITY
-                    },
-                Pattern::RadialGradient(grad1) =>
-                    match other {
-                        Pattern::RadialGradient(grad2) =>
-                            grad1.center_1.relative_error_from(&grad2.center_1)
-                            .max(grad1.radius_1.relative_error_from(&grad2.radius_1))
-                            .max(grad1.color_1.relative_error_from(&grad2.color_1))
-                            .max(grad1.center_2.relative_error_from(&grad2.center_2))
-                            .max(grad1.radius_2.relative_error_from(&grad2.radius_2))
-                            .max(grad1.color_2.relative_error_from(&grad2.color_2)),
-                        _ => f64::INFINITY
-                    }
-            }
+    fn visit_string<E>(self, v: String) -> Result<Extend, E>
+    where
+        E: serde::de::Error
+    {
+        match v.as_str() {
+            "none" => Ok(Extend::None),
+            "repeat" => Ok(Extend::Repeat),
+            "reflect" => Ok(Extend::Reflect),
+            "pad" => Ok(Extend::Pad),
+            other => Err(serde::de::Error::unknown_variant(other, &["none", "repeat", "reflect", "pad"]))
         }
     }
+}
 
-    impl Relative for Segment {
-        fn relative_error_from(&self, other: &Segment) -> f64 {
-            match self {
-                Segment::Line(line1) =>
-                    match other {
-                        Segment::Line(line2) =>
-                            line1.point_2.relative_error_from(&line2.point_2),
-                        _ => f64::INFINITY
-                    },
-                Segment::QuadraticBezier(bezier1) =>
-                    match other {
-                        Segment::QuadraticBezier(bezier2) =>
-                            bezier1.point_2.relative_error_from(&bezier2.point_2)
-                            .max(bezier1.point_3.relative_error_from(&bezier2.point_3)),
-                        _ => f64::INFINITY
-                    },
-                Segment::CubicBezier(bezier1) =>
-                    match other {
-                        Segment::CubicBezier(bezier2) =>
-                            bezier1.point_2.relative_error_from(&bezier2.point_2)
-                            .max(bezier1.point_3.relative_error_from(&bezier2.point_3))
-                            .max(bezier1.point_4.relative_error_from(&bezier2.point_4)),
-                        _ => f64::INFINITY
-                    }
-            }
-        }
+impl<'de> Deserialize<'de> for Extend {
+    fn deserialize<D>(deserializer: D) -> Result<Extend, D::Error>
+    where
+        D: Deserializer<'de>
+    {
+        deserializer.deserialize_str(ExtendVisitor)
     }
+}
 
-    macro_rules! assert_near {
+impl Serialize for Extend {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer
+    {
+        match self {
+            Extend::None => serializer.serialize_str("none"),
+            Extend::Repeat => serializer.serialize_str("repeat"),
+            Extend::Reflect => serializer.serialize_str("reflect"),
+            Extend::Pad => serializer.serialize_str("pad"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LineJoin {
+    Miter,
+    Round,
+    Bevel
+}
+
+struct LineJoinVisitor;
+
+impl<'de> Visitor<'de> for LineJoinVisitor {
+    type Value = LineJoin;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str("line join")
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<LineJoin, E>
+    where
+        E: serde::de::Error
+    {
+        match v {
+            "miter" => Ok(LineJoin::Miter),
+            "round" => Ok(LineJoin::Round),
+            "bevel" => Ok(LineJoin::Bevel),
+            other => Err(serde::de::Error::unknown_variant(other, &["miter", "round", "bevel"]))
+        }
+    }
+
+    fn visit_borrowed_str<E>(self, v: &'de str) -> Result<LineJoin, E>
+    where
+        E: serde::de::Error
+    {
+        match v {
+            "miter" => Ok(LineJoin::Miter),
+            "round" => Ok(LineJoin::Round),
+            "bevel" => Ok(LineJoin::Bevel),
+            other => Err(serde::de::Error::unknown_variant(other, &["miter", "round", "bevel"]))
+        }
+    }
+
+    fn visit_string<E>(self, v: String) -> Result<LineJoin, E>
+    where
+        E: serde::de::Error
+    {
+        match v.as_str() {
+            "miter" => Ok(LineJoin::Miter),
+            "round" => Ok(LineJoin::Round),
+            "bevel" => Ok(LineJoin::Bevel),
+            other => Err(serde::de::Error::unknown_variant(other, &["miter", "round", "bevel"]))
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for LineJoin {
+    fn deserialize<D>(deserializer: D) -> Result<LineJoin, D::Error>
+    where
+        D: Deserializer<'de>
+    {
+        deserializer.deserialize_str(LineJoinVisitor)
+    }
+}
+
+impl Serialize for LineJoin {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer
+    {
+        match self {
+            LineJoin::Miter => serializer.serialize_str("miter"),
+            LineJoin::Round => serializer.serialize_str("round"),
+            LineJoin::Bevel => serializer.serialize_str("bevel"),
+        }
+    }
+}
+
+/// Alternating lengths of dashes and gaps along a stroke, starting with a dash, in image
+/// units. `offset` is how far into the pattern the stroke starts.
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Dash {
+    #[serde(deserialize_with = "deserialize_dash_pattern")]
+    pub pattern: Vec<f64>,
+    #[serde(deserialize_with = "deserialize_finite", default)]
+    pub offset: f64
+}
+
+impl Dash {
+    /// Returns whether the dash draws a solid line, as an empty or all-zero pattern does.
+    pub fn is_solid(&self) -> bool {
+        self.pattern.iter().all(|length| *length == 0.0)
+    }
+
+    /// Returns the dash with its lengths and offset multiplied by `factor`.
+    pub fn scaled(&self, factor: f64) -> Dash {
+        Dash {
+            pattern: self.pattern.iter().map(|length| length * factor).collect(),
+            offset: self.offset * factor
+        }
+    }
+}
+
+fn deserialize_dash_pattern<'de, D>(deserializer: D) -> Result<Vec<f64>, D::Error>
+where
+    D: Deserializer<'de>
+{
+    let pattern = Vec::<f64>::deserialize(deserializer)?;
+
+    match pattern.iter().find(|length| !(length.is_finite() && **length >= 0.0)) {
+        Some(length) => Err(serde::de::Error::custom(format!("dash length {} must not be negative", length))),
+        None => Ok(pattern)
+    }
+}
+
+/// Unit of the lengths of a `Dash`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DashMode {
+    /// Image units.
+    Absolute,
+    /// Fractions of the length of the stroked path.
+    Relative
+}
+
+struct DashModeVisitor;
+
+impl<'de> Visitor<'de> for DashModeVisitor {
+    type Value = DashMode;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str("dash mode")
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<DashMode, E>
+    where
+        E: serde::de::Error
+    {
+        match v {
+            "absolute" => Ok(DashMode::Absolute),
+            "relative" => Ok(DashMode::Relative),
+            other => Err(serde::de::Error::unknown_variant(other, &["absolute", "relative"]))
+        }
+    }
+
+    fn visit_borrowed_str<E>(self, v: &'de str) -> Result<DashMode, E>
+    where
+        E: serde::de::Error
+    {
+        match v {
+            "absolute" => Ok(DashMode::Absolute),
+            "relative" => Ok(DashMode::Relative),
+            other => Err(serde::de::Error::unknown_variant(other, &["absolute", "relative"]))
+        }
+    }
+
+    fn visit_string<E>(self, v: String) -> Result<DashMode, E>
+    where
+        E: serde::de::Error
+    {
+        match v.as_str() {
+            "absolute" => Ok(DashMode::Absolute),
+            "relative" => Ok(DashMode::Relative),
+            other => Err(serde::de::Error::unknown_variant(other, &["absolute", "relative"]))
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for DashMode {
+    fn deserialize<D>(deserializer: D) -> Result<DashMode, D::Error>
+    where
+        D: Deserializer<'de>
+    {
+        deserializer.deserialize_str(DashModeVisitor)
+    }
+}
+
+impl Serialize for DashMode {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer
+    {
+        match self {
+            DashMode::Absolute => serializer.serialize_str("absolute"),
+            DashMode::Relative => serializer.serialize_str("relative"),
+        }
+    }
+}
+
+/// Placement of a stroke relative to the path.
+///
+/// `Inner` and `Outer` only apply to regions; curves are always stroked centered on the path.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StrokeAlign {
+    Center,
+    Inner,
+    Outer
+}
+
+struct StrokeAlignVisitor;
+
+impl<'de> Visitor<'de> for StrokeAlignVisitor {
+    type Value = StrokeAlign;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str("stroke align")
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<StrokeAlign, E>
+    where
+        E: serde::de::Error
+    {
+        match v {
+            "center" => Ok(StrokeAlign::Center),
+            "inner" => Ok(StrokeAlign::Inner),
+            "outer" => Ok(StrokeAlign::Outer),
+            other => Err(serde::de::Error::unknown_variant(other, &["center", "inner", "outer"]))
+        }
+    }
+
+    fn visit_borrowed_str<E>(self, v: &'de str) -> Result<StrokeAlign, E>
+    where
+        E: serde::de::Error
+    {
+        match v {
+            "center" => Ok(StrokeAlign::Center),
+            "inner" => Ok(StrokeAlign::Inner),
+            "outer" => Ok(StrokeAlign::Outer),
+            other => Err(serde::de::Error::unknown_variant(other, &["center", "inner", "outer"]))
+        }
+    }
+
+    fn visit_string<E>(self, v: String) -> Result<StrokeAlign, E>
+    where
+        E: serde::de::Error
+    {
+        match v.as_str() {
+            "center" => Ok(StrokeAlign::Center),
+            "inner" => Ok(StrokeAlign::Inner),
+            "outer" => Ok(StrokeAlign::Outer),
+            other => Err(serde::de::Error::unknown_variant(other, &["center", "inner", "outer"]))
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for StrokeAlign {
+    fn deserialize<D>(deserializer: D) -> Result<StrokeAlign, D::Error>
+    where
+        D: Deserializer<'de>
+    {
+        deserializer.deserialize_str(StrokeAlignVisitor)
+    }
+}
+
+impl Serialize for StrokeAlign {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer
+    {
+        match self {
+            StrokeAlign::Center => serializer.serialize_str("center"),
+            StrokeAlign::Inner => serializer.serialize_str("inner"),
+            StrokeAlign::Outer => serializer.serialize_str("outer"),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Pen {
+    pub pattern: Pattern,
+    #[serde(deserialize_with = "deserialize_finite")]
+    pub width: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cap: Option<LineCap>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub join: Option<LineJoin>,
+    /// Ratio of the miter length to the line width above which a miter join is drawn as a
+    /// bevel. Cairo's default of 10 is used when absent.
+    #[serde(skip_serializing_if = "Option::is_none", deserialize_with = "deserialize_miter_limit", default)]
+    pub miter_limit: Option<f64>,
+    /// Solid when absent.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dash: Option<Dash>,
+    /// How the lengths of `dash` are measured; `absolute` if `None`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dash_mode: Option<DashMode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub align: Option<StrokeAlign>,
+    /// Identifies the pen across images, as used by `Image::apply_theme`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>
+}
+
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Brush {
+    pub pattern: Pattern,
+    /// Identifies the brush across images, as used by `Image::apply_theme`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>
+}
+
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct GroupShape {
+    /// Identifies the shape, as used by `RenderOptions::styles`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// Opacity with which the children are composited as a whole, so that they do not
+    /// blend with each other.
+    #[serde(skip_serializing_if = "Option::is_none", deserialize_with = "deserialize_opacity", default)]
+    pub opacity: Option<f64>,
+    /// Operator with which the children, composited as a whole, are painted; `over` if
+    /// `None`.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub operator: Option<Operator>,
+    /// Affine transform `[xx, yx, xy, yy, x0, y0]` from the coordinates of the children to
+    /// those of the group, laid out as a cairo matrix with the translation in image units.
+    #[serde(skip_serializing_if = "Option::is_none", deserialize_with = "deserialize_transform", default)]
+    pub transform: Option<[f64; 6]>,
+    pub content: Vec<Shape>,
+    #[serde(skip_serializing_if = "serde_json::Value::is_null", default)]
+    pub edit_annot: serde_json::Value
+}
+
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct CurveShape {
+    /// Identifies the shape, as used by `RenderOptions::styles`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    pub pen: usize,
+    pub data: CurveData
+}
+
+/// The compositing operator with which a group is painted onto what is below it.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Operator {
+    Over,
+    Multiply,
+    Screen,
+    Overlay,
+    Darken,
+    Lighten,
+    Difference,
+    Add,
+    Xor
+}
+
+const OPERATOR_NAMES: [&str; 9] = ["over", "multiply", "screen", "overlay", "darken", "lighten", "difference", "add", "xor"];
+
+struct OperatorVisitor;
+
+impl<'de> Visitor<'de> for OperatorVisitor {
+    type Value = Operator;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str("compositing operator")
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Operator, E>
+    where
+        E: serde::de::Error
+    {
+        match v {
+            "over" => Ok(Operator::Over),
+            "multiply" => Ok(Operator::Multiply),
+            "screen" => Ok(Operator::Screen),
+            "overlay" => Ok(Operator::Overlay),
+            "darken" => Ok(Operator::Darken),
+            "lighten" => Ok(Operator::Lighten),
+            "difference" => Ok(Operator::Difference),
+            "add" => Ok(Operator::Add),
+            "xor" => Ok(Operator::Xor),
+            other => Err(serde::de::Error::unknown_variant(other, &OPERATOR_NAMES))
+        }
+    }
+
+    fn visit_borrowed_str<E>(self, v: &'de str) -> Result<Operator, E>
+    where
+        E: serde::de::Error
+    {
+        match v {
+            "over" => Ok(Operator::Over),
+            "multiply" => Ok(Operator::Multiply),
+            "screen" => Ok(Operator::Screen),
+            "overlay" => Ok(Operator::Overlay),
+            "darken" => Ok(Operator::Darken),
+            "lighten" => Ok(Operator::Lighten),
+            "difference" => Ok(Operator::Difference),
+            "add" => Ok(Operator::Add),
+            "xor" => Ok(Operator::Xor),
+            other => Err(serde::de::Error::unknown_variant(other, &OPERATOR_NAMES))
+        }
+    }
+
+    fn visit_string<E>(self, v: String) -> Result<Operator, E>
+    where
+        E: serde::de::Error
+    {
+        match v.as_str() {
+            "over" => Ok(Operator::Over),
+            "multiply" => Ok(Operator::Multiply),
+            "screen" => Ok(Operator::Screen),
+            "overlay" => Ok(Operator::Overlay),
+            "darken" => Ok(Operator::Darken),
+            "lighten" => Ok(Operator::Lighten),
+            "difference" => Ok(Operator::Difference),
+            "add" => Ok(Operator::Add),
+            "xor" => Ok(Operator::Xor),
+            other => Err(serde::de::Error::unknown_variant(other, &OPERATOR_NAMES))
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Operator {
+    fn deserialize<D>(deserializer: D) -> Result<Operator, D::Error>
+    where
+        D: Deserializer<'de>
+    {
+        deserializer.deserialize_str(OperatorVisitor)
+    }
+}
+
+impl Serialize for Operator {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer
+    {
+        match self {
+            Operator::Over => serializer.serialize_str("over"),
+            Operator::Multiply => serializer.serialize_str("multiply"),
+            Operator::Screen => serializer.serialize_str("screen"),
+            Operator::Overlay => serializer.serialize_str("overlay"),
+            Operator::Darken => serializer.serialize_str("darken"),
+            Operator::Lighten => serializer.serialize_str("lighten"),
+            Operator::Difference => serializer.serialize_str("difference"),
+            Operator::Add => serializer.serialize_str("add"),
+            Operator::Xor => serializer.serialize_str("xor"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FillRule {
+    EvenOdd,
+    Nonzero
+}
+
+struct FillRuleVisitor;
+
+impl<'de> Visitor<'de> for FillRuleVisitor {
+    type Value = FillRule;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str("fill rule")
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<FillRule, E>
+    where
+        E: serde::de::Error
+    {
+        match v {
+            "even-odd" => Ok(FillRule::EvenOdd),
+            "nonzero" => Ok(FillRule::Nonzero),
+            other => Err(serde::de::Error::unknown_variant(other, &["even-odd", "nonzero"]))
+        }
+    }
+
+    fn visit_borrowed_str<E>(self, v: &'de str) -> Result<FillRule, E>
+    where
+        E: serde::de::Error
+    {
+        match v {
+            "even-odd" => Ok(FillRule::EvenOdd),
+            "nonzero" => Ok(FillRule::Nonzero),
+            other => Err(serde::de::Error::unknown_variant(other, &["even-odd", "nonzero"]))
+        }
+    }
+
+    fn visit_string<E>(self, v: String) -> Result<FillRule, E>
+    where
+        E: serde::de::Error
+    {
+        match v.as_str() {
+            "even-odd" => Ok(FillRule::EvenOdd),
+            "nonzero" => Ok(FillRule::Nonzero),
+            other => Err(serde::de::Error::unknown_variant(other, &["even-odd", "nonzero"]))
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for FillRule {
+    fn deserialize<D>(deserializer: D) -> Result<FillRule, D::Error>
+    where
+        D: Deserializer<'de>
+    {
+        deserializer.deserialize_str(FillRuleVisitor)
+    }
+}
+
+impl Serialize for FillRule {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer
+    {
+        match self {
+            FillRule::EvenOdd => serializer.serialize_str("even-odd"),
+            FillRule::Nonzero => serializer.serialize_str("nonzero"),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct RegionShape {
+    /// Identifies the shape, as used by `RenderOptions::styles`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pen: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub brush: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none", deserialize_with = "deserialize_opacity", default)]
+    pub fill_opacity: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none", deserialize_with = "deserialize_opacity", default)]
+    pub stroke_opacity: Option<f64>,
+    /// Point where the origin of the fill pattern is placed, so that the pattern can be
+    /// aligned to the region instead of the image.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pattern_origin: Option<Point>,
+    /// Even-odd when absent.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fill_rule: Option<FillRule>,
+    pub data: Vec<CurveData>
+}
+
+fn deserialize_opacity<'de, D>(deserializer: D) -> Result<Option<f64>, D::Error>
+where
+    D: Deserializer<'de>
+{
+    match Option::<f64>::deserialize(deserializer)? {
+        Some(opacity) if !(0.0..=1.0).contains(&opacity) =>
+            Err(serde::de::Error::custom(format!("opacity {} is out of range [0, 1]", opacity))),
+        opacity => Ok(opacity)
+    }
+}
+
+fn deserialize_transform<'de, D>(deserializer: D) -> Result<Option<[f64; 6]>, D::Error>
+where
+    D: Deserializer<'de>
+{
+    match Option::<[f64; 6]>::deserialize(deserializer)? {
+        Some(transform) if invert_transform(&transform).is_none() =>
+            Err(serde::de::Error::custom(format!("transform {:?} is not invertible", transform))),
+        transform => Ok(transform)
+    }
+}
+
+/// Maps `p` by the affine transform `[xx, yx, xy, yy, x0, y0]`.
+pub fn apply_transform(transform: &[f64; 6], p: Point) -> Point {
+    let [xx, yx, xy, yy, x0, y0] = *transform;
+    Point { x: xx * p.x + xy * p.y + x0, y: yx * p.x + yy * p.y + y0 }
+}
+
+/// Returns the transform that applies `inner` and then `outer`.
+pub fn compose_transforms(outer: &[f64; 6], inner: &[f64; 6]) -> [f64; 6] {
+    let [oxx, oyx, oxy, oyy, ox0, oy0] = *outer;
+    let [ixx, iyx, ixy, iyy, ix0, iy0] = *inner;
+    [
+        oxx * ixx + oxy * iyx,
+        oyx * ixx + oyy * iyx,
+        oxx * ixy + oxy * iyy,
+        oyx * ixy + oyy * iyy,
+        oxx * ix0 + oxy * iy0 + ox0,
+        oyx * ix0 + oyy * iy0 + oy0
+    ]
+}
+
+/// Returns the inverse of the transform, or `None` if it is singular or not finite.
+pub fn invert_transform(transform: &[f64; 6]) -> Option<[f64; 6]> {
+    let [xx, yx, xy, yy, x0, y0] = *transform;
+    let det = xx * yy - xy * yx;
+
+    if !(det.is_finite() && det != 0.0 && x0.is_finite() && y0.is_finite()) {
+        return None;
+    }
+
+    Some([
+        yy / det,
+        -yx / det,
+        -xy / det,
+        xx / det,
+        (xy * y0 - yy * x0) / det,
+        (yx * x0 - xx * y0) / det
+    ])
+}
+
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
+#[serde(rename_all = "kebab-case", tag = "type")]
+pub enum Shape {
+    Group(GroupShape),
+    Curve(CurveShape),
+    Region(RegionShape)
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct LineSegment {
+    pub point_2: Point
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct QuadraticBezierSegment {
+    pub point_2: Point,
+    pub point_3: Point
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct CubicBezierSegment {
+    pub point_2: Point,
+    pub point_3: Point,
+    pub point_4: Point
+}
+
+/// A quadratic Bezier segment whose control point is the reflection of the previous one.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct SmoothQuadraticBezierSegment {
+    pub point_3: Point
+}
+
+/// A cubic Bezier segment whose first control point is the reflection of the second control
+/// point of the previous one.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct SmoothCubicBezierSegment {
+    pub point_3: Point,
+    pub point_4: Point
+}
+
+/// An elliptical arc given by its end point, as in SVG path data.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ArcSegment {
+    pub radius_x: f64,
+    pub radius_y: f64,
+    /// Rotation of the x axis of the ellipse in degrees.
+    pub rotation: f64,
+    pub large_arc: bool,
+    pub sweep: bool,
+    pub point_2: Point
+}
+
+impl ArcSegment {
+    /// Converts the arc starting at `start` to cubic Bezier segments of at most a quarter turn
+    /// each. Following SVG, an arc with a zero radius becomes a line, an arc ending where it
+    /// starts is dropped, and radii too small to reach the end point are scaled up.
+    pub fn to_beziers(&self, start: Point) -> Vec<Segment> {
+        let end = self.point_2;
+
+        if start == end {
+            return vec![];
+        }
+
+        let (mut rx, mut ry) = (self.radius_x.abs(), self.radius_y.abs());
+
+        if rx == 0.0 || ry == 0.0 {
+            return vec![Segment::Line(LineSegment { point_2: end })];
+        }
+
+        // Endpoint to center parameterization, SVG 1.1 appendix F.6.5.
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        let (dx, dy) = ((start.x - end.x) / 2.0, (start.y - end.y) / 2.0);
+        let (x1, y1) = (cos * dx + sin * dy, -sin * dx + cos * dy);
+
+        let lambda = (x1 * x1) / (rx * rx) + (y1 * y1) / (ry * ry);
+        if lambda > 1.0 {
+            rx *= lambda.sqrt();
+            ry *= lambda.sqrt();
+        }
+
+        let numerator = rx * rx * ry * ry - rx * rx * y1 * y1 - ry * ry * x1 * x1;
+        let denominator = rx * rx * y1 * y1 + ry * ry * x1 * x1;
+        let sign = if self.large_arc == self.sweep { -1.0 } else { 1.0 };
+        let coef = sign * (numerator / denominator).max(0.0).sqrt();
+        let (cx1, cy1) = (coef * rx * y1 / ry, -coef * ry * x1 / rx);
+
+        let center = Point {
+            x: cos * cx1 - sin * cy1 + (start.x + end.x) / 2.0,
+            y: sin * cx1 + cos * cy1 + (start.y + end.y) / 2.0
+        };
+
+        let theta = ((y1 - cy1) / ry).atan2((x1 - cx1) / rx);
+        let mut delta = ((-y1 - cy1) / ry).atan2((-x1 - cx1) / rx) - theta;
+
+        if self.sweep && delta < 0.0 {
+            delta += 2.0 * std::f64::consts::PI;
+        } else if !self.sweep && delta > 0.0 {
+            delta -= 2.0 * std::f64::consts::PI;
+        }
+
+        let count = (delta.abs() / std::f64::consts::FRAC_PI_2).ceil().max(1.0) as usize;
+        let step = delta / count as f64;
+        let k = 4.0 / 3.0 * (step / 4.0).tan();
+
+        // Maps a point on the unit circle to the ellipse.
+        let map = |ux: f64, uy: f64| Point {
+            x: center.x + rx * ux * cos - ry * uy * sin,
+            y: center.y + rx * ux * sin + ry * uy * cos
+        };
+
+        (0..count)
+            .map(|i| {
+                let (sin_1, cos_1) = (theta + step * i as f64).sin_cos();
+                let (sin_2, cos_2) = (theta + step * (i + 1) as f64).sin_cos();
+
+                Segment::CubicBezier(CubicBezierSegment {
+                    point_2: map(cos_1 - k * sin_1, sin_1 + k * cos_1),
+                    point_3: map(cos_2 + k * sin_2, sin_2 - k * cos_2),
+                    point_4: if i + 1 == count { end } else { map(cos_2, sin_2) }
+                })
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Segment {
+    Line(LineSegment),
+    QuadraticBezier(QuadraticBezierSegment),
+    CubicBezier(CubicBezierSegment),
+    SmoothQuadraticBezier(SmoothQuadraticBezierSegment),
+    SmoothCubicBezier(SmoothCubicBezierSegment),
+    Arc(ArcSegment)
+}
+
+struct SegmentVisitor;
+
+impl<'de> Visitor<'de> for SegmentVisitor {
+    type Value = Segment;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str("segment")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Segment, A::Error>
+    where
+        A: SeqAccess<'de>
+    {
+        let tag = seq.next_element::<String>()?
+            .ok_or_else(|| serde::de::Error::invalid_length(0, &self))?;
+
+        match tag.as_str() {
+            "L" => {
+                let point_2 = seq.next_element::<Point>()?
+                    .ok_or_else(|| serde::de::Error::invalid_length(1, &self))?;
+
+                match seq.next_element::<Point>()? {
+                    None => Ok(Segment::Line(LineSegment { point_2 })),
+                    Some(_) => Err(serde::de::Error::invalid_length(2, &self))
+                }
+            },
+            "Q" => {
+                let point_2 = seq.next_element::<Point>()?
+                    .ok_or_else(|| serde::de::Error::invalid_length(1, &self))?;
+                let point_3 = seq.next_element::<Point>()?
+                    .ok_or_else(|| serde::de::Error::invalid_length(2, &self))?;
+
+                match seq.next_element::<Point>()? {
+                    None => Ok(Segment::QuadraticBezier(QuadraticBezierSegment { point_2, point_3 })),
+                    Some(_) => Err(serde::de::Error::invalid_length(3, &self))
+                }
+            },
+            "C" => {
+                let point_2 = seq.next_element::<Point>()?
+                    .ok_or_else(|| serde::de::Error::invalid_length(1, &self))?;
+                let point_3 = seq.next_element::<Point>()?
+                    .ok_or_else(|| serde::de::Error::invalid_length(2, &self))?;
+                let point_4 = seq.next_element::<Point>()?
+                    .ok_or_else(|| serde::de::Error::invalid_length(3, &self))?;
+
+                match seq.next_element::<Point>()? {
+                    None => Ok(Segment::CubicBezier(CubicBezierSegment { point_2, point_3, point_4 })),
+                    Some(_) => Err(serde::de::Error::invalid_length(4, &self))
+                }
+            },
+            "T" => {
+                let point_3 = seq.next_element::<Point>()?
+                    .ok_or_else(|| serde::de::Error::invalid_length(1, &self))?;
+
+                match seq.next_element::<Point>()? {
+                    None => Ok(Segment::SmoothQuadraticBezier(SmoothQuadraticBezierSegment { point_3 })),
+                    Some(_) => Err(serde::de::Error::invalid_length(2, &self))
+                }
+            },
+            "S" => {
+                let point_3 = seq.next_element::<Point>()?
+                    .ok_or_else(|| serde::de::Error::invalid_length(1, &self))?;
+                let point_4 = seq.next_element::<Point>()?
+                    .ok_or_else(|| serde::de::Error::invalid_length(2, &self))?;
+
+                match seq.next_element::<Point>()? {
+                    None => Ok(Segment::SmoothCubicBezier(SmoothCubicBezierSegment { point_3, point_4 })),
+                    Some(_) => Err(serde::de::Error::invalid_length(3, &self))
+                }
+            },
+            "A" => {
+                let radius_x = seq.next_element::<f64>()?
+                    .ok_or_else(|| serde::de::Error::invalid_length(1, &self))?;
+                let radius_y = seq.next_element::<f64>()?
+                    .ok_or_else(|| serde::de::Error::invalid_length(2, &self))?;
+                let rotation = seq.next_element::<f64>()?
+                    .ok_or_else(|| serde::de::Error::invalid_length(3, &self))?;
+                let large_arc = seq.next_element::<bool>()?
+                    .ok_or_else(|| serde::de::Error::invalid_length(4, &self))?;
+                let sweep = seq.next_element::<bool>()?
+                    .ok_or_else(|| serde::de::Error::invalid_length(5, &self))?;
+                let point_2 = seq.next_element::<Point>()?
+                    .ok_or_else(|| serde::de::Error::invalid_length(6, &self))?;
+
+                let radius_x = check_finite(radius_x, "radius")?;
+                let radius_y = check_finite(radius_y, "radius")?;
+                let rotation = check_finite(rotation, "rotation")?;
+
+                match seq.next_element::<Point>()? {
+                    None => Ok(Segment::Arc(ArcSegment { radius_x, radius_y, rotation, large_arc, sweep, point_2 })),
+                    Some(_) => Err(serde::de::Error::invalid_length(7, &self))
+                }
+            },
+            other => Err(serde::de::Error::unknown_variant(other, &["L", "Q", "C", "T", "S", "A"]))
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Segment {
+    fn deserialize<D>(deserializer: D) -> Result<Segment, D::Error>
+    where
+        D: Deserializer<'de>
+    {
+        deserializer.deserialize_seq(SegmentVisitor)
+    }
+}
+
+impl Serialize for Segment {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer
+    {
+        let mut seq = serializer.serialize_seq(None)?;
+        
+        match self {
+            Segment::Line(s) => {
+                seq.serialize_element("L")?;
+                seq.serialize_element(&s.point_2)?;
+            },
+            Segment::QuadraticBezier(s) => {
+                seq.serialize_element("Q")?;
+                seq.serialize_element(&s.point_2)?;
+                seq.serialize_element(&s.point_3)?;
+            },
+            Segment::CubicBezier(s) => {
+                seq.serialize_element("C")?;
+                seq.serialize_element(&s.point_2)?;
+                seq.serialize_element(&s.point_3)?;
+                seq.serialize_element(&s.point_4)?;
+            },
+            Segment::SmoothQuadraticBezier(s) => {
+                seq.serialize_element("T")?;
+                seq.serialize_element(&s.point_3)?;
+            },
+            Segment::SmoothCubicBezier(s) => {
+                seq.serialize_element("S")?;
+                seq.serialize_element(&s.point_3)?;
+                seq.serialize_element(&s.point_4)?;
+            },
+            Segment::Arc(s) => {
+                seq.serialize_element("A")?;
+                seq.serialize_element(&s.radius_x)?;
+                seq.serialize_element(&s.radius_y)?;
+                seq.serialize_element(&s.rotation)?;
+                seq.serialize_element(&s.large_arc)?;
+                seq.serialize_element(&s.sweep)?;
+                seq.serialize_element(&s.point_2)?;
+            }
+        }
+
+        seq.end()
+    }
+}
+
+impl Image {
+    /// Returns the cap of `pen`, falling back to the image default and then to `Butt`.
+    pub fn line_cap(&self, pen: &Pen) -> LineCap {
+        pen.cap.or(self.default_cap).unwrap_or(LineCap::Butt)
+    }
+
+    /// Returns the join of `pen`, falling back to the image default and then to `Miter`.
+    pub fn line_join(&self, pen: &Pen) -> LineJoin {
+        pen.join.or(self.default_join).unwrap_or(LineJoin::Miter)
+    }
+
+    /// Returns `pattern` with straight-alpha colors, un-premultiplying them if the image
+    /// declares premultiplied colors.
+    pub fn straight_pattern(&self, pattern: &Pattern) -> Pattern {
+        match self.alpha_mode {
+            Some(AlphaMode::Premultiplied) => pattern.map_colors(Color::unpremultiply),
+            None | Some(AlphaMode::Straight) => pattern.clone()
+        }
+    }
+
+    /// Returns the distinct colors used by the pens and brushes, in order of first use.
+    pub fn colors(&self) -> Vec<Color> {
+        let patterns = self.pens.iter().map(|pen| &pen.pattern)
+            .chain(self.brushes.iter().map(|brush| &brush.pattern));
+
+        let mut colors: Vec<Color> = Vec::new();
+
+        for color in patterns.flat_map(Pattern::colors) {
+            if !colors.contains(&color) {
+                colors.push(color);
+            }
+        }
+
+        colors
+    }
+
+    /// Returns the used colors that are not within `tolerance` of any color in `allowed`.
+    ///
+    /// Two colors are within tolerance when no component (including alpha) differs by more
+    /// than `tolerance`.
+    pub fn check_palette(&self, allowed: &[Color], tolerance: f64) -> Vec<Color> {
+        self.colors()
+            .into_iter()
+            .filter(|color| !allowed.iter().any(|entry| color.distance(entry) <= tolerance))
+            .collect()
+    }
+
+    /// Restyles named pens and brushes with those of the same name in `theme`, keeping their
+    /// indices so that shapes need no remapping. Unnamed pens and brushes, and those without a
+    /// match in the theme, are left as they are.
+    pub fn apply_theme(&mut self, theme: &Image) {
+        for pen in self.pens.iter_mut() {
+            if let Some(name) = &pen.name
+                && let Some(themed) = theme.pens.iter().find(|themed| themed.name.as_ref() == Some(name)) {
+                *pen = themed.clone();
+            }
+        }
+
+        for brush in self.brushes.iter_mut() {
+            if let Some(name) = &brush.name
+                && let Some(themed) = theme.brushes.iter().find(|themed| themed.name.as_ref() == Some(name)) {
+                *brush = themed.clone();
+            }
+        }
+    }
+
+    /// Replaces the pattern of every pen and brush with a monochrome of `color`, keeping the
+    /// geometry intact.
+    pub fn redact(&mut self, color: Color) {
+        let pattern = Pattern::Monochrome(MonochromePattern { color });
+
+        for pen in self.pens.iter_mut() {
+            pen.pattern = pattern.clone();
+        }
+
+        for brush in self.brushes.iter_mut() {
+            brush.pattern = pattern.clone();
+        }
+    }
+}
+
+impl Image {
+    /// Applies `f` to every point of the image: the start and control points of all curve
+    /// data, the points of gradients and the pattern origins of regions. Radii and widths are
+    /// left as they are. Inside a transformed group, points are mapped so that they end up
+    /// where `f` takes their transformed position, leaving the transform as it is. This holds
+    /// for the gradients of pens and brushes too: one used under several group transforms is
+    /// copied so that each use is mapped by its own, and the shapes are renumbered to match.
+    pub fn map_points<F: FnMut(Point) -> Point>(&mut self, mut f: F) {
+        let pen_count = self.pens.len();
+        let brush_count = self.brushes.len();
+        let mut pen_uses = vec![None; pen_count];
+        let mut brush_uses = vec![None; brush_count];
+        let mut stack: Vec<(&mut Shape, Option<[f64; 6]>)> =
+            self.shapes.iter_mut().rev().map(|shape| (shape, None)).collect();
+
+        while let Some((shape, transform)) = stack.pop() {
+            match shape {
+                Shape::Group(group) => {
+                    let inner = match group.transform.filter(|t| invert_transform(t).is_some()) {
+                        Some(t) => Some(transform.map_or(t, |outer| compose_transforms(&outer, &t))),
+                        None => transform
+                    };
+
+                    stack.extend(group.content.iter_mut().rev().map(|child| (child, inner)));
+                },
+                Shape::Curve(curve) =>
+                    assign_transform(&mut self.pens, &mut pen_uses, pen_count, &mut curve.pen, transform, |pen| &pen.pattern),
+                Shape::Region(region) => {
+                    if let Some(pen) = region.pen.as_mut() {
+                        assign_transform(&mut self.pens, &mut pen_uses, pen_count, pen, transform, |pen| &pen.pattern);
+                    }
+
+                    if let Some(brush) = region.brush.as_mut() {
+                        assign_transform(&mut self.brushes, &mut brush_uses, brush_count, brush, transform, |brush| &brush.pattern);
+                    }
+                }
+            }
+        }
+
+        for (pen, uses) in self.pens.iter_mut().zip(pen_uses) {
+            pen.pattern = map_pattern_points(&pen.pattern, uses.and_then(|(_, t)| t), &mut f);
+        }
+
+        for (brush, uses) in self.brushes.iter_mut().zip(brush_uses) {
+            brush.pattern = map_pattern_points(&brush.pattern, uses.and_then(|(_, t)| t), &mut f);
+        }
+
+        for shape in self.shapes.iter_mut() {
+            shape.map_points(&mut f);
+        }
+    }
+
+    /// Returns an image holding only the top-level shape at `index`, with just the pens and
+    /// brushes it refers to, renumbered in order of first use. The canvas is kept as it is,
+    /// unless `crop` is set, in which case the shape is moved so that the canvas becomes its
+    /// bounding box.
+    ///
+    /// Returns `None` if there is no such shape or it refers to a pen or brush that does not
+    /// exist.
+    pub fn extract_shape(&self, index: usize, crop: bool) -> Option<Image> {
+        let mut shape = self.shapes.get(index)?.clone();
+        let mut pen_map = Vec::new();
+        let mut brush_map = Vec::new();
+        let mut stack: Vec<&mut Shape> = vec![&mut shape];
+
+        let remap = |map: &mut Vec<usize>, index: &mut usize, count: usize| {
+            if *index >= count {
+                return false;
+            }
+
+            *index = index_of(map, index);
+            true
+        };
+
+        while let Some(shape) = stack.pop() {
+            let valid = match shape {
+                Shape::Group(group) => {
+                    // Popping reverses the order, so push the children back to front.
+                    stack.extend(group.content.iter_mut().rev());
+                    true
+                },
+                Shape::Curve(curve) => remap(&mut pen_map, &mut curve.pen, self.pens.len()),
+                Shape::Region(region) =>
+                    region.pen.as_mut().is_none_or(|pen| remap(&mut pen_map, pen, self.pens.len()))
+                    && region.brush.as_mut().is_none_or(|brush| remap(&mut brush_map, brush, self.brushes.len()))
+            };
+
+            if !valid {
+                return None;
+            }
+        }
+
+        let mut image = Image {
+            width: self.width,
+            height: self.height,
+            unit_per_inch: self.unit_per_inch,
+            editor: self.editor.clone(),
+            default_cap: self.default_cap,
+            default_join: self.default_join,
+            alpha_mode: self.alpha_mode,
+            pens: pen_map.iter().map(|&pen| self.pens[pen].clone()).collect(),
+            brushes: brush_map.iter().map(|&brush| self.brushes[brush].clone()).collect(),
+            shapes: vec![shape]
+        };
+
+        if crop && let Some((min, max)) = image.bounding_box() {
+            image.map_points(|p| Point { x: p.x - min.x, y: p.y - min.y });
+            image.width = max.x - min.x;
+            image.height = max.y - min.y;
+        }
+
+        Some(image)
+    }
+
+    /// Rounds every point, as visited by `map_points`, to the nearest multiple of `step`.
+    ///
+    /// Segments shorter than `step` can collapse onto their start point. Such segments are
+    /// removed, so a sub-path may be left with no segments at all.
+    ///
+    /// Panics if `step` is not positive and finite.
+    pub fn snap_to_grid(&mut self, step: f64) {
+        assert!(step > 0.0 && step.is_finite(), "grid step must be positive and finite, got {}", step);
+
+        let snap = |value: f64| (value / step).round() * step;
+
+        self.map_points(|p| Point { x: snap(p.x), y: snap(p.y) });
+
+        let mut stack: Vec<&mut Shape> = self.shapes.iter_mut().collect();
+
+        while let Some(shape) = stack.pop() {
+            match shape {
+                Shape::Group(group) => stack.extend(group.content.iter_mut()),
+                Shape::Curve(curve) => curve.data.remove_degenerate(),
+                Shape::Region(region) => region.data.iter_mut().for_each(CurveData::remove_degenerate)
+            }
+        }
+    }
+}
+
+impl Color {
+    fn distance(&self, other: &Color) -> f64 {
+        (self.red - other.red).abs()
+            .max((self.green - other.green).abs())
+            .max((self.blue - other.blue).abs())
+            .max((self.alpha - other.alpha).abs())
+    }
+}
+
+#[derive(Serialize, Clone, Copy, Default, PartialEq, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct ImageStats {
+    pub pens: usize,
+    pub brushes: usize,
+    pub shapes: usize,
+    pub groups: usize,
+    pub curves: usize,
+    pub regions: usize,
+    pub segments: usize
+}
+
+impl ImageStats {
+    fn add_shape(&mut self, shape: &Shape) {
+        match shape {
+            Shape::Group(group) => {
+                self.groups += 1;
+
+                for child in group.content.iter() {
+                    self.add_shape(child);
+                }
+            },
+            Shape::Curve(curve) => {
+                self.curves += 1;
+                self.segments += curve.data.segments.len();
+            },
+            Shape::Region(region) => {
+                self.regions += 1;
+                self.segments += region.data.iter().map(|data| data.segments.len()).sum::<usize>();
+            }
+        }
+    }
+}
+
+fn union_bounds(bounds: Option<(Point, Point)>, other: Option<(Point, Point)>) -> Option<(Point, Point)> {
+    match (bounds, other) {
+        (Some((min1, max1)), Some((min2, max2))) => Some((
+            Point { x: min1.x.min(min2.x), y: min1.y.min(min2.y) },
+            Point { x: max1.x.max(max2.x), y: max1.y.max(max2.y) }
+        )),
+        (Some(bounds), None) | (None, Some(bounds)) => Some(bounds),
+        (None, None) => None
+    }
+}
+
+fn inflate_bounds(bounds: (Point, Point), amount: f64) -> (Point, Point) {
+    let (min, max) = bounds;
+    (
+        Point { x: min.x - amount, y: min.y - amount },
+        Point { x: max.x + amount, y: max.y + amount }
+    )
+}
+
+impl Image {
+    pub fn stats(&self) -> ImageStats {
+        let mut stats = ImageStats {
+            pens: self.pens.len(),
+            brushes: self.brushes.len(),
+            shapes: self.shapes.len(),
+            ..ImageStats::default()
+        };
+
+        for shape in self.shapes.iter() {
+            stats.add_shape(shape);
+        }
+
+        stats
+    }
+
+    /// Returns the minimum and maximum corners enclosing all shapes, including their strokes.
+    ///
+    /// Bezier segments are bounded by their control points, so the box may be larger than
+    /// the drawn content. Returns `None` if there is nothing to draw.
+    pub fn bounding_box(&self) -> Option<(Point, Point)> {
+        self.shapes.iter().fold(None, |bounds, shape| union_bounds(bounds, shape.bounding_box(self)))
+    }
+
+    /// Returns the union of the bounding boxes of the top-level shapes at `indices`.
+    /// Indices past the end are ignored.
+    pub fn bounds_of(&self, indices: &[usize]) -> Option<(Point, Point)> {
+        indices.iter()
+            .filter_map(|index| self.shapes.get(*index))
+            .fold(None, |bounds, shape| union_bounds(bounds, shape.bounding_box(self)))
+    }
+
+    /// Returns the canvas `[0, width] × [0, height]` grown to include the bounding box of
+    /// all shapes.
+    pub fn expanded_canvas(&self) -> (Point, Point) {
+        let canvas = (Point { x: 0.0, y: 0.0 }, Point { x: self.width, y: self.height });
+        union_bounds(Some(canvas), self.bounding_box()).unwrap_or(canvas)
+    }
+
+    /// Returns problems that do not prevent rendering but likely differ from what the author
+    /// intended.
+    pub fn warnings(&self) -> Vec<ValidationWarning> {
+        let mut warnings = Vec::new();
+
+        for (index, shape) in self.shapes.iter().enumerate() {
+            if let Some((min, max)) = shape.bounding_box(self)
+                && (min.x < 0.0 || min.y < 0.0 || max.x > self.width || max.y > self.height) {
+                warnings.push(ValidationWarning::OffCanvas { index });
+            }
+
+            let mut shapes = Shapes { stack: vec![std::slice::from_ref(shape).iter()] };
+            let zero_area = shapes.any(|shape| match shape {
+                Shape::Region(region) => !region.data.is_empty() && region.data.iter().all(CurveData::is_zero_area),
+                _ => false
+            });
+
+            if zero_area {
+                warnings.push(ValidationWarning::ZeroArea { index });
+            }
+        }
+
+        let is_uniform_gradient = |pattern: &Pattern| !matches!(pattern, Pattern::Monochrome(_))
+            && pattern.uniform_color().is_some();
+
+        for (index, pen) in self.pens.iter().enumerate() {
+            if is_uniform_gradient(&pen.pattern) {
+                warnings.push(ValidationWarning::UniformPenGradient { index });
+            }
+        }
+
+        for (index, brush) in self.brushes.iter().enumerate() {
+            if is_uniform_gradient(&brush.pattern) {
+                warnings.push(ValidationWarning::UniformBrushGradient { index });
+            }
+        }
+
+        warnings
+    }
+
+    /// Compares the shapes with those of `reference` and returns the largest relative error of
+    /// each top-level shape, as measured by `Relative`. A shape without a counterpart in
+    /// `reference`, or one whose pen, brush or kind differs, has an infinite error.
+    pub fn validate_against(&self, reference: &Image) -> Vec<f64> {
+        use crate::geometry::Relative;
+
+        self.shapes.iter()
+            .enumerate()
+            .map(|(index, shape)| match reference.shapes.get(index) {
+                Some(expect) => shape.relative_error_from(expect),
+                None => f64::INFINITY
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ValidationWarning {
+    /// The top-level shape at `index` extends beyond the canvas and is clipped.
+    OffCanvas { index: usize },
+    /// The top-level shape at `index` contains a region whose sub-paths enclose no area, so
+    /// only its stroke is drawn.
+    ZeroArea { index: usize },
+    /// The pen at `index` has a gradient that paints a single color, which a monochrome
+    /// pattern would paint more cheaply.
+    UniformPenGradient { index: usize },
+    /// The brush at `index` has a gradient that paints a single color.
+    UniformBrushGradient { index: usize }
+}
+
+impl fmt::Display for ValidationWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationWarning::OffCanvas { index } => write!(f, "shape {} extends beyond the canvas", index),
+            ValidationWarning::ZeroArea { index } => write!(f, "shape {} has a region with zero area", index),
+            ValidationWarning::UniformPenGradient { index } =>
+                write!(f, "pen {} has a gradient of a single color; use a monochrome pattern", index),
+            ValidationWarning::UniformBrushGradient { index } =>
+                write!(f, "brush {} has a gradient of a single color; use a monochrome pattern", index)
+        }
+    }
+}
+
+/// A reference to a pen or brush that does not exist. `path` holds the index of the top-level
+/// shape followed by the indices within each enclosing group.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum ValidationError {
+    PenOutOfRange { path: Vec<usize>, index: usize, count: usize },
+    BrushOutOfRange { path: Vec<usize>, index: usize, count: usize }
+}
+
+impl ValidationError {
+    /// Returns the JSON Pointer of the offending shape, such as `/shapes/2/content/0`.
+    pub fn pointer(&self) -> String {
+        let (ValidationError::PenOutOfRange { path, .. } | ValidationError::BrushOutOfRange { path, .. }) = self;
+        let mut pointer = String::from("/shapes");
+
+        for (depth, index) in path.iter().enumerate() {
+            if depth > 0 {
+                pointer.push_str("/content");
+            }
+
+            pointer.push_str(&format!("/{}", index));
+        }
+
+        pointer
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationError::PenOutOfRange { index, count, .. } =>
+                write!(f, "{} refers to pen {}, but there are {} pens", self.pointer(), index, count),
+            ValidationError::BrushOutOfRange { index, count, .. } =>
+                write!(f, "{} refers to brush {}, but there are {} brushes", self.pointer(), index, count)
+        }
+    }
+}
+
+impl error::Error for ValidationError {}
+
+impl Image {
+    /// Checks that every pen and brush index of every shape, including those inside groups,
+    /// is in range, so that the image can be rendered without panicking. Stops at the first
+    /// bad reference.
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        let mut path = Vec::new();
+        self.check_shape_references(&self.shapes, &mut path)
+    }
+
+    /// Like `validate`, but checks only `shape`, as if it were the top-level shape at `index`.
+    pub fn validate_shape(&self, index: usize, shape: &Shape) -> Result<(), ValidationError> {
+        let mut path = Vec::new();
+
+        self.check_shape_references(std::slice::from_ref(shape), &mut path).map_err(|mut e| {
+            let (ValidationError::PenOutOfRange { path, .. } | ValidationError::BrushOutOfRange { path, .. }) = &mut e;
+            path[0] = index;
+            e
+        })
+    }
+
+    fn check_shape_references(&self, shapes: &[Shape], path: &mut Vec<usize>) -> Result<(), ValidationError> {
+        for (i, shape) in shapes.iter().enumerate() {
+            path.push(i);
+
+            let (pen, brush) = match shape {
+                Shape::Group(group) => {
+                    self.check_shape_references(&group.content, path)?;
+                    (None, None)
+                },
+                Shape::Curve(curve) => (Some(curve.pen), None),
+                Shape::Region(region) => (region.pen, region.brush)
+            };
+
+            if let Some(index) = pen.filter(|pen| *pen >= self.pens.len()) {
+                return Err(ValidationError::PenOutOfRange { path: path.clone(), index, count: self.pens.len() });
+            }
+
+            if let Some(index) = brush.filter(|brush| *brush >= self.brushes.len()) {
+                return Err(ValidationError::BrushOutOfRange { path: path.clone(), index, count: self.brushes.len() });
+            }
+
+            path.pop();
+        }
+
+        Ok(())
+    }
+}
+
+impl Shape {
+    /// Returns the bounding box of the shape in image units, including the stroke width of
+    /// its pen. See `Image::bounding_box`. The box of a transformed group encloses the
+    /// transformed box of its children, so it may be loose for rotations.
+    pub fn bounding_box(&self, image: &Image) -> Option<(Point, Point)> {
+        let half_width = |pen: usize| image.pens.get(pen).map_or(0.0, |pen| pen.width / 2.0);
+
+        match self {
+            Shape::Group(group) => {
+                let bounds = group.content.iter()
+                    .fold(None, |bounds, child| union_bounds(bounds, child.bounding_box(image)));
+
+                match (bounds, group.transform) {
+                    (Some((min, max)), Some(transform)) => [
+                        Point { x: min.x, y: min.y },
+                        Point { x: max.x, y: min.y },
+                        Point { x: min.x, y: max.y },
+                        Point { x: max.x, y: max.y }
+                    ]
+                        .into_iter()
+                        .map(|p| Some((apply_transform(&transform, p), apply_transform(&transform, p))))
+                        .fold(None, union_bounds),
+                    (bounds, _) => bounds
+                }
+            },
+            Shape::Curve(curve) =>
+                Some(inflate_bounds(curve.data.bounding_box(), half_width(curve.pen))),
+            Shape::Region(region) => region.bounding_box(image)
+        }
+    }
+
+    fn map_points(&mut self, f: &mut dyn FnMut(Point) -> Point) {
+        match self {
+            Shape::Group(group) => {
+                match group.transform.and_then(|transform| Some((transform, invert_transform(&transform)?))) {
+                    Some((transform, inverse)) => {
+                        let mut local = |p| apply_transform(&inverse, f(apply_transform(&transform, p)));
+
+                        for child in group.content.iter_mut() {
+                            child.map_points(&mut local);
+                        }
+                    },
+                    None => {
+                        for child in group.content.iter_mut() {
+                            child.map_points(f);
+                        }
+                    }
+                }
+            },
+            Shape::Curve(curve) => curve.data.map_points(f),
+            Shape::Region(region) => {
+                region.pattern_origin = region.pattern_origin.map(&mut *f);
+
+                for data in region.data.iter_mut() {
+                    data.map_points(&mut *f);
+                }
+            }
+        }
+    }
+}
+
+/// The original index of a pen or brush and the group transform it is used under.
+type TransformUse = (usize, Option<[f64; 6]>);
+
+/// Makes `index` refer to a pen or brush used only under `transform`, copying the original
+/// when it is already used under a different one. `uses` holds the original index and
+/// transform of each item, and `count` the number of items before any copies were made.
+/// Items whose `pattern` has no points are shared by every use, since mapping leaves them as
+/// they are.
+fn assign_transform<T: Clone>(items: &mut Vec<T>, uses: &mut Vec<Option<TransformUse>>,
+                              count: usize, index: &mut usize, transform: Option<[f64; 6]>,
+                              pattern: fn(&T) -> &Pattern) {
+    if *index >= count || !has_points(pattern(&items[*index])) {
+        return;
+    }
+
+    let original = *index;
+
+    match uses.iter().position(|u| *u == Some((original, transform))) {
+        Some(i) => *index = i,
+        None if uses[original].is_none() => uses[original] = Some((original, transform)),
+        None => {
+            items.push(items[original].clone());
+            uses.push(Some((original, transform)));
+            *index = items.len() - 1;
+        }
+    }
+}
+
+/// Returns whether `Pattern::map_points` can change `pattern`.
+fn has_points(pattern: &Pattern) -> bool {
+    match pattern {
+        Pattern::Monochrome(_) => false,
+        Pattern::Image(pat) => pat.origin.is_some(),
+        Pattern::LinearGradient(_) | Pattern::RadialGradient(_) | Pattern::ConicGradient(_) => true
+    }
+}
+
+/// Returns `pattern` with `f` applied to its points as seen through `transform`.
+fn map_pattern_points(pattern: &Pattern, transform: Option<[f64; 6]>, f: &mut dyn FnMut(Point) -> Point) -> Pattern {
+    match transform.and_then(|transform| Some((transform, invert_transform(&transform)?))) {
+        Some((transform, inverse)) =>
+            pattern.map_points(|p| apply_transform(&inverse, f(apply_transform(&transform, p)))),
+        None => pattern.map_points(f)
+    }
+}
+
+impl RegionShape {
+    /// Returns the bounding box of the region including the stroke width of its pen. See
+    /// `Image::bounding_box`.
+    pub fn bounding_box(&self, image: &Image) -> Option<(Point, Point)> {
+        let half_width = self.pen
+            .and_then(|pen| image.pens.get(pen))
+            .map_or(0.0, |pen| pen.width / 2.0);
+
+        self.data.iter()
+            .fold(None, |bounds, data| union_bounds(bounds, Some(data.bounding_box())))
+            .map(|bounds| inflate_bounds(bounds, half_width))
+    }
+
+    /// Appends a line back to the start of each sub-path whose end differs from its start,
+    /// so that the closing edge is explicit in the data.
+    pub fn close_subpaths(&mut self) {
+        for data in self.data.iter_mut() {
+            data.close();
+        }
+    }
+
+    /// Reverses sub-paths so that outer boundaries have a positive signed area and holes a
+    /// negative one. With the y axis pointing down, as the image is displayed, positive means
+    /// clockwise on screen.
+    ///
+    /// A sub-path is taken to be a hole when its start point lies inside an odd number of the
+    /// other sub-paths, so islands inside holes count as outer boundaries again. Sub-paths that
+    /// cross each other are not detected and may be classified arbitrarily.
+    pub fn normalize_orientation(&mut self) {
+        let polygons: Vec<Vec<Point>> = self.data.iter()
+            .map(|data| data.sample_points(ORIENTATION_STEPS))
+            .collect();
+
+        for (i, data) in self.data.iter_mut().enumerate() {
+            let depth = polygons.iter()
+                .enumerate()
+                .filter(|(j, polygon)| *j != i && polygon_contains(polygon, data.start))
+                .count();
+            let area = signed_area(&polygons[i]);
+
+            if (depth % 2 == 0 && area < 0.0) || (depth % 2 == 1 && area > 0.0) {
+                data.reverse();
+            }
+        }
+    }
+
+    /// Returns the winding number of the sub-paths around `p`, each implicitly closed and
+    /// flattened into a polygon. A sub-path with a positive signed area, clockwise on screen,
+    /// counts +1 for each turn and one with a negative area -1, so `p` is inside the region
+    /// when the result is nonzero or odd, according to the fill rule.
+    pub fn winding_number(&self, p: Point) -> i32 {
+        self.data.iter()
+            .map(|data| polygon_winding_number(&data.sample_points(WINDING_STEPS), p))
+            .sum()
+    }
+}
+
+/// Number of points sampled from each Bezier segment when `RegionShape::normalize_orientation`
+/// approximates sub-paths with polygons.
+const ORIENTATION_STEPS: usize = 8;
+
+/// Number of points sampled from each Bezier segment by `RegionShape::winding_number`.
+const WINDING_STEPS: usize = 32;
+
+/// Returns the signed area of the implicitly closed polygon by the shoelace formula.
+fn signed_area(polygon: &[Point]) -> f64 {
+    let next = polygon.iter().cycle().skip(1);
+
+    polygon.iter()
+        .zip(next)
+        .map(|(p, q)| p.x * q.y - q.x * p.y)
+        .sum::<f64>() / 2.0
+}
+
+/// Tests whether `point` lies inside the implicitly closed polygon by the even-odd rule.
+fn polygon_contains(polygon: &[Point], point: Point) -> bool {
+    let next = polygon.iter().cycle().skip(1);
+
+    polygon.iter()
+        .zip(next)
+        .filter(|(p, q)| (p.y > point.y) != (q.y > point.y)
+            && point.x < p.x + (point.y - p.y) * (q.x - p.x) / (q.y - p.y))
+        .count() % 2 == 1
+}
+
+/// Returns the winding number of the implicitly closed polygon around `point`, with the same
+/// sign as its signed area.
+fn polygon_winding_number(polygon: &[Point], point: Point) -> i32 {
+    let next = polygon.iter().cycle().skip(1);
+    // Positive when `point` lies to the left of the edge from p to q, taking the y axis up.
+    let side = |p: &Point, q: &Point| (q.x - p.x) * (point.y - p.y) - (point.x - p.x) * (q.y - p.y);
+
+    polygon.iter()
+        .zip(next)
+        .map(|(p, q)| {
+            if p.y <= point.y && q.y > point.y && side(p, q) > 0.0 {
+                1
+            } else if p.y > point.y && q.y <= point.y && side(p, q) < 0.0 {
+                -1
+            } else {
+                0
+            }
+        })
+        .sum()
+}
+
+impl GroupShape {
+    /// Splits the children into those matching `pred` and the rest, preserving their order.
+    /// Both groups keep the other attributes of the original.
+    pub fn partition<F: Fn(&Shape) -> bool>(&self, pred: F) -> (GroupShape, GroupShape) {
+        let (matched, rest): (Vec<Shape>, Vec<Shape>) = self.content.iter()
+            .cloned()
+            .partition(|shape| pred(shape));
+
+        (
+            GroupShape { content: matched, ..self.clone_empty() },
+            GroupShape { content: rest, ..self.clone_empty() }
+        )
+    }
+
+    fn clone_empty(&self) -> GroupShape {
+        GroupShape {
+            id: self.id.clone(),
+            opacity: self.opacity,
+            operator: self.operator,
+            transform: self.transform,
+            content: Vec::new(),
+            edit_annot: self.edit_annot.clone()
+        }
+    }
+}
+
+/// Iterates over shapes and their descendants in pre-order.
+struct Shapes<'a> {
+    stack: Vec<std::slice::Iter<'a, Shape>>
+}
+
+impl<'a> Iterator for Shapes<'a> {
+    type Item = &'a Shape;
+
+    fn next(&mut self) -> Option<&'a Shape> {
+        loop {
+            let top = self.stack.last_mut()?;
+
+            match top.next() {
+                Some(shape) => {
+                    if let Shape::Group(group) = shape {
+                        self.stack.push(group.content.iter());
+                    }
+
+                    return Some(shape);
+                },
+                None => {
+                    self.stack.pop();
+                }
+            }
+        }
+    }
+}
+
+impl Image {
+    /// Returns every shape, including groups and the shapes inside them, in pre-order.
+    pub fn iter_shapes(&self) -> impl Iterator<Item = &Shape> {
+        Shapes { stack: vec![self.shapes.iter()] }
+    }
+
+    /// Returns every curve and region with the groups around them expanded, in rendering
+    /// order. The opacity, operators and transforms of the groups are not applied.
+    pub fn iter_flattened(&self) -> impl Iterator<Item = &Shape> {
+        self.iter_shapes().filter(|shape| !matches!(shape, Shape::Group(_)))
+    }
+
+    /// Returns the data of every curve and every sub-path of every region, including those
+    /// inside groups, in rendering order.
+    pub fn iter_curve_data(&self) -> impl Iterator<Item = &CurveData> {
+        self.iter_flattened().flat_map(|shape| match shape {
+            Shape::Group(_) => &[],
+            Shape::Curve(curve) => std::slice::from_ref(&curve.data),
+            Shape::Region(region) => region.data.as_slice()
+        })
+    }
+}
+
+/// A single drawing operation with its pens and brushes resolved.
+#[derive(Clone, PartialEq, Debug)]
+#[allow(clippy::large_enum_variant)]
+pub enum DrawOp {
+    Curve {
+        pen: Pen,
+        data: CurveData
+    },
+    Region {
+        pen: Option<Pen>,
+        brush: Option<Brush>,
+        fill_opacity: Option<f64>,
+        stroke_opacity: Option<f64>,
+        pattern_origin: Option<Point>,
+        fill_rule: Option<FillRule>,
+        data: Vec<CurveData>
+    }
+}
+
+impl Image {
+    /// Flattens the shapes into drawing operations in rendering order. The opacity and
+    /// operators of groups are not represented, and their transforms apply to the points of
+    /// the curve data and the pattern origins only.
+    ///
+    /// Panics if a shape refers to a pen or a brush that does not exist.
+    pub fn to_draw_list(&self) -> Vec<DrawOp> {
+        let mut ops = Vec::new();
+
+        for shape in self.shapes.iter() {
+            self.push_draw_ops(&mut ops, shape, None);
+        }
+
+        ops
+    }
+
+    fn resolve_pen(&self, pen: usize) -> Pen {
+        let pen = &self.pens[pen];
+
+        Pen {
+            pattern: self.straight_pattern(&pen.pattern),
+            cap: Some(self.line_cap(pen)),
+            join: Some(self.line_join(pen)),
+            ..pen.clone()
+        }
+    }
+
+    fn resolve_brush(&self, brush: usize) -> Brush {
+        let brush = &self.brushes[brush];
+
+        Brush { pattern: self.straight_pattern(&brush.pattern), name: brush.name.clone() }
+    }
+
+    fn push_draw_ops(&self, ops: &mut Vec<DrawOp>, shape: &Shape, transform: Option<[f64; 6]>) {
+        let transformed = |data: &CurveData| {
+            let mut data = data.clone();
+
+            if let Some(transform) = transform {
+                data.map_points(|p| apply_transform(&transform, p));
+            }
+
+            data
+        };
+
+        match shape {
+            Shape::Group(group) => {
+                let transform = match (transform, group.transform) {
+                    (Some(outer), Some(inner)) => Some(compose_transforms(&outer, &inner)),
+                    (outer, inner) => outer.or(inner)
+                };
+
+                for child in group.content.iter() {
+                    self.push_draw_ops(ops, child, transform);
+                }
+            },
+            Shape::Curve(curve) => {
+                ops.push(DrawOp::Curve {
+                    pen: self.resolve_pen(curve.pen),
+                    data: transformed(&curve.data)
+                });
+            },
+            Shape::Region(region) => {
+                ops.push(DrawOp::Region {
+                    pen: region.pen.map(|pen| self.resolve_pen(pen)),
+                    brush: region.brush.map(|brush| self.resolve_brush(brush)),
+                    fill_opacity: region.fill_opacity,
+                    stroke_opacity: region.stroke_opacity,
+                    pattern_origin: region.pattern_origin
+                        .map(|p| transform.map_or(p, |transform| apply_transform(&transform, p))),
+                    fill_rule: region.fill_rule,
+                    data: region.data.iter().map(transformed).collect()
+                });
+            }
+        }
+    }
+}
+
+fn index_of<T: PartialEq + Clone>(items: &mut Vec<T>, item: &T) -> usize {
+    match items.iter().position(|i| i == item) {
+        Some(index) => index,
+        None => {
+            items.push(item.clone());
+            items.len() - 1
+        }
+    }
+}
+
+/// Builds an image from drawing operations, sharing identical pens and brushes.
+pub fn from_draw_list(ops: &[DrawOp], width: f64, height: f64, unit_per_inch: f64) -> Image {
+    let mut pens = Vec::new();
+    let mut brushes = Vec::new();
+    let mut shapes = Vec::new();
+
+    for op in ops.iter() {
+        match op {
+            DrawOp::Curve { pen, data } => {
+                shapes.push(Shape::Curve(CurveShape {
+                    id: None,
+                    pen: index_of(&mut pens, pen),
+                    data: data.clone()
+                }));
+            },
+            DrawOp::Region { pen, brush, fill_opacity, stroke_opacity, pattern_origin, fill_rule, data } => {
+                shapes.push(Shape::Region(RegionShape {
+                    id: None,
+                    pen: pen.as_ref().map(|pen| index_of(&mut pens, pen)),
+                    brush: brush.as_ref().map(|brush| index_of(&mut brushes, brush)),
+                    fill_opacity: *fill_opacity,
+                    stroke_opacity: *stroke_opacity,
+                    pattern_origin: *pattern_origin,
+                    fill_rule: *fill_rule,
+                    data: data.clone()
+                }));
+            }
+        }
+    }
+
+    Image { pens, brushes, shapes, ..Image::new(width, height, unit_per_inch) }
+}
+
+impl Image {
+    /// Returns an image of the given size with no pens, brushes or shapes.
+    pub fn new(width: f64, height: f64, unit_per_inch: f64) -> Image {
+        Image {
+            width,
+            height,
+            unit_per_inch,
+            editor: None,
+            default_cap: None,
+            default_join: None,
+            alpha_mode: None,
+            pens: Vec::new(),
+            brushes: Vec::new(),
+            shapes: Vec::new()
+        }
+    }
+
+    /// Appends `pen` and returns its index.
+    pub fn add_pen(&mut self, pen: Pen) -> usize {
+        self.pens.push(pen);
+        self.pens.len() - 1
+    }
+
+    /// Appends `brush` and returns its index.
+    pub fn add_brush(&mut self, brush: Brush) -> usize {
+        self.brushes.push(brush);
+        self.brushes.len() - 1
+    }
+
+    /// Appends `shape` at the top and returns its index.
+    pub fn add_shape(&mut self, shape: Shape) -> usize {
+        self.shapes.push(shape);
+        self.shapes.len() - 1
+    }
+
+    /// Like `add_pen`, but takes and returns the image for chaining.
+    pub fn with_pen(mut self, pen: Pen) -> (usize, Image) {
+        (self.add_pen(pen), self)
+    }
+
+    /// Like `add_brush`, but takes and returns the image for chaining.
+    pub fn with_brush(mut self, brush: Brush) -> (usize, Image) {
+        (self.add_brush(brush), self)
+    }
+
+    /// Like `add_shape`, but takes and returns the image for chaining.
+    pub fn with_shape(mut self, shape: Shape) -> Image {
+        self.add_shape(shape);
+        self
+    }
+}
+
+impl Pen {
+    /// Returns a pen with the defaults of the image for everything but `pattern` and `width`.
+    pub fn new(pattern: Pattern, width: f64) -> Pen {
+        Pen {
+            pattern,
+            width,
+            cap: None,
+            join: None,
+            miter_limit: None,
+            dash: None,
+            dash_mode: None,
+            align: None,
+            name: None
+        }
+    }
+}
+
+impl Brush {
+    pub fn new(pattern: Pattern) -> Brush {
+        Brush { pattern, name: None }
+    }
+}
+
+impl Shape {
+    pub fn group(content: Vec<Shape>) -> Shape {
+        Shape::Group(GroupShape {
+            id: None,
+            opacity: None,
+            operator: None,
+            transform: None,
+            content,
+            edit_annot: serde_json::Value::Null
+        })
+    }
+
+    pub fn curve(pen: usize, data: CurveData) -> Shape {
+        Shape::Curve(CurveShape { id: None, pen, data })
+    }
+
+    /// Returns a region filled with `brush` and stroked with `pen`, if any.
+    pub fn region(pen: Option<usize>, brush: Option<usize>, data: Vec<CurveData>) -> Shape {
+        Shape::Region(RegionShape {
+            id: None,
+            pen,
+            brush,
+            fill_opacity: None,
+            stroke_opacity: None,
+            pattern_origin: None,
+            fill_rule: None,
+            data
+        })
+    }
+}
+
+impl Segment {
+    pub fn end_point(&self) -> Point {
+        match self {
+            Segment::Line(s) => s.point_2,
+            Segment::QuadraticBezier(s) => s.point_3,
+            Segment::CubicBezier(s) => s.point_4,
+            Segment::SmoothQuadraticBezier(s) => s.point_3,
+            Segment::SmoothCubicBezier(s) => s.point_4,
+            Segment::Arc(s) => s.point_2
+        }
+    }
+
+    /// Returns the points that define the segment after its start point. Smooth segments
+    /// leave out their reflected control point and arcs give their end point only; see
+    /// `CurveData::expand` for their Bezier control points.
+    pub fn control_points(&self) -> Vec<Point> {
+        match self {
+            Segment::Line(s) => vec![s.point_2],
+            Segment::QuadraticBezier(s) => vec![s.point_2, s.point_3],
+            Segment::CubicBezier(s) => vec![s.point_2, s.point_3, s.point_4],
+            Segment::SmoothQuadraticBezier(s) => vec![s.point_3],
+            Segment::SmoothCubicBezier(s) => vec![s.point_3, s.point_4],
+            Segment::Arc(s) => vec![s.point_2]
+        }
+    }
+
+    /// Returns whether the segment takes a control point from the segment before it.
+    pub fn is_smooth(&self) -> bool {
+        matches!(self, Segment::SmoothQuadraticBezier(_) | Segment::SmoothCubicBezier(_))
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct CurveData {
+    pub start: Point,
+    pub segments: Vec<Segment>
+}
+
+struct CurveDataVisitor;
+
+impl<'de> Visitor<'de> for CurveDataVisitor {
+    type Value = CurveData;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str("curve data")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<CurveData, A::Error>
+    where
+        A: SeqAccess<'de>
+    {
+        let start = seq.next_element::<Point>()?
+            .ok_or_else(|| serde::de::Error::invalid_length(0, &self))?;
+
+        let mut segments = vec![];
+
+        while let Some(seg) = seq.next_element::<Segment>()? {
+            segments.push(seg);
+        }
+
+        Ok(CurveData { start, segments })
+    }
+}
+
+impl<'de> Deserialize<'de> for CurveData {
+    fn deserialize<D>(deserializer: D) -> Result<CurveData, D::Error>
+    where
+        D: Deserializer<'de>
+    {
+        deserializer.deserialize_seq(CurveDataVisitor)
+    }
+}
+
+impl Serialize for CurveData {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer
+    {
+        let mut seq = serializer.serialize_seq(None)?;
+        seq.serialize_element(&self.start)?;
+
+        for seg in self.segments.iter() {
+            seq.serialize_element(&seg)?;
+        }
+
+        seq.end()
+    }
+}
+
+impl CurveData {
+    /// Returns lines through `points` in order, starting at the first.
+    ///
+    /// Panics if `points` is empty.
+    pub fn polyline(points: &[Point]) -> CurveData {
+        let (start, rest) = points.split_first().expect("a polyline needs at least one point");
+
+        CurveData {
+            start: *start,
+            segments: rest.iter().map(|&point_2| Segment::Line(LineSegment { point_2 })).collect()
+        }
+    }
+
+    /// Returns the outline of the polygon with vertices `points`, for a sub-path of a region.
+    /// Like `polyline`, but a last point equal to the first is dropped, since regions are
+    /// closed implicitly.
+    ///
+    /// Panics if `points` is empty.
+    pub fn polygon(points: &[Point]) -> CurveData {
+        match points {
+            [first, rest @ .., last] if first == last => CurveData::polyline(&points[..rest.len() + 1]),
+            _ => CurveData::polyline(points)
+        }
+    }
+
+    /// Returns the outline of the rectangle with the corner `(x, y)` and the size `w` by `h`,
+    /// running through the other corners and back with four lines.
+    pub fn rect(x: f64, y: f64, w: f64, h: f64) -> CurveData {
+        let mut rect = CurveData::polyline(&[
+            Point { x, y },
+            Point { x: x + w, y },
+            Point { x: x + w, y: y + h },
+            Point { x, y: y + h }
+        ]);
+        rect.close();
+        rect
+    }
+
+    /// Like `rect`, but with each corner cut by a quadratic Bezier segment controlled by the
+    /// corner, `radius` away from it along both sides, giving eight segments. The radius is
+    /// limited to half the shorter side.
+    pub fn rounded_rect(x: f64, y: f64, w: f64, h: f64, radius: f64) -> CurveData {
+        let r = radius.max(0.0).min(w.abs() / 2.0).min(h.abs() / 2.0);
+        let (rx, ry) = (r * w.signum(), r * h.signum());
+        let corners = [
+            Point { x: x + w, y },
+            Point { x: x + w, y: y + h },
+            Point { x, y: y + h },
+            Point { x, y }
+        ];
+        // Where each side leaves its first corner and enters the next one.
+        let sides = [
+            (Point { x: x + rx, y }, Point { x: x + w - rx, y }),
+            (Point { x: x + w, y: y + ry }, Point { x: x + w, y: y + h - ry }),
+            (Point { x: x + w - rx, y: y + h }, Point { x: x + rx, y: y + h }),
+            (Point { x, y: y + h - ry }, Point { x, y: y + ry })
+        ];
+
+        let segments = (0..4)
+            .flat_map(|i| [
+                Segment::Line(LineSegment { point_2: sides[i].1 }),
+                Segment::QuadraticBezier(QuadraticBezierSegment { point_2: corners[i], point_3: sides[(i + 1) % 4].0 })
+            ])
+            .collect();
+
+        CurveData { start: sides[0].0, segments }
+    }
+
+    /// Returns an approximation of the ellipse with radii `rx` and `ry` along the axes by
+    /// four cubic Bezier segments, one for each quadrant. It starts at the rightmost point and
+    /// turns toward positive y, clockwise on screen.
+    pub fn ellipse(center: Point, rx: f64, ry: f64) -> CurveData {
+        let (kx, ky) = (rx * ELLIPSE_KAPPA, ry * ELLIPSE_KAPPA);
+        let p = |dx: f64, dy: f64| Point { x: center.x + dx, y: center.y + dy };
+        let quadrant = |point_2, point_3, point_4| Segment::CubicBezier(CubicBezierSegment { point_2, point_3, point_4 });
+
+        CurveData {
+            start: p(rx, 0.0),
+            segments: vec![
+                quadrant(p(rx, ky), p(kx, ry), p(0.0, ry)),
+                quadrant(p(-kx, ry), p(-rx, ky), p(-rx, 0.0)),
+                quadrant(p(-rx, -ky), p(-kx, -ry), p(0.0, -ry)),
+                quadrant(p(kx, -ry), p(rx, -ky), p(rx, 0.0))
+            ]
+        }
+    }
+
+    /// Returns an approximation of the circle with radius `r`, as `ellipse` does.
+    pub fn circle(center: Point, r: f64) -> CurveData {
+        CurveData::ellipse(center, r, r)
+    }
+
+    /// Appends a line back to the start if the sub-path ends elsewhere.
+    fn close(&mut self) {
+        if self.end_point() != self.start {
+            self.segments.push(Segment::Line(LineSegment { point_2: self.start }));
+        }
+    }
+
+    pub fn end_point(&self) -> Point {
+        self.segments.last().map_or(self.start, Segment::end_point)
+    }
+
+    /// Returns the sub-path with only lines and plain Bezier segments. Smooth segments get
+    /// the reflection of the previous control point, or the current point when the previous
+    /// segment is not a Bezier segment of the same degree, as in SVG. Arcs are replaced by the
+    /// segments of `ArcSegment::to_beziers`.
+    pub fn expand(&self) -> CurveData {
+        let mut segments = Vec::with_capacity(self.segments.len());
+        let mut quadratic_control = None;
+        let mut cubic_control = None;
+
+        for (start, seg) in self.iter_with_start() {
+            let reflect = |control: Option<Point>| control.map_or(start, |c: Point| Point {
+                x: 2.0 * start.x - c.x,
+                y: 2.0 * start.y - c.y
+            });
+
+            (quadratic_control, cubic_control) = match seg {
+                Segment::QuadraticBezier(bezier) => {
+                    segments.push(*seg);
+                    (Some(bezier.point_2), None)
+                },
+                Segment::CubicBezier(bezier) => {
+                    segments.push(*seg);
+                    (None, Some(bezier.point_3))
+                },
+                Segment::SmoothQuadraticBezier(bezier) => {
+                    let point_2 = reflect(quadratic_control);
+                    segments.push(Segment::QuadraticBezier(QuadraticBezierSegment { point_2, point_3: bezier.point_3 }));
+                    (Some(point_2), None)
+                },
+                Segment::SmoothCubicBezier(bezier) => {
+                    let point_2 = reflect(cubic_control);
+                    segments.push(Segment::CubicBezier(CubicBezierSegment { point_2, point_3: bezier.point_3, point_4: bezier.point_4 }));
+                    (None, Some(bezier.point_3))
+                },
+                Segment::Arc(arc) => {
+                    segments.extend(arc.to_beziers(start));
+                    (None, None)
+                },
+                Segment::Line(_) => {
+                    segments.push(*seg);
+                    (None, None)
+                }
+            };
+        }
+
+        CurveData { start: self.start, segments }
+    }
+
+    /// Returns the minimum and maximum corners of the control-point hull, taking arcs by
+    /// their Bezier approximation.
+    pub fn bounding_box(&self) -> (Point, Point) {
+        let mut min = self.start;
+        let mut max = self.start;
+
+        for p in self.expand().segments.iter().flat_map(Segment::control_points) {
+            min = Point { x: min.x.min(p.x), y: min.y.min(p.y) };
+            max = Point { x: max.x.max(p.x), y: max.y.max(p.y) };
+        }
+
+        (min, max)
+    }
+
+    /// Iterates over the segments together with the point each segment starts from.
+    pub fn iter_with_start(&self) -> impl Iterator<Item = (Point, &Segment)> {
+        self.segments.iter().scan(self.start, |current, seg| {
+            let start = *current;
+            *current = seg.end_point();
+            Some((start, seg))
+        })
+    }
+
+    /// Returns whether the sub-path encloses no area, that is, whether its start and control
+    /// points all lie within `ZERO_AREA_TOLERANCE` of a single line.
+    pub fn is_zero_area(&self) -> bool {
+        let points: Vec<Point> = std::iter::once(self.start)
+            .chain(self.expand().segments.iter().flat_map(Segment::control_points))
+            .collect();
+
+        let distance = |p: &Point| (p.x - self.start.x).hypot(p.y - self.start.y);
+        let far = points.iter().copied().max_by(|p, q| distance(p).total_cmp(&distance(q)));
+
+        match far {
+            Some(far) if distance(&far) > ZERO_AREA_TOLERANCE => {
+                let (dx, dy) = (far.x - self.start.x, far.y - self.start.y);
+                let length = dx.hypot(dy);
+
+                points.iter().all(|p| ((p.x - self.start.x) * dy - (p.y - self.start.y) * dx).abs() / length <= ZERO_AREA_TOLERANCE)
+            },
+            _ => true
+        }
+    }
+
+    /// Applies `f` to the start point and every control point. Arcs and smooth segments are
+    /// expanded first, since `f` need not map an ellipse to an ellipse nor keep reflections.
+    pub fn map_points<F: FnMut(Point) -> Point>(&mut self, mut f: F) {
+        if self.segments.iter().any(|seg| seg.is_smooth() || matches!(seg, Segment::Arc(_))) {
+            *self = self.expand();
+        }
+
+        self.start = f(self.start);
+
+        for seg in self.segments.iter_mut() {
+            match seg {
+                Segment::Line(line) => {
+                    line.point_2 = f(line.point_2);
+                },
+                Segment::QuadraticBezier(bezier) => {
+                    bezier.point_2 = f(bezier.point_2);
+                    bezier.point_3 = f(bezier.point_3);
+                },
+                Segment::CubicBezier(bezier) => {
+                    bezier.point_2 = f(bezier.point_2);
+                    bezier.point_3 = f(bezier.point_3);
+                    bezier.point_4 = f(bezier.point_4);
+                },
+                Segment::SmoothQuadraticBezier(_) | Segment::SmoothCubicBezier(_) | Segment::Arc(_) =>
+                    unreachable!("the sub-path is expanded")
+            }
+        }
+    }
+
+    /// Removes segments whose control points all coincide with their start point.
+    fn remove_degenerate(&mut self) {
+        let mut current = self.start;
+
+        self.segments.retain(|seg| {
+            let start = current;
+            current = seg.end_point();
+            seg.control_points().iter().any(|p| *p != start)
+        });
+    }
+
+    /// Reverses the direction of the sub-path, keeping the same outline. Smooth segments are
+    /// expanded first.
+    pub fn reverse(&mut self) {
+        if self.segments.iter().any(Segment::is_smooth) {
+            *self = self.expand();
+        }
+
+        let mut segments: Vec<Segment> = self.iter_with_start()
+            .map(|(start, seg)| match seg {
+                Segment::Line(_) => Segment::Line(LineSegment { point_2: start }),
+                Segment::QuadraticBezier(bezier) => Segment::QuadraticBezier(QuadraticBezierSegment {
+                    point_2: bezier.point_2,
+                    point_3: start
+                }),
+                Segment::CubicBezier(bezier) => Segment::CubicBezier(CubicBezierSegment {
+                    point_2: bezier.point_3,
+                    point_3: bezier.point_2,
+                    point_4: start
+                }),
+                Segment::Arc(arc) => Segment::Arc(ArcSegment {
+                    sweep: !arc.sweep,
+                    point_2: start,
+                    ..*arc
+                }),
+                Segment::SmoothQuadraticBezier(_) | Segment::SmoothCubicBezier(_) =>
+                    unreachable!("smooth segments are expanded")
+            })
+            .collect();
+        segments.reverse();
+
+        self.start = self.end_point();
+        self.segments = segments;
+    }
+
+    /// Approximates the sub-path by its start point and `steps` evenly spaced points on each
+    /// Bezier segment. Lines contribute their end points only.
+    fn sample_points(&self, steps: usize) -> Vec<Point> {
+        let mut points = vec![self.start];
+
+        for (start, seg) in self.expand().iter_with_start() {
+            match seg {
+                Segment::Line(line) => points.push(line.point_2),
+                Segment::QuadraticBezier(bezier) => {
+                    points.extend((1..=steps).map(|i| {
+                        let t = i as f64 / steps as f64;
+                        let (a, b, c) = ((1.0 - t) * (1.0 - t), 2.0 * t * (1.0 - t), t * t);
+                        Point {
+                            x: a * start.x + b * bezier.point_2.x + c * bezier.point_3.x,
+                            y: a * start.y + b * bezier.point_2.y + c * bezier.point_3.y
+                        }
+                    }));
+                },
+                Segment::CubicBezier(bezier) => {
+                    points.extend((1..=steps).map(|i| {
+                        let t = i as f64 / steps as f64;
+                        let u = 1.0 - t;
+                        let (a, b, c, d) = (u * u * u, 3.0 * t * u * u, 3.0 * t * t * u, t * t * t);
+                        Point {
+                            x: a * start.x + b * bezier.point_2.x + c * bezier.point_3.x + d * bezier.point_4.x,
+                            y: a * start.y + b * bezier.point_2.y + c * bezier.point_3.y + d * bezier.point_4.y
+                        }
+                    }));
+                },
+                Segment::SmoothQuadraticBezier(_) | Segment::SmoothCubicBezier(_) | Segment::Arc(_) =>
+                    unreachable!("the sub-path is expanded")
+            }
+        }
+
+        points
+    }
+
+    /// Approximates the sub-path by a polyline, starting with the start point, that lies
+    /// within `tolerance` of it. Bezier segments are halved until their control points lie
+    /// within `tolerance` of the chord, or `MAX_FLATTEN_DEPTH` times, so a zero tolerance or
+    /// coincident control points still terminate. Arcs are flattened through their Bezier
+    /// approximation.
+    pub fn flatten(&self, tolerance: f64) -> Vec<Point> {
+        let mut points = vec![self.start];
+
+        for (start, seg) in self.expand().iter_with_start() {
+            match seg {
+                Segment::Line(line) => points.push(line.point_2),
+                Segment::QuadraticBezier(bezier) =>
+                    flatten_quadratic([start, bezier.point_2, bezier.point_3], tolerance, MAX_FLATTEN_DEPTH, &mut points),
+                Segment::CubicBezier(bezier) =>
+                    flatten_cubic([start, bezier.point_2, bezier.point_3, bezier.point_4], tolerance, MAX_FLATTEN_DEPTH, &mut points),
+                Segment::SmoothQuadraticBezier(_) | Segment::SmoothCubicBezier(_) | Segment::Arc(_) =>
+                    unreachable!("the sub-path is expanded")
+            }
+        }
+
+        points
+    }
+
+    /// Returns the length of the sub-path, summing the lines of `flatten`. Lines count exactly;
+    /// Bezier segments and arcs are underestimated by an error that shrinks with `tolerance`.
+    /// The closing line of a region is not included.
+    pub fn length(&self, tolerance: f64) -> f64 {
+        polyline_length(&self.flatten(tolerance))
+    }
+
+    /// Merges runs of line segments whose intermediate points all lie within `tolerance` of
+    /// the line joining the ends of the run. Bezier segments are left as they are.
+    pub fn merge_collinear(&mut self, tolerance: f64) {
+        let mut merged: Vec<Segment> = Vec::with_capacity(self.segments.len());
+        let mut last_start = self.start;
+        // Points dropped from the run ending in the last merged segment.
+        let mut dropped: Vec<Point> = Vec::new();
+
+        for seg in std::mem::take(&mut self.segments) {
+            if let (Some(Segment::Line(last)), Segment::Line(line)) = (merged.last_mut(), &seg)
+                && std::iter::once(last.point_2).chain(dropped.iter().copied())
+                    .all(|p| distance_to_segment(p, last_start, line.point_2) <= tolerance) {
+                dropped.push(last.point_2);
+                last.point_2 = line.point_2;
+                continue;
+            }
+
+            last_start = merged.last().map_or(self.start, Segment::end_point);
+            dropped.clear();
+            merged.push(seg);
+        }
+
+        self.segments = merged;
+    }
+}
+
+/// Distance of the control points from the ends of a cubic Bezier segment approximating a
+/// quarter of a unit circle, 4/3 (sqrt(2) - 1), so that its midpoint lies on the circle.
+const ELLIPSE_KAPPA: f64 = 0.552_284_749_830_793_4;
+
+/// Distance in image units within which points count as collinear in `CurveData::is_zero_area`.
+pub const ZERO_AREA_TOLERANCE: f64 = 1e-9;
+
+/// Number of times `CurveData::flatten` halves a Bezier segment at most, which leaves at
+/// most 2^16 lines per segment.
+const MAX_FLATTEN_DEPTH: u32 = 16;
+
+fn polyline_length(points: &[Point]) -> f64 {
+    points.windows(2)
+        .map(|pair| (pair[1].x - pair[0].x).hypot(pair[1].y - pair[0].y))
+        .sum()
+}
+
+/// Memoizes `CurveData::flatten` for callers that query the same curves repeatedly, such as
+/// editors measuring or hit-testing shapes. Entries are keyed by the contents of the curve
+/// data and the tolerance, so an edited curve is flattened afresh; the cache never shrinks
+/// unless it is cleared.
+#[derive(Clone, Default, Debug)]
+pub struct FlattenCache {
+    polylines: HashMap<(Vec<u64>, u64), Vec<Point>>,
+    computed: usize
+}
+
+impl FlattenCache {
+    pub fn new() -> FlattenCache {
+        FlattenCache::default()
+    }
+
+    /// Returns `data.flatten(tolerance)`, computing it only if the same data has not been
+    /// flattened at the same tolerance before.
+    pub fn flatten(&mut self, data: &CurveData, tolerance: f64) -> &[Point] {
+        let key = (flatten_key(data), tolerance.to_bits());
+        let computed = &mut self.computed;
+
+        self.polylines.entry(key).or_insert_with(|| {
+            *computed += 1;
+            data.flatten(tolerance)
+        })
+    }
+
+    /// Returns `data.length(tolerance)` from the cached polyline.
+    pub fn length(&mut self, data: &CurveData, tolerance: f64) -> f64 {
+        polyline_length(self.flatten(data, tolerance))
+    }
+
+    /// Number of polylines computed rather than found in the cache.
+    pub fn computed(&self) -> usize {
+        self.computed
+    }
+
+    pub fn len(&self) -> usize {
+        self.polylines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.polylines.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.polylines.clear();
+    }
+}
+
+/// Returns the bit patterns of the start point and of every segment, each tagged with its
+/// kind, so that identical curve data give equal keys.
+fn flatten_key(data: &CurveData) -> Vec<u64> {
+    let mut key = vec![data.start.x.to_bits(), data.start.y.to_bits()];
+    let mut push = |tag: u64, values: &[f64]| {
+        key.push(tag);
+        key.extend(values.iter().map(|value| value.to_bits()));
+    };
+
+    for seg in data.segments.iter() {
+        match seg {
+            Segment::Line(line) =>
+                push(0, &[line.point_2.x, line.point_2.y]),
+            Segment::QuadraticBezier(bezier) =>
+                push(1, &[bezier.point_2.x, bezier.point_2.y, bezier.point_3.x, bezier.point_3.y]),
+            Segment::CubicBezier(bezier) =>
+                push(2, &[bezier.point_2.x, bezier.point_2.y, bezier.point_3.x, bezier.point_3.y, bezier.point_4.x, bezier.point_4.y]),
+            Segment::SmoothQuadraticBezier(bezier) =>
+                push(3, &[bezier.point_3.x, bezier.point_3.y]),
+            Segment::SmoothCubicBezier(bezier) =>
+                push(4, &[bezier.point_3.x, bezier.point_3.y, bezier.point_4.x, bezier.point_4.y]),
+            Segment::Arc(arc) => {
+                let flags = u8::from(arc.large_arc) * 2 + u8::from(arc.sweep);
+                push(5 + u64::from(flags), &[arc.radius_x, arc.radius_y, arc.rotation, arc.point_2.x, arc.point_2.y]);
+            }
+        }
+    }
+
+    key
+}
+
+fn midpoint(p: Point, q: Point) -> Point {
+    Point { x: (p.x + q.x) / 2.0, y: (p.y + q.y) / 2.0 }
+}
+
+/// Pushes the points after the first of a polyline approximating the quadratic Bezier curve.
+/// The curve lies in the hull of its control points, so it is flat enough once the control
+/// point is within `tolerance` of the chord.
+fn flatten_quadratic([p0, p1, p2]: [Point; 3], tolerance: f64, depth: u32, points: &mut Vec<Point>) {
+    if depth == 0 || distance_to_segment(p1, p0, p2) <= tolerance {
+        points.push(p2);
+        return;
+    }
+
+    let (q0, q1) = (midpoint(p0, p1), midpoint(p1, p2));
+    let mid = midpoint(q0, q1);
+
+    flatten_quadratic([p0, q0, mid], tolerance, depth - 1, points);
+    flatten_quadratic([mid, q1, p2], tolerance, depth - 1, points);
+}
+
+/// Pushes the points after the first of a polyline approximating the cubic Bezier curve, as
+/// `flatten_quadratic` does.
+fn flatten_cubic([p0, p1, p2, p3]: [Point; 4], tolerance: f64, depth: u32, points: &mut Vec<Point>) {
+    if depth == 0 || distance_to_segment(p1, p0, p3).max(distance_to_segment(p2, p0, p3)) <= tolerance {
+        points.push(p3);
+        return;
+    }
+
+    let (q0, q1, q2) = (midpoint(p0, p1), midpoint(p1, p2), midpoint(p2, p3));
+    let (r0, r1) = (midpoint(q0, q1), midpoint(q1, q2));
+    let mid = midpoint(r0, r1);
+
+    flatten_cubic([p0, q0, r0, mid], tolerance, depth - 1, points);
+    flatten_cubic([mid, r1, q2, p3], tolerance, depth - 1, points);
+}
+
+fn distance_to_segment(p: Point, a: Point, b: Point) -> f64 {
+    let (dx, dy) = (b.x - a.x, b.y - a.y);
+    let length_squared = dx * dx + dy * dy;
+
+    let t = if length_squared == 0.0 {
+        0.0
+    } else {
+        (((p.x - a.x) * dx + (p.y - a.y) * dy) / length_squared).clamp(0.0, 1.0)
+    };
+
+    (p.x - (a.x + t * dx)).hypot(p.y - (a.y + t * dy))
+}
+
+/// Error in SVG path data.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ParseError {
+    /// Byte offset in the path data at which the error was found.
+    pub position: usize,
+    pub message: &'static str
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position)
+    }
+}
+
+impl error::Error for ParseError {}
+
+struct PathParser<'a> {
+    data: &'a [u8],
+    position: usize
+}
+
+impl PathParser<'_> {
+    fn error(&self, message: &'static str) -> ParseError {
+        ParseError { position: self.position, message }
+    }
+
+    /// Skips whitespace and commas and returns the next byte.
+    fn peek(&mut self) -> Option<u8> {
+        while self.data.get(self.position).is_some_and(|c| c.is_ascii_whitespace() || *c == b',') {
+            self.position += 1;
+        }
+
+        self.data.get(self.position).copied()
+    }
+
+    fn is_digit(&self, position: usize) -> bool {
+        self.data.get(position).is_some_and(u8::is_ascii_digit)
+    }
+
+    /// Reads the longest number at the current position, so that `1.5.5` reads as `1.5` and
+    /// `.5`, and `1-2` as `1` and `-2`.
+    fn number(&mut self) -> Result<f64, ParseError> {
+        self.peek();
+
+        let start = self.position;
+        let mut end = start;
+
+        if matches!(self.data.get(end), Some(b'+' | b'-')) {
+            end += 1;
+        }
+
+        let digits_start = end;
+
+        while self.is_digit(end) {
+            end += 1;
+        }
+
+        let mut digits = end - digits_start;
+
+        if self.data.get(end) == Some(&b'.') {
+            end += 1;
+            let fraction_start = end;
+
+            while self.is_digit(end) {
+                end += 1;
+            }
+
+            digits += end - fraction_start;
+        }
+
+        if digits == 0 {
+            return Err(self.error("expected a number"));
+        }
+
+        if matches!(self.data.get(end), Some(b'e' | b'E')) {
+            let mut exponent = end + 1;
+
+            if matches!(self.data.get(exponent), Some(b'+' | b'-')) {
+                exponent += 1;
+            }
+
+            if self.is_digit(exponent) {
+                while self.is_digit(exponent) {
+                    exponent += 1;
+                }
+
+                end = exponent;
+            }
+        }
+
+        let number: f64 = std::str::from_utf8(&self.data[start..end]).ok()
+            .and_then(|number| number.parse().ok())
+            .ok_or_else(|| self.error("expected a number"))?;
+
+        // Overflowing literals such as `1e999` parse as infinity.
+        if !number.is_finite() {
+            return Err(self.error("number out of range"));
+        }
+
+        self.position = end;
+        Ok(number)
+    }
+
+    /// Reads an arc flag, which needs no separator from what follows.
+    fn flag(&mut self) -> Result<bool, ParseError> {
+        let flag = match self.peek() {
+            Some(b'0') => false,
+            Some(b'1') => true,
+            _ => return Err(self.error("expected a flag"))
+        };
+
+        self.position += 1;
+        Ok(flag)
+    }
+
+    /// Reads a number and adds it to `origin`, which a relative coordinate may overflow.
+    fn coordinate(&mut self, origin: f64) -> Result<f64, ParseError> {
+        self.peek();
+        let position = self.position;
+        let value = origin + self.number()?;
+
+        if value.is_finite() {
+            Ok(value)
+        } else {
+            Err(ParseError { position, message: "coordinate out of range" })
+        }
+    }
+
+    fn point(&mut self, origin: Point) -> Result<Point, ParseError> {
+        let x = self.coordinate(origin.x)?;
+        let y = self.coordinate(origin.y)?;
+        Ok(Point { x, y })
+    }
+}
+
+impl CurveData {
+    /// Parses SVG path data into sub-paths, starting a new one at each moveto. Relative
+    /// commands are made absolute, and horizontal and vertical lines become lines. A closepath
+    /// appends a line back to the start of the sub-path unless it already ends there, and
+    /// drawing after it without a moveto starts a new sub-path at the same point, as in SVG.
+    pub fn from_svg_path(d: &str) -> Result<Vec<CurveData>, ParseError> {
+        let mut parser = PathParser { data: d.as_bytes(), position: 0 };
+        let mut paths: Vec<CurveData> = Vec::new();
+        let mut current = Point { x: 0.0, y: 0.0 };
+        let mut previous: Option<u8> = None;
+        let mut closed = false;
+
+        while let Some(next) = parser.peek() {
+            let command = if next.is_ascii_alphabetic() {
+                next
+            } else {
+                // Arguments without a command repeat the previous one, a moveto turning into
+                // a lineto.
+                match previous {
+                    Some(b'M') => b'L',
+                    Some(b'm') => b'l',
+                    Some(b'Z' | b'z') | None => return Err(parser.error("expected a command")),
+                    Some(command) => command
+                }
+            };
+
+            if paths.is_empty() && !matches!(command, b'M' | b'm') {
+                return Err(parser.error("expected a moveto"));
+            }
+
+            if next.is_ascii_alphabetic() {
+                parser.position += 1;
+            }
+
+            if closed && !matches!(command, b'M' | b'm' | b'Z' | b'z') {
+                paths.push(CurveData { start: current, segments: vec![] });
+                closed = false;
+            }
+
+            let origin = if command.is_ascii_lowercase() { current } else { Point { x: 0.0, y: 0.0 } };
+
+            let segment = match command.to_ascii_uppercase() {
+                b'M' => {
+                    current = parser.point(origin)?;
+                    paths.push(CurveData { start: current, segments: vec![] });
+                    closed = false;
+                    None
+                },
+                b'Z' => {
+                    let path = paths.last_mut().unwrap();
+
+                    if current != path.start {
+                        path.segments.push(Segment::Line(LineSegment { point_2: path.start }));
+                    }
+
+                    current = path.start;
+                    closed = true;
+                    None
+                },
+                b'L' => Some(Segment::Line(LineSegment { point_2: parser.point(origin)? })),
+                b'H' => Some(Segment::Line(LineSegment { point_2: Point { x: parser.coordinate(origin.x)?, y: current.y } })),
+                b'V' => Some(Segment::Line(LineSegment { point_2: Point { x: current.x, y: parser.coordinate(origin.y)? } })),
+                b'C' => Some(Segment::CubicBezier(CubicBezierSegment {
+                    point_2: parser.point(origin)?,
+                    point_3: parser.point(origin)?,
+                    point_4: parser.point(origin)?
+                })),
+                b'S' => Some(Segment::SmoothCubicBezier(SmoothCubicBezierSegment {
+                    point_3: parser.point(origin)?,
+                    point_4: parser.point(origin)?
+                })),
+                b'Q' => Some(Segment::QuadraticBezier(QuadraticBezierSegment {
+                    point_2: parser.point(origin)?,
+                    point_3: parser.point(origin)?
+                })),
+                b'T' => Some(Segment::SmoothQuadraticBezier(SmoothQuadraticBezierSegment {
+                    point_3: parser.point(origin)?
+                })),
+                b'A' => Some(Segment::Arc(ArcSegment {
+                    radius_x: parser.number()?,
+                    radius_y: parser.number()?,
+                    rotation: parser.number()?,
+                    large_arc: parser.flag()?,
+                    sweep: parser.flag()?,
+                    point_2: parser.point(origin)?
+                })),
+                _ => {
+                    parser.position -= 1;
+                    return Err(parser.error("unknown command"));
+                }
+            };
+
+            if let Some(segment) = segment {
+                current = segment.end_point();
+                paths.last_mut().unwrap().segments.push(segment);
+            }
+
+            previous = Some(command);
+        }
+
+        Ok(paths)
+    }
+
+    /// Formats the sub-path as SVG path data with absolute commands, the inverse of
+    /// `from_svg_path`. Numbers are written in the shortest form that reads back exactly.
+    pub fn to_svg_path(&self) -> String {
+        let point = |p: Point| format!("{} {}", p.x, p.y);
+        let mut d = format!("M {}", point(self.start));
+
+        for seg in self.segments.iter() {
+            let command = match seg {
+                Segment::Line(s) => format!("L {}", point(s.point_2)),
+                Segment::QuadraticBezier(s) => format!("Q {} {}", point(s.point_2), point(s.point_3)),
+                Segment::CubicBezier(s) => format!("C {} {} {}", point(s.point_2), point(s.point_3), point(s.point_4)),
+                Segment::SmoothQuadraticBezier(s) => format!("T {}", point(s.point_3)),
+                Segment::SmoothCubicBezier(s) => format!("S {} {}", point(s.point_3), point(s.point_4)),
+                Segment::Arc(s) => format!(
+                    "A {} {} {} {} {} {}",
+                    s.radius_x, s.radius_y, s.rotation, u8::from(s.large_arc), u8::from(s.sweep), point(s.point_2)
+                )
+            };
+
+            d.push(' ');
+            d.push_str(&command);
+        }
+
+        d
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::Relative;
+
+    macro_rules! assert_near {
         ($expect_expr:expr, $actual_expr:expr) => {
             assert_near!($expect_expr, $actual_expr, 0.0001);
         };
-        ($expect_expr:expr, $actual_expr:expr, $max_error:expr) => {
-            let actual = $actual_expr;
-            let expect = $expect_expr;
-            let error = actual.relative_error_from(&expect).abs();
-            assert!(error <= $max_error);
+        ($expect_expr:expr, $actual_expr:expr, $max_error:expr) => {
+            let actual = $actual_expr;
+            let expect = $expect_expr;
+            let error = actual.relative_error_from(&expect).abs();
+            assert!(error <= $max_error);
+        };
+    }
+
+    #[test]
+    fn test_image_de() {
+        let image_str = r#"{
+  "width": 640,
+  "height": 480,
+  "unit-per-inch": 140,
+  "pens": [],
+  "brushes": [],
+  "shapes": []
+}"#;
+        let image: Image = serde_json::from_str(image_str).unwrap();
+        assert_near!(640.0, image.width);
+        assert_near!(480.0, image.height);
+        assert_near!(140.0, image.unit_per_inch);
+        assert_eq!(None, image.editor);
+
+        let image2_str = r#"{
+  "width": 1920,
+  "height": 1080,
+  "unit-per-inch": 220,
+  "editor": "T2SY95",
+  "pens": [],
+  "brushes": [],
+  "shapes": []
+}"#;
+        let image2: Image = serde_json::from_str(image2_str).unwrap();
+        assert_near!(1920.0, image2.width);
+        assert_near!(1080.0, image2.height);
+        assert_near!(220.0, image2.unit_per_inch);
+        assert_eq!(Some(String::from("T2SY95")), image2.editor);
+        assert!(image2.default_cap.is_none());
+        assert!(image2.default_join.is_none());
+
+        let image3_str = r#"{
+  "width": 100,
+  "height": 100,
+  "unit-per-inch": 96,
+  "default-cap": "square",
+  "default-join": "bevel",
+  "pens": [
+    { "pattern": { "type": "monochrome", "color": [0, 0, 0] }, "width": 1 },
+    { "pattern": { "type": "monochrome", "color": [0, 0, 0] }, "width": 1, "cap": "round", "join": "round" }
+  ],
+  "brushes": [],
+  "shapes": []
+}"#;
+        let image3: Image = serde_json::from_str(image3_str).unwrap();
+        assert!(Some(LineCap::Square) == image3.default_cap);
+        assert!(Some(LineJoin::Bevel) == image3.default_join);
+        assert!(LineCap::Square == image3.line_cap(&image3.pens[0]));
+        assert!(LineJoin::Bevel == image3.line_join(&image3.pens[0]));
+        assert!(LineCap::Round == image3.line_cap(&image3.pens[1]));
+        assert!(LineJoin::Round == image3.line_join(&image3.pens[1]));
+
+        let image4 = Image { default_cap: None, default_join: None, ..image3 };
+        assert!(LineCap::Butt == image4.line_cap(&image4.pens[0]));
+        assert!(LineJoin::Miter == image4.line_join(&image4.pens[0]));
+    }
+
+    #[test]
+    fn test_image_ser() {
+        let image = Image {
+            width: 200.0,
+            height: 100.0,
+            unit_per_inch: 72.0,
+            editor: Some(String::from("A7E6W9UF")),
+            default_cap: None,
+            default_join: None,
+            alpha_mode: None,
+            pens: vec![],
+            brushes: vec![],
+            shapes: vec![]
+        };
+        let image_str = serde_json::to_string(&image).unwrap();
+        assert_eq!(r#"{"width":200.0,"height":100.0,"unit-per-inch":72.0,"editor":"A7E6W9UF","pens":[],"brushes":[],"shapes":[]}"#, &image_str);
+
+        let image2 = Image {
+            width: 100.0,
+            height: 200.0,
+            unit_per_inch: 96.0,
+            editor: None,
+            default_cap: None,
+            default_join: None,
+            alpha_mode: None,
+            pens: vec![],
+            brushes: vec![],
+            shapes: vec![]
+        };
+        let image2_str = serde_json::to_string(&image2).unwrap();
+        assert_eq!(r#"{"width":100.0,"height":200.0,"unit-per-inch":96.0,"pens":[],"brushes":[],"shapes":[]}"#, &image2_str);
+
+        let image3 = Image { default_cap: Some(LineCap::Round), ..image2 };
+        let image3_str = serde_json::to_string(&image3).unwrap();
+        assert_eq!(r#"{"width":100.0,"height":200.0,"unit-per-inch":96.0,"default-cap":"round","pens":[],"brushes":[],"shapes":[]}"#, &image3_str);
+    }
+
+    #[test]
+    fn test_from_value_at() {
+        let image_str = r#"{
+  "width": 640,
+  "height": 480,
+  "unit-per-inch": 140,
+  "pens": [],
+  "brushes": [],
+  "shapes": [{ "type": "group", "content": [] }]
+}"#;
+        let document_str = format!(r#"{{"data": {{"name": "asset", "image": {}}}}}"#, image_str);
+        let document: serde_json::Value = serde_json::from_str(&document_str).unwrap();
+
+        let image: Image = serde_json::from_str(image_str).unwrap();
+        let image2 = from_value_at(&document, "/data/image").unwrap();
+        assert_eq!(serde_json::to_string(&image).unwrap(), serde_json::to_string(&image2).unwrap());
+
+        let missing = from_value_at(&document, "/data/missing");
+        assert!(matches!(missing, Err(LisonError::PointerNotFound(_))));
+
+        let invalid = from_value_at(&document, "/data");
+        assert!(matches!(invalid, Err(LisonError::Json(_))));
+    }
+
+    #[test]
+    fn test_from_data_uri() {
+        use base64::Engine;
+        use std::io::Write;
+
+        let image_str = r#"{"width": 10, "height": 20, "unit-per-inch": 72, "pens": [], "brushes": [], "shapes": []}"#;
+        let base64 = base64::engine::general_purpose::STANDARD;
+
+        let uri = format!("data:application/json;base64,{}", base64.encode(image_str));
+        let image = from_data_uri(&uri).unwrap();
+        assert_eq!(10.0, image.width);
+        assert_eq!(20.0, image.height);
+
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(image_str.as_bytes()).unwrap();
+        let uri = format!("data:application/gzip;base64,{}", base64.encode(encoder.finish().unwrap()));
+        assert_eq!(20.0, from_data_uri(&uri).unwrap().height);
+
+        let http = format!("http://example.com/application/json;base64,{}", base64.encode(image_str));
+        assert!(matches!(from_data_uri(&http), Err(LisonError::InvalidDataUri(_))));
+
+        let plain = format!("data:application/json,{}", image_str);
+        assert!(matches!(from_data_uri(&plain), Err(LisonError::InvalidDataUri(_))));
+    }
+
+    #[cfg(feature = "json5")]
+    #[test]
+    fn test_from_str_relaxed() {
+        let relaxed_str = r#"{
+  // Written by hand.
+  width: 20,
+  height: 10,
+  'unit-per-inch': 96,
+  pens: [
+    { pattern: { type: 'monochrome', color: [0, 0, 0.5] }, width: 2, cap: 'round', },
+  ],
+  brushes: [],
+  shapes: [
+    /* A single line. */
+    { type: 'group', content: [{ type: 'curve', pen: 0, data: [[0, 5], ['L', [20, 5]],] }], 'edit-annot': { locked: true } },
+  ],
+}"#;
+        let strict_str = r#"{
+  "width": 20,
+  "height": 10,
+  "unit-per-inch": 96,
+  "pens": [{ "pattern": { "type": "monochrome", "color": [0, 0, 0.5] }, "width": 2, "cap": "round" }],
+  "brushes": [],
+  "shapes": [{ "type": "group", "content": [{ "type": "curve", "pen": 0, "data": [[0, 5], ["L", [20, 5]]] }], "edit-annot": { "locked": true } }]
+}"#;
+        let strict: Image = serde_json::from_str(strict_str).unwrap();
+        assert!(serde_json::from_str::<Image>(relaxed_str).is_err());
+        assert_eq!(strict, from_str_relaxed(relaxed_str).unwrap());
+
+        assert!(matches!(from_str_relaxed("{ width: 20, }"), Err(LisonError::Json5(_))));
+    }
+
+    #[cfg(not(feature = "json5"))]
+    #[test]
+    fn test_from_str_relaxed() {
+        let image_str = r#"{"width": 10, "height": 20, "unit-per-inch": 72, "pens": [], "brushes": [], "shapes": []}"#;
+        assert!(matches!(from_str_relaxed(image_str), Err(LisonError::RelaxedUnavailable)));
+    }
+
+    #[test]
+    fn test_process_file() {
+        let dir = std::env::temp_dir();
+        let input = dir.join(format!("lison-process-file-{}-in.lison", std::process::id()));
+        let output = dir.join(format!("lison-process-file-{}-out.lison", std::process::id()));
+
+        std::fs::write(&input, include_str!("../samples/curve.lison")).unwrap();
+        process_file(&input, &output, |image| image.map_points(|p| Point { x: p.x + 10.0, y: p.y - 5.0 })).unwrap();
+
+        let original: Image = serde_json::from_str(include_str!("../samples/curve.lison")).unwrap();
+        let processed: Image = serde_json::from_str(&std::fs::read_to_string(&output).unwrap()).unwrap();
+        let (min1, max1) = original.bounding_box().unwrap();
+        let (min2, max2) = processed.bounding_box().unwrap();
+        assert_near!(Point { x: min1.x + 10.0, y: min1.y - 5.0 }, min2);
+        assert_near!(Point { x: max1.x + 10.0, y: max1.y - 5.0 }, max2);
+
+        assert!(matches!(process_file(&dir.join("lison-no-such-file.lison"), &output, |_| ()), Err(LisonError::Io(_))));
+
+        std::fs::remove_file(&input).unwrap();
+        std::fs::remove_file(&output).unwrap();
+    }
+
+    #[test]
+    fn test_gzip_image() {
+        let dir = std::env::temp_dir();
+        let plain = dir.join(format!("lison-gzip-{}.lison", std::process::id()));
+        let compressed = dir.join(format!("lison-gzip-{}.lison.gz", std::process::id()));
+        let image: Image = serde_json::from_str(include_str!("../samples/curve.lison")).unwrap();
+
+        write_image(&plain, &image).unwrap();
+        write_image(&compressed, &image).unwrap();
+
+        let compressed_bytes = std::fs::read(&compressed).unwrap();
+        assert!(compressed_bytes.starts_with(&GZIP_MAGIC));
+        assert!(compressed_bytes.len() < std::fs::read(&plain).unwrap().len());
+        assert_eq!(image, read_image(&compressed).unwrap());
+        assert_eq!(image, read_image(&plain).unwrap());
+
+        // Compressed data is detected by its content, not its name.
+        std::fs::rename(&compressed, &plain).unwrap();
+        assert_eq!(image, read_image(&plain).unwrap());
+        assert_eq!(cfg!(feature = "json5"), read_image_relaxed(&plain).is_ok());
+
+        std::fs::write(&plain, "{ width: 10, height: 10, 'unit-per-inch': 96, pens: [], brushes: [], shapes: [], }").unwrap();
+        assert!(matches!(read_image(&plain), Err(LisonError::Json(_))));
+        assert_eq!(cfg!(feature = "json5"), read_image_relaxed(&plain).is_ok());
+
+        assert!(decompress(vec![0x1f, 0x8b, 0]).is_err());
+        assert_eq!(b"{}".to_vec(), decompress(b"{}".to_vec()).unwrap());
+        assert!(is_gzip_path(Path::new("a.lison.GZ")));
+        assert!(!is_gzip_path(Path::new("a.lison")));
+
+        std::fs::remove_file(&plain).unwrap();
+    }
+
+    #[test]
+    fn test_to_string_sorted() {
+        let image_str = r#"{
+  "width": 100,
+  "height": 100,
+  "unit-per-inch": 96,
+  "pens": [
+    { "pattern": { "type": "monochrome", "color": [0, 0, 0] }, "width": 1, "name": "outline" }
+  ],
+  "brushes": [],
+  "shapes": [
+    { "type": "group", "content": [
+      { "type": "group", "content": [], "edit-annot": { "zoom": 2, "layer": "ink", "locked": { "y": 1, "x": 0 } } }
+    ], "edit-annot": { "name": "root", "id": 7 } }
+  ]
+}"#;
+        let image: Image = serde_json::from_str(image_str).unwrap();
+
+        let first = to_string_sorted(&image).unwrap();
+        let second = to_string_sorted(&image.clone()).unwrap();
+        assert_eq!(first, second);
+        assert_eq!(r#"{"width":100.0,"height":100.0,"unit-per-inch":96.0,"pens":[{"pattern":{"type":"monochrome","color":[0.0,0.0,0.0]},"width":1.0,"name":"outline"}],"brushes":[],"shapes":[{"type":"group","content":[{"type":"group","content":[],"edit-annot":{"layer":"ink","locked":{"x":0,"y":1},"zoom":2}}],"edit-annot":{"id":7,"name":"root"}}]}"#, &first);
+    }
+
+    #[test]
+    fn test_point_de() {
+        let p_str = r#"[2.4, 5.6]"#;
+        let p: Point = serde_json::from_str(p_str).unwrap();
+        assert_near!(Point { x: 2.4, y: 5.6 }, p);
+
+        let bad_p1_str = r#"[1]"#;
+        let bad_p1 = serde_json::from_str::<Point>(bad_p1_str);
+        assert!(bad_p1.is_err());
+
+        let bad_p2_str = r#"[1, 2, 3]"#;
+        let bad_p2 = serde_json::from_str::<Point>(bad_p2_str);
+        assert!(bad_p2.is_err());
+    }
+
+    #[test]
+    fn test_point_de_finite() {
+        use serde::de::value::{Error, SeqDeserializer};
+
+        let de = |values: Vec<f64>| Point::deserialize(SeqDeserializer::<_, Error>::new(values.into_iter()));
+
+        assert!(de(vec![f64::NAN, 0.0]).is_err());
+        assert!(de(vec![1.0, f64::INFINITY]).is_err());
+        assert_near!(Point { x: 1.0, y: -2.0 }, de(vec![1.0, -2.0]).unwrap());
+
+        let color = Color::deserialize(SeqDeserializer::<_, Error>::new(vec![0.0, f64::NAN, 0.0].into_iter()));
+        assert!(color.is_err());
+    }
+
+    #[test]
+    fn test_point_de_object() {
+        let p1_str = r#"{"x": 2.4, "y": 5.6}"#;
+        let p1: Point = serde_json::from_str(p1_str).unwrap();
+        let p2: Point = serde_json::from_str(r#"[2.4, 5.6]"#).unwrap();
+        assert_near!(p2, p1);
+
+        let p3_str = r#"{"y": -1, "x": 3}"#;
+        let p3: Point = serde_json::from_str(p3_str).unwrap();
+        assert_near!(Point { x: 3.0, y: -1.0 }, p3);
+
+        let p3_ser = serde_json::to_string(&p3).unwrap();
+        assert_eq!(r#"[3.0,-1.0]"#, &p3_ser);
+
+        let bad_p1_str = r#"{"x": 1}"#;
+        let bad_p1 = serde_json::from_str::<Point>(bad_p1_str);
+        assert!(bad_p1.is_err());
+
+        let bad_p2_str = r#"{"x": 1, "y": 2, "z": 3}"#;
+        let bad_p2 = serde_json::from_str::<Point>(bad_p2_str);
+        assert!(bad_p2.is_err());
+
+        let bad_p3_str = r#"{"x": 1, "x": 2, "y": 3}"#;
+        let bad_p3 = serde_json::from_str::<Point>(bad_p3_str);
+        assert!(bad_p3.is_err());
+    }
+
+    #[test]
+    fn test_point_ser() {
+        let p = Point { x: 10.0, y: -8.5 };
+        let p_str = serde_json::to_string(&p).unwrap();
+        assert_eq!(r#"[10.0,-8.5]"#, &p_str);
+    }
+
+    #[test]
+    fn test_color_de() {
+        let c1_str = r#"[0.5, 1.0, 0.0]"#;
+        let c1: Color = serde_json::from_str(c1_str).unwrap();
+        assert_near!(Color { red: 0.5, green: 1.0, blue: 0.0, alpha: 1.0 }, c1);
+
+        let c2_str = r#"[0.541, 0.169, 0.886, 0.7]"#;
+        let c2: Color = serde_json::from_str(c2_str).unwrap();
+        assert_near!(Color { red: 0.541, green: 0.169, blue: 0.886, alpha: 0.7 }, c2);
+
+        let bad_c1_str = r#"[0.1, 0.2]"#;
+        let bad_c1 = serde_json::from_str::<Color>(bad_c1_str);
+        assert!(bad_c1.is_err());
+
+        let bad_c2_str = r#"[0.1, 0.2, 0.3, 0.4, 0.5]"#;
+        let bad_c2 = serde_json::from_str::<Color>(bad_c2_str);
+        assert!(bad_c2.is_err());
+    }
+
+    #[test]
+    fn test_color_clamped() {
+        let over = Color { red: 2.0, green: 0.5, blue: 1.0, alpha: 1.0 }.clamped();
+        assert!(Color { red: 1.0, green: 0.5, blue: 1.0, alpha: 1.0 } == over);
+
+        let negative = Color { red: -0.25, green: 0.0, blue: 0.75, alpha: -1.0 }.clamped();
+        assert!(Color { red: 0.0, green: 0.0, blue: 0.75, alpha: 0.0 } == negative);
+    }
+
+    #[test]
+    fn test_color_de_hsl() {
+        let c1: Color = serde_json::from_str(r#"{ "hsl": [0, 1, 0.5] }"#).unwrap();
+        assert!(Color { red: 1.0, green: 0.0, blue: 0.0, alpha: 1.0 } == c1);
+
+        let c2: Color = serde_json::from_str(r#"{ "hsl": [0, 0, 0.5] }"#).unwrap();
+        assert!(Color { red: 0.5, green: 0.5, blue: 0.5, alpha: 1.0 } == c2);
+
+        let c3: Color = serde_json::from_str(r#"{ "hsla": [120, 1, 0.25, 0.5] }"#).unwrap();
+        assert!(Color { red: 0.0, green: 0.5, blue: 0.0, alpha: 0.5 } == c3);
+
+        let c4: Color = serde_json::from_str(r#"{ "hsl": [210, 0.5, 0.6] }"#).unwrap();
+        assert_near!(Color { red: 0.4, green: 0.6, blue: 0.8, alpha: 1.0 }, c4);
+
+        for bad in [
+            r#"{ "hsl": [0, 1] }"#,
+            r#"{ "hsl": [0, 1, 0.5, 1] }"#,
+            r#"{ "hsla": [0, 1, 0.5] }"#,
+            r#"{ "hsv": [0, 1, 1] }"#,
+            r#"{ "hsl": [0, 1, 0.5], "hsla": [0, 1, 0.5, 1] }"#,
+            r#"{}"#,
+            r#"{ "hsl": [0, 1.5, 0.5] }"#,
+            r#"{ "hsl": [0, -0.1, 0.5] }"#,
+            r#"{ "hsla": [0, 1, 2, 1] }"#
+        ] {
+            assert!(serde_json::from_str::<Color>(bad).is_err());
+        }
+    }
+
+    #[test]
+    fn test_named_color() {
+        assert!(Some(Color { red: 1.0, green: 0.0, blue: 0.0, alpha: 1.0 }) == named_color("red"));
+        assert!(Some(Color { red: 100.0 / 255.0, green: 149.0 / 255.0, blue: 237.0 / 255.0, alpha: 1.0 }) == named_color("cornflowerblue"));
+        assert!(Some(Color { red: 0.0, green: 0.0, blue: 0.0, alpha: 0.0 }) == named_color("transparent"));
+        assert!(named_color("grey") == named_color("gray"));
+        assert!(named_color("reddish").is_none());
+        assert!(named_color("Red") == named_color("red"));
+        assert!(named_color("CornflowerBlue") == named_color("cornflowerblue"));
+
+        let c1: Color = serde_json::from_str(r#""rebeccapurple""#).unwrap();
+        assert!(Color { red: 0.4, green: 0.2, blue: 0.6, alpha: 1.0 } == c1);
+
+        let bad = serde_json::from_str::<Color>(r#""reddish""#).unwrap_err();
+        assert!(bad.to_string().contains("CSS color name"));
+    }
+
+    #[test]
+    fn test_color_de_hex() {
+        let orange = Color { red: 1.0, green: 136.0 / 255.0, blue: 0.0, alpha: 1.0 };
+
+        let c1: Color = serde_json::from_str(r##""#f80""##).unwrap();
+        assert!(orange == c1);
+
+        let c2: Color = serde_json::from_str(r##""#ff8800""##).unwrap();
+        assert!(orange == c2);
+
+        let c3: Color = serde_json::from_str(r##""#FF8800CC""##).unwrap();
+        assert!(Color { alpha: 0.8, ..orange } == c3);
+
+        let c4: Color = serde_json::from_str(r##""#f808""##).unwrap();
+        assert!(Color { alpha: 136.0 / 255.0, ..orange } == c4);
+
+        for bad in [r##""#xyz""##, r##""ff8800""##, r##""#ff880""##, r##""#""##] {
+            assert!(serde_json::from_str::<Color>(bad).is_err());
+        }
+
+        // Serialization keeps the array form.
+        assert_eq!(r#"[1.0,0.0,0.0]"#, &serde_json::to_string(&serde_json::from_str::<Color>(r##""#f00""##).unwrap()).unwrap());
+    }
+
+    #[test]
+    fn test_color_ser() {
+        let c1 = Color { red: 1.0, green: 0.5, blue: 0.25, alpha: 1.0 };
+        let c1_str = serde_json::to_string(&c1).unwrap();
+        assert_eq!(r#"[1.0,0.5,0.25]"#, &c1_str);
+
+        let c2 = Color { red: 0.25, green: 0.125, blue: 1.0, alpha: 0.5 };
+        let c2_str = serde_json::to_string(&c2).unwrap();
+        assert_eq!(r#"[0.25,0.125,1.0,0.5]"#, &c2_str);
+    }
+
+    #[test]
+    fn test_pattern_de() {
+        let p1_str = r#"{
+  "type": "monochrome",
+  "color": [1, 1, 0]
+}"#;
+        let p1: Pattern = serde_json::from_str(p1_str).unwrap();
+        assert_near!(Pattern::Monochrome(MonochromePattern {
+            color: Color { red: 1.0, green: 1.0, blue: 0.0, alpha: 1.0 }
+        }), p1);
+
+        let p2_str = r#"{
+  "type": "linear-gradient",
+  "point-1": [0, 0],
+  "color-1": [0, 1, 1],
+  "point-2": [100, 100],
+  "color-2": [1, 1, 1]
+}"#;
+        let p2: Pattern = serde_json::from_str(p2_str).unwrap();
+        assert_near!(Pattern::LinearGradient(LinearGradientPattern {
+            point_1: Point { x: 0.0, y: 0.0 },
+            color_1: Color { red: 0.0, green: 1.0, blue: 1.0, alpha: 1.0 },
+            point_2: Point { x: 100.0, y: 100.0 },
+            color_2: Color { red: 1.0, green: 1.0, blue: 1.0, alpha: 1.0 },
+            interpolate: None,
+            stops: None,
+            extend: None
+        }), p2);
+
+        let p3_str = r#"{
+  "type": "radial-gradient",
+  "center-1": [50, 50],
+  "radius-1": 5,
+  "color-1": [1, 0, 1],
+  "center-2": [50, 50],
+  "radius-2": 70.7,
+  "color-2": [1, 0, 1, 0.1]
+}"#;
+        let p3: Pattern = serde_json::from_str(p3_str).unwrap();
+        assert_near!(Pattern::RadialGradient(RadialGradientPattern {
+            center_1: Point { x: 50.0, y: 50.0 },
+            radius_1: 5.0,
+            color_1: Color { red: 1.0, green: 0.0, blue: 1.0, alpha: 1.0 },
+            center_2: Point { x: 50.0, y: 50.0 },
+            radius_2: 70.7,
+            color_2: Color { red: 1.0, green: 0.0, blue: 1.0, alpha: 0.1 },
+            interpolate: None,
+            stops: None,
+            extend: None,
+            aspect: None
+        }), p3);
+
+        let p4_str = r#"{
+  "type": "image",
+  "data": "iVBORw0KGgo=",
+  "pixel-size": 0.5,
+  "extend": "pad"
+}"#;
+        let p4: Pattern = serde_json::from_str(p4_str).unwrap();
+        assert_near!(Pattern::Image(ImagePattern {
+            data: "iVBORw0KGgo=".to_string(),
+            origin: None,
+            pixel_size: Some(0.5),
+            extend: Some(Extend::Pad)
+        }), p4);
+
+        let p7_str = r#"{
+  "type": "radial-gradient",
+  "center-1": [0, 0],
+  "radius-1": 0,
+  "color-1": [1, 1, 1],
+  "center-2": [0, 0],
+  "radius-2": 10,
+  "color-2": [0, 0, 0],
+  "extend": "repeat"
+}"#;
+        let Pattern::RadialGradient(p7) = serde_json::from_str(p7_str).unwrap() else { panic!() };
+        assert!(Some(Extend::Repeat) == p7.extend);
+        assert!(p7.aspect.is_none());
+
+        let p8_str = p7_str.replace(r#""extend": "repeat""#, r#""aspect": 2.5"#);
+        let Pattern::RadialGradient(p8) = serde_json::from_str(&p8_str).unwrap() else { panic!() };
+        assert!(Some(2.5) == p8.aspect);
+        assert!(serde_json::to_string(&Pattern::RadialGradient(p8)).unwrap().ends_with(r#","aspect":2.5}"#));
+
+        let p9_str = p7_str.replace(r#""extend": "repeat""#, r#""aspect": -1"#);
+        let error = serde_json::from_str::<Pattern>(&p9_str).unwrap_err();
+        assert!(error.to_string().starts_with("aspect -1 must be positive"));
+
+        let p5_str = r#"{ "type": "image", "data": "not base64!" }"#;
+        assert!(serde_json::from_str::<Pattern>(p5_str).is_err());
+
+        let p6_str = r#"{ "type": "image", "data": "iVBORw0KGgo=", "pixel-size": 0 }"#;
+        let error = serde_json::from_str::<Pattern>(p6_str).unwrap_err();
+        assert!(error.to_string().starts_with("pixel size 0 must be positive"));
+    }
+
+    #[test]
+    fn test_pattern_ser() {
+        let p1 = Pattern::Monochrome(MonochromePattern {
+            color: Color { red: 1.0, green: 0.0, blue: 0.0, alpha: 1.0 }
+        });
+        let p1_str = serde_json::to_string(&p1).unwrap();
+        assert_eq!(r#"{"type":"monochrome","color":[1.0,0.0,0.0]}"#, &p1_str);
+
+        let p2 = Pattern::LinearGradient(LinearGradientPattern {
+            point_1: Point { x: 0.0, y: 0.0 },
+            color_1: Color { red: 0.5, green: 0.5, blue: 1.0, alpha: 1.0 },
+            point_2: Point { x: 100.0, y: 0.0 },
+            color_2: Color { red: 0.0, green: 0.0, blue: 1.0, alpha: 1.0 },
+            interpolate: None,
+            stops: None,
+            extend: None
+        });
+        let p2_str = serde_json::to_string(&p2).unwrap();
+        assert_eq!(r#"{"type":"linear-gradient","point-1":[0.0,0.0],"color-1":[0.5,0.5,1.0],"point-2":[100.0,0.0],"color-2":[0.0,0.0,1.0]}"#, &p2_str);
+
+        let p3 = Pattern::RadialGradient(RadialGradientPattern {
+            center_1: Point { x: 50.0, y: 50.0 },
+            radius_1: 5.0,
+            color_1: Color { red: 0.0, green: 0.5, blue: 0.0, alpha: 1.0 },
+            center_2: Point { x: 50.0, y: 50.0 },
+            radius_2: 50.0,
+            color_2: Color { red: 0.0, green: 0.5, blue: 0.0, alpha: 0.25 },
+            interpolate: None,
+            stops: None,
+            extend: None,
+            aspect: None
+        });
+        let p3_str = serde_json::to_string(&p3).unwrap();
+        assert_eq!(r#"{"type":"radial-gradient","center-1":[50.0,50.0],"radius-1":5.0,"color-1":[0.0,0.5,0.0],"center-2":[50.0,50.0],"radius-2":50.0,"color-2":[0.0,0.5,0.0,0.25]}"#, &p3_str);
+
+        let p5 = Pattern::LinearGradient(LinearGradientPattern {
+            point_1: Point { x: 0.0, y: 0.0 },
+            color_1: Color { red: 1.0, green: 1.0, blue: 1.0, alpha: 1.0 },
+            point_2: Point { x: 10.0, y: 0.0 },
+            color_2: Color { red: 0.0, green: 0.0, blue: 0.0, alpha: 1.0 },
+            interpolate: None,
+            stops: None,
+            extend: Some(Extend::Reflect)
+        });
+        let p5_str = serde_json::to_string(&p5).unwrap();
+        assert_eq!(r#"{"type":"linear-gradient","point-1":[0.0,0.0],"color-1":[1.0,1.0,1.0],"point-2":[10.0,0.0],"color-2":[0.0,0.0,0.0],"extend":"reflect"}"#, &p5_str);
+
+        let p4 = Pattern::Image(ImagePattern {
+            data: "iVBORw0KGgo=".to_string(),
+            origin: Some(Point { x: 10.0, y: 20.0 }),
+            pixel_size: None,
+            extend: Some(Extend::Reflect)
+        });
+        let p4_str = serde_json::to_string(&p4).unwrap();
+        assert_eq!(r#"{"type":"image","data":"iVBORw0KGgo=","origin":[10.0,20.0],"extend":"reflect"}"#, &p4_str);
+
+        let p6 = Pattern::RadialGradient(RadialGradientPattern {
+            center_1: Point { x: 50.0, y: 50.0 },
+            radius_1: 5.0,
+            color_1: Color { red: 0.0, green: 0.5, blue: 0.0, alpha: 1.0 },
+            center_2: Point { x: 50.0, y: 50.0 },
+            radius_2: 50.0,
+            color_2: Color { red: 0.0, green: 0.5, blue: 0.0, alpha: 0.25 },
+            interpolate: Some(ColorInterp::HslLong),
+            stops: None,
+            extend: None,
+            aspect: None
+        });
+        let p6_str = serde_json::to_string(&p6).unwrap();
+        assert_eq!(r#"{"type":"radial-gradient","center-1":[50.0,50.0],"radius-1":5.0,"color-1":[0.0,0.5,0.0],"center-2":[50.0,50.0],"radius-2":50.0,"color-2":[0.0,0.5,0.0,0.25],"interpolate":"hsl-long"}"#, &p6_str);
+    }
+
+    #[test]
+    fn test_extend_de() {
+        let extend1: Extend = serde_json::from_str(r#""none""#).unwrap();
+        assert!(Extend::None == extend1);
+
+        let extend2: Extend = serde_json::from_str(r#""repeat""#).unwrap();
+        assert!(Extend::Repeat == extend2);
+
+        let extend3: Extend = serde_json::from_str(r#""reflect""#).unwrap();
+        assert!(Extend::Reflect == extend3);
+
+        let extend4: Extend = serde_json::from_str(r#""pad""#).unwrap();
+        assert!(Extend::Pad == extend4);
+
+        assert!(serde_json::from_str::<Extend>(r#""bad-extend""#).is_err());
+    }
+
+    #[test]
+    fn test_gradient_stops() {
+        let p_str = r#"{"type":"linear-gradient","point-1":[0.0,0.0],"color-1":[0.0,0.0,0.0],"point-2":[10.0,0.0],"color-2":[1.0,1.0,1.0],"stops":[[0.25,[1.0,0.0,0.0]],[0.75,[0.0,0.0,1.0,0.5]]]}"#;
+        let p: Pattern = serde_json::from_str(p_str).unwrap();
+        assert_eq!(p_str, serde_json::to_string(&p).unwrap());
+
+        match &p {
+            Pattern::LinearGradient(pat) => {
+                let stops = pat.color_stops();
+                assert_eq!(4, stops.len());
+                assert_eq!(0.75, stops[2].0);
+                assert_near!(Color { red: 0.0, green: 0.0, blue: 1.0, alpha: 0.5 }, stops[2].1);
+                assert_eq!(1.0, stops[3].0);
+            },
+            _ => panic!("unexpected pattern type")
+        }
+        assert_eq!(4, p.colors().len());
+
+        let radial_str = r#"{"type":"radial-gradient","center-1":[5.0,5.0],"radius-1":0.0,"color-1":[0.0,0.0,0.0],"center-2":[5.0,5.0],"radius-2":5.0,"color-2":[1.0,1.0,1.0],"stops":[[0.5,[0.0,1.0,0.0]]]}"#;
+        let radial: Pattern = serde_json::from_str(radial_str).unwrap();
+        assert_eq!(radial_str, serde_json::to_string(&radial).unwrap());
+
+        let conic_str = r#"{"type":"conic-gradient","center":[5.0,5.0],"start-angle":-90.0,"stops":[[0.0,[1.0,0.0,0.0]],[1.0,[0.0,0.0,1.0]]],"interpolate":"hsl"}"#;
+        let conic: Pattern = serde_json::from_str(conic_str).unwrap();
+        assert_eq!(conic_str, serde_json::to_string(&conic).unwrap());
+        assert_eq!(2, conic.colors().len());
+
+        let conic_unsorted_str = r#"{"type":"conic-gradient","center":[5,5],"start-angle":0,"stops":[[1,[1,0,0]],[0,[0,0,1]]]}"#;
+        assert!(serde_json::from_str::<Pattern>(conic_unsorted_str).is_err());
+
+        let unsorted_str = r#"{"type":"linear-gradient","point-1":[0,0],"color-1":[0,0,0],"point-2":[10,0],"color-2":[1,1,1],"stops":[[0.75,[1,0,0]],[0.25,[0,0,1]]]}"#;
+        assert!(serde_json::from_str::<Pattern>(unsorted_str).is_err());
+
+        let out_of_range_str = r#"{"type":"linear-gradient","point-1":[0,0],"color-1":[0,0,0],"point-2":[10,0],"color-2":[1,1,1],"stops":[[1.5,[1,0,0]]]}"#;
+        assert!(serde_json::from_str::<Pattern>(out_of_range_str).is_err());
+    }
+
+    #[test]
+    fn test_color_interpolate() {
+        let red = Color { red: 1.0, green: 0.0, blue: 0.0, alpha: 1.0 };
+        let green = Color { red: 0.0, green: 1.0, blue: 0.0, alpha: 0.0 };
+
+        let rgb = red.interpolate(green, 0.5, ColorInterp::Rgb);
+        assert_near!(Color { red: 0.5, green: 0.5, blue: 0.0, alpha: 0.5 }, rgb);
+
+        let hsl = red.interpolate(green, 0.5, ColorInterp::Hsl);
+        assert_near!(Color { red: 1.0, green: 1.0, blue: 0.0, alpha: 0.5 }, hsl);
+
+        let hsl_long = red.interpolate(green, 0.5, ColorInterp::HslLong);
+        assert!(hsl_long.red.abs() < 1e-9 && hsl_long.green.abs() < 1e-9);
+        assert_near!(1.0, hsl_long.blue);
+
+        let gray = Color { red: 0.5, green: 0.5, blue: 0.5, alpha: 1.0 };
+        let blue = Color { red: 0.0, green: 0.0, blue: 1.0, alpha: 1.0 };
+        let end = gray.interpolate(blue, 1.0, ColorInterp::Hsl);
+        assert!(end.red.abs() < 1e-9 && end.green.abs() < 1e-9);
+        assert_near!(1.0, end.blue);
+
+        let interp: ColorInterp = serde_json::from_str(r#""hsl-long""#).unwrap();
+        assert!(ColorInterp::HslLong == interp);
+        assert!(serde_json::from_str::<ColorInterp>(r#""hsv""#).is_err());
+    }
+
+    #[test]
+    #[allow(clippy::needless_borrow)]
+    fn test_line_cap_de() {
+        let cap1_str = r#""butt""#;
+        let cap1: LineCap = serde_json::from_str(&cap1_str).unwrap();
+        assert!(LineCap::Butt == cap1);
+
+        let cap2_str = r#""round""#;
+        let cap2: LineCap = serde_json::from_str(&cap2_str).unwrap();
+        assert!(LineCap::Round == cap2);
+
+        let cap3_str = r#""square""#;
+        let cap3: LineCap = serde_json::from_str(&cap3_str).unwrap();
+        assert!(LineCap::Square == cap3);
+
+        let cap4_str = r#""bad-cap""#;
+        let cap4 = serde_json::from_str::<LineCap>(&cap4_str);
+        assert!(cap4.is_err());
+    }
+
+    #[test]
+    fn test_line_cap_ser() {
+        let cap1 = LineCap::Butt;
+        let cap1_str = serde_json::to_string(&cap1).unwrap();
+        assert_eq!(r#""butt""#, &cap1_str);
+
+        let cap2 = LineCap::Round;
+        let cap2_str = serde_json::to_string(&cap2).unwrap();
+        assert_eq!(r#""round""#, &cap2_str);
+
+        let cap3 = LineCap::Square;
+        let cap3_str = serde_json::to_string(&cap3).unwrap();
+        assert_eq!(r#""square""#, &cap3_str);
+    }
+
+    #[test]
+    #[allow(clippy::needless_borrow)]
+    fn test_line_join_de() {
+        let join1_str = r#""miter""#;
+        let join1: LineJoin = serde_json::from_str(&join1_str).unwrap();
+        assert!(LineJoin::Miter == join1);
+
+        let join2_str = r#""round""#;
+        let join2: LineJoin = serde_json::from_str(&join2_str).unwrap();
+        assert!(LineJoin::Round == join2);
+
+        let join3_str = r#""bevel""#;
+        let join3: LineJoin = serde_json::from_str(&join3_str).unwrap();
+        assert!(LineJoin::Bevel == join3);
+
+        let join4_str = r#""bad-join""#;
+        let join4 = serde_json::from_str::<LineJoin>(&join4_str);
+        assert!(join4.is_err());
+    }
+
+    #[test]
+    fn test_line_join_ser() {
+        let join1 = LineJoin::Miter;
+        let join1_str = serde_json::to_string(&join1).unwrap();
+        assert_eq!(r#""miter""#, &join1_str);
+
+        let join2 = LineJoin::Round;
+        let join2_str = serde_json::to_string(&join2).unwrap();
+        assert_eq!(r#""round""#, &join2_str);
+
+        let join3 = LineJoin::Bevel;
+        let join3_str = serde_json::to_string(&join3).unwrap();
+        assert_eq!(r#""bevel""#, &join3_str);
+    }
+
+    #[test]
+    fn test_pen_de() {
+        let pen_str = r#"{
+  "pattern": {
+    "type": "monochrome",
+    "color": [0.3, 0.4, 0.5, 0.6]
+  },
+  "width": 5,
+  "cap": "butt",
+  "join": "bevel"
+}"#;
+        let pen: Pen = serde_json::from_str(pen_str).unwrap();
+        assert_near!(Pattern::Monochrome(MonochromePattern {
+            color: Color { red: 0.3, green: 0.4, blue: 0.5, alpha: 0.6 }
+        }), pen.pattern);
+        assert_near!(5.0, pen.width);
+        assert!(Some(LineCap::Butt) == pen.cap);
+        assert!(Some(LineJoin::Bevel) == pen.join);
+        assert!(pen.align.is_none());
+
+        let pen3_str = r#"{
+  "pattern": {
+    "type": "monochrome",
+    "color": [0.3, 0.4, 0.5, 0.6]
+  },
+  "width": 5,
+  "cap": "butt",
+  "join": "bevel",
+  "align": "inner"
+}"#;
+        let pen3: Pen = serde_json::from_str(pen3_str).unwrap();
+        assert!(Some(StrokeAlign::Inner) == pen3.align);
+        assert!(pen3.miter_limit.is_none());
+
+        let pen4_str = r#"{
+  "pattern": {
+    "type": "monochrome",
+    "color": [0.3, 0.4, 0.5, 0.6]
+  },
+  "width": 5,
+  "join": "miter",
+  "miter-limit": 2.5
+}"#;
+        let pen4: Pen = serde_json::from_str(pen4_str).unwrap();
+        assert_eq!(Some(2.5), pen4.miter_limit);
+
+        let bad_pen_str = r#"{ "pattern": { "type": "monochrome", "color": [0, 0, 0] }, "width": 1, "miter-limit": 0.5 }"#;
+        assert!(serde_json::from_str::<Pen>(bad_pen_str).is_err());
+
+        let pen5_str = r#"{
+  "pattern": {
+    "type": "monochrome",
+    "color": [0.3, 0.4, 0.5, 0.6]
+  },
+  "width": 5,
+  "dash": { "pattern": [4, 2], "offset": 1 }
+}"#;
+        let pen5: Pen = serde_json::from_str(pen5_str).unwrap();
+        assert_eq!(Some(Dash { pattern: vec![4.0, 2.0], offset: 1.0 }), pen5.dash);
+    }
+
+    #[test]
+    fn test_dash_de() {
+        let dash: Dash = serde_json::from_str(r#"{ "pattern": [3, 1.5] }"#).unwrap();
+        assert_eq!(Dash { pattern: vec![3.0, 1.5], offset: 0.0 }, dash);
+        assert!(!dash.is_solid());
+
+        let empty: Dash = serde_json::from_str(r#"{ "pattern": [] }"#).unwrap();
+        assert!(empty.is_solid());
+
+        let zero: Dash = serde_json::from_str(r#"{ "pattern": [0, 0], "offset": 2 }"#).unwrap();
+        assert!(zero.is_solid());
+
+        assert!(serde_json::from_str::<Dash>(r#"{ "pattern": [1, -1] }"#).is_err());
+        assert!(serde_json::from_str::<Dash>(r#"{ "offset": 1 }"#).is_err());
+        assert!(serde_json::from_str::<Dash>(r#"{ "pattern": [1], "phase": 1 }"#).is_err());
+    }
+
+    #[test]
+    fn test_operator_de() {
+        let operator1: Operator = serde_json::from_str(r#""over""#).unwrap();
+        assert_eq!(Operator::Over, operator1);
+
+        let operator2: Operator = serde_json::from_str(r#""multiply""#).unwrap();
+        assert_eq!(Operator::Multiply, operator2);
+
+        let operator3: Operator = serde_json::from_value(serde_json::json!("screen")).unwrap();
+        assert_eq!(Operator::Screen, operator3);
+
+        assert!(serde_json::from_str::<Operator>(r#""Multiply""#).is_err());
+        assert!(serde_json::from_str::<Operator>(r#""source""#).is_err());
+    }
+
+    #[test]
+    fn test_operator_ser() {
+        assert_eq!(r#""over""#, serde_json::to_string(&Operator::Over).unwrap());
+        assert_eq!(r#""difference""#, serde_json::to_string(&Operator::Difference).unwrap());
+        assert_eq!(r#""xor""#, serde_json::to_string(&Operator::Xor).unwrap());
+    }
+
+    #[test]
+    fn test_dash_mode_de() {
+        assert_eq!(DashMode::Absolute, serde_json::from_str::<DashMode>(r#""absolute""#).unwrap());
+        assert_eq!(DashMode::Relative, serde_json::from_str::<DashMode>(r#""relative""#).unwrap());
+        assert!(serde_json::from_str::<DashMode>(r#""percent""#).is_err());
+
+        let dash = Dash { pattern: vec![0.25, 0.5], offset: 0.1 };
+        assert_eq!(Dash { pattern: vec![10.0, 20.0], offset: 4.0 }, dash.scaled(40.0));
+    }
+
+    #[test]
+    fn test_stroke_align_de() {
+        let align1: StrokeAlign = serde_json::from_str(r#""center""#).unwrap();
+        assert!(StrokeAlign::Center == align1);
+
+        let align2: StrokeAlign = serde_json::from_str(r#""inner""#).unwrap();
+        assert!(StrokeAlign::Inner == align2);
+
+        let align3: StrokeAlign = serde_json::from_str(r#""outer""#).unwrap();
+        assert!(StrokeAlign::Outer == align3);
+
+        let align4 = serde_json::from_str::<StrokeAlign>(r#""middle""#);
+        assert!(align4.is_err());
+    }
+
+    #[test]
+    fn test_stroke_align_ser() {
+        assert_eq!(r#""center""#, serde_json::to_string(&StrokeAlign::Center).unwrap());
+        assert_eq!(r#""inner""#, serde_json::to_string(&StrokeAlign::Inner).unwrap());
+        assert_eq!(r#""outer""#, serde_json::to_string(&StrokeAlign::Outer).unwrap());
+    }
+
+    #[test]
+    fn test_pen_ser() {
+        let pen = Pen {
+            pattern: Pattern::Monochrome(MonochromePattern {
+                color: Color { red: 0.9, green: 0.8, blue: 0.7, alpha: 0.6 }
+            }),
+            width: 2.5,
+            cap: Some(LineCap::Round),
+            join: Some(LineJoin::Round),
+            miter_limit: None,
+            dash: None,
+            dash_mode: None,
+            align: None,
+            name: None
+        };
+        let pen_str = serde_json::to_string(&pen).unwrap();
+        assert_eq!(r#"{"pattern":{"type":"monochrome","color":[0.9,0.8,0.7,0.6]},"width":2.5,"cap":"round","join":"round"}"#, &pen_str);
+
+        let pen3 = Pen {
+            align: Some(StrokeAlign::Outer),
+            ..pen.clone()
+        };
+        let pen3_str = serde_json::to_string(&pen3).unwrap();
+        assert_eq!(r#"{"pattern":{"type":"monochrome","color":[0.9,0.8,0.7,0.6]},"width":2.5,"cap":"round","join":"round","align":"outer"}"#, &pen3_str);
+
+        let pen4 = Pen {
+            join: Some(LineJoin::Miter),
+            miter_limit: Some(4.0),
+            ..pen.clone()
+        };
+        let pen4_str = serde_json::to_string(&pen4).unwrap();
+        assert_eq!(r#"{"pattern":{"type":"monochrome","color":[0.9,0.8,0.7,0.6]},"width":2.5,"cap":"round","join":"miter","miter-limit":4.0}"#, &pen4_str);
+
+        let pen5 = Pen {
+            dash: Some(Dash { pattern: vec![3.0, 1.0], offset: 0.5 }),
+            ..pen
+        };
+        let pen5_str = serde_json::to_string(&pen5).unwrap();
+        assert_eq!(r#"{"pattern":{"type":"monochrome","color":[0.9,0.8,0.7,0.6]},"width":2.5,"cap":"round","join":"round","dash":{"pattern":[3.0,1.0],"offset":0.5}}"#, &pen5_str);
+
+        let round_trip: Pen = serde_json::from_str(&pen5_str).unwrap();
+        assert!(pen5 == round_trip);
+
+        let pen6 = Pen {
+            dash_mode: Some(DashMode::Relative),
+            ..pen5
+        };
+        let pen6_str = serde_json::to_string(&pen6).unwrap();
+        assert_eq!(r#"{"pattern":{"type":"monochrome","color":[0.9,0.8,0.7,0.6]},"width":2.5,"cap":"round","join":"round","dash":{"pattern":[3.0,1.0],"offset":0.5},"dash-mode":"relative"}"#, &pen6_str);
+        assert!(pen6 == serde_json::from_str::<Pen>(&pen6_str).unwrap());
+    }
+
+    #[test]
+    fn test_brush_de() {
+        let brush_str = r#"{
+  "pattern": {
+    "type": "monochrome",
+    "color": [0.5, 0.6, 0.7]
+  }
+}"#;
+        let brush: Brush = serde_json::from_str(brush_str).unwrap();
+        assert_near!(Pattern::Monochrome(MonochromePattern {
+            color: Color { red: 0.5, green: 0.6, blue: 0.7, alpha: 1.0 }
+        }), brush.pattern);
+    }
+
+    #[test]
+    fn test_brush_ser() {
+        let brush = Brush {
+            pattern: Pattern::Monochrome(MonochromePattern {
+                color: Color { red: 0.5, green: 1.0, blue: 0.25, alpha: 1.0 }
+            }),
+            name: None
+        };
+        let brush_str = serde_json::to_string(&brush).unwrap();
+        assert_eq!(r#"{"pattern":{"type":"monochrome","color":[0.5,1.0,0.25]}}"#, &brush_str);
+    }
+
+    #[test]
+    fn test_segment_de() {
+        let seg1_str = r#"["L", [10, 11]]"#;
+        let seg1: Segment = serde_json::from_str(seg1_str).unwrap();
+        assert_near!(Segment::Line(LineSegment {
+            point_2: Point { x: 10.0, y: 11.0 }
+        }), seg1);
+
+        let seg2_str = r#"["Q", [12, 13], [14, 15]]"#;
+        let seg2: Segment = serde_json::from_str(seg2_str).unwrap();
+        assert_near!(Segment::QuadraticBezier(QuadraticBezierSegment {
+            point_2: Point { x: 12.0, y: 13.0 },
+            point_3: Point { x: 14.0, y: 15.0 },
+        }), seg2);
+
+        let seg3_str = r#"["C", [16, 17], [18, 19], [20, 21]]"#;
+        let seg3: Segment = serde_json::from_str(seg3_str).unwrap();
+        assert_near!(Segment::CubicBezier(CubicBezierSegment {
+            point_2: Point { x: 16.0, y: 17.0 },
+            point_3: Point { x: 18.0, y: 19.0 },
+            point_4: Point { x: 20.0, y: 21.0 },
+        }), seg3);
+
+        let seg4_str = r#"["A", 10, 5, 30, false, true, [22, 23]]"#;
+        let seg4: Segment = serde_json::from_str(seg4_str).unwrap();
+        assert_near!(Segment::Arc(ArcSegment {
+            radius_x: 10.0,
+            radius_y: 5.0,
+            rotation: 30.0,
+            large_arc: false,
+            sweep: true,
+            point_2: Point { x: 22.0, y: 23.0 }
+        }), seg4);
+
+        let seg5_str = r#"["A", 10, 5, 30, false, [22, 23]]"#;
+        assert!(serde_json::from_str::<Segment>(seg5_str).is_err());
+
+        let seg6_str = r#"["T", [24, 25]]"#;
+        let seg6: Segment = serde_json::from_str(seg6_str).unwrap();
+        assert_near!(Segment::SmoothQuadraticBezier(SmoothQuadraticBezierSegment {
+            point_3: Point { x: 24.0, y: 25.0 }
+        }), seg6);
+
+        let seg7_str = r#"["S", [26, 27], [28, 29]]"#;
+        let seg7: Segment = serde_json::from_str(seg7_str).unwrap();
+        assert_near!(Segment::SmoothCubicBezier(SmoothCubicBezierSegment {
+            point_3: Point { x: 26.0, y: 27.0 },
+            point_4: Point { x: 28.0, y: 29.0 }
+        }), seg7);
+    }
+
+    #[test]
+    fn test_segment_ser() {
+        let seg1 = Segment::Line(LineSegment {
+            point_2: Point { x: 1.0, y: 2.0 }
+        });
+        let seg1_str = serde_json::to_string(&seg1).unwrap();
+        assert_eq!(r#"["L",[1.0,2.0]]"#, &seg1_str);
+
+        let seg2 = Segment::QuadraticBezier(QuadraticBezierSegment {
+            point_2: Point { x: 1.0, y: 2.0 },
+            point_3: Point { x: 3.0, y: -4.0 }
+        });
+        let seg2_str = serde_json::to_string(&seg2).unwrap();
+        assert_eq!(r#"["Q",[1.0,2.0],[3.0,-4.0]]"#, &seg2_str);
+
+        let seg3 = Segment::CubicBezier(CubicBezierSegment {
+            point_2: Point { x: 1.0, y: 2.0 },
+            point_3: Point { x: 3.0, y: 4.0 },
+            point_4: Point { x: 5.0, y: 6.0 }
+        });
+        let seg3_str = serde_json::to_string(&seg3).unwrap();
+        assert_eq!(r#"["C",[1.0,2.0],[3.0,4.0],[5.0,6.0]]"#, &seg3_str);
+
+        let seg4 = Segment::Arc(ArcSegment {
+            radius_x: 3.0,
+            radius_y: 2.0,
+            rotation: 0.0,
+            large_arc: true,
+            sweep: false,
+            point_2: Point { x: 5.0, y: 6.0 }
+        });
+        let seg4_str = serde_json::to_string(&seg4).unwrap();
+        assert_eq!(r#"["A",3.0,2.0,0.0,true,false,[5.0,6.0]]"#, &seg4_str);
+        assert_near!(seg4, serde_json::from_str::<Segment>(&seg4_str).unwrap());
+
+        let seg5 = Segment::SmoothQuadraticBezier(SmoothQuadraticBezierSegment {
+            point_3: Point { x: 1.0, y: 2.0 }
+        });
+        let seg5_str = serde_json::to_string(&seg5).unwrap();
+        assert_eq!(r#"["T",[1.0,2.0]]"#, &seg5_str);
+
+        let seg6 = Segment::SmoothCubicBezier(SmoothCubicBezierSegment {
+            point_3: Point { x: 1.0, y: 2.0 },
+            point_4: Point { x: 3.0, y: 4.0 }
+        });
+        let seg6_str = serde_json::to_string(&seg6).unwrap();
+        assert_eq!(r#"["S",[1.0,2.0],[3.0,4.0]]"#, &seg6_str);
+    }
+
+    #[test]
+    fn test_curve_data_expand_smooth() {
+        let dat_str = r#"[
+  [0, 0],
+  ["Q", [5, 10], [10, 0]],
+  ["T", [20, 0]],
+  ["T", [30, 0]],
+  ["C", [30, 10], [40, 10], [40, 0]],
+  ["S", [50, -10], [50, 0]],
+  ["L", [60, 0]],
+  ["S", [70, 10], [70, 0]],
+  ["T", [80, 0]]
+]"#;
+        let dat: CurveData = serde_json::from_str(dat_str).unwrap();
+
+        let expect_str = r#"[
+  [0, 0],
+  ["Q", [5, 10], [10, 0]],
+  ["Q", [15, -10], [20, 0]],
+  ["Q", [25, 10], [30, 0]],
+  ["C", [30, 10], [40, 10], [40, 0]],
+  ["C", [40, -10], [50, -10], [50, 0]],
+  ["L", [60, 0]],
+  ["C", [60, 0], [70, 10], [70, 0]],
+  ["Q", [70, 0], [80, 0]]
+]"#;
+        let expect: CurveData = serde_json::from_str(expect_str).unwrap();
+
+        let expanded = dat.expand();
+        assert!(expanded.start == expect.start);
+        assert_eq!(expect.segments.len(), expanded.segments.len());
+        for (expect, actual) in expect.segments.iter().zip(expanded.segments.iter()) {
+            assert_near!(expect.control_points(), actual.control_points());
+        }
+    }
+
+    #[test]
+    fn test_arc_to_beziers() {
+        let quarter = ArcSegment {
+            radius_x: 10.0,
+            radius_y: 10.0,
+            rotation: 0.0,
+            large_arc: false,
+            sweep: true,
+            point_2: Point { x: 0.0, y: 10.0 }
+        };
+        let beziers = quarter.to_beziers(Point { x: 10.0, y: 0.0 });
+        assert_eq!(1, beziers.len());
+
+        // The usual circle constant 4 / 3 * (sqrt(2) - 1).
+        let kappa = 10.0 * 0.5522847498;
+        assert_near!(Segment::CubicBezier(CubicBezierSegment {
+            point_2: Point { x: 10.0, y: kappa },
+            point_3: Point { x: kappa, y: 10.0 },
+            point_4: Point { x: 0.0, y: 10.0 }
+        }), beziers[0]);
+
+        let large = ArcSegment { large_arc: true, ..quarter };
+        let beziers = large.to_beziers(Point { x: 10.0, y: 0.0 });
+        assert_eq!(3, beziers.len());
+        assert_near!(Point { x: 20.0, y: 10.0 }, beziers[0].end_point());
+        assert_near!(Point { x: 10.0, y: 20.0 }, beziers[1].end_point());
+
+        // Radii too small to reach the end point are scaled up to a half circle.
+        let small = ArcSegment { radius_x: 1.0, radius_y: 1.0, point_2: Point { x: 20.0, y: 10.0 }, ..quarter };
+        let beziers = small.to_beziers(Point { x: 0.0, y: 10.0 });
+        assert_eq!(2, beziers.len());
+        assert_near!(Point { x: 10.0, y: 0.0 }, beziers[0].end_point());
+
+        let flat = ArcSegment { radius_y: 0.0, ..quarter };
+        assert!(matches!(flat.to_beziers(Point { x: 10.0, y: 0.0 })[..], [Segment::Line(_)]));
+        assert!(quarter.to_beziers(Point { x: 0.0, y: 10.0 }).is_empty());
+
+        let mut data = CurveData { start: Point { x: 10.0, y: 0.0 }, segments: vec![Segment::Arc(quarter)] };
+        let (min, max) = data.bounding_box();
+        assert!(Point { x: 0.0, y: 0.0 } == min);
+        assert!(Point { x: 10.0, y: 10.0 } == max);
+
+        data.reverse();
+        assert!(Point { x: 0.0, y: 10.0 } == data.start);
+        let Segment::Arc(reversed) = data.segments[0] else { panic!() };
+        assert!(!reversed.sweep);
+        assert!(Point { x: 10.0, y: 0.0 } == reversed.point_2);
+    }
+
+    #[test]
+    fn test_curve_data_de() {
+        let dat_str = r#"[
+  [10, 11],
+  ["L", [12, 13]],
+  ["Q", [14, 15], [16, 17]]
+]"#;
+        let dat: CurveData = serde_json::from_str(dat_str).unwrap();
+        assert_near!(10.0, dat.start.x);
+        assert_near!(11.0, dat.start.y);
+        assert_eq!(2, dat.segments.len());
+        assert_near!(Segment::Line(LineSegment {
+            point_2: Point { x: 12.0, y: 13.0 }
+        }), dat.segments[0]);
+        assert_near!(Segment::QuadraticBezier(QuadraticBezierSegment {
+            point_2: Point { x: 14.0, y: 15.0 },
+            point_3: Point { x: 16.0, y: 17.0 }
+        }), dat.segments[1]);
+    }
+
+    #[test]
+    fn test_curve_data_ser() {
+        let dat = CurveData {
+            start: Point { x: 1.0, y: 2.0 },
+            segments: vec![
+                Segment::Line(LineSegment {
+                    point_2: Point { x: 3.0, y: 4.0 }
+                }),
+                Segment::QuadraticBezier(QuadraticBezierSegment {
+                    point_2: Point { x: 5.0, y: 6.0 },
+                    point_3: Point { x: 7.0, y: 8.0 }
+                })
+            ]
+        };
+        let dat_str = serde_json::to_string(&dat).unwrap();
+        assert_eq!(r#"[[1.0,2.0],["L",[3.0,4.0]],["Q",[5.0,6.0],[7.0,8.0]]]"#, &dat_str);
+    }
+
+    #[test]
+    fn test_curve_data_merge_collinear() {
+        let data_str = r#"[[0, 0], ["L", [10, 0]], ["L", [20, 0.001]], ["L", [30, 0]], ["Q", [40, 10], [50, 0]], ["L", [50, 10]], ["L", [50, 5]]]"#;
+        let mut data: CurveData = serde_json::from_str(data_str).unwrap();
+
+        data.merge_collinear(0.01);
+
+        assert_eq!(4, data.segments.len());
+        assert_near!(Segment::Line(LineSegment { point_2: Point { x: 30.0, y: 0.0 } }), data.segments[0]);
+        assert!(matches!(data.segments[1], Segment::QuadraticBezier(_)));
+        // Turning back along the same line is not a merge.
+        assert_near!(Segment::Line(LineSegment { point_2: Point { x: 50.0, y: 10.0 } }), data.segments[2]);
+        assert_near!(Segment::Line(LineSegment { point_2: Point { x: 50.0, y: 5.0 } }), data.segments[3]);
+
+        let mut bent: CurveData = serde_json::from_str(r#"[[0, 0], ["L", [10, 0]], ["L", [20, 1]]]"#).unwrap();
+        bent.merge_collinear(0.01);
+        assert_eq!(2, bent.segments.len());
+
+        // Each point of a gently curving arc is close to the line through its neighbors, but
+        // not to a chord spanning many of them.
+        let mut arc = CurveData { start: Point { x: 100.0, y: 0.0 }, segments: Vec::new() };
+        for i in 1..=900 {
+            let angle = (f64::from(i) / 10.0).to_radians();
+            arc.segments.push(Segment::Line(LineSegment { point_2: Point { x: 100.0 * angle.cos(), y: 100.0 * angle.sin() } }));
+        }
+        let points = arc.flatten(0.01);
+        arc.merge_collinear(0.01);
+        assert!(arc.segments.len() > 1);
+        let merged = arc.flatten(0.01);
+        for p in points {
+            let distance = merged.windows(2)
+                .map(|pair| distance_to_segment(p, pair[0], pair[1]))
+                .fold(f64::INFINITY, f64::min);
+            assert!(distance <= 0.01);
+        }
+    }
+
+    #[test]
+    fn test_curve_data_from_svg_path() {
+        let p = |x: f64, y: f64| Point { x, y };
+        let line = |x: f64, y: f64| Segment::Line(LineSegment { point_2: Point { x, y } });
+
+        // Implicit lineto after a moveto, both absolute and relative.
+        let paths = CurveData::from_svg_path("M 1 2 3 4 5,6 m 1 1 2 0 0 2").unwrap();
+        assert_eq!(2, paths.len());
+        assert_eq!(CurveData { start: p(1.0, 2.0), segments: vec![line(3.0, 4.0), line(5.0, 6.0)] }, paths[0]);
+        assert_eq!(CurveData { start: p(6.0, 7.0), segments: vec![line(8.0, 7.0), line(8.0, 9.0)] }, paths[1]);
+
+        // Repeated command letters and relative horizontal and vertical lines.
+        let paths = CurveData::from_svg_path("M10,10 L20,10 L20,20 h-5 v5 H0 V0 l1-1").unwrap();
+        assert_eq!(
+            vec![line(20.0, 10.0), line(20.0, 20.0), line(15.0, 20.0), line(15.0, 25.0), line(0.0, 25.0), line(0.0, 0.0), line(1.0, -1.0)],
+            paths[0].segments
+        );
+
+        // Relative curves take every control point from the start of the segment.
+        let paths = CurveData::from_svg_path("m1 1c1 0 2 1 2 2s1 2 2 2q1 0 1 1t1 1").unwrap();
+        assert_eq!(
+            vec![
+                Segment::CubicBezier(CubicBezierSegment { point_2: p(2.0, 1.0), point_3: p(3.0, 2.0), point_4: p(3.0, 3.0) }),
+                Segment::SmoothCubicBezier(SmoothCubicBezierSegment { point_3: p(4.0, 5.0), point_4: p(5.0, 5.0) }),
+                Segment::QuadraticBezier(QuadraticBezierSegment { point_2: p(6.0, 5.0), point_3: p(6.0, 6.0) }),
+                Segment::SmoothQuadraticBezier(SmoothQuadraticBezierSegment { point_3: p(7.0, 7.0) })
+            ],
+            paths[0].segments
+        );
+
+        // Flags need no separators, and numbers split at a second point or a sign.
+        let paths = CurveData::from_svg_path("M0 0a5 5 30 1010 10A1.5.5-2e1 0 1 .5-1E1").unwrap();
+        assert_eq!(
+            vec![
+                Segment::Arc(ArcSegment { radius_x: 5.0, radius_y: 5.0, rotation: 30.0, large_arc: true, sweep: false, point_2: p(10.0, 10.0) }),
+                Segment::Arc(ArcSegment { radius_x: 1.5, radius_y: 0.5, rotation: -20.0, large_arc: false, sweep: true, point_2: p(0.5, -10.0) })
+            ],
+            paths[0].segments
+        );
+
+        // A closepath returns to the start, and drawing on starts a new sub-path there.
+        let paths = CurveData::from_svg_path("M 0 0 L 10 0 L 10 10 Z l 5 5 z M 1 1 L 0 0 L 1 1 Z").unwrap();
+        assert_eq!(3, paths.len());
+        assert_eq!(vec![line(10.0, 0.0), line(10.0, 10.0), line(0.0, 0.0)], paths[0].segments);
+        assert_eq!(CurveData { start: p(0.0, 0.0), segments: vec![line(5.0, 5.0), line(0.0, 0.0)] }, paths[1]);
+        assert_eq!(vec![line(0.0, 0.0), line(1.0, 1.0)], paths[2].segments);
+
+        assert_eq!(Ok(vec![]), CurveData::from_svg_path(" "));
+        assert_eq!(0, CurveData::from_svg_path("L 1 1").unwrap_err().position);
+        assert_eq!(6, CurveData::from_svg_path("M 0 0 X 1").unwrap_err().position);
+        assert_eq!(10, CurveData::from_svg_path("M 0 0 L 1 ").unwrap_err().position);
+        assert_eq!(8, CurveData::from_svg_path("M 0 0 z 1 1").unwrap_err().position);
+        assert_eq!(14, CurveData::from_svg_path("M 0 0 A 1 1 0 2 0 1 1").unwrap_err().position);
+
+        // Numbers and relative coordinates must stay finite.
+        let error = CurveData::from_svg_path("M 1e999 0").unwrap_err();
+        assert_eq!(ParseError { position: 2, message: "number out of range" }, error);
+        let error = CurveData::from_svg_path("M 1e308 0 h 1e308").unwrap_err();
+        assert_eq!(ParseError { position: 12, message: "coordinate out of range" }, error);
+        assert!(CurveData::from_svg_path("M 0 0 L 1 -1e999").is_err());
+    }
+
+    #[test]
+    fn test_curve_data_to_svg_path() {
+        let data: CurveData = serde_json::from_str(r#"[[0, -1.5], ["L", [10, 0.1]], ["Q", [1, 2], [3, 4]], ["T", [5, 6]], ["C", [1, 2], [3, 4], [5, 6]], ["S", [7, 8], [9, 10]], ["A", 5, 2.5, 30, true, false, [1e-7, 123456.789]]]"#).unwrap();
+
+        let d = data.to_svg_path();
+        assert_eq!("M 0 -1.5 L 10 0.1 Q 1 2 3 4 T 5 6 C 1 2 3 4 5 6 S 7 8 9 10 A 5 2.5 30 1 0 0.0000001 123456.789", d);
+        assert_eq!(vec![data], CurveData::from_svg_path(&d).unwrap());
+
+        let thirds: CurveData = serde_json::from_str(r#"[[0.1, 0.2], ["L", [0.30000000000000004, 0.3333333333333333]]]"#).unwrap();
+        assert_eq!(vec![thirds.clone()], CurveData::from_svg_path(&thirds.to_svg_path()).unwrap());
+    }
+
+    #[test]
+    fn test_curve_data_polyline() {
+        let triangle = [Point { x: 0.0, y: 0.0 }, Point { x: 4.0, y: 0.0 }, Point { x: 0.0, y: 3.0 }];
+
+        let polyline = CurveData::polyline(&triangle);
+        assert_eq!(triangle[0], polyline.start);
+        assert_eq!(2, polyline.segments.len());
+        assert_eq!(triangle[2], polyline.end_point());
+
+        let polygon = CurveData::polygon(&[triangle[0], triangle[1], triangle[2], triangle[0]]);
+        assert_eq!(polyline, polygon);
+        assert_eq!(polyline, CurveData::polygon(&triangle));
+
+        let Shape::Region(region) = Shape::region(None, Some(0), vec![polygon]) else { panic!() };
+        assert_eq!(1, region.winding_number(Point { x: 1.0, y: 1.0 }).abs());
+
+        let point = CurveData::polygon(&triangle[..1]);
+        assert_eq!(triangle[0], point.start);
+        assert!(point.segments.is_empty());
+    }
+
+    #[test]
+    fn test_curve_data_rect() {
+        let rect = CurveData::rect(1.0, 2.0, 10.0, 5.0);
+        let corners: Vec<Point> = rect.segments.iter().map(Segment::end_point).collect();
+        assert_eq!(Point { x: 1.0, y: 2.0 }, rect.start);
+        assert_eq!(
+            vec![Point { x: 11.0, y: 2.0 }, Point { x: 11.0, y: 7.0 }, Point { x: 1.0, y: 7.0 }, Point { x: 1.0, y: 2.0 }],
+            corners
+        );
+
+        let rounded = CurveData::rounded_rect(1.0, 2.0, 10.0, 5.0, 1.0);
+        assert_eq!(8, rounded.segments.len());
+        assert_eq!(Point { x: 2.0, y: 2.0 }, rounded.start);
+        assert_eq!(rounded.start, rounded.end_point());
+        assert_eq!(
+            Segment::QuadraticBezier(QuadraticBezierSegment { point_2: Point { x: 11.0, y: 2.0 }, point_3: Point { x: 11.0, y: 3.0 } }),
+            rounded.segments[1]
+        );
+
+        // The radius is limited to half the height.
+        let pill = CurveData::rounded_rect(0.0, 0.0, 10.0, 4.0, 5.0);
+        assert_eq!(Point { x: 2.0, y: 0.0 }, pill.start);
+        assert_eq!(Point { x: 10.0, y: 2.0 }, pill.segments[1].end_point());
+    }
+
+    #[test]
+    fn test_curve_data_ellipse() {
+        let center = Point { x: 10.0, y: 20.0 };
+        let ellipse = CurveData::ellipse(center, 4.0, 2.0);
+        let ends: Vec<Point> = ellipse.segments.iter().map(Segment::end_point).collect();
+        assert_eq!(Point { x: 14.0, y: 20.0 }, ellipse.start);
+        assert_eq!(
+            vec![Point { x: 10.0, y: 22.0 }, Point { x: 6.0, y: 20.0 }, Point { x: 10.0, y: 18.0 }, Point { x: 14.0, y: 20.0 }],
+            ends
+        );
+
+        // Every sampled point stays close to the circle.
+        let circle = CurveData::circle(center, 3.0);
+        assert_eq!(4, circle.segments.len());
+        for p in circle.sample_points(16) {
+            let r = (p.x - center.x).hypot(p.y - center.y);
+            assert!((r - 3.0).abs() < 3.0 * 3e-4, "{:?} is {} from the center", p, r);
+        }
+    }
+
+    #[test]
+    fn test_curve_data_flatten() {
+        // A cubic with its control points on the chord is flat at once.
+        let straight: CurveData = serde_json::from_str(r#"[[0, 0], ["C", [1, 1], [2, 2], [3, 3]]]"#).unwrap();
+        assert_eq!(vec![Point { x: 0.0, y: 0.0 }, Point { x: 3.0, y: 3.0 }], straight.flatten(0.1));
+
+        let lines = CurveData::rect(0.0, 0.0, 2.0, 1.0);
+        assert_eq!(5, lines.flatten(0.0).len());
+
+        // The first halving puts B(1/2) = (1/2, 3/4) on the polyline.
+        let arch: CurveData = serde_json::from_str(r#"[[0, 0], ["C", [0, 1], [1, 1], [1, 0]]]"#).unwrap();
+        let points = arch.flatten(0.01);
+        assert!(points.contains(&Point { x: 0.5, y: 0.75 }));
+        assert_eq!(Point { x: 1.0, y: 0.0 }, *points.last().unwrap());
+        assert!(points.len() > 4 && points.len() < 64, "{} points", points.len());
+
+        let coarse = arch.flatten(1.0);
+        assert_eq!(vec![Point { x: 0.0, y: 0.0 }, Point { x: 1.0, y: 0.0 }], coarse);
+
+        // Every point of a flattened circle, and every midpoint of its lines, stays within the
+        // tolerance of the circle, up to the error of the Bezier approximation.
+        let center = Point { x: 10.0, y: 20.0 };
+        let points = CurveData::circle(center, 5.0).flatten(0.01);
+        for pair in points.windows(2) {
+            for p in [pair[0], midpoint(pair[0], pair[1])] {
+                let r = (p.x - center.x).hypot(p.y - center.y);
+                assert!((r - 5.0).abs() < 0.01 + 5.0 * 3e-4, "{:?} is {} from the center", p, r);
+            }
+        }
+
+        let quadratic: CurveData = serde_json::from_str(r#"[[0, 0], ["Q", [1, 2], [2, 0]]]"#).unwrap();
+        assert!(quadratic.flatten(0.001).contains(&Point { x: 1.0, y: 1.0 }));
+
+        // Coincident control points, or a zero tolerance, stop at the depth limit.
+        let point: CurveData = serde_json::from_str(r#"[[1, 1], ["C", [1, 1], [1, 1], [1, 1]]]"#).unwrap();
+        assert_eq!(2, point.flatten(0.0).len());
+        assert_eq!(1 + (1 << MAX_FLATTEN_DEPTH), arch.flatten(0.0).len());
+    }
+
+    #[test]
+    fn test_curve_data_length() {
+        assert_eq!(4.0, CurveData::rect(0.0, 0.0, 1.0, 1.0).length(0.1));
+        assert_eq!(5.0, CurveData::polyline(&[Point { x: 1.0, y: 1.0 }, Point { x: 4.0, y: 5.0 }]).length(0.1));
+        assert_eq!(0.0, CurveData { start: Point { x: 1.0, y: 1.0 }, segments: vec![] }.length(0.1));
+
+        let straight: CurveData = serde_json::from_str(r#"[[0, 0], ["Q", [1, 0], [2, 0]], ["C", [2, 0], [2, 0], [2, 0]]]"#).unwrap();
+        assert_eq!(2.0, straight.length(0.0));
+
+        let circle = CurveData::circle(Point { x: 0.0, y: 0.0 }, 10.0);
+        let circumference = 2.0 * std::f64::consts::PI * 10.0;
+        assert!((circle.length(0.001) - circumference).abs() < 0.01);
+        assert!(circle.length(1.0) < circle.length(0.001));
+    }
+
+    #[test]
+    fn test_flatten_cache() {
+        let mut cache = FlattenCache::new();
+        let arch: CurveData = serde_json::from_str(r#"[[0, 0], ["C", [0, 1], [1, 1], [1, 0]]]"#).unwrap();
+
+        let first = cache.flatten(&arch, 0.01).to_vec();
+        assert_eq!(arch.flatten(0.01), first);
+        assert_eq!(1, cache.computed());
+
+        // An identical curve at the same tolerance reuses the polyline.
+        let copy = arch.clone();
+        assert_eq!(first, cache.flatten(&copy, 0.01));
+        assert_eq!(arch.length(0.01), cache.length(&copy, 0.01));
+        assert_eq!(1, cache.computed());
+
+        // A different tolerance or an edited curve is flattened afresh.
+        cache.flatten(&arch, 0.1);
+        assert_eq!(2, cache.computed());
+
+        let mut edited = arch.clone();
+        edited.segments[0] = Segment::QuadraticBezier(QuadraticBezierSegment { point_2: Point { x: 0.0, y: 1.0 }, point_3: Point { x: 1.0, y: 0.0 } });
+        cache.flatten(&edited, 0.01);
+        assert_eq!(3, cache.computed());
+        assert_eq!(3, cache.len());
+
+        let arc = |sweep| CurveData {
+            start: Point { x: 0.0, y: 0.0 },
+            segments: vec![Segment::Arc(ArcSegment { radius_x: 1.0, radius_y: 1.0, rotation: 0.0, large_arc: false, sweep, point_2: Point { x: 2.0, y: 0.0 } })]
         };
+        assert_ne!(cache.flatten(&arc(false), 0.01).to_vec(), cache.flatten(&arc(true), 0.01).to_vec());
+
+        cache.clear();
+        assert!(cache.is_empty());
     }
 
     #[test]
-    fn test_image_de() {
+    fn test_curve_data_iter_with_start() {
+        let dat_str = r#"[
+  [0, 1],
+  ["L", [2, 3]],
+  ["Q", [4, 5], [6, 7]],
+  ["C", [8, 9], [10, 11], [12, 13]],
+  ["L", [14, 15]]
+]"#;
+        let dat: CurveData = serde_json::from_str(dat_str).unwrap();
+        let starts: Vec<Point> = dat.iter_with_start().map(|(start, _)| start).collect();
+        assert_eq!(4, starts.len());
+        assert_near!(Point { x: 0.0, y: 1.0 }, starts[0]);
+        assert_near!(Point { x: 2.0, y: 3.0 }, starts[1]);
+        assert_near!(Point { x: 6.0, y: 7.0 }, starts[2]);
+        assert_near!(Point { x: 12.0, y: 13.0 }, starts[3]);
+        assert_near!(Point { x: 14.0, y: 15.0 }, dat.end_point());
+
+        let empty = CurveData { start: Point { x: 1.0, y: 2.0 }, segments: vec![] };
+        assert_eq!(0, empty.iter_with_start().count());
+        assert_near!(Point { x: 1.0, y: 2.0 }, empty.end_point());
+    }
+
+    #[test]
+    fn test_image_colors() {
         let image_str = r#"{
-  "width": 640,
-  "height": 480,
-  "unit-per-inch": 140,
-  "pens": [],
-  "brushes": [],
+  "width": 100,
+  "height": 100,
+  "unit-per-inch": 96,
+  "pens": [
+    { "pattern": { "type": "monochrome", "color": [1, 0, 0] }, "width": 1, "cap": "butt", "join": "miter" },
+    {
+      "pattern": {
+        "type": "linear-gradient",
+        "point-1": [0, 0],
+        "color-1": [1, 0, 0],
+        "point-2": [1, 1],
+        "color-2": [0, 0, 1, 0.5]
+      },
+      "width": 1,
+      "cap": "butt",
+      "join": "miter"
+    }
+  ],
+  "brushes": [
+    { "pattern": { "type": "monochrome", "color": [0.5, 0.5, 0.5] } }
+  ],
   "shapes": []
 }"#;
         let image: Image = serde_json::from_str(image_str).unwrap();
-        assert_near!(640.0, image.width);
-        assert_near!(480.0, image.height);
-        assert_near!(140.0, image.unit_per_inch);
-        assert_eq!(None, image.editor);
 
-        let image2_str = r#"{
-  "width": 1920,
-  "height": 1080,
-  "unit-per-inch": 220,
-  "editor": "T2SY95",
+        let colors = image.colors();
+        assert_eq!(3, colors.len());
+        assert_eq!(Color { red: 1.0, green: 0.0, blue: 0.0, alpha: 1.0 }, colors[0]);
+        assert_eq!(Color { red: 0.0, green: 0.0, blue: 1.0, alpha: 0.5 }, colors[1]);
+        assert_eq!(Color { red: 0.5, green: 0.5, blue: 0.5, alpha: 1.0 }, colors[2]);
+
+        let palette = [
+            Color { red: 1.0, green: 0.0, blue: 0.0, alpha: 1.0 },
+            Color { red: 0.0, green: 0.0, blue: 0.99, alpha: 0.5 }
+        ];
+        let off_palette = image.check_palette(&palette, 0.02);
+        assert_eq!(vec![Color { red: 0.5, green: 0.5, blue: 0.5, alpha: 1.0 }], off_palette);
+
+        let off_palette = image.check_palette(&palette, 0.0);
+        assert_eq!(2, off_palette.len());
+    }
+
+    #[test]
+    fn test_alpha_mode() {
+        let image_str = r#"{
+  "width": 100,
+  "height": 100,
+  "unit-per-inch": 96,
+  "alpha-mode": "premultiplied",
   "pens": [],
-  "brushes": [],
+  "brushes": [
+    { "pattern": { "type": "monochrome", "color": [0.4, 0.2, 0, 0.5] } },
+    { "pattern": { "type": "monochrome", "color": [0, 0, 0, 0] } }
+  ],
   "shapes": []
 }"#;
-        let image2: Image = serde_json::from_str(image2_str).unwrap();
-        assert_near!(1920.0, image2.width);
-        assert_near!(1080.0, image2.height);
-        assert_near!(220.0, image2.unit_per_inch);
-        assert_eq!(Some(String::from("T2SY95")), image2.editor);
+        let image: Image = serde_json::from_str(image_str).unwrap();
+        assert!(Some(AlphaMode::Premultiplied) == image.alpha_mode);
+
+        let straight = Pattern::Monochrome(MonochromePattern { color: Color { red: 0.8, green: 0.4, blue: 0.0, alpha: 0.5 } });
+        assert!(straight == image.straight_pattern(&image.brushes[0].pattern));
+
+        let transparent = Pattern::Monochrome(MonochromePattern { color: Color { red: 0.0, green: 0.0, blue: 0.0, alpha: 0.0 } });
+        assert!(transparent == image.straight_pattern(&image.brushes[1].pattern));
+
+        let image = Image { alpha_mode: Some(AlphaMode::Straight), ..image };
+        assert!(image.brushes[0].pattern == image.straight_pattern(&image.brushes[0].pattern));
+        assert_eq!(r#""straight""#, serde_json::to_string(&image.alpha_mode).unwrap());
     }
 
     #[test]
-    fn test_image_ser() {
-        let image = Image {
-            width: 200.0,
-            height: 100.0,
-            unit_per_inch: 72.0,
-            editor: Some(String::from("A7E6W9UF")),
-            pens: vec![],
-            brushes: vec![],
-            shapes: vec![]
-        };
-        let image_str = serde_json::to_string(&image).unwrap();
-        assert_eq!(r#"{"width":200.0,"height":100.0,"unit-per-inch":72.0,"editor":"A7E6W9UF","pens":[],"brushes":[],"shapes":[]}"#, &image_str);
+    fn test_image_redact() {
+        let image_str = r#"{
+  "width": 100,
+  "height": 100,
+  "unit-per-inch": 96,
+  "pens": [
+    {
+      "pattern": { "type": "linear-gradient", "point-1": [0, 0], "color-1": [1, 0, 0], "point-2": [1, 1], "color-2": [0, 1, 0] },
+      "width": 3
+    }
+  ],
+  "brushes": [
+    { "pattern": { "type": "monochrome", "color": [0, 0, 1] } },
+    {
+      "pattern": {
+        "type": "radial-gradient",
+        "center-1": [0, 0], "radius-1": 0, "color-1": [1, 1, 0],
+        "center-2": [0, 0], "radius-2": 5, "color-2": [0, 1, 1]
+      }
+    }
+  ],
+  "shapes": [
+    { "type": "curve", "pen": 0, "data": [[0, 0], ["L", [10, 10]]] }
+  ]
+}"#;
+        let mut image: Image = serde_json::from_str(image_str).unwrap();
+        let gray = Color { red: 0.5, green: 0.5, blue: 0.5, alpha: 1.0 };
 
-        let image2 = Image {
-            width: 100.0,
-            height: 200.0,
-            unit_per_inch: 96.0,
-            editor: None,
-            pens: vec![],
-            brushes: vec![],
-            shapes: vec![]
-        };
-        let image2_str = serde_json::to_string(&image2).unwrap();
-        assert_eq!(r#"{"width":100.0,"height":200.0,"unit-per-inch":96.0,"pens":[],"brushes":[],"shapes":[]}"#, &image2_str);
+        image.redact(gray);
+
+        let patterns = image.pens.iter().map(|pen| &pen.pattern)
+            .chain(image.brushes.iter().map(|brush| &brush.pattern));
+
+        for pattern in patterns {
+            match pattern {
+                Pattern::Monochrome(p) => assert_eq!(gray, p.color),
+                _ => panic!("unexpected pattern type")
+            }
+        }
+
+        assert_eq!(3.0, image.pens[0].width);
+        assert_eq!(1, image.shapes.len());
     }
 
     #[test]
-    fn test_point_de() {
-        let p_str = r#"[2.4, 5.6]"#;
-        let p: Point = serde_json::from_str(p_str).unwrap();
-        assert_near!(Point { x: 2.4, y: 5.6 }, p);
+    fn test_apply_theme() {
+        let image_str = r#"{
+  "width": 100,
+  "height": 100,
+  "unit-per-inch": 96,
+  "pens": [
+    { "pattern": { "type": "monochrome", "color": [0, 0, 0] }, "width": 1, "name": "outline" },
+    { "pattern": { "type": "monochrome", "color": [0, 0, 0] }, "width": 2 },
+    { "pattern": { "type": "monochrome", "color": [0, 0, 0] }, "width": 3, "name": "missing" }
+  ],
+  "brushes": [
+    { "pattern": { "type": "monochrome", "color": [1, 1, 1] }, "name": "fill" }
+  ],
+  "shapes": []
+}"#;
+        let theme_str = r#"{
+  "width": 10,
+  "height": 10,
+  "unit-per-inch": 96,
+  "pens": [
+    { "pattern": { "type": "monochrome", "color": [0, 1, 0] }, "width": 2 },
+    { "pattern": { "type": "monochrome", "color": [1, 0, 0] }, "width": 4, "name": "outline" }
+  ],
+  "brushes": [
+    { "pattern": { "type": "monochrome", "color": [0, 0, 1] }, "name": "fill" }
+  ],
+  "shapes": []
+}"#;
+        let mut image: Image = serde_json::from_str(image_str).unwrap();
+        let theme: Image = serde_json::from_str(theme_str).unwrap();
+        let unnamed = image.pens[1].clone();
+        let unmatched = image.pens[2].clone();
 
-        let bad_p1_str = r#"[1]"#;
-        let bad_p1 = serde_json::from_str::<Point>(bad_p1_str);
-        assert!(bad_p1.is_err());
+        image.apply_theme(&theme);
 
-        let bad_p2_str = r#"[1, 2, 3]"#;
-        let bad_p2 = serde_json::from_str::<Point>(bad_p2_str);
-        assert!(bad_p2.is_err());
+        assert!(theme.pens[1] == image.pens[0]);
+        assert!(unnamed == image.pens[1]);
+        assert!(unmatched == image.pens[2]);
+        assert!(theme.brushes[0] == image.brushes[0]);
     }
 
     #[test]
-    fn test_point_ser() {
-        let p = Point { x: 10.0, y: -8.5 };
-        let p_str = serde_json::to_string(&p).unwrap();
-        assert_eq!(r#"[10.0,-8.5]"#, &p_str);
+    fn test_image_stats() {
+        let image_str = r#"{
+  "width": 100,
+  "height": 100,
+  "unit-per-inch": 96,
+  "pens": [
+    { "pattern": { "type": "monochrome", "color": [1, 0, 0] }, "width": 4, "cap": "butt", "join": "miter" }
+  ],
+  "brushes": [],
+  "shapes": [
+    { "type": "curve", "pen": 0, "data": [[10, 20], ["L", [30, 40]], ["Q", [50, 0], [60, 20]]] },
+    {
+      "type": "group",
+      "content": [
+        { "type": "region", "data": [[[-5, 50], ["L", [10, 60]], ["C", [10, 70], [20, 90], [0, 80]]]] },
+        { "type": "group", "content": [] }
+      ]
+    }
+  ]
+}"#;
+        let image: Image = serde_json::from_str(image_str).unwrap();
+
+        assert_eq!(ImageStats {
+            pens: 1,
+            brushes: 0,
+            shapes: 2,
+            groups: 2,
+            curves: 1,
+            regions: 1,
+            segments: 4
+        }, image.stats());
+
+        let (min, max) = image.bounding_box().unwrap();
+        assert_near!(Point { x: -5.0, y: -2.0 }, min);
+        assert_near!(Point { x: 62.0, y: 90.0 }, max);
+
+        let (min, max) = image.shapes[1].bounding_box(&image).unwrap();
+        assert_near!(Point { x: -5.0, y: 50.0 }, min);
+        assert_near!(Point { x: 20.0, y: 90.0 }, max);
+
+        let empty = Image { shapes: vec![], ..image };
+        assert!(empty.bounding_box().is_none());
     }
 
     #[test]
-    fn test_color_de() {
-        let c1_str = r#"[0.5, 1.0, 0.0]"#;
-        let c1: Color = serde_json::from_str(c1_str).unwrap();
-        assert_near!(Color { red: 0.5, green: 1.0, blue: 0.0, alpha: 1.0 }, c1);
+    fn test_bounds_of() {
+        let image_str = r#"{
+  "width": 100,
+  "height": 100,
+  "unit-per-inch": 96,
+  "pens": [],
+  "brushes": [],
+  "shapes": [
+    { "type": "region", "data": [[[10, 10], ["L", [20, 10]], ["L", [20, 20]]]] },
+    { "type": "region", "data": [[[50, 50], ["L", [60, 50]], ["L", [60, 60]]]] },
+    { "type": "region", "data": [[[70, 5], ["L", [90, 5]], ["L", [90, 95]]]] }
+  ]
+}"#;
+        let image: Image = serde_json::from_str(image_str).unwrap();
 
-        let c2_str = r#"[0.541, 0.169, 0.886, 0.7]"#;
-        let c2: Color = serde_json::from_str(c2_str).unwrap();
-        assert_near!(Color { red: 0.541, green: 0.169, blue: 0.886, alpha: 0.7 }, c2);
+        let (min, max) = image.bounds_of(&[0, 1]).unwrap();
+        assert_near!(Point { x: 10.0, y: 10.0 }, min);
+        assert_near!(Point { x: 60.0, y: 60.0 }, max);
 
-        let bad_c1_str = r#"[0.1, 0.2]"#;
-        let bad_c1 = serde_json::from_str::<Color>(bad_c1_str);
-        assert!(bad_c1.is_err());
+        let (min, max) = image.bounds_of(&[1, 7]).unwrap();
+        assert_near!(Point { x: 50.0, y: 50.0 }, min);
+        assert_near!(Point { x: 60.0, y: 60.0 }, max);
 
-        let bad_c2_str = r#"[0.1, 0.2, 0.3, 0.4, 0.5]"#;
-        let bad_c2 = serde_json::from_str::<Color>(bad_c2_str);
-        assert!(bad_c2.is_err());
+        assert!(image.bounds_of(&[]).is_none());
     }
 
     #[test]
-    fn test_color_ser() {
-        let c1 = Color { red: 1.0, green: 0.5, blue: 0.25, alpha: 1.0 };
-        let c1_str = serde_json::to_string(&c1).unwrap();
-        assert_eq!(r#"[1.0,0.5,0.25]"#, &c1_str);
+    fn test_validate_off_canvas() {
+        let image_str = r#"{
+  "width": 100,
+  "height": 100,
+  "unit-per-inch": 96,
+  "pens": [
+    { "pattern": { "type": "monochrome", "color": [0, 0, 0] }, "width": 4 }
+  ],
+  "brushes": [],
+  "shapes": [
+    { "type": "curve", "pen": 0, "data": [[10, 10], ["L", [90, 90]]] },
+    { "type": "curve", "pen": 0, "data": [[50, 50], ["L", [120, 50]]] },
+    { "type": "region", "data": [[[-10, 20], ["L", [30, 20]], ["L", [30, 40]]]] }
+  ]
+}"#;
+        let image: Image = serde_json::from_str(image_str).unwrap();
 
-        let c2 = Color { red: 0.25, green: 0.125, blue: 1.0, alpha: 0.5 };
-        let c2_str = serde_json::to_string(&c2).unwrap();
-        assert_eq!(r#"[0.25,0.125,1.0,0.5]"#, &c2_str);
+        let warnings = image.warnings();
+        assert_eq!(vec![ValidationWarning::OffCanvas { index: 1 }, ValidationWarning::OffCanvas { index: 2 }], warnings);
+
+        let (min, max) = image.expanded_canvas();
+        assert_near!(Point { x: -10.0, y: 0.0 }, min);
+        assert_near!(Point { x: 122.0, y: 100.0 }, max);
+
+        let inside = Image { shapes: vec![image.shapes[0].clone()], ..image };
+        assert!(inside.warnings().is_empty());
+        let (min, max) = inside.expanded_canvas();
+        assert!(Point { x: 0.0, y: 0.0 } == min);
+        assert!(Point { x: 100.0, y: 100.0 } == max);
     }
 
     #[test]
-    fn test_pattern_de() {
-        let p1_str = r#"{
-  "type": "monochrome",
-  "color": [1, 1, 0]
+    fn test_validate_against() {
+        let image_str = r#"{
+  "width": 100,
+  "height": 100,
+  "unit-per-inch": 96,
+  "pens": [
+    { "pattern": { "type": "monochrome", "color": [0, 0, 0] }, "width": 4 }
+  ],
+  "brushes": [],
+  "shapes": [
+    { "type": "curve", "pen": 0, "data": [[0, 10], ["L", [90, 90]]] },
+    { "type": "group", "content": [
+      { "type": "region", "data": [[[10, 20], ["L", [30, 20]], ["L", [30, 40]]]] }
+    ] }
+  ]
 }"#;
-        let p1: Pattern = serde_json::from_str(p1_str).unwrap();
-        assert_near!(Pattern::Monochrome(MonochromePattern {
-            color: Color { red: 1.0, green: 1.0, blue: 0.0, alpha: 1.0 }
-        }), p1);
+        let reference: Image = serde_json::from_str(image_str).unwrap();
+
+        let errors = reference.validate_against(&reference);
+        assert!(vec![0.0, 0.0] == errors);
+
+        let mut image = reference.clone();
+        image.map_points(|p| Point { x: p.x, y: p.y * 1.01 });
+
+        let errors = image.validate_against(&reference);
+        assert_eq!(2, errors.len());
+        assert_near!(0.01, errors[0]);
+        assert_near!(0.01, errors[1]);
+
+        image.shapes.push(reference.shapes[0].clone());
+        let Shape::Curve(curve) = &mut image.shapes[0] else { panic!() };
+        curve.pen = 1;
+
+        let errors = image.validate_against(&reference);
+        assert!(errors[0].is_infinite());
+        assert_near!(0.01, errors[1]);
+        assert!(errors[2].is_infinite());
+
+        // A NaN coordinate, or a finite one against an infinite reference, is not hidden by
+        // the other, matching coordinates.
+        let mut broken = reference.clone();
+        let Shape::Curve(curve) = &mut broken.shapes[0] else { panic!() };
+        curve.data.start.x = f64::NAN;
+        assert_eq!(f64::INFINITY, broken.validate_against(&reference)[0]);
+        assert_eq!(f64::INFINITY, reference.validate_against(&broken)[0]);
+
+        let mut far = reference.clone();
+        let Shape::Curve(curve) = &mut far.shapes[0] else { panic!() };
+        curve.data.start.y = f64::INFINITY;
+        assert_eq!(f64::INFINITY, reference.validate_against(&far)[0]);
+    }
 
-        let p2_str = r#"{
-  "type": "linear-gradient",
-  "point-1": [0, 0],
-  "color-1": [0, 1, 1],
-  "point-2": [100, 100],
-  "color-2": [1, 1, 1]
+    #[test]
+    fn test_validate() {
+        let image_str = r#"{
+  "width": 100,
+  "height": 100,
+  "unit-per-inch": 72,
+  "pens": [{ "width": 1, "pattern": { "type": "monochrome", "color": [0, 0, 0] } }],
+  "brushes": [{ "pattern": { "type": "monochrome", "color": [1, 0, 0] } }],
+  "shapes": [
+    { "type": "curve", "pen": 0, "data": [[0, 0], ["L", [10, 10]]] },
+    { "type": "group", "content": [
+      { "type": "curve", "pen": 0, "data": [[0, 0], ["L", [10, 10]]] },
+      { "type": "group", "content": [
+        { "type": "region", "pen": 0, "brush": 0, "data": [[[0, 0], ["L", [10, 0]], ["L", [10, 10]]]] }
+      ] }
+    ] }
+  ]
 }"#;
-        let p2: Pattern = serde_json::from_str(p2_str).unwrap();
-        assert_near!(Pattern::LinearGradient(LinearGradientPattern {
-            point_1: Point { x: 0.0, y: 0.0 },
-            color_1: Color { red: 0.0, green: 1.0, blue: 1.0, alpha: 1.0 },
-            point_2: Point { x: 100.0, y: 100.0 },
-            color_2: Color { red: 1.0, green: 1.0, blue: 1.0, alpha: 1.0 }
-        }), p2);
+        let mut image: Image = serde_json::from_str(image_str).unwrap();
+        assert_eq!(Ok(()), image.validate());
 
-        let p3_str = r#"{
-  "type": "radial-gradient",
-  "center-1": [50, 50],
-  "radius-1": 5,
-  "color-1": [1, 0, 1],
-  "center-2": [50, 50],
-  "radius-2": 70.7,
-  "color-2": [1, 0, 1, 0.1]
-}"#;
-        let p3: Pattern = serde_json::from_str(p3_str).unwrap();
-        assert_near!(Pattern::RadialGradient(RadialGradientPattern {
-            center_1: Point { x: 50.0, y: 50.0 },
-            radius_1: 5.0,
-            color_1: Color { red: 1.0, green: 0.0, blue: 1.0, alpha: 1.0 },
-            center_2: Point { x: 50.0, y: 50.0 },
-            radius_2: 70.7,
-            color_2: Color { red: 1.0, green: 0.0, blue: 1.0, alpha: 0.1 },
-        }), p3);
+        if let Shape::Curve(curve) = &mut image.shapes[0] {
+            curve.pen = 1;
+        }
+
+        let error = image.validate().unwrap_err();
+        assert_eq!(ValidationError::PenOutOfRange { path: vec![0], index: 1, count: 1 }, error);
+        assert_eq!("/shapes/0", error.pointer());
+        assert_eq!(Ok(()), image.validate_shape(3, &image.shapes[1]));
+        assert_eq!(Err(ValidationError::PenOutOfRange { path: vec![3], index: 1, count: 1 }), image.validate_shape(3, &image.shapes[0]));
+
+        image.shapes.remove(0);
+
+        if let Shape::Group(group) = &mut image.shapes[0]
+            && let Shape::Group(inner) = &mut group.content[1]
+            && let Shape::Region(region) = &mut inner.content[0] {
+            region.brush = Some(3);
+        }
+
+        let error = image.validate().unwrap_err();
+        assert_eq!(ValidationError::BrushOutOfRange { path: vec![0, 1, 0], index: 3, count: 1 }, error);
+        assert_eq!("/shapes/0/content/1/content/0", error.pointer());
     }
 
     #[test]
-    fn test_pattern_ser() {
-        let p1 = Pattern::Monochrome(MonochromePattern {
-            color: Color { red: 1.0, green: 0.0, blue: 0.0, alpha: 1.0 }
-        });
-        let p1_str = serde_json::to_string(&p1).unwrap();
-        assert_eq!(r#"{"type":"monochrome","color":[1.0,0.0,0.0]}"#, &p1_str);
+    fn test_validate_uniform_gradient() {
+        let image_str = r#"{
+  "width": 100,
+  "height": 100,
+  "unit-per-inch": 96,
+  "pens": [
+    { "pattern": { "type": "linear-gradient", "point-1": [0, 0], "color-1": [1, 0, 0], "point-2": [100, 0], "color-2": [1, 0, 0] }, "width": 2 }
+  ],
+  "brushes": [
+    { "pattern": { "type": "monochrome", "color": [1, 0, 0] } },
+    { "pattern": { "type": "linear-gradient", "point-1": [0, 0], "color-1": [1, 0, 0], "point-2": [100, 0], "color-2": [1, 0, 0], "stops": [[0.5, [0, 0, 1]]] } },
+    { "pattern": { "type": "radial-gradient", "center-1": [50, 50], "radius-1": 0, "color-1": [0, 1, 0], "center-2": [50, 50], "radius-2": 50, "color-2": [0, 1, 0] } },
+    { "pattern": { "type": "radial-gradient", "center-1": [0, 50], "radius-1": 10, "color-1": [0, 1, 0], "center-2": [100, 50], "radius-2": 10, "color-2": [0, 1, 0] } },
+    { "pattern": { "type": "linear-gradient", "point-1": [0, 0], "color-1": [1, 0, 0], "point-2": [100, 0], "color-2": [1, 0, 0], "extend": "none" } },
+    { "pattern": { "type": "conic-gradient", "center": [50, 50], "start-angle": 0, "stops": [[0, [0, 0, 1]], [1, [0, 0, 1]]] } }
+  ],
+  "shapes": []
+}"#;
+        let image: Image = serde_json::from_str(image_str).unwrap();
 
-        let p2 = Pattern::LinearGradient(LinearGradientPattern {
-            point_1: Point { x: 0.0, y: 0.0 },
-            color_1: Color { red: 0.5, green: 0.5, blue: 1.0, alpha: 1.0 },
-            point_2: Point { x: 100.0, y: 0.0 },
-            color_2: Color { red: 0.0, green: 0.0, blue: 1.0, alpha: 1.0 }
-        });
-        let p2_str = serde_json::to_string(&p2).unwrap();
-        assert_eq!(r#"{"type":"linear-gradient","point-1":[0.0,0.0],"color-1":[0.5,0.5,1.0],"point-2":[100.0,0.0],"color-2":[0.0,0.0,1.0]}"#, &p2_str);
+        assert!(Some(Color { red: 0.0, green: 1.0, blue: 0.0, alpha: 1.0 }) == image.brushes[2].pattern.uniform_color());
+        assert!(image.brushes[3].pattern.uniform_color().is_none());
 
-        let p3 = Pattern::RadialGradient(RadialGradientPattern {
-            center_1: Point { x: 50.0, y: 50.0 },
-            radius_1: 5.0,
-            color_1: Color { red: 0.0, green: 0.5, blue: 0.0, alpha: 1.0 },
-            center_2: Point { x: 50.0, y: 50.0 },
-            radius_2: 50.0,
-            color_2: Color { red: 0.0, green: 0.5, blue: 0.0, alpha: 0.25 },
-            
-        });
-        let p3_str = serde_json::to_string(&p3).unwrap();
-        assert_eq!(r#"{"type":"radial-gradient","center-1":[50.0,50.0],"radius-1":5.0,"color-1":[0.0,0.5,0.0],"center-2":[50.0,50.0],"radius-2":50.0,"color-2":[0.0,0.5,0.0,0.25]}"#, &p3_str);
+        let warnings = image.warnings();
+        assert_eq!(vec![
+            ValidationWarning::UniformPenGradient { index: 0 },
+            ValidationWarning::UniformBrushGradient { index: 2 },
+            ValidationWarning::UniformBrushGradient { index: 5 }
+        ], warnings);
     }
 
     #[test]
-    fn test_line_cap_de() {
-        let cap1_str = r#""butt""#;
-        let cap1: LineCap = serde_json::from_str(&cap1_str).unwrap();
-        assert!(LineCap::Butt == cap1);
+    fn test_validate_zero_area() {
+        let image_str = r#"{
+  "width": 100,
+  "height": 100,
+  "unit-per-inch": 96,
+  "pens": [],
+  "brushes": [],
+  "shapes": [
+    { "type": "region", "data": [[[10, 10], ["L", [50, 50]], ["L", [30, 30]]]] },
+    { "type": "group", "content": [{ "type": "region", "data": [[[10, 10], ["C", [20, 20], [30, 30], [40, 40]]], [[5, 5]]] }] },
+    { "type": "region", "data": [[[10, 10], ["L", [50, 50]], ["L", [30, 31]]]] },
+    { "type": "region", "data": [[[10, 10], ["L", [50, 50]]], [[10, 20], ["Q", [50, 50], [30, 30]]]] },
+    { "type": "region", "data": [] }
+  ]
+}"#;
+        let image: Image = serde_json::from_str(image_str).unwrap();
 
-        let cap2_str = r#""round""#;
-        let cap2: LineCap = serde_json::from_str(&cap2_str).unwrap();
-        assert!(LineCap::Round == cap2);
+        let warnings = image.warnings();
+        assert_eq!(vec![ValidationWarning::ZeroArea { index: 0 }, ValidationWarning::ZeroArea { index: 1 }], warnings);
+    }
+
+    #[test]
+    fn test_draw_list() {
+        let image_str = r#"{
+  "width": 100,
+  "height": 100,
+  "unit-per-inch": 96,
+  "pens": [
+    { "pattern": { "type": "monochrome", "color": [1, 0, 0] }, "width": 1, "cap": "butt", "join": "miter" },
+    { "pattern": { "type": "monochrome", "color": [1, 0, 0] }, "width": 1, "cap": "butt", "join": "miter" }
+  ],
+  "brushes": [
+    { "pattern": { "type": "monochrome", "color": [0, 1, 0] } }
+  ],
+  "shapes": [
+    { "type": "curve", "pen": 1, "data": [[0, 0], ["L", [10, 10]]] },
+    {
+      "type": "group",
+      "content": [
+        { "type": "region", "pen": 0, "brush": 0, "fill-opacity": 0.5, "data": [[[0, 0], ["L", [10, 0]], ["L", [10, 10]]]] }
+      ]
+    }
+  ]
+}"#;
+        let image: Image = serde_json::from_str(image_str).unwrap();
+
+        let ops = image.to_draw_list();
+        assert_eq!(2, ops.len());
+
+        let image2 = from_draw_list(&ops, image.width, image.height, image.unit_per_inch);
+        assert_eq!(1, image2.pens.len());
+        assert_eq!(1, image2.brushes.len());
+        assert_eq!(2, image2.shapes.len());
+
+        if let Shape::Curve(s) = &image2.shapes[0] {
+            assert_eq!(0, s.pen);
+        } else {
+            panic!("unexpected shape type");
+        }
+
+        if let Shape::Region(s) = &image2.shapes[1] {
+            assert_eq!(Some(0), s.pen);
+            assert_eq!(Some(0), s.brush);
+            assert_eq!(Some(0.5), s.fill_opacity);
+            assert_eq!(1, s.data.len());
+        } else {
+            panic!("unexpected shape type");
+        }
+    }
 
-        let cap3_str = r#""square""#;
-        let cap3: LineCap = serde_json::from_str(&cap3_str).unwrap();
-        assert!(LineCap::Square == cap3);
+    #[test]
+    fn test_image_builder() {
+        let black = Pattern::Monochrome(MonochromePattern { color: Color { red: 0.0, green: 0.0, blue: 0.0, alpha: 1.0 } });
+        let (pen, image) = Image::new(20.0, 10.0, 96.0).with_pen(Pen::new(black, 2.0));
+        let image = image.with_shape(Shape::curve(pen, CurveData {
+            start: Point { x: 0.0, y: 5.0 },
+            segments: vec![Segment::Line(LineSegment { point_2: Point { x: 20.0, y: 5.0 } })]
+        }));
+
+        assert_eq!(0, pen);
+        assert_eq!(
+            r#"{"width":20.0,"height":10.0,"unit-per-inch":96.0,"pens":[{"pattern":{"type":"monochrome","color":[0.0,0.0,0.0]},"width":2.0}],"brushes":[],"shapes":[{"type":"curve","pen":0,"data":[[0.0,5.0],["L",[20.0,5.0]]]}]}"#,
+            serde_json::to_string(&image).unwrap()
+        );
+
+        let mut image = image;
+        assert_eq!(1, image.add_pen(image.pens[0].clone()));
+        assert_eq!(1, image.add_shape(Shape::group(vec![])));
+        assert!(image.validate().is_ok());
+    }
 
-        let cap4_str = r#""bad-cap""#;
-        let cap4 = serde_json::from_str::<LineCap>(&cap4_str);
-        assert!(cap4.is_err());
+    #[test]
+    fn test_image_eq() {
+        let image_str = include_str!("../samples/region.lison");
+        let image1: Image = serde_json::from_str(image_str).unwrap();
+        let image2: Image = serde_json::from_str(image_str).unwrap();
+        assert_eq!(image1, image2);
+
+        let mut image3 = image2.clone();
+        image3.map_points(|p| Point { x: p.x + 1.0, y: p.y });
+        assert_ne!(image1, image3);
+
+        let build = || {
+            let (brush, image) = Image::new(10.0, 10.0, 72.0)
+                .with_brush(Brush::new(Pattern::Monochrome(MonochromePattern { color: Color { red: 1.0, green: 0.0, blue: 0.0, alpha: 1.0 } })));
+            image.with_shape(Shape::region(None, Some(brush), vec![CurveData { start: Point { x: 1.0, y: 2.0 }, segments: vec![] }]))
+        };
+        assert_eq!(build(), build());
     }
 
     #[test]
-    fn test_line_cap_ser() {
-        let cap1 = LineCap::Butt;
-        let cap1_str = serde_json::to_string(&cap1).unwrap();
-        assert_eq!(r#""butt""#, &cap1_str);
+    fn test_group_partition() {
+        let group_str = r#"{
+  "type": "group",
+  "content": [
+    { "type": "curve", "pen": 0, "data": [[0, 0]] },
+    { "type": "region", "brush": 0, "data": [] },
+    { "type": "curve", "pen": 1, "data": [[0, 0]] },
+    { "type": "region", "brush": 1, "data": [] }
+  ],
+  "edit-annot": "layer"
+}"#;
+        let group = match serde_json::from_str(group_str).unwrap() {
+            Shape::Group(group) => group,
+            _ => panic!("unexpected shape type")
+        };
 
-        let cap2 = LineCap::Round;
-        let cap2_str = serde_json::to_string(&cap2).unwrap();
-        assert_eq!(r#""round""#, &cap2_str);
+        let (curves, regions) = group.partition(|shape| matches!(shape, Shape::Curve(_)));
 
-        let cap3 = LineCap::Square;
-        let cap3_str = serde_json::to_string(&cap3).unwrap();
-        assert_eq!(r#""square""#, &cap3_str);
+        let pens: Vec<usize> = curves.content.iter().map(|shape| match shape {
+            Shape::Curve(s) => s.pen,
+            _ => panic!("unexpected shape type")
+        }).collect();
+        assert_eq!(vec![0, 1], pens);
+
+        let brushes: Vec<Option<usize>> = regions.content.iter().map(|shape| match shape {
+            Shape::Region(s) => s.brush,
+            _ => panic!("unexpected shape type")
+        }).collect();
+        assert_eq!(vec![Some(0), Some(1)], brushes);
+
+        assert_eq!(serde_json::json!("layer"), curves.edit_annot);
+        assert_eq!(serde_json::json!("layer"), regions.edit_annot);
     }
 
     #[test]
-    fn test_line_join_de() {
-        let join1_str = r#""miter""#;
-        let join1: LineJoin = serde_json::from_str(&join1_str).unwrap();
-        assert!(LineJoin::Miter == join1);
+    fn test_group_transform() {
+        let image_str = r#"{
+  "width": 100,
+  "height": 100,
+  "unit-per-inch": 96,
+  "pens": [{ "pattern": { "type": "monochrome", "color": [0, 0, 0] }, "width": 2 }],
+  "brushes": [],
+  "shapes": [{ "type": "group", "content": [
+    { "type": "curve", "pen": 0, "data": [[0, 0], ["L", [10, 10]]] }
+  ] }]
+}"#;
+        let mut image: Image = serde_json::from_str(image_str).unwrap();
+        let mut bounds_with = |transform: [f64; 6]| {
+            if let Shape::Group(group) = &mut image.shapes[0] {
+                group.transform = Some(transform);
+            }
 
-        let join2_str = r#""round""#;
-        let join2: LineJoin = serde_json::from_str(&join2_str).unwrap();
-        assert!(LineJoin::Round == join2);
+            image.bounding_box().unwrap()
+        };
 
-        let join3_str = r#""bevel""#;
-        let join3: LineJoin = serde_json::from_str(&join3_str).unwrap();
-        assert!(LineJoin::Bevel == join3);
+        let (min, max) = bounds_with([1.0, 0.0, 0.0, 1.0, 10.0, 20.0]);
+        assert!(Point { x: 9.0, y: 19.0 } == min);
+        assert!(Point { x: 21.0, y: 31.0 } == max);
+
+        let (min, max) = bounds_with([2.0, 0.0, 0.0, 3.0, 0.0, 0.0]);
+        assert!(Point { x: -2.0, y: -3.0 } == min);
+        assert!(Point { x: 22.0, y: 33.0 } == max);
+
+        // A quarter turn maps (x, y) to (-y, x).
+        let (min, max) = bounds_with([0.0, 1.0, -1.0, 0.0, 0.0, 0.0]);
+        assert!(Point { x: -11.0, y: -1.0 } == min);
+        assert!(Point { x: 1.0, y: 11.0 } == max);
+
+        // Points inside the group move so that they appear translated in the image.
+        bounds_with([2.0, 0.0, 0.0, 2.0, 0.0, 0.0]);
+        image.map_points(|p| Point { x: p.x + 5.0, y: p.y });
+        let data: Vec<&CurveData> = image.iter_curve_data().collect();
+        assert!(Point { x: 2.5, y: 0.0 } == data[0].start);
+
+        let ops = image.to_draw_list();
+        match &ops[0] {
+            DrawOp::Curve { data, .. } => {
+                assert!(Point { x: 5.0, y: 0.0 } == data.start);
+                assert!(Point { x: 25.0, y: 20.0 } == data.end_point());
+            },
+            _ => panic!("unexpected draw operation")
+        }
 
-        let join4_str = r#""bad-join""#;
-        let join4 = serde_json::from_str::<LineJoin>(&join4_str);
-        assert!(join4.is_err());
+        assert!(serde_json::from_str::<Shape>(r#"{ "type": "group", "transform": [1, 2, 2, 4, 0, 0], "content": [] }"#).is_err());
+        assert!(serde_json::from_str::<Shape>(r#"{ "type": "group", "transform": [1, 0, 0, 1], "content": [] }"#).is_err());
     }
 
     #[test]
-    fn test_line_join_ser() {
-        let join1 = LineJoin::Miter;
-        let join1_str = serde_json::to_string(&join1).unwrap();
-        assert_eq!(r#""miter""#, &join1_str);
-
-        let join2 = LineJoin::Round;
-        let join2_str = serde_json::to_string(&join2).unwrap();
-        assert_eq!(r#""round""#, &join2_str);
+    fn test_map_points_group_patterns() {
+        let image_str = r#"{
+  "width": 100,
+  "height": 100,
+  "unit-per-inch": 96,
+  "pens": [],
+  "brushes": [{ "pattern": { "type": "linear-gradient", "point-1": [0, 0], "color-1": [0, 0, 0], "point-2": [10, 0], "color-2": [255, 255, 255] } }],
+  "shapes": [
+    { "type": "region", "brush": 0, "data": [[[0, 0], ["L", [10, 0]], ["L", [10, 10]]]] },
+    { "type": "group", "transform": [2, 0, 0, 2, 0, 0], "content": [
+      { "type": "region", "brush": 0, "data": [[[0, 0], ["L", [10, 0]], ["L", [10, 10]]]] },
+      { "type": "region", "brush": 0, "data": [[[0, 0], ["L", [5, 0]], ["L", [5, 5]]]] }
+    ] },
+    { "type": "region", "brush": 1, "data": [[[0, 0], ["L", [10, 0]], ["L", [10, 10]]]] }
+  ]
+}"#;
+        let mut image: Image = serde_json::from_str(image_str).unwrap();
+        image.map_points(|p| Point { x: p.x + 5.0, y: p.y });
+
+        // The brush is copied for its use inside the group, which is mapped through the
+        // group transform just like the region data.
+        assert!(image.brushes.len() == 2);
+        let brush_of = |shape: &Shape| match shape {
+            Shape::Region(region) => region.brush,
+            _ => panic!("unexpected shape")
+        };
+        assert!(brush_of(&image.shapes[0]) == Some(0));
+        match &image.shapes[1] {
+            Shape::Group(group) => {
+                assert!(brush_of(&group.content[0]) == Some(1));
+                assert!(brush_of(&group.content[1]) == Some(1));
+            },
+            _ => panic!("unexpected shape")
+        }
+        assert!(brush_of(&image.shapes[2]) == Some(1), "out-of-range references are left alone");
 
-        let join3 = LineJoin::Bevel;
-        let join3_str = serde_json::to_string(&join3).unwrap();
-        assert_eq!(r#""bevel""#, &join3_str);
+        let start_of = |brush: &Brush| match &brush.pattern {
+            Pattern::LinearGradient(p) => (p.point_1, p.point_2),
+            _ => panic!("unexpected pattern")
+        };
+        assert!(start_of(&image.brushes[0]) == (Point { x: 5.0, y: 0.0 }, Point { x: 15.0, y: 0.0 }));
+        assert!(start_of(&image.brushes[1]) == (Point { x: 2.5, y: 0.0 }, Point { x: 12.5, y: 0.0 }));
     }
 
     #[test]
-    fn test_pen_de() {
-        let pen_str = r#"{
-  "pattern": {
-    "type": "monochrome",
-    "color": [0.3, 0.4, 0.5, 0.6]
-  },
-  "width": 5,
-  "cap": "butt",
-  "join": "bevel"
+    fn test_map_points_monochrome_shared() {
+        let image_str = r#"{
+  "width": 100,
+  "height": 100,
+  "unit-per-inch": 96,
+  "pens": [{ "pattern": { "type": "monochrome", "color": [0, 0, 0] }, "width": 1 }],
+  "brushes": [{ "pattern": { "type": "image", "data": "iVBORw0KGgo=" } }],
+  "shapes": [
+    { "type": "group", "transform": [2, 0, 0, 2, 0, 0], "content": [
+      { "type": "region", "pen": 0, "brush": 0, "data": [[[0, 0], ["L", [10, 0]], ["L", [10, 10]]]] }
+    ] },
+    { "type": "group", "transform": [1, 0, 0, 1, 5, 5], "content": [
+      { "type": "curve", "pen": 0, "data": [[0, 0], ["L", [10, 0]]] },
+      { "type": "region", "brush": 0, "data": [[[0, 0], ["L", [10, 0]], ["L", [10, 10]]]] }
+    ] }
+  ]
 }"#;
-        let pen: Pen = serde_json::from_str(pen_str).unwrap();
-        assert_near!(Pattern::Monochrome(MonochromePattern {
-            color: Color { red: 0.3, green: 0.4, blue: 0.5, alpha: 0.6 }
-        }), pen.pattern);
-        assert_near!(5.0, pen.width);
-        assert!(LineCap::Butt == pen.cap);
-        assert!(LineJoin::Bevel == pen.join);
+        let mut image: Image = serde_json::from_str(image_str).unwrap();
+        image.map_points(|p| Point { x: p.x + 5.0, y: p.y });
+        image.snap_to_grid(1.0);
+
+        // Neither pattern has points, so both uses keep the original pen and brush.
+        assert_eq!(1, image.pens.len());
+        assert_eq!(1, image.brushes.len());
     }
 
     #[test]
-    fn test_pen_ser() {
-        let pen = Pen {
-            pattern: Pattern::Monochrome(MonochromePattern {
-                color: Color { red: 0.9, green: 0.8, blue: 0.7, alpha: 0.6 }
-            }),
-            width: 2.5,
-            cap: LineCap::Round,
-            join: LineJoin::Round
+    fn test_region_close_subpaths() {
+        let region_str = r#"{
+  "type": "region",
+  "data": [
+    [[0, 0], ["L", [10, 0]], ["L", [10, 10]]],
+    [[20, 20], ["L", [30, 20]], ["L", [20, 20]]],
+    [[40, 40]]
+  ]
+}"#;
+        let mut region = match serde_json::from_str(region_str).unwrap() {
+            Shape::Region(region) => region,
+            _ => panic!("unexpected shape type")
         };
-        let pen_str = serde_json::to_string(&pen).unwrap();
-        assert_eq!(r#"{"pattern":{"type":"monochrome","color":[0.9,0.8,0.7,0.6]},"width":2.5,"cap":"round","join":"round"}"#, &pen_str);
+
+        region.close_subpaths();
+
+        assert_eq!(3, region.data[0].segments.len());
+        assert!(Point { x: 0.0, y: 0.0 } == region.data[0].end_point());
+        assert!(matches!(region.data[0].segments[2], Segment::Line(_)));
+        assert_eq!(2, region.data[1].segments.len());
+        assert_eq!(0, region.data[2].segments.len());
     }
 
     #[test]
-    fn test_brush_de() {
-        let brush_str = r#"{
-  "pattern": {
-    "type": "monochrome",
-    "color": [0.5, 0.6, 0.7]
-  }
+    fn test_region_normalize_orientation() {
+        let region_str = r#"{
+  "type": "region",
+  "data": [
+    [[0, 0], ["L", [0, 10]], ["C", [4, 12], [6, 12], [10, 10]], ["L", [10, 0]]],
+    [[2, 2], ["L", [8, 2]], ["L", [8, 8]], ["L", [2, 8]]]
+  ]
 }"#;
-        let brush: Brush = serde_json::from_str(brush_str).unwrap();
-        assert_near!(Pattern::Monochrome(MonochromePattern {
-            color: Color { red: 0.5, green: 0.6, blue: 0.7, alpha: 1.0 }
-        }), brush.pattern);
+        let mut region = match serde_json::from_str(region_str).unwrap() {
+            Shape::Region(region) => region,
+            _ => panic!("unexpected shape type")
+        };
+
+        region.normalize_orientation();
+
+        let outer = &region.data[0];
+        assert!(Point { x: 10.0, y: 0.0 } == outer.start);
+        assert!(signed_area(&outer.sample_points(ORIENTATION_STEPS)) > 0.0);
+        match outer.segments[1] {
+            Segment::CubicBezier(bezier) => {
+                assert!(Point { x: 6.0, y: 12.0 } == bezier.point_2);
+                assert!(Point { x: 4.0, y: 12.0 } == bezier.point_3);
+                assert!(Point { x: 0.0, y: 10.0 } == bezier.point_4);
+            },
+            _ => panic!("unexpected segment type")
+        }
+        assert!(Point { x: 0.0, y: 0.0 } == outer.end_point());
+
+        let hole = &region.data[1];
+        assert!(Point { x: 2.0, y: 8.0 } == hole.start);
+        assert!(signed_area(&hole.sample_points(ORIENTATION_STEPS)) < 0.0);
+
+        region.normalize_orientation();
+        assert!(Point { x: 10.0, y: 0.0 } == region.data[0].start);
+        assert!(Point { x: 2.0, y: 8.0 } == region.data[1].start);
     }
 
     #[test]
-    fn test_brush_ser() {
-        let brush = Brush {
-            pattern: Pattern::Monochrome(MonochromePattern {
-                color: Color { red: 0.5, green: 1.0, blue: 0.25, alpha: 1.0 }
-            })
+    fn test_region_winding_number() {
+        let region_str = r#"{
+  "type": "region",
+  "data": [
+    [[0, 0], ["L", [10, 0]], ["L", [10, 10]], ["L", [0, 10]]],
+    [[2, 2], ["L", [8, 2]], ["C", [9, 4], [9, 6], [8, 8]], ["L", [2, 8]]]
+  ]
+}"#;
+        let mut region = match serde_json::from_str(region_str).unwrap() {
+            Shape::Region(region) => region,
+            _ => panic!("unexpected shape type")
         };
-        let brush_str = serde_json::to_string(&brush).unwrap();
-        assert_eq!(r#"{"pattern":{"type":"monochrome","color":[0.5,1.0,0.25]}}"#, &brush_str);
+
+        assert_eq!(1, region.winding_number(Point { x: 1.0, y: 5.0 }));
+        assert_eq!(2, region.winding_number(Point { x: 5.0, y: 5.0 }));
+        assert_eq!(2, region.winding_number(Point { x: 8.5, y: 5.0 }));
+        assert_eq!(0, region.winding_number(Point { x: 12.0, y: 5.0 }));
+        assert_eq!(0, region.winding_number(Point { x: 5.0, y: -1.0 }));
+
+        region.normalize_orientation();
+        assert_eq!(0, region.winding_number(Point { x: 5.0, y: 5.0 }));
+
+        for data in region.data.iter_mut() {
+            data.reverse();
+        }
+
+        assert_eq!(-1, region.winding_number(Point { x: 1.0, y: 5.0 }));
+        assert_eq!(0, region.winding_number(Point { x: 5.0, y: 5.0 }));
     }
 
     #[test]
-    fn test_segment_de() {
-        let seg1_str = r#"["L", [10, 11]]"#;
-        let seg1: Segment = serde_json::from_str(seg1_str).unwrap();
-        assert_near!(Segment::Line(LineSegment {
-            point_2: Point { x: 10.0, y: 11.0 }
-        }), seg1);
-
-        let seg2_str = r#"["Q", [12, 13], [14, 15]]"#;
-        let seg2: Segment = serde_json::from_str(seg2_str).unwrap();
-        assert_near!(Segment::QuadraticBezier(QuadraticBezierSegment {
-            point_2: Point { x: 12.0, y: 13.0 },
-            point_3: Point { x: 14.0, y: 15.0 },
-        }), seg2);
+    fn test_extract_shape() {
+        let image_str = r#"{
+  "width": 100,
+  "height": 100,
+  "unit-per-inch": 96,
+  "pens": [
+    { "pattern": { "type": "monochrome", "color": [1, 0, 0] }, "width": 2 },
+    { "pattern": { "type": "monochrome", "color": [0, 1, 0] }, "width": 4 }
+  ],
+  "brushes": [
+    { "pattern": { "type": "monochrome", "color": [0, 0, 1] } }
+  ],
+  "shapes": [
+    { "type": "curve", "pen": 0, "data": [[0, 0], ["L", [10, 10]]] },
+    { "type": "group", "content": [
+      { "type": "region", "pen": 1, "brush": 0, "data": [[[20, 30], ["L", [40, 30]], ["L", [40, 50]]]] },
+      { "type": "curve", "pen": 0, "data": [[20, 30], ["L", [40, 50]]] }
+    ] },
+    { "type": "curve", "pen": 5, "data": [[0, 0], ["L", [10, 10]]] }
+  ]
+}"#;
+        let image: Image = serde_json::from_str(image_str).unwrap();
 
-        let seg3_str = r#"["C", [16, 17], [18, 19], [20, 21]]"#;
-        let seg3: Segment = serde_json::from_str(seg3_str).unwrap();
-        assert_near!(Segment::CubicBezier(CubicBezierSegment {
-            point_2: Point { x: 16.0, y: 17.0 },
-            point_3: Point { x: 18.0, y: 19.0 },
-            point_4: Point { x: 20.0, y: 21.0 },
-        }), seg3);
+        let extracted = image.extract_shape(1, false).unwrap();
+        assert!(100.0 == extracted.width && 100.0 == extracted.height);
+        assert_eq!(2, extracted.pens.len());
+        assert!(4.0 == extracted.pens[0].width);
+        assert!(2.0 == extracted.pens[1].width);
+        assert_eq!(1, extracted.brushes.len());
+        let Shape::Group(group) = &extracted.shapes[0] else { panic!() };
+        let Shape::Region(region) = &group.content[0] else { panic!() };
+        assert_eq!((Some(0), Some(0)), (region.pen, region.brush));
+        let Shape::Curve(curve) = &group.content[1] else { panic!() };
+        assert_eq!(1, curve.pen);
+
+        let cropped = image.extract_shape(1, true).unwrap();
+        assert_near!(24.0, cropped.width);
+        assert_near!(24.0, cropped.height);
+        let (min, _) = cropped.bounding_box().unwrap();
+        assert!(min.x.abs() < 1e-9 && min.y.abs() < 1e-9);
+
+        assert!(image.extract_shape(2, false).is_none());
+        assert!(image.extract_shape(3, false).is_none());
     }
 
     #[test]
-    fn test_segment_ser() {
-        let seg1 = Segment::Line(LineSegment {
-            point_2: Point { x: 1.0, y: 2.0 }
-        });
-        let seg1_str = serde_json::to_string(&seg1).unwrap();
-        assert_eq!(r#"["L",[1.0,2.0]]"#, &seg1_str);
+    fn test_snap_to_grid() {
+        let image_str = r#"{
+  "width": 100,
+  "height": 100,
+  "unit-per-inch": 72,
+  "pens": [{ "width": 1, "pattern": { "type": "linear-gradient", "point-1": [0.4, 0.6], "color-1": [0, 0, 0], "point-2": [9.7, 0], "color-2": [1, 1, 1] } }],
+  "brushes": [],
+  "shapes": [{ "type": "group", "content": [
+    { "type": "curve", "pen": 0, "data": [[1.2, 2.9], ["L", [1.1, 3.1]], ["Q", [4.6, 5.4], [7.5, 1.1]], ["L", [8.2, 0.8]]] }
+  ] }]
+}"#;
+        let mut image: Image = serde_json::from_str(image_str).unwrap();
 
-        let seg2 = Segment::QuadraticBezier(QuadraticBezierSegment {
-            point_2: Point { x: 1.0, y: 2.0 },
-            point_3: Point { x: 3.0, y: -4.0 }
-        });
-        let seg2_str = serde_json::to_string(&seg2).unwrap();
-        assert_eq!(r#"["Q",[1.0,2.0],[3.0,-4.0]]"#, &seg2_str);
+        image.snap_to_grid(0.5);
 
-        let seg3 = Segment::CubicBezier(CubicBezierSegment {
-            point_2: Point { x: 1.0, y: 2.0 },
-            point_3: Point { x: 3.0, y: 4.0 },
-            point_4: Point { x: 5.0, y: 6.0 }
-        });
-        let seg3_str = serde_json::to_string(&seg3).unwrap();
-        assert_eq!(r#"["C",[1.0,2.0],[3.0,4.0],[5.0,6.0]]"#, &seg3_str);
+        match &image.pens[0].pattern {
+            Pattern::LinearGradient(pat) => {
+                assert!(Point { x: 0.5, y: 0.5 } == pat.point_1);
+                assert!(Point { x: 9.5, y: 0.0 } == pat.point_2);
+            },
+            _ => panic!("unexpected pattern type")
+        }
+
+        let data: Vec<&CurveData> = image.iter_curve_data().collect();
+        assert!(Point { x: 1.0, y: 3.0 } == data[0].start);
+        assert_eq!(2, data[0].segments.len());
+        match data[0].segments[0] {
+            Segment::QuadraticBezier(bezier) => {
+                assert!(Point { x: 4.5, y: 5.5 } == bezier.point_2);
+                assert!(Point { x: 7.5, y: 1.0 } == bezier.point_3);
+            },
+            _ => panic!("unexpected segment type")
+        }
+        assert!(Point { x: 8.0, y: 1.0 } == data[0].end_point());
+
+        for step in [0.0, -0.5, f64::NAN, f64::INFINITY] {
+            let mut image = image.clone();
+            assert!(std::panic::catch_unwind(move || image.snap_to_grid(step)).is_err());
+        }
     }
 
     #[test]
-    fn test_curve_data_de() {
-        let dat_str = r#"[
-  [10, 11],
-  ["L", [12, 13]],
-  ["Q", [14, 15], [16, 17]]
-]"#;
-        let dat: CurveData = serde_json::from_str(dat_str).unwrap();
-        assert_near!(10.0, dat.start.x);
-        assert_near!(11.0, dat.start.y);
-        assert_eq!(2, dat.segments.len());
-        assert_near!(Segment::Line(LineSegment {
-            point_2: Point { x: 12.0, y: 13.0 }
-        }), dat.segments[0]);
-        assert_near!(Segment::QuadraticBezier(QuadraticBezierSegment {
-            point_2: Point { x: 14.0, y: 15.0 },
-            point_3: Point { x: 16.0, y: 17.0 }
-        }), dat.segments[1]);
+    fn test_iter_shapes() {
+        let image_str = r#"{
+  "width": 10,
+  "height": 10,
+  "unit-per-inch": 72,
+  "pens": [],
+  "brushes": [],
+  "shapes": [
+    { "type": "curve", "id": "a", "pen": 0, "data": [[0, 0]] },
+    { "type": "group", "id": "g1", "content": [
+      { "type": "region", "id": "b", "data": [] },
+      { "type": "group", "id": "g2", "content": [
+        { "type": "curve", "id": "c", "pen": 0, "data": [[0, 0]] },
+        { "type": "group", "id": "g3", "content": [] }
+      ] },
+      { "type": "curve", "id": "d", "pen": 0, "data": [[0, 0]] }
+    ] },
+    { "type": "region", "id": "e", "data": [] }
+  ]
+}"#;
+        let image: Image = serde_json::from_str(image_str).unwrap();
+        let id = |shape: &Shape| match shape {
+            Shape::Group(group) => group.id.clone(),
+            Shape::Curve(curve) => curve.id.clone(),
+            Shape::Region(region) => region.id.clone()
+        }.unwrap();
+
+        let all: Vec<String> = image.iter_shapes().map(id).collect();
+        assert_eq!(vec!["a", "g1", "b", "g2", "c", "g3", "d", "e"], all);
+
+        let flattened: Vec<String> = image.iter_flattened().map(id).collect();
+        assert_eq!(vec!["a", "b", "c", "d", "e"], flattened);
     }
 
     #[test]
-    fn test_curve_data_ser() {
-        let dat = CurveData {
-            start: Point { x: 1.0, y: 2.0 },
-            segments: vec![
-                Segment::Line(LineSegment {
-                    point_2: Point { x: 3.0, y: 4.0 }
-                }),
-                Segment::QuadraticBezier(QuadraticBezierSegment {
-                    point_2: Point { x: 5.0, y: 6.0 },
-                    point_3: Point { x: 7.0, y: 8.0 }
-                })
-            ]
-        };
-        let dat_str = serde_json::to_string(&dat).unwrap();
-        assert_eq!(r#"[[1.0,2.0],["L",[3.0,4.0]],["Q",[5.0,6.0],[7.0,8.0]]]"#, &dat_str);
+    fn test_iter_curve_data() {
+        let image_str = r#"{
+  "width": 100,
+  "height": 100,
+  "unit-per-inch": 96,
+  "pens": [],
+  "brushes": [],
+  "shapes": [
+    { "type": "curve", "pen": 0, "data": [[1, 0]] },
+    {
+      "type": "group",
+      "content": [
+        { "type": "region", "data": [[[2, 0]], [[3, 0]]] },
+        { "type": "group", "content": [{ "type": "curve", "pen": 0, "data": [[4, 0]] }] },
+        { "type": "group", "content": [] }
+      ]
+    },
+    { "type": "region", "data": [] },
+    { "type": "curve", "pen": 0, "data": [[5, 0]] }
+  ]
+}"#;
+        let image: Image = serde_json::from_str(image_str).unwrap();
+
+        let xs: Vec<f64> = image.iter_curve_data().map(|data| data.start.x).collect();
+        assert_eq!(vec![1.0, 2.0, 3.0, 4.0, 5.0], xs);
     }
 
     #[test]
+    #[allow(clippy::assertions_on_constants)]
     fn test_shape_de() {
         let sh1_str = r#"{
   "type": "group",
@@ -1106,14 +6574,41 @@ mod tests {
             assert_eq!(1, s.data.len());
             assert_near!(7.0, s.data[0].start.x);
             assert_near!(8.0, s.data[0].start.y);
+            assert_eq!(None, s.fill_opacity);
+            assert_eq!(None, s.stroke_opacity);
         } else {
             assert!(false);
         }
+
+        let sh4_str = r#"{
+  "type": "region",
+  "brush": 0,
+  "fill-opacity": 0.5,
+  "stroke-opacity": 1,
+  "data": []
+}"#;
+        let sh4: Shape = serde_json::from_str(sh4_str).unwrap();
+        if let Shape::Region(s) = sh4 {
+            assert_eq!(Some(0.5), s.fill_opacity);
+            assert_eq!(Some(1.0), s.stroke_opacity);
+        } else {
+            panic!("unexpected shape type");
+        }
+
+        let bad_sh1_str = r#"{"type": "region", "fill-opacity": 1.5, "data": []}"#;
+        assert!(serde_json::from_str::<Shape>(bad_sh1_str).is_err());
+
+        let bad_sh2_str = r#"{"type": "region", "stroke-opacity": -0.1, "data": []}"#;
+        assert!(serde_json::from_str::<Shape>(bad_sh2_str).is_err());
     }
 
     #[test]
     fn test_shape_ser() {
         let sh1 = Shape::Group(GroupShape {
+            id: None,
+            opacity: None,
+            operator: None,
+            transform: None,
             content: vec![],
             edit_annot: serde_json::Value::Null
         });
@@ -1121,8 +6616,16 @@ mod tests {
         assert_eq!(r#"{"type":"group","content":[]}"#, &sh1_str);
 
         let sh2 = Shape::Group(GroupShape {
+            id: None,
+            opacity: None,
+            operator: None,
+            transform: None,
             content: vec![
                 Shape::Group(GroupShape {
+                    id: None,
+                    opacity: None,
+                    operator: None,
+                    transform: None,
                     content: vec![],
                     edit_annot: serde_json::Value::Null
                 })
@@ -1132,7 +6635,25 @@ mod tests {
         let sh2_str = serde_json::to_string(&sh2).unwrap();
         assert_eq!(r#"{"type":"group","content":[{"type":"group","content":[]}],"edit-annot":true}"#, &sh2_str);
 
+        let sh2b = Shape::Group(GroupShape {
+            id: None,
+            opacity: Some(0.5),
+            operator: Some(Operator::Multiply),
+            transform: Some([1.0, 0.0, 0.0, 1.0, 2.0, 3.0]),
+            content: vec![],
+            edit_annot: serde_json::Value::Null
+        });
+        let sh2b_str = serde_json::to_string(&sh2b).unwrap();
+        assert_eq!(r#"{"type":"group","opacity":0.5,"operator":"multiply","transform":[1.0,0.0,0.0,1.0,2.0,3.0],"content":[]}"#, &sh2b_str);
+
+        let Shape::Group(sh2b_de) = serde_json::from_str::<Shape>(&sh2b_str).unwrap() else { panic!() };
+        assert_eq!(Some(0.5), sh2b_de.opacity);
+        assert_eq!(Some(Operator::Multiply), sh2b_de.operator);
+        assert_eq!(Some([1.0, 0.0, 0.0, 1.0, 2.0, 3.0]), sh2b_de.transform);
+        assert!(serde_json::from_str::<Shape>(r#"{"type":"group","opacity":1.5,"content":[]}"#).is_err());
+
         let sh3 = Shape::Curve(CurveShape {
+            id: None,
             pen: 1,
             data: CurveData {
                 start: Point { x: 1.0, y: 2.0 },
@@ -1147,8 +6668,13 @@ mod tests {
         assert_eq!(r#"{"type":"curve","pen":1,"data":[[1.0,2.0],["L",[3.0,4.0]]]}"#, &sh3_str);
 
         let sh4 = Shape::Region(RegionShape {
+            id: None,
             pen: Some(0),
             brush: None,
+            fill_opacity: None,
+            stroke_opacity: None,
+            pattern_origin: None,
+            fill_rule: None,
             data: vec![
                 CurveData {
                     start: Point { x: 5.0, y: 6.0 },
@@ -1164,8 +6690,13 @@ mod tests {
         assert_eq!(r#"{"type":"region","pen":0,"data":[[[5.0,6.0],["L",[7.0,8.0]]]]}"#, &sh4_str);
 
         let sh5 = Shape::Region(RegionShape {
+            id: None,
             pen: None,
             brush: Some(1),
+            fill_opacity: None,
+            stroke_opacity: None,
+            pattern_origin: None,
+            fill_rule: None,
             data: vec![
                 CurveData {
                     start: Point { x: 9.0, y: 10.0 },
@@ -1175,5 +6706,48 @@ mod tests {
         });
         let sh5_str = serde_json::to_string(&sh5).unwrap();
         assert_eq!(r#"{"type":"region","brush":1,"data":[[[9.0,10.0]]]}"#, &sh5_str);
+
+        let sh6 = Shape::Region(RegionShape {
+            id: None,
+            pen: Some(0),
+            brush: Some(1),
+            fill_opacity: Some(0.5),
+            stroke_opacity: Some(0.25),
+            pattern_origin: None,
+            fill_rule: None,
+            data: vec![]
+        });
+        let sh6_str = serde_json::to_string(&sh6).unwrap();
+        assert_eq!(r#"{"type":"region","pen":0,"brush":1,"fill-opacity":0.5,"stroke-opacity":0.25,"data":[]}"#, &sh6_str);
+
+        let sh7 = Shape::Region(RegionShape {
+            id: None,
+            pen: None,
+            brush: Some(0),
+            fill_opacity: None,
+            stroke_opacity: None,
+            pattern_origin: None,
+            fill_rule: Some(FillRule::Nonzero),
+            data: vec![]
+        });
+        let sh7_str = serde_json::to_string(&sh7).unwrap();
+        assert_eq!(r#"{"type":"region","brush":0,"fill-rule":"nonzero","data":[]}"#, &sh7_str);
+
+        let sh7_de: Shape = serde_json::from_str(&sh7_str).unwrap();
+        assert_eq!(sh7_str, serde_json::to_string(&sh7_de).unwrap());
+        assert!(serde_json::from_str::<Shape>(r#"{"type":"region","fill-rule":"winding","data":[]}"#).is_err());
+
+        let sh8 = Shape::Region(RegionShape {
+            id: None,
+            pen: None,
+            brush: Some(1),
+            fill_opacity: None,
+            stroke_opacity: None,
+            pattern_origin: Some(Point { x: 1.0, y: 2.0 }),
+            fill_rule: None,
+            data: vec![]
+        });
+        let sh8_str = serde_json::to_string(&sh8).unwrap();
+        assert_eq!(r#"{"type":"region","brush":1,"pattern-origin":[1.0,2.0],"data":[]}"#, &sh8_str);
     }
 }
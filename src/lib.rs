@@ -1,3 +1,48 @@
-
+pub mod cache;
+pub mod geometry;
 pub mod image;
 pub mod render;
+
+/// Returns the JSON Schema of the LISON format, the same as `lison-schema.json`.
+///
+/// It follows the serde representation of `image::Image`, so a file that validates can be
+/// deserialized, apart from constraints the schema cannot express such as pen and brush
+/// indices being in range.
+pub fn json_schema() -> serde_json::Value {
+    serde_json::from_str(include_str!("../lison-schema.json"))
+        .expect("lison-schema.json is valid JSON")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_schema() {
+        let validator = jsonschema::validator_for(&json_schema()).unwrap();
+
+        for sample in [
+            include_str!("../samples/curve.lison"),
+            include_str!("../samples/pattern.lison"),
+            include_str!("../samples/region.lison")
+        ] {
+            let value: serde_json::Value = serde_json::from_str(sample).unwrap();
+            assert!(validator.is_valid(&value));
+            assert!(serde_json::from_value::<image::Image>(value).is_ok());
+        }
+
+        let invalid = serde_json::json!({
+            "width": 100,
+            "height": 100,
+            "unit-per-inch": 96,
+            "pens": [{ "pattern": { "type": "monochrome", "color": [0, 0, 0] }, "width": 1, "cap": "pointy" }],
+            "brushes": [],
+            "shapes": []
+        });
+        assert!(!validator.is_valid(&invalid));
+        assert!(serde_json::from_value::<image::Image>(invalid).is_err());
+
+        let missing = serde_json::json!({ "width": 100, "height": 100, "pens": [], "brushes": [], "shapes": [] });
+        assert!(!validator.is_valid(&missing));
+    }
+}
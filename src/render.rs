@@ -1,224 +1,2957 @@
 
 use crate::image::*;
 
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use std::io;
+use std::time::{Duration, Instant};
+
 use cairo::{Context, Result};
+use serde::de::{self, DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor};
+
+#[derive(Debug)]
+pub enum RenderError {
+    Cairo(cairo::Error),
+    /// The named output backend is not available in this build of cairo.
+    BackendUnavailable(&'static str),
+    /// The output would have more pixels than `RenderOptions::max_pixels` allows.
+    TooManyPixels { width: i32, height: i32, max_pixels: u64 },
+    /// The streamed document is not a valid image.
+    Json(serde_json::Error),
+    /// A shape refers to pen `index`, but the image has only `count` pens.
+    PenOutOfRange { index: usize, count: usize },
+    /// A shape refers to brush `index`, but the image has only `count` brushes.
+    BrushOutOfRange { index: usize, count: usize },
+    /// The data of an image pattern is not valid base64.
+    ImageData(LisonError),
+    /// The data of an image pattern is not a PNG file that cairo can read.
+    ImagePng(cairo::IoError)
+}
+
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RenderError::Cairo(e) => write!(f, "cairo error: {}", e),
+            RenderError::BackendUnavailable(name) => write!(f, "cairo is built without the {} backend", name),
+            RenderError::TooManyPixels { width, height, max_pixels } =>
+                write!(f, "{}x{} pixels exceeds the limit of {} pixels", width, height, max_pixels),
+            RenderError::Json(e) => write!(f, "JSON error: {}", e),
+            RenderError::PenOutOfRange { index, count } =>
+                write!(f, "invalid pen index {}, must be less than {}", index, count),
+            RenderError::BrushOutOfRange { index, count } =>
+                write!(f, "invalid brush index {}, must be less than {}", index, count),
+            RenderError::ImageData(e) => write!(f, "image pattern: {}", e),
+            RenderError::ImagePng(e) => write!(f, "image pattern is not a readable PNG file: {}", e)
+        }
+    }
+}
+
+impl error::Error for RenderError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            RenderError::Cairo(e) => Some(e),
+            RenderError::Json(e) => Some(e),
+            RenderError::ImageData(e) => Some(e),
+            RenderError::ImagePng(e) => Some(e),
+            RenderError::BackendUnavailable(_)
+                | RenderError::TooManyPixels { .. }
+                | RenderError::PenOutOfRange { .. }
+                | RenderError::BrushOutOfRange { .. } => None
+        }
+    }
+}
+
+impl From<cairo::Error> for RenderError {
+    fn from(e: cairo::Error) -> RenderError {
+        RenderError::Cairo(e)
+    }
+}
+
+/// Returns the cairo error reported for `e` by the functions that return `cairo::Result`.
+fn cairo_error(e: RenderError) -> cairo::Error {
+    match e {
+        RenderError::Cairo(e) => e,
+        RenderError::PenOutOfRange { .. } | RenderError::BrushOutOfRange { .. } => cairo::Error::InvalidIndex,
+        RenderError::TooManyPixels { .. } => cairo::Error::InvalidSize,
+        RenderError::BackendUnavailable(_) | RenderError::Json(_) | RenderError::ImageData(_)
+            | RenderError::ImagePng(_) => cairo::Error::ReadError
+    }
+}
+
+impl From<ValidationError> for RenderError {
+    fn from(e: ValidationError) -> RenderError {
+        match e {
+            ValidationError::PenOutOfRange { index, count, .. } => RenderError::PenOutOfRange { index, count },
+            ValidationError::BrushOutOfRange { index, count, .. } => RenderError::BrushOutOfRange { index, count }
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Backend {
+    Png,
+    Svg,
+    Pdf
+}
+
+impl Backend {
+    pub fn name(self) -> &'static str {
+        match self {
+            Backend::Png => "png",
+            Backend::Svg => "svg",
+            Backend::Pdf => "pdf"
+        }
+    }
+}
+
+/// Checks that `backend` can create surfaces, so that a missing backend is reported
+/// as `RenderError::BackendUnavailable` instead of an obscure failure while writing.
+///
+/// The svg and pdf backends need the `svg` and `pdf` features respectively.
+pub fn check_backend(backend: Backend) -> std::result::Result<(), RenderError> {
+    let available = match backend {
+        Backend::Png => true,
+        #[cfg(feature = "svg")]
+        Backend::Svg => cairo::SvgSurface::for_stream(1.0, 1.0, std::io::sink()).is_ok(),
+        #[cfg(feature = "pdf")]
+        Backend::Pdf => cairo::PdfSurface::for_stream(1.0, 1.0, std::io::sink()).is_ok(),
+        #[allow(unreachable_patterns)]
+        _ => false
+    };
+
+    if available {
+        Ok(())
+    } else {
+        Err(RenderError::BackendUnavailable(backend.name()))
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct RenderOptions {
+    /// Measure the time spent on each top-level shape.
+    pub profile: bool,
+    /// Antialiasing mode; `None` keeps the setting of the context.
+    pub antialias: Option<cairo::Antialias>,
+    /// Maximum error, in device pixels, when cairo flattens curves; `None` keeps the
+    /// setting of the context.
+    pub tolerance: Option<f64>,
+    /// Render at this many times the resolution and downsample. Only used by `rasterize`;
+    /// 0 and 1 disable supersampling.
+    pub supersample: u32,
+    /// Minimum and maximum corners, in image units, of the area to render in place of the
+    /// canvas. `rasterize` sizes its surface to fit.
+    pub viewport: Option<(Point, Point)>,
+    /// Hinting, subpixel order and antialiasing of text; `None` keeps the setting of the
+    /// context.
+    pub font_options: Option<cairo::FontOptions>,
+    /// Largest number of pixels `rasterize` creates a surface for, which with supersampling
+    /// is the enlarged surface rendered first.
+    pub max_pixels: Option<u64>,
+    /// Clip drawing to the canvas rectangle, even where the surface extends beyond it.
+    pub clip_to_canvas: bool,
+    /// Record the path of every curve and region in `RenderReport::paths`.
+    pub dump_paths: bool,
+    /// Label each top-level shape with its index at the top-left corner of its bounding box,
+    /// after all shapes are drawn.
+    pub debug_index: bool,
+    /// Color painted over the whole surface before any shape is drawn; `None` leaves it
+    /// transparent. Only used by `render_with_options` and the functions built on it.
+    pub background: Option<Color>,
+    /// Overrides applied to the shapes whose `id` is a key, and to the descendants of such
+    /// groups unless they have an override of their own. The image itself is not modified.
+    pub styles: HashMap<String, StyleOverride>
+}
+
+/// Replaces the pen, brush or colors of shapes at render time. Indices are applied first and
+/// must be valid, as those in the image; a color then replaces the pattern of the pen or
+/// brush with a monochrome one. A region without a brush gets one of `fill_color`, but a
+/// stroke color has no effect on a shape without a pen.
+#[derive(Clone, Default)]
+pub struct StyleOverride {
+    pub pen: Option<usize>,
+    pub brush: Option<usize>,
+    pub stroke_color: Option<Color>,
+    pub fill_color: Option<Color>
+}
+
+/// Checks that the pen and brush of every override in `styles` exist in `image`, whether or
+/// not a shape has its `id`.
+fn validate_styles(image: &Image, styles: &HashMap<String, StyleOverride>) -> std::result::Result<(), RenderError> {
+    for style in styles.values() {
+        if let Some(index) = style.pen.filter(|index| *index >= image.pens.len()) {
+            return Err(RenderError::PenOutOfRange { index, count: image.pens.len() });
+        }
+
+        if let Some(index) = style.brush.filter(|index| *index >= image.brushes.len()) {
+            return Err(RenderError::BrushOutOfRange { index, count: image.brushes.len() });
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns a copy of `image` with `styles` applied to its shapes, as described by
+/// `RenderOptions::styles`. Recolored pens and brushes are appended to the copy.
+fn styled_image(image: &Image, styles: &HashMap<String, StyleOverride>) -> std::result::Result<Image, RenderError> {
+    fn restyle(shape: &mut Shape, inherited: Option<&StyleOverride>, styles: &HashMap<String, StyleOverride>, pens: &mut Vec<Pen>, brushes: &mut Vec<Brush>) {
+        let id = match shape {
+            Shape::Group(group) => &group.id,
+            Shape::Curve(curve) => &curve.id,
+            Shape::Region(region) => &region.id
+        };
+        let style = id.as_ref().and_then(|id| styles.get(id)).or(inherited);
+
+        let recolor_pen = |pen: usize, pens: &mut Vec<Pen>| match style.and_then(|style| style.stroke_color) {
+            Some(color) => {
+                let pen = Pen { pattern: Pattern::Monochrome(MonochromePattern { color }), ..pens[pen].clone() };
+                pens.push(pen);
+                pens.len() - 1
+            },
+            None => pen
+        };
+
+        match shape {
+            Shape::Group(group) => {
+                for child in group.content.iter_mut() {
+                    restyle(child, style, styles, pens, brushes);
+                }
+            },
+            Shape::Curve(curve) => {
+                let Some(style) = style else { return };
+                curve.pen = recolor_pen(style.pen.unwrap_or(curve.pen), pens);
+            },
+            Shape::Region(region) => {
+                let Some(style) = style else { return };
+                region.pen = style.pen.or(region.pen).map(|pen| recolor_pen(pen, pens));
+                region.brush = style.brush.or(region.brush);
+
+                if let Some(color) = style.fill_color {
+                    brushes.push(Brush { pattern: Pattern::Monochrome(MonochromePattern { color }), name: None });
+                    region.brush = Some(brushes.len() - 1);
+                }
+            }
+        }
+    }
+
+    validate_styles(image, styles)?;
+
+    let mut styled = image.clone();
+
+    for shape in styled.shapes.iter_mut() {
+        restyle(shape, None, styles, &mut styled.pens, &mut styled.brushes);
+    }
+
+    Ok(styled)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Quality {
+    Fast,
+    Balanced,
+    Best
+}
+
+impl Quality {
+    /// Returns the render options of the preset:
+    ///
+    /// | preset | antialias | tolerance | supersample |
+    /// |:-|:-|:-|:-|
+    /// | `Fast` | `Fast` | 0.5 | 1 |
+    /// | `Balanced` | `Good` | 0.1 | 1 |
+    /// | `Best` | `Best` | 0.01 | 2 |
+    pub fn options(self) -> RenderOptions {
+        let (antialias, tolerance, supersample) = match self {
+            Quality::Fast => (cairo::Antialias::Fast, 0.5, 1),
+            Quality::Balanced => (cairo::Antialias::Good, 0.1, 1),
+            Quality::Best => (cairo::Antialias::Best, 0.01, 2)
+        };
+
+        RenderOptions {
+            antialias: Some(antialias),
+            tolerance: Some(tolerance),
+            supersample,
+            ..RenderOptions::default()
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct RenderReport {
+    /// Index and render time of each top-level shape, when profiling is enabled.
+    pub timings: Vec<(usize, Duration)>,
+    /// Path operations passed to cairo, when `RenderOptions::dump_paths` is set. Coordinates
+    /// are scaled but not transformed by the viewport.
+    pub paths: Vec<PathOp>,
+    /// Indices of the top-level shapes labelled when `RenderOptions::debug_index` is set.
+    /// Shapes without a bounding box, such as empty groups, get no label.
+    pub labels: Vec<usize>
+}
+
+/// A path construction call made on the cairo context.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PathOp {
+    MoveTo(f64, f64),
+    LineTo(f64, f64),
+    CurveTo(f64, f64, f64, f64, f64, f64),
+    ClosePath
+}
 
 struct Scaler {
-    factor: f64
+    factor: f64,
+    path_log: Option<RefCell<Vec<PathOp>>>,
+    /// Bitmaps of the image patterns, keyed by their data. See `decode_images`.
+    images: HashMap<String, cairo::ImageSurface>
 }
 
 impl Scaler {
     fn new(image: &Image, ppi: f64, scale: f64) -> Scaler {
         Scaler {
-            factor: ppi / image.unit_per_inch * scale
+            factor: ppi / image.unit_per_inch * scale,
+            path_log: None,
+            images: HashMap::new()
         }
     }
 
     fn scale(&self, value: f64) -> f64 {
         value * self.factor
     }
+
+    /// Applies `op` to the context and records it if paths are dumped.
+    fn plot(&self, context: &Context, op: PathOp) {
+        match op {
+            PathOp::MoveTo(x, y) => context.move_to(x, y),
+            PathOp::LineTo(x, y) => context.line_to(x, y),
+            PathOp::CurveTo(x1, y1, x2, y2, x3, y3) => context.curve_to(x1, y1, x2, y2, x3, y3),
+            PathOp::ClosePath => context.close_path()
+        }
+
+        if let Some(log) = &self.path_log {
+            log.borrow_mut().push(op);
+        }
+    }
 }
 
 pub fn render(context: &Context, image: &Image, ppi: f64, scale: f64) -> Result<()> {
+    render_prefix(context, image, ppi, scale, image.shapes.len())
+}
+
+/// Renders only the first `n` top-level shapes of the image.
+pub fn render_prefix(context: &Context, image: &Image, ppi: f64, scale: f64, n: usize) -> Result<()> {
+    render_shapes(context, image, ppi, scale, n, &RenderOptions::default()).map_err(cairo_error)?;
+    Ok(())
+}
+
+/// Renders the image so that `origin`, in image units, is placed at the origin of the context.
+///
+/// Adjacent crops line up exactly, since patterns are built in the same image space
+/// regardless of the offset.
+pub fn render_crop(context: &Context, image: &Image, ppi: f64, scale: f64, origin: Point) -> Result<()> {
     let scaler = Scaler::new(image, ppi, scale);
 
-    context.set_operator(cairo::Operator::Over);
-    context.set_fill_rule(cairo::FillRule::EvenOdd);
-    context.new_path();
+    context.save()?;
+    context.translate(-scaler.scale(origin.x), -scaler.scale(origin.y));
+    let result = render(context, image, ppi, scale);
+    context.restore()?;
+
+    result
+}
+
+pub fn render_with_options(context: &Context, image: &Image, ppi: f64, scale: f64, options: &RenderOptions) -> Result<RenderReport> {
+    render_checked(context, image, ppi, scale, options).map_err(cairo_error)
+}
 
-    for shape in image.shapes.iter() {
-        render_shape(context, shape, image, &scaler)?;
+/// Like `render_with_options`, but fails with the `RenderError` that tells what is wrong.
+fn render_checked(context: &Context, image: &Image, ppi: f64, scale: f64, options: &RenderOptions) -> std::result::Result<RenderReport, RenderError> {
+    if let Some(background) = options.background {
+        paint_background(context, background)?;
     }
 
-    Ok(())
+    if options.viewport.is_none() && !options.clip_to_canvas {
+        return render_shapes(context, image, ppi, scale, image.shapes.len(), options);
+    }
+
+    let scaler = Scaler::new(image, ppi, scale);
+
+    context.save()?;
+
+    if let Some((origin, _)) = options.viewport {
+        context.translate(-scaler.scale(origin.x), -scaler.scale(origin.y));
+    }
+
+    if options.clip_to_canvas {
+        context.rectangle(0.0, 0.0, scaler.scale(image.width), scaler.scale(image.height));
+        context.clip();
+    }
+
+    let result = render_shapes(context, image, ppi, scale, image.shapes.len(), options);
+    context.restore()?;
+
+    result
 }
 
-fn render_shape(context: &Context, shape: &Shape, image: &Image, scaler: &Scaler) -> Result<()> {
-    match shape {
-        Shape::Group(group) => render_group(context, group, image, scaler),
-        Shape::Curve(curve) => render_curve(context, curve, image, scaler),
-        Shape::Region(region) => render_region(context, region, image, scaler)
+/// Paints the whole clip region of the context, unaffected by the viewport or the canvas.
+fn paint_background(context: &Context, background: Color) -> Result<()> {
+    let color = background.clamped();
+
+    context.save()?;
+    context.set_source_rgba(color.red, color.green, color.blue, color.alpha);
+    context.paint()?;
+    context.restore()
+}
+
+/// Returns the size in pixels of the image rendered at `ppi` and `scale`, or `None` if it is
+/// empty or too large for a surface.
+pub fn pixel_size(image: &Image, ppi: f64, scale: f64) -> Option<(i32, i32)> {
+    viewport_pixel_size(image, image.width, image.height, ppi, scale)
+}
+
+fn viewport_pixel_size(image: &Image, width: f64, height: f64, ppi: f64, scale: f64) -> Option<(i32, i32)> {
+    let width = (width * ppi / image.unit_per_inch * scale).round();
+    let height = (height * ppi / image.unit_per_inch * scale).round();
+
+    if width <= 0.0 || width > i32::MAX.into() || height <= 0.0 || height > i32::MAX.into() {
+        return None;
     }
+
+    Some((width as i32, height as i32))
 }
 
-fn render_group(context: &Context, group: &GroupShape, image: &Image, scaler: &Scaler) -> Result<()> {
-    for child in group.content.iter() {
-        render_shape(context, child, image, scaler)?;
+/// Returns `RenderError::TooManyPixels` if a `width` by `height` output exceeds `max_pixels`.
+pub fn check_pixel_count(width: i32, height: i32, max_pixels: Option<u64>) -> std::result::Result<(), RenderError> {
+    match max_pixels {
+        Some(max_pixels) if width as u64 * height as u64 > max_pixels =>
+            Err(RenderError::TooManyPixels { width, height, max_pixels }),
+        _ => Ok(())
     }
+}
 
-    Ok(())
+/// Renders the image into a new surface of `pixel_size`, supersampling as requested by
+/// `options`. The size, and that of the supersampled surface, is checked against
+/// `options.max_pixels` before any surface is created, and so are the pen and brush
+/// references of the shapes and of `options.styles`.
+pub fn rasterize(image: &Image, ppi: f64, scale: f64, options: &RenderOptions) -> std::result::Result<(cairo::ImageSurface, RenderReport), RenderError> {
+    let (width, height) = output_size(image, ppi, scale, options)?;
+    image.validate()?;
+    validate_styles(image, &options.styles)?;
+    let factor = options.supersample.max(1);
+
+    if factor == 1 {
+        let surface = cairo::ImageSurface::create(cairo::Format::ARgb32, width, height)?;
+        let context = Context::new(&surface)?;
+        let report = render_checked(&context, image, ppi, scale, options)?;
+        drop(context);
+        return Ok((surface, report));
+    }
+
+    let large_size = |size: i32| i32::try_from(factor).ok()
+        .and_then(|factor| size.checked_mul(factor))
+        .ok_or(cairo::Error::InvalidSize);
+    let (large_width, large_height) = (large_size(width)?, large_size(height)?);
+    check_pixel_count(large_width, large_height, options.max_pixels)?;
+
+    let surface = cairo::ImageSurface::create(cairo::Format::ARgb32, width, height)?;
+    let context = Context::new(&surface)?;
+    let large = cairo::ImageSurface::create(cairo::Format::ARgb32, large_width, large_height)?;
+    let large_context = Context::new(&large)?;
+    let report = render_checked(&large_context, image, ppi, scale * f64::from(factor), options)?;
+    drop(large_context);
+
+    context.scale(1.0 / f64::from(factor), 1.0 / f64::from(factor));
+    context.set_source_surface(&large, 0.0, 0.0)?;
+    context.source().set_filter(cairo::Filter::Good);
+    context.paint()?;
+    drop(context);
+
+    Ok((surface, report))
 }
 
-fn set_pattern(context: &Context, pattern: &Pattern, scaler: &Scaler) -> Result<()> {
-    match pattern {
-        Pattern::Monochrome(pat) => {
-            context.set_source_rgba(pat.color.red, pat.color.green, pat.color.blue, pat.color.alpha);
-        },
-        Pattern::LinearGradient(pat) => {
-            let grad = cairo::LinearGradient::new(
-                scaler.scale(pat.point_1.x),
-                scaler.scale(pat.point_1.y),
-                scaler.scale(pat.point_2.x),
-                scaler.scale(pat.point_2.y)
-            );
-            grad.add_color_stop_rgba(
-                0.0,
-                pat.color_1.red,
-                pat.color_1.green,
-                pat.color_1.blue,
-                pat.color_1.alpha
-            );
-            grad.add_color_stop_rgba(
-                1.0,
-                pat.color_2.red,
-                pat.color_2.green,
-                pat.color_2.blue,
-                pat.color_2.alpha
-            );
-            context.set_source(grad)?;
-        },
-        Pattern::RadialGradient(pat) => {
-            let grad = cairo::RadialGradient::new(
-                scaler.scale(pat.center_1.x),
-                scaler.scale(pat.center_1.y),
-                scaler.scale(pat.radius_1),
-                scaler.scale(pat.center_2.x),
-                scaler.scale(pat.center_2.y),
-                scaler.scale(pat.radius_2),
-            );
-            grad.add_color_stop_rgba(
-                0.0,
-                pat.color_1.red,
-                pat.color_1.green,
-                pat.color_1.blue,
-                pat.color_1.alpha
-            );
-            grad.add_color_stop_rgba(
-                1.0,
-                pat.color_2.red,
-                pat.color_2.green,
-                pat.color_2.blue,
-                pat.color_2.alpha
-            );
-            context.set_source(grad)?;
+/// Returns the size in pixels of the output covering `options.viewport`, or the canvas by
+/// default, checked against `options.max_pixels`.
+fn output_size(image: &Image, ppi: f64, scale: f64, options: &RenderOptions) -> std::result::Result<(i32, i32), RenderError> {
+    let size = match options.viewport {
+        Some((min, max)) => viewport_pixel_size(image, max.x - min.x, max.y - min.y, ppi, scale),
+        None => pixel_size(image, ppi, scale)
+    };
+    let (width, height) = size.ok_or(cairo::Error::InvalidSize)?;
+    check_pixel_count(width, height, options.max_pixels)?;
+
+    Ok((width, height))
+}
+
+/// Renders the image as an SVG document written to `stream`, and returns the stream once the
+/// document is complete. The document is as large as `pixel_size`, or that of
+/// `options.viewport`, in CSS pixels. Paths, strokes and linear and radial gradients are kept
+/// as vectors; cairo embeds bitmaps for what SVG cannot express, such as conic gradients.
+/// `options.supersample` is ignored.
+///
+/// Needs the `svg` feature.
+#[cfg(feature = "svg")]
+pub fn render_svg<W: io::Write + 'static>(image: &Image, ppi: f64, scale: f64, options: &RenderOptions, stream: W) -> std::result::Result<(W, RenderReport), RenderError> {
+    let (width, height) = output_size(image, ppi, scale, options)?;
+    // The document is sized in points. A CSS pixel is 1/96 inch and a point 1/72 inch.
+    let points_per_pixel = 72.0 / 96.0;
+    let surface = cairo::SvgSurface::for_stream(
+        f64::from(width) * points_per_pixel,
+        f64::from(height) * points_per_pixel,
+        stream
+    )?;
+
+    render_to_stream(&surface, image, ppi * points_per_pixel, scale, options)
+}
+
+/// Renders the image as a single-page PDF document written to `stream`, and returns the
+/// stream once the document is complete. The page has the physical size of the image, or of
+/// `options.viewport`, multiplied by `scale`. As with `render_svg`, what PDF cannot express
+/// is embedded as bitmaps and `options.supersample` is ignored.
+///
+/// Needs the `pdf` feature.
+#[cfg(feature = "pdf")]
+pub fn render_pdf<W: io::Write + 'static>(image: &Image, scale: f64, options: &RenderOptions, stream: W) -> std::result::Result<(W, RenderReport), RenderError> {
+    let (width, height) = options.viewport
+        .map_or((image.width, image.height), |(min, max)| (max.x - min.x, max.y - min.y));
+    let points_per_unit = POINTS_PER_INCH / image.unit_per_inch * scale;
+    let (width, height) = (width * points_per_unit, height * points_per_unit);
+
+    if !(width > 0.0 && height > 0.0) {
+        return Err(cairo::Error::InvalidSize.into());
+    }
+
+    let surface = cairo::PdfSurface::for_stream(width, height, stream)?;
+
+    render_to_stream(&surface, image, POINTS_PER_INCH, scale, options)
+}
+
+#[cfg(feature = "pdf")]
+const POINTS_PER_INCH: f64 = 72.0;
+
+/// Renders into a vector surface whose unit is the point, finishes the surface and returns
+/// its output stream.
+#[cfg(any(feature = "svg", feature = "pdf"))]
+fn render_to_stream<W: 'static>(surface: &cairo::Surface, image: &Image, ppi: f64, scale: f64, options: &RenderOptions) -> std::result::Result<(W, RenderReport), RenderError> {
+    image.validate()?;
+    validate_styles(image, &options.styles)?;
+
+    let context = Context::new(surface)?;
+    let report = render_checked(&context, image, ppi, scale, options)?;
+    drop(context);
+
+    let stream = surface.finish_output_stream()
+        .map_err(|_| cairo::Error::WriteError)?
+        .downcast::<W>()
+        .map_err(|_| cairo::Error::WriteError)?;
+
+    Ok((*stream, report))
+}
+
+impl RegionShape {
+    /// Renders the region alone into a surface covering its bounding box, and returns the
+    /// surface as a pattern placed where the region appears when the image is rendered at
+    /// `ppi` and `scale`.
+    pub fn to_pattern(&self, image: &Image, ppi: f64, scale: f64) -> std::result::Result<cairo::SurfacePattern, RenderError> {
+        let pen = self.resolved_pen(image)?.map(|pen| &pen.pattern);
+        let brush = self.resolved_brush(image)?.map(|brush| &brush.pattern);
+
+        let mut scaler = Scaler::new(image, ppi, scale);
+        let (min, max) = self.bounding_box(image).ok_or(cairo::Error::InvalidSize)?;
+
+        let x = scaler.scale(min.x).floor();
+        let y = scaler.scale(min.y).floor();
+        let width = scaler.scale(max.x).ceil() - x;
+        let height = scaler.scale(max.y).ceil() - y;
+
+        if width > i32::MAX.into() || height > i32::MAX.into() {
+            return Err(RenderError::Cairo(cairo::Error::InvalidSize));
         }
+
+        scaler.images = decode_images(pen.into_iter().chain(brush))?;
+        let surface = cairo::ImageSurface::create(cairo::Format::ARgb32, (width as i32).max(1), (height as i32).max(1))?;
+        let context = Context::new(&surface)?;
+
+        context.translate(-x, -y);
+        context.set_fill_rule(cairo::FillRule::EvenOdd);
+        render_region(&context, self, image, &scaler)?;
+        drop(context);
+
+        let pattern = cairo::SurfacePattern::create(&surface);
+        pattern.set_matrix(cairo::Matrix::new(1.0, 0.0, 0.0, 1.0, -x, -y));
+
+        Ok(pattern)
     }
 
-    Ok(())
+    /// Returns the pen of the region, or `None` if it has no pen.
+    pub fn resolved_pen<'a>(&self, image: &'a Image) -> std::result::Result<Option<&'a Pen>, RenderError> {
+        self.pen
+            .map(|index| image.pens.get(index).ok_or(RenderError::PenOutOfRange { index, count: image.pens.len() }))
+            .transpose()
+    }
+
+    /// Returns the brush of the region, or `None` if it has no brush.
+    pub fn resolved_brush<'a>(&self, image: &'a Image) -> std::result::Result<Option<&'a Brush>, RenderError> {
+        self.brush
+            .map(|index| image.brushes.get(index).ok_or(RenderError::BrushOutOfRange { index, count: image.brushes.len() }))
+            .transpose()
+    }
 }
 
-fn translate_line_cap(cap: LineCap) -> cairo::LineCap {
-    match cap {
-        LineCap::Butt => cairo::LineCap::Butt,
-        LineCap::Round => cairo::LineCap::Round,
-        LineCap::Square => cairo::LineCap::Square
+impl CurveShape {
+    /// Returns the pen of the curve.
+    pub fn resolved_pen<'a>(&self, image: &'a Image) -> std::result::Result<&'a Pen, RenderError> {
+        image.pens.get(self.pen).ok_or(RenderError::PenOutOfRange { index: self.pen, count: image.pens.len() })
     }
 }
 
-fn translate_line_join(join: LineJoin) -> cairo::LineJoin {
-    match join {
-        LineJoin::Miter => cairo::LineJoin::Miter,
-        LineJoin::Round => cairo::LineJoin::Round,
-        LineJoin::Bevel => cairo::LineJoin::Bevel
+/// Fills a `width` by `height` swatch with `pattern` and returns its pixels as RGBA bytes,
+/// row by row, with straight alpha.
+///
+/// Gradients are scaled uniformly and centered so that their end points, or the circles of a
+/// radial gradient, span the swatch. Conic gradients are centered.
+pub fn render_swatch(pattern: &Pattern, width: i32, height: i32) -> std::result::Result<Vec<u8>, RenderError> {
+    let images = decode_images(std::iter::once(pattern))?;
+    let mut surface = cairo::ImageSurface::create(cairo::Format::ARgb32, width, height)?;
+    let context = Context::new(&surface)?;
+
+    if let Some((min, max)) = pattern_extent(pattern) {
+        let (dx, dy) = (max.x - min.x, max.y - min.y);
+        let factor = match (dx > 0.0, dy > 0.0) {
+            (true, true) => (f64::from(width) / dx).min(f64::from(height) / dy),
+            (true, false) => f64::from(width) / dx,
+            (false, true) => f64::from(height) / dy,
+            (false, false) => 1.0
+        };
+
+        context.translate(f64::from(width) / 2.0, f64::from(height) / 2.0);
+        context.scale(factor, factor);
+        context.translate(-(min.x + max.x) / 2.0, -(min.y + max.y) / 2.0);
+    }
+
+    set_pattern(&context, pattern, &Scaler { factor: 1.0, path_log: None, images })?;
+    context.paint()?;
+    drop(context);
+
+    let stride = surface.stride() as usize;
+    let data = surface.data().map_err(|_| RenderError::Cairo(cairo::Error::SurfaceFinished))?;
+    let mut rgba = Vec::with_capacity(width as usize * height as usize * 4);
+
+    for row in data.chunks(stride) {
+        for pixel in row[..width as usize * 4].chunks_exact(4) {
+            let [a, r, g, b] = u32::from_ne_bytes([pixel[0], pixel[1], pixel[2], pixel[3]]).to_be_bytes();
+            let unpremultiply = |c: u8| if a == 0 { 0 } else { ((u32::from(c) * 255 + u32::from(a) / 2) / u32::from(a)) as u8 };
+            rgba.extend_from_slice(&[unpremultiply(r), unpremultiply(g), unpremultiply(b), a]);
+        }
     }
+
+    Ok(rgba)
 }
 
-fn set_pen(context: &Context, pen: &Pen, scaler: &Scaler) -> Result<()> {
-    set_pattern(context, &pen.pattern, scaler)?;
-    context.set_line_width(scaler.scale(pen.width));
-    context.set_line_cap(translate_line_cap(pen.cap));
-    context.set_line_join(translate_line_join(pen.join));
+/// Returns the corners of the box spanned by the geometry of a gradient.
+fn pattern_extent(pattern: &Pattern) -> Option<(Point, Point)> {
+    match pattern {
+        Pattern::Monochrome(_) => None,
+        Pattern::LinearGradient(pat) => Some((
+            Point { x: pat.point_1.x.min(pat.point_2.x), y: pat.point_1.y.min(pat.point_2.y) },
+            Point { x: pat.point_1.x.max(pat.point_2.x), y: pat.point_1.y.max(pat.point_2.y) }
+        )),
+        Pattern::RadialGradient(pat) => {
+            let circle = |center: Point, radius: f64| (
+                Point { x: center.x - radius, y: center.y - radius },
+                Point { x: center.x + radius, y: center.y + radius }
+            );
+            let (min_1, max_1) = circle(pat.center_1, pat.radius_1);
+            let (min_2, max_2) = circle(pat.center_2, pat.radius_2);
+            let aspect = pat.aspect.unwrap_or(1.0);
+            let stretch = |x: f64| pat.center_1.x + (x - pat.center_1.x) * aspect;
 
-    Ok(())
+            Some((
+                Point { x: stretch(min_1.x.min(min_2.x)), y: min_1.y.min(min_2.y) },
+                Point { x: stretch(max_1.x.max(max_2.x)), y: max_1.y.max(max_2.y) }
+            ))
+        },
+        Pattern::ConicGradient(pat) => Some((pat.center, pat.center)),
+        Pattern::Image(_) => None
+    }
 }
 
-fn set_brush(context: &Context, brush: &Brush, scaler: &Scaler) -> Result<()> {
-    set_pattern(context, &brush.pattern, scaler)
+/// Renders an image as it is read from `reader`, one top-level shape at a time, so that no
+/// more than a single shape is held in memory.
+///
+/// Every other member of the image object, including `pens` and `brushes`, must come before
+/// `shapes`; otherwise reading fails with `RenderError::Json`. A shape that refers to a pen
+/// or brush that does not exist fails with `RenderError::PenOutOfRange` or
+/// `RenderError::BrushOutOfRange`. Shapes read before a failure have already been drawn, but
+/// an image pattern that cannot be decoded fails before any is.
+pub fn render_streaming<R: io::Read>(reader: R, context: &Context, ppi: f64, scale: f64) -> std::result::Result<(), RenderError> {
+    let mut deserializer = serde_json::Deserializer::from_reader(reader);
+    let mut failure = None;
+
+    let result = deserializer
+        .deserialize_map(StreamingVisitor { context, ppi, scale, failure: &mut failure })
+        .and_then(|()| deserializer.end());
+
+    match failure {
+        Some(e) => Err(e),
+        None => result.map_err(RenderError::Json)
+    }
 }
 
-fn plot_curve_data(context: &Context, data: &CurveData, scaler: &Scaler, closed: bool) -> Result<()> {
-    context.move_to(scaler.scale(data.start.x), scaler.scale(data.start.y));
+struct StreamingVisitor<'a> {
+    context: &'a Context,
+    ppi: f64,
+    scale: f64,
+    failure: &'a mut Option<RenderError>
+}
 
-    for seg in data.segments.iter() {
-        match seg {
-            Segment::Line(line) => {
-                context.line_to(scaler.scale(line.point_2.x), scaler.scale(line.point_2.y));
-            },
-            Segment::QuadraticBezier(bezier) => {
-                let (x1, y1) = context.current_point()?;
-                let x2 = scaler.scale(bezier.point_2.x);
-                let y2 = scaler.scale(bezier.point_2.y);
-                let x3 = scaler.scale(bezier.point_3.x);
-                let y3 = scaler.scale(bezier.point_3.y);
-                context.curve_to(
-                    1.0 / 3.0 * x1 + 2.0 / 3.0 * x2,
-                    1.0 / 3.0 * y1 + 2.0 / 3.0 * y2,
-                    1.0 / 3.0 * x3 + 2.0 / 3.0 * x2,
-                    1.0 / 3.0 * y3 + 2.0 / 3.0 * y2,
-                    x3,
-                    y3
-                );
-            },
-            Segment::CubicBezier(bezier) => {
-                context.curve_to(
-                    scaler.scale(bezier.point_2.x),
-                    scaler.scale(bezier.point_2.y),
-                    scaler.scale(bezier.point_3.x),
-                    scaler.scale(bezier.point_3.y),
-                    scaler.scale(bezier.point_4.x),
-                    scaler.scale(bezier.point_4.y)
-                );
+impl<'de> Visitor<'de> for StreamingVisitor<'_> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str("image")
+    }
+
+    fn visit_map<A>(self, mut map: A) -> std::result::Result<(), A::Error>
+    where
+        A: MapAccess<'de>
+    {
+        // Everything but the shapes is collected and deserialized as an image without shapes.
+        let mut header = serde_json::Map::new();
+        let mut rendered = false;
+
+        while let Some(key) = map.next_key::<String>()? {
+            if rendered {
+                return Err(de::Error::custom(format!("'{}' must come before 'shapes'", key)));
+            }
+
+            if key != "shapes" {
+                let value = map.next_value::<serde_json::Value>()?;
+                header.insert(key, value);
+                continue;
+            }
+
+            header.insert(key, serde_json::Value::Array(vec![]));
+            let image: Image = serde_json::from_value(serde_json::Value::Object(std::mem::take(&mut header)))
+                .map_err(de::Error::custom)?;
+            let mut scaler = Scaler::new(&image, self.ppi, self.scale);
+
+            match decode_images(image_patterns(&image)) {
+                Ok(images) => scaler.images = images,
+                Err(e) => {
+                    *self.failure = Some(e);
+                    return Err(de::Error::custom("invalid image pattern"));
+                }
             }
+
+            map.next_value_seed(StreamingShapes {
+                context: self.context,
+                image: &image,
+                scaler,
+                failure: &mut *self.failure
+            })?;
+            rendered = true;
         }
-    }
 
-    if closed {
-        context.close_path();
+        if !rendered {
+            return Err(de::Error::missing_field("shapes"));
+        }
+
+        Ok(())
     }
+}
 
-    Ok(())
+struct StreamingShapes<'a> {
+    context: &'a Context,
+    image: &'a Image,
+    scaler: Scaler,
+    failure: &'a mut Option<RenderError>
 }
 
-fn render_curve(context: &Context, curve: &CurveShape, image: &Image, scaler: &Scaler) -> Result<()> {
-    plot_curve_data(context, &curve.data, scaler, false)?;
+impl<'de> DeserializeSeed<'de> for StreamingShapes<'_> {
+    type Value = ();
 
-    if curve.pen >= image.pens.len() {
-        panic!("invalid pen index {}, must be less than {}.", curve.pen, image.pens.len());
+    fn deserialize<D>(self, deserializer: D) -> std::result::Result<(), D::Error>
+    where
+        D: Deserializer<'de>
+    {
+        deserializer.deserialize_seq(self)
     }
+}
 
-    set_pen(context, &image.pens[curve.pen], scaler)?;
-    context.stroke()
+impl<'de> Visitor<'de> for StreamingShapes<'_> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str("array of shapes")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> std::result::Result<(), A::Error>
+    where
+        A: SeqAccess<'de>
+    {
+        self.context.set_operator(cairo::Operator::Over);
+        self.context.set_fill_rule(cairo::FillRule::EvenOdd);
+        self.context.new_path();
+
+        let mut index = 0;
+
+        while let Some(shape) = seq.next_element::<Shape>()? {
+            if let Err(e) = self.image.validate_shape(index, &shape) {
+                *self.failure = Some(e.into());
+                return Err(de::Error::custom("invalid reference"));
+            }
+
+            if let Err(e) = render_shape(self.context, &shape, self.image, &self.scaler) {
+                *self.failure = Some(e.into());
+                return Err(de::Error::custom("rendering operation failed"));
+            }
+
+            index += 1;
+        }
+
+        Ok(())
+    }
 }
 
-fn render_region(context: &Context, region: &RegionShape, image: &Image, scaler: &Scaler) -> Result<()> {
-    if region.data.len() != 0 {
-        plot_curve_data(context, &region.data[0], scaler, true)?;
+fn render_shapes(context: &Context, image: &Image, ppi: f64, scale: f64, n: usize, options: &RenderOptions) -> std::result::Result<RenderReport, RenderError> {
+    let styled;
+    let image = if options.styles.is_empty() {
+        image
+    } else {
+        styled = styled_image(image, &options.styles)?;
+        &styled
+    };
+
+    let mut scaler = Scaler::new(image, ppi, scale);
+    let mut report = RenderReport::default();
+    scaler.images = decode_images(image_patterns(image))?;
+
+    if options.dump_paths {
+        scaler.path_log = Some(RefCell::new(Vec::new()));
     }
 
-    for i in 1..region.data.len() {
-        context.new_sub_path();
-        plot_curve_data(context, &region.data[i], scaler, true)?;
+    if let Some(antialias) = options.antialias {
+        context.set_antialias(antialias);
     }
 
-    if let Some(brush) = region.brush {
-        if brush >= image.brushes.len() {
-            panic!("invalid brush index {}, must be less than {}.", brush, image.brushes.len());
-        }
+    if let Some(tolerance) = options.tolerance {
+        context.set_tolerance(tolerance);
+    }
 
-        set_brush(context, &image.brushes[brush], scaler)?;
-        context.fill_preserve()?;
+    if let Some(font_options) = &options.font_options {
+        context.set_font_options(font_options);
     }
 
-    if let Some(pen) = region.pen {
-        if pen >= image.pens.len() {
-            panic!("invalid pen index {}, must be less than {}.", pen, image.pens.len());
+    context.set_operator(cairo::Operator::Over);
+    context.set_fill_rule(cairo::FillRule::EvenOdd);
+    context.new_path();
+
+    for (i, shape) in image.shapes.iter().take(n).enumerate() {
+        if options.profile {
+            let start = Instant::now();
+            render_shape(context, shape, image, &scaler)?;
+            report.timings.push((i, start.elapsed()));
+        } else {
+            render_shape(context, shape, image, &scaler)?;
         }
+    }
 
-        set_pen(context, &image.pens[pen], scaler)?;
-        context.stroke()?;
-    } else {
-        context.new_path();
+    if options.debug_index {
+        report.labels = draw_index_labels(context, image, n, &scaler)?;
+    }
+
+    report.paths = scaler.path_log.map(RefCell::into_inner).unwrap_or_default();
+
+    Ok(report)
+}
+
+/// Returns the patterns of the pens and brushes of the image.
+fn image_patterns(image: &Image) -> impl Iterator<Item = &Pattern> {
+    image.pens.iter().map(|pen| &pen.pattern).chain(image.brushes.iter().map(|brush| &brush.pattern))
+}
+
+/// Decodes the bitmap of every image pattern among `patterns`, so that each is decoded once
+/// per render however many shapes use it, and bad data is reported before anything is drawn.
+fn decode_images<'a>(patterns: impl Iterator<Item = &'a Pattern>) -> std::result::Result<HashMap<String, cairo::ImageSurface>, RenderError> {
+    let mut images = HashMap::new();
+
+    for pattern in patterns {
+        let Pattern::Image(pat) = pattern else { continue };
+
+        if !images.contains_key(&pat.data) {
+            let png = pat.png().map_err(RenderError::ImageData)?;
+            let surface = cairo::ImageSurface::create_from_png(&mut png.as_slice())
+                .map_err(RenderError::ImagePng)?;
+            images.insert(pat.data.clone(), surface);
+        }
+    }
+
+    Ok(images)
+}
+
+/// Font size of the labels of `RenderOptions::debug_index`, in device units.
+const INDEX_LABEL_SIZE: f64 = 12.0;
+
+/// Draws the index of each of the first `n` top-level shapes just inside the top-left
+/// corner of its bounding box, and returns the indices drawn.
+fn draw_index_labels(context: &Context, image: &Image, n: usize, scaler: &Scaler) -> Result<Vec<usize>> {
+    let mut labels = Vec::new();
+
+    context.save()?;
+    context.select_font_face("sans-serif", cairo::FontSlant::Normal, cairo::FontWeight::Bold);
+    context.set_font_size(INDEX_LABEL_SIZE);
+    context.set_source_rgb(1.0, 0.0, 1.0);
+
+    for (i, shape) in image.shapes.iter().take(n).enumerate() {
+        if let Some((min, _)) = shape.bounding_box(image) {
+            context.move_to(scaler.scale(min.x), scaler.scale(min.y) + INDEX_LABEL_SIZE);
+            context.show_text(&i.to_string())?;
+            labels.push(i);
+        }
+    }
+
+    context.new_path();
+    context.restore()?;
+
+    Ok(labels)
+}
+
+fn render_shape(context: &Context, shape: &Shape, image: &Image, scaler: &Scaler) -> Result<()> {
+    match shape {
+        Shape::Group(group) => render_group(context, group, image, scaler),
+        Shape::Curve(curve) => render_curve(context, curve, image, scaler),
+        Shape::Region(region) => render_region(context, region, image, scaler)
+    }
+}
+
+fn render_group(context: &Context, group: &GroupShape, image: &Image, scaler: &Scaler) -> Result<()> {
+    with_compositing(context, group.opacity, group.operator, || {
+        // The children are plotted in device units, so the translation is scaled likewise.
+        let Some([xx, yx, xy, yy, x0, y0]) = group.transform else {
+            return render_children(context, group, image, scaler);
+        };
+
+        context.save()?;
+        context.transform(cairo::Matrix::new(xx, yx, xy, yy, scaler.scale(x0), scaler.scale(y0)));
+        let result = render_children(context, group, image, scaler);
+        context.restore()?;
+
+        result
+    })
+}
+
+fn render_children(context: &Context, group: &GroupShape, image: &Image, scaler: &Scaler) -> Result<()> {
+    for child in group.content.iter() {
+        render_shape(context, child, image, scaler)?;
+    }
+
+    Ok(())
+}
+
+/// Number of stops approximating a gradient interpolated in HSL.
+const HSL_STOPS: u32 = 16;
+
+fn add_color_stops(grad: &cairo::Gradient, stops: &[(f64, Color)], interpolate: Option<ColorInterp>) {
+    let steps = match interpolate {
+        None | Some(ColorInterp::Rgb) => 1,
+        Some(ColorInterp::Hsl) | Some(ColorInterp::HslLong) => HSL_STOPS
+    };
+    let interp = interpolate.unwrap_or(ColorInterp::Rgb);
+
+    for (i, pair) in stops.windows(2).enumerate() {
+        let ((offset_1, color_1), (offset_2, color_2)) = (pair[0], pair[1]);
+        let (color_1, color_2) = (color_1.clamped(), color_2.clamped());
+
+        // Each pair after the first starts at the stop that ended the previous pair.
+        for j in u32::from(i > 0)..=steps {
+            let t = f64::from(j) / f64::from(steps);
+            let color = color_1.interpolate(color_2, t, interp);
+            grad.add_color_stop_rgba(offset_1 + (offset_2 - offset_1) * t, color.red, color.green, color.blue, color.alpha);
+        }
+    }
+}
+
+/// Sets `pattern` as the source. Color components are clamped to [0, 1] first; gradient
+/// stops are clamped before they are interpolated. A gradient that paints a single color is
+/// set as a solid source, which cairo fills faster.
+///
+/// Gradients are specified in user space, so any translation of the context, as done by
+/// `render_crop`, applies to them just like to the shapes.
+fn set_pattern(context: &Context, pattern: &Pattern, scaler: &Scaler) -> Result<()> {
+    if let Some(color) = pattern.uniform_color() {
+        let color = color.clamped();
+        context.set_source_rgba(color.red, color.green, color.blue, color.alpha);
+        return Ok(());
+    }
+
+    match pattern {
+        Pattern::Monochrome(_) => unreachable!("a monochrome pattern has a uniform color"),
+        Pattern::LinearGradient(pat) => {
+            let grad = cairo::LinearGradient::new(
+                scaler.scale(pat.point_1.x),
+                scaler.scale(pat.point_1.y),
+                scaler.scale(pat.point_2.x),
+                scaler.scale(pat.point_2.y)
+            );
+            add_color_stops(&grad, &pat.color_stops(), pat.interpolate);
+            grad.set_extend(translate_extend(pat.extend.unwrap_or(Extend::Pad)));
+            context.set_source(grad)?;
+        },
+        Pattern::RadialGradient(pat) => {
+            let grad = cairo::RadialGradient::new(
+                scaler.scale(pat.center_1.x),
+                scaler.scale(pat.center_1.y),
+                scaler.scale(pat.radius_1),
+                scaler.scale(pat.center_2.x),
+                scaler.scale(pat.center_2.y),
+                scaler.scale(pat.radius_2),
+            );
+            add_color_stops(&grad, &pat.color_stops(), pat.interpolate);
+            grad.set_extend(translate_extend(pat.extend.unwrap_or(Extend::Pad)));
+
+            // The pattern matrix maps user space to gradient space. Scaling x by a about
+            // c = center_1 in user space means x' = c + (x - c) / a in gradient space, that
+            // is, xx = 1 / a and x0 = c (1 - 1 / a).
+            if let Some(aspect) = pat.aspect {
+                let cx = scaler.scale(pat.center_1.x);
+                grad.set_matrix(cairo::Matrix::new(1.0 / aspect, 0.0, 0.0, 1.0, cx * (1.0 - 1.0 / aspect), 0.0));
+            }
+
+            context.set_source(grad)?;
+        },
+        Pattern::ConicGradient(pat) => {
+            let (cx, cy) = (scaler.scale(pat.center.x), scaler.scale(pat.center.y));
+            let (x1, y1, x2, y2) = context.clip_extents()?;
+            let radius = [(x1, y1), (x1, y2), (x2, y1), (x2, y2)].iter()
+                .map(|(x, y)| (x - cx).hypot(y - cy))
+                .fold(0.0, f64::max);
+
+            context.set_source(conic_mesh(pat, cx, cy, radius * CONIC_RADIUS_MARGIN + 1.0))?;
+        },
+        Pattern::Image(pat) => {
+            // Only bitmaps decoded before rendering are available.
+            let surface = scaler.images.get(&pat.data).ok_or(cairo::Error::ReadError)?;
+            let origin = pat.origin.unwrap_or(Point { x: 0.0, y: 0.0 });
+            let pixel_size = scaler.scale(pat.pixel_size.unwrap_or(1.0));
+            let pattern = cairo::SurfacePattern::create(surface);
+            pattern.set_extend(translate_extend(pat.extend.unwrap_or(Extend::Repeat)));
+            // Maps user space to bitmap pixels.
+            pattern.set_matrix(cairo::Matrix::new(
+                1.0 / pixel_size,
+                0.0,
+                0.0,
+                1.0 / pixel_size,
+                -scaler.scale(origin.x) / pixel_size,
+                -scaler.scale(origin.y) / pixel_size
+            ));
+            context.set_source(pattern)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Number of sectors a conic gradient is divided into, in addition to those split at stops.
+const CONIC_SECTORS: u32 = 64;
+
+/// Ratio by which sectors extend beyond the clip, so that their straight outer edges, which
+/// lie inside the circle, still cover it.
+const CONIC_RADIUS_MARGIN: f64 = 1.01;
+
+/// Builds a conic gradient as a mesh pattern, since cairo has no native one.
+///
+/// The full turn is divided into thin triangular sectors around the center. Each sector is a
+/// patch whose corners on one radial edge have the color at its start angle and those on the
+/// other edge the color at its end angle, so the color varies only with the angle. The last
+/// sector ends exactly where the first begins, and patches of a single mesh are rasterized
+/// together, so there is no crack at the start angle. The colors on either side of it are
+/// those of the first and last stops.
+fn conic_mesh(pat: &ConicGradientPattern, cx: f64, cy: f64, radius: f64) -> cairo::Mesh {
+    let interp = pat.interpolate.unwrap_or(ColorInterp::Rgb);
+    let stops: Vec<(f64, Color)> = pat.stops.iter().map(|(offset, color)| (*offset, color.clamped())).collect();
+    let color_at = |t: f64| {
+        let next = stops.iter().position(|(offset, _)| *offset > t);
+
+        match next {
+            Some(0) => stops[0].1,
+            Some(i) => {
+                let ((offset_1, color_1), (offset_2, color_2)) = (stops[i - 1], stops[i]);
+                color_1.interpolate(color_2, (t - offset_1) / (offset_2 - offset_1), interp)
+            },
+            None => stops.last().map_or(Color { red: 0.0, green: 0.0, blue: 0.0, alpha: 0.0 }, |(_, color)| *color)
+        }
+    };
+
+    let mut breaks: Vec<f64> = (0..=CONIC_SECTORS)
+        .map(|i| f64::from(i) / f64::from(CONIC_SECTORS))
+        .chain(pat.stops.iter().map(|(offset, _)| *offset))
+        .collect();
+    breaks.sort_by(f64::total_cmp);
+    breaks.dedup();
+
+    let mesh = cairo::Mesh::new();
+    let point_at = |t: f64| {
+        let angle = pat.start_angle.to_radians() + std::f64::consts::TAU * t;
+        (cx + radius * angle.cos(), cy + radius * angle.sin())
+    };
+
+    for pair in breaks.windows(2) {
+        let (t1, t2) = (pair[0], pair[1]);
+        // Just after `t1` and just before `t2`, so that hard stops take effect at the break.
+        let color_1 = color_at(t1);
+        let color_2 = color_at(t2 - (t2 - t1) * 1e-9);
+        let (x1, y1) = point_at(t1);
+        let (x2, y2) = point_at(t2);
+
+        mesh.begin_patch();
+        mesh.move_to(cx, cy);
+        mesh.line_to(x1, y1);
+        mesh.line_to(x2, y2);
+        mesh.line_to(cx, cy);
+
+        for (corner, color) in [
+            (cairo::MeshCorner::MeshCorner0, color_1),
+            (cairo::MeshCorner::MeshCorner1, color_1),
+            (cairo::MeshCorner::MeshCorner2, color_2),
+            (cairo::MeshCorner::MeshCorner3, color_2)
+        ] {
+            mesh.set_corner_color_rgba(corner, color.red, color.green, color.blue, color.alpha);
+        }
+
+        mesh.end_patch();
+    }
+
+    mesh
+}
+
+fn translate_extend(extend: Extend) -> cairo::Extend {
+    match extend {
+        Extend::None => cairo::Extend::None,
+        Extend::Repeat => cairo::Extend::Repeat,
+        Extend::Reflect => cairo::Extend::Reflect,
+        Extend::Pad => cairo::Extend::Pad
+    }
+}
+
+fn translate_line_cap(cap: LineCap) -> cairo::LineCap {
+    match cap {
+        LineCap::Butt => cairo::LineCap::Butt,
+        LineCap::Round => cairo::LineCap::Round,
+        LineCap::Square => cairo::LineCap::Square
+    }
+}
+
+fn translate_line_join(join: LineJoin) -> cairo::LineJoin {
+    match join {
+        LineJoin::Miter => cairo::LineJoin::Miter,
+        LineJoin::Round => cairo::LineJoin::Round,
+        LineJoin::Bevel => cairo::LineJoin::Bevel
+    }
+}
+
+fn translate_fill_rule(rule: FillRule) -> cairo::FillRule {
+    match rule {
+        FillRule::EvenOdd => cairo::FillRule::EvenOdd,
+        FillRule::Nonzero => cairo::FillRule::Winding
+    }
+}
+
+fn translate_operator(operator: Operator) -> cairo::Operator {
+    match operator {
+        Operator::Over => cairo::Operator::Over,
+        Operator::Multiply => cairo::Operator::Multiply,
+        Operator::Screen => cairo::Operator::Screen,
+        Operator::Overlay => cairo::Operator::Overlay,
+        Operator::Darken => cairo::Operator::Darken,
+        Operator::Lighten => cairo::Operator::Lighten,
+        Operator::Difference => cairo::Operator::Difference,
+        Operator::Add => cairo::Operator::Add,
+        Operator::Xor => cairo::Operator::Xor
+    }
+}
+
+fn set_pen(context: &Context, pen: &Pen, image: &Image, scaler: &Scaler) -> Result<()> {
+    set_pattern(context, &image.straight_pattern(&pen.pattern), scaler)?;
+    context.set_line_width(scaler.scale(pen.width));
+    context.set_line_cap(translate_line_cap(image.line_cap(pen)));
+    context.set_line_join(translate_line_join(image.line_join(pen)));
+    context.set_miter_limit(pen.miter_limit.unwrap_or(DEFAULT_MITER_LIMIT));
+
+    match &pen.dash {
+        Some(dash) if !dash.is_solid() => {
+            let pattern: Vec<f64> = dash.pattern.iter().map(|length| scaler.scale(*length)).collect();
+            context.set_dash(&pattern, scaler.scale(dash.offset));
+        },
+        _ => context.set_dash(&[], 0.0)
+    }
+
+    Ok(())
+}
+
+/// Miter limit of a new cairo context, restored for pens without one so that a previous pen
+/// does not leak its limit.
+const DEFAULT_MITER_LIMIT: f64 = 10.0;
+
+/// Flatness tolerance, in device pixels, with which the length of a path is measured for a
+/// relative dash.
+const DASH_LENGTH_TOLERANCE: f64 = 0.1;
+
+/// Returns the pen with a relative dash made absolute for a path of `length` image units,
+/// which is only computed for such a pen.
+fn absolute_dash_pen<F>(pen: &Pen, length: F) -> Cow<'_, Pen>
+where
+    F: FnOnce() -> f64
+{
+    match &pen.dash {
+        Some(dash) if pen.dash_mode == Some(DashMode::Relative) =>
+            Cow::Owned(Pen { dash: Some(dash.scaled(length())), dash_mode: None, ..pen.clone() }),
+        _ => Cow::Borrowed(pen)
+    }
+}
+
+/// Returns the length of the sub-paths of a region, each including its closing line.
+fn region_length(data: &[CurveData], tolerance: f64) -> f64 {
+    data.iter()
+        .map(|data| {
+            let end = data.end_point();
+            data.length(tolerance) + (end.x - data.start.x).hypot(end.y - data.start.y)
+        })
+        .sum()
+}
+
+fn stroke(context: &Context, pen: &Pen, image: &Image, scaler: &Scaler) -> Result<()> {
+    set_pen(context, pen, image, scaler)?;
+    context.stroke()
+}
+
+fn stroke_region(context: &Context, pen: &Pen, image: &Image, scaler: &Scaler) -> Result<()> {
+    let align = match pen.align {
+        None | Some(StrokeAlign::Center) => return stroke(context, pen, image, scaler),
+        Some(align) => align
+    };
+
+    // Clip to the inside or the outside of the region and stroke with twice the width so
+    // that exactly the requested width remains visible on that side.
+    context.save()?;
+
+    if align == StrokeAlign::Inner {
+        context.clip_preserve();
+    } else {
+        // The rectangle around the region inverts it only by the even-odd rule.
+        context.set_fill_rule(cairo::FillRule::EvenOdd);
+        let path = context.copy_path()?;
+        let (x1, y1, x2, y2) = context.clip_extents()?;
+        context.new_path();
+        context.rectangle(x1, y1, x2 - x1, y2 - y1);
+        context.append_path(&path);
+        context.clip();
+        context.append_path(&path);
+    }
+
+    let doubled = Pen { width: pen.width * 2.0, ..pen.clone() };
+    stroke(context, &doubled, image, scaler)?;
+    context.restore()
+}
+
+fn with_opacity<F>(context: &Context, opacity: Option<f64>, draw: F) -> Result<()>
+where
+    F: FnOnce() -> Result<()>
+{
+    match opacity {
+        None => draw(),
+        Some(opacity) => {
+            // The group is popped even if drawing fails, so the context is left as it was.
+            context.push_group();
+            let result = draw();
+            context.pop_group_to_source()?;
+            result?;
+            context.paint_with_alpha(opacity)
+        }
+    }
+}
+
+/// Draws into a group of its own when an operator is given, which is then painted with the
+/// operator and the opacity. The operator is restored afterwards, and the group popped even
+/// if drawing fails, so nested groups each composite into the group around them.
+fn with_compositing<F>(context: &Context, opacity: Option<f64>, operator: Option<Operator>, draw: F) -> Result<()>
+where
+    F: FnOnce() -> Result<()>
+{
+    let Some(operator) = operator else {
+        return with_opacity(context, opacity, draw);
+    };
+
+    context.push_group();
+    let result = draw();
+    context.pop_group_to_source()?;
+    result?;
+
+    context.save()?;
+    context.set_operator(translate_operator(operator));
+    context.paint_with_alpha(opacity.unwrap_or(1.0))?;
+    context.restore()
+}
+
+fn set_brush(context: &Context, brush: &Brush, image: &Image, scaler: &Scaler) -> Result<()> {
+    set_pattern(context, &image.straight_pattern(&brush.pattern), scaler)
+}
+
+fn plot_curve_data(context: &Context, data: &CurveData, scaler: &Scaler, closed: bool) -> Result<()> {
+    scaler.plot(context, PathOp::MoveTo(scaler.scale(data.start.x), scaler.scale(data.start.y)));
+
+    // Smooth segments need the control point of the segment before them.
+    if data.segments.iter().any(Segment::is_smooth) {
+        for (start, seg) in data.expand().iter_with_start() {
+            plot_segment(context, start, seg, scaler);
+        }
+    } else {
+        for (start, seg) in data.iter_with_start() {
+            plot_segment(context, start, seg, scaler);
+        }
+    }
+
+    if closed {
+        scaler.plot(context, PathOp::ClosePath);
+    }
+
+    Ok(())
+}
+
+fn plot_segment(context: &Context, start: Point, seg: &Segment, scaler: &Scaler) {
+    match seg {
+        Segment::Line(line) => {
+            scaler.plot(context, PathOp::LineTo(scaler.scale(line.point_2.x), scaler.scale(line.point_2.y)));
+        },
+        Segment::QuadraticBezier(bezier) => {
+            let x1 = scaler.scale(start.x);
+            let y1 = scaler.scale(start.y);
+            let x2 = scaler.scale(bezier.point_2.x);
+            let y2 = scaler.scale(bezier.point_2.y);
+            let x3 = scaler.scale(bezier.point_3.x);
+            let y3 = scaler.scale(bezier.point_3.y);
+            scaler.plot(context, PathOp::CurveTo(
+                1.0 / 3.0 * x1 + 2.0 / 3.0 * x2,
+                1.0 / 3.0 * y1 + 2.0 / 3.0 * y2,
+                1.0 / 3.0 * x3 + 2.0 / 3.0 * x2,
+                1.0 / 3.0 * y3 + 2.0 / 3.0 * y2,
+                x3,
+                y3
+            ));
+        },
+        Segment::CubicBezier(bezier) => {
+            scaler.plot(context, PathOp::CurveTo(
+                scaler.scale(bezier.point_2.x),
+                scaler.scale(bezier.point_2.y),
+                scaler.scale(bezier.point_3.x),
+                scaler.scale(bezier.point_3.y),
+                scaler.scale(bezier.point_4.x),
+                scaler.scale(bezier.point_4.y)
+            ));
+        },
+        Segment::SmoothQuadraticBezier(_) | Segment::SmoothCubicBezier(_) => {
+            unreachable!("smooth segments are expanded by plot_curve_data");
+        },
+        Segment::Arc(arc) => {
+            let beziers = arc.to_beziers(start);
+            let starts = std::iter::once(start).chain(beziers.iter().map(Segment::end_point));
+
+            for (start, bezier) in starts.zip(beziers.iter()) {
+                plot_segment(context, start, bezier, scaler);
+            }
+        }
+    }
+}
+
+fn render_curve(context: &Context, curve: &CurveShape, image: &Image, scaler: &Scaler) -> Result<()> {
+    // Like in `render_region`, only cairo errors pass through here.
+    let pen = curve.resolved_pen(image).map_err(|_| cairo::Error::InvalidIndex)?;
+
+    plot_curve_data(context, &curve.data, scaler, false)?;
+
+    let tolerance = DASH_LENGTH_TOLERANCE / scaler.scale(1.0);
+    let pen = absolute_dash_pen(pen, || curve.data.length(tolerance));
+    stroke(context, &pen, image, scaler)
+}
+
+fn render_region(context: &Context, region: &RegionShape, image: &Image, scaler: &Scaler) -> Result<()> {
+    // Only cairo errors pass through here; the functions returning `RenderError` check the
+    // references up front to report the index.
+    let pen = region.resolved_pen(image).map_err(|_| cairo::Error::InvalidIndex)?;
+    let brush = region.resolved_brush(image).map_err(|_| cairo::Error::InvalidIndex)?;
+
+    context.set_fill_rule(translate_fill_rule(region.fill_rule.unwrap_or(FillRule::EvenOdd)));
+    let result = paint_region(context, region, pen, brush, image, scaler);
+    context.set_fill_rule(cairo::FillRule::EvenOdd);
+
+    result
+}
+
+fn paint_region(context: &Context, region: &RegionShape, pen: Option<&Pen>, brush: Option<&Brush>, image: &Image, scaler: &Scaler) -> Result<()> {
+    // Sub-paths without area fill nothing but are still stroked.
+    let has_zero_area = region.data.iter().any(CurveData::is_zero_area);
+
+    if has_zero_area {
+        plot_region_data(context, region.data.iter().filter(|data| !data.is_zero_area()), scaler)?;
+    } else {
+        plot_region_data(context, region.data.iter(), scaler)?;
+    }
+
+    if let Some(brush) = brush {
+        with_opacity(context, region.fill_opacity, || {
+            set_brush(context, brush, image, scaler)?;
+
+            if let Some(origin) = region.pattern_origin {
+                let (x, y) = (scaler.scale(origin.x), scaler.scale(origin.y));
+                let source = context.source();
+                let offset = cairo::Matrix::new(1.0, 0.0, 0.0, 1.0, -x, -y);
+                source.set_matrix(cairo::Matrix::multiply(&offset, &source.matrix()));
+            }
+
+            context.fill_preserve()
+        })?;
+    }
+
+    if has_zero_area {
+        context.new_path();
+        plot_region_data(context, region.data.iter(), scaler)?;
+    }
+
+    if let Some(pen) = pen {
+        let tolerance = DASH_LENGTH_TOLERANCE / scaler.scale(1.0);
+        let pen = absolute_dash_pen(pen, || region_length(&region.data, tolerance));
+
+        with_opacity(context, region.stroke_opacity, || {
+            stroke_region(context, &pen, image, scaler)
+        })?;
+    } else {
+        context.new_path();
+    }
+
+    Ok(())
+}
+
+fn plot_region_data<'a>(context: &Context, data: impl Iterator<Item = &'a CurveData>, scaler: &Scaler) -> Result<()> {
+    for (i, data) in data.enumerate() {
+        if i > 0 {
+            context.new_sub_path();
+        }
+
+        plot_curve_data(context, data, scaler, true)?;
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_surface(image: &Image) -> (cairo::ImageSurface, Context) {
+        let surface = cairo::ImageSurface::create(
+            cairo::Format::ARgb32,
+            image.width as i32,
+            image.height as i32
+        ).unwrap();
+        let context = Context::new(&surface).unwrap();
+        (surface, context)
+    }
+
+    fn render_to_surface(image: &Image) -> cairo::ImageSurface {
+        let (surface, context) = create_surface(image);
+        render(&context, image, image.unit_per_inch, 1.0).unwrap();
+        drop(context);
+        surface
+    }
+
+    // Returns the premultiplied [alpha, red, green, blue] components of a pixel.
+    fn pixel(surface: &mut cairo::ImageSurface, x: usize, y: usize) -> [u8; 4] {
+        let offset = y * surface.stride() as usize + x * 4;
+        let data = surface.data().unwrap();
+        u32::from_ne_bytes(data[offset..offset + 4].try_into().unwrap()).to_be_bytes()
+    }
+
+    fn pixel_alpha(surface: &mut cairo::ImageSurface, x: usize, y: usize) -> u8 {
+        pixel(surface, x, y)[0]
+    }
+
+    #[test]
+    fn test_self_overlap() {
+        let image_str = r#"{
+  "width": 40,
+  "height": 40,
+  "unit-per-inch": 96,
+  "pens": [
+    {
+      "pattern": { "type": "monochrome", "color": [1, 0, 0, 0.5] },
+      "width": 6,
+      "cap": "butt",
+      "join": "miter"
+    }
+  ],
+  "brushes": [],
+  "shapes": [
+    {
+      "type": "curve",
+      "pen": 0,
+      "data": [[5, 20], ["L", [35, 20]], ["L", [35, 35]], ["L", [20, 35]], ["L", [20, 5]]]
+    }
+  ]
+}"#;
+        let image: Image = serde_json::from_str(image_str).unwrap();
+
+        // A single stroke is composited once, so a semi-transparent pen does not darken
+        // where it crosses itself.
+        let mut surface = render_to_surface(&image);
+        let crossing = pixel_alpha(&mut surface, 20, 20);
+        assert!((crossing as i32 - 128).abs() <= 1);
+        assert_eq!(pixel_alpha(&mut surface, 10, 20), crossing);
+    }
+
+    #[test]
+    fn test_region_opacity() {
+        let image_str = r#"{
+  "width": 40,
+  "height": 40,
+  "unit-per-inch": 96,
+  "pens": [
+    {
+      "pattern": { "type": "monochrome", "color": [1, 0, 0] },
+      "width": 4,
+      "cap": "butt",
+      "join": "miter"
+    }
+  ],
+  "brushes": [
+    {
+      "pattern": {
+        "type": "linear-gradient",
+        "point-1": [0, 0],
+        "color-1": [0, 1, 0],
+        "point-2": [40, 0],
+        "color-2": [0, 0, 1]
+      }
+    }
+  ],
+  "shapes": [
+    {
+      "type": "region",
+      "pen": 0,
+      "brush": 0,
+      "fill-opacity": 0.5,
+      "data": [[[10, 10], ["L", [30, 10]], ["L", [30, 30]], ["L", [10, 30]]]]
+    }
+  ]
+}"#;
+        let mut image: Image = serde_json::from_str(image_str).unwrap();
+
+        let mut surface = render_to_surface(&image);
+        assert!((pixel_alpha(&mut surface, 20, 20) as i32 - 128).abs() <= 1);
+        assert_eq!(255, pixel_alpha(&mut surface, 10, 20));
+
+        if let Shape::Region(region) = &mut image.shapes[0] {
+            region.fill_opacity = None;
+            region.stroke_opacity = Some(0.5);
+        }
+
+        let mut surface = render_to_surface(&image);
+        assert_eq!(255, pixel_alpha(&mut surface, 20, 20));
+        assert!(pixel_alpha(&mut surface, 9, 20) < 255);
+    }
+
+    #[test]
+    fn test_render_prefix() {
+        let image_str = r#"{
+  "width": 40,
+  "height": 20,
+  "unit-per-inch": 96,
+  "pens": [],
+  "brushes": [
+    { "pattern": { "type": "monochrome", "color": [0, 0, 1] } }
+  ],
+  "shapes": [
+    { "type": "region", "brush": 0, "data": [[[0, 0], ["L", [20, 0]], ["L", [20, 20]], ["L", [0, 20]]]] },
+    { "type": "region", "brush": 0, "data": [[[20, 0], ["L", [40, 0]], ["L", [40, 20]], ["L", [20, 20]]]] }
+  ]
+}"#;
+        let image: Image = serde_json::from_str(image_str).unwrap();
+
+        let (mut surface, context) = create_surface(&image);
+        render_prefix(&context, &image, 96.0, 1.0, 1).unwrap();
+        drop(context);
+        assert_eq!(255, pixel_alpha(&mut surface, 10, 10));
+        assert_eq!(0, pixel_alpha(&mut surface, 30, 10));
+
+        let (mut surface, context) = create_surface(&image);
+        render_prefix(&context, &image, 96.0, 1.0, 5).unwrap();
+        drop(context);
+        assert_eq!(255, pixel_alpha(&mut surface, 10, 10));
+        assert_eq!(255, pixel_alpha(&mut surface, 30, 10));
+    }
+
+    #[test]
+    fn test_stroke_align() {
+        let image_str = r#"{
+  "width": 40,
+  "height": 40,
+  "unit-per-inch": 96,
+  "pens": [
+    {
+      "pattern": { "type": "monochrome", "color": [1, 0, 0] },
+      "width": 4,
+      "cap": "butt",
+      "join": "miter",
+      "align": "outer"
+    }
+  ],
+  "brushes": [],
+  "shapes": [
+    { "type": "region", "pen": 0, "data": [[[10, 10], ["L", [30, 10]], ["L", [30, 30]], ["L", [10, 30]]]] }
+  ]
+}"#;
+        let mut image: Image = serde_json::from_str(image_str).unwrap();
+
+        let mut outer = render_to_surface(&image);
+        assert_eq!(255, pixel_alpha(&mut outer, 7, 20));
+        assert_eq!(255, pixel_alpha(&mut outer, 9, 20));
+        assert_eq!(0, pixel_alpha(&mut outer, 10, 20));
+        assert_eq!(0, pixel_alpha(&mut outer, 20, 20));
+        assert_eq!(0, pixel_alpha(&mut outer, 5, 20));
+
+        image.pens[0].align = Some(StrokeAlign::Inner);
+
+        let mut inner = render_to_surface(&image);
+        assert_eq!(0, pixel_alpha(&mut inner, 9, 20));
+        assert_eq!(255, pixel_alpha(&mut inner, 10, 20));
+        assert_eq!(255, pixel_alpha(&mut inner, 13, 20));
+        assert_eq!(0, pixel_alpha(&mut inner, 14, 20));
+
+        image.pens[0].align = None;
+
+        let mut center = render_to_surface(&image);
+        assert_eq!(0, pixel_alpha(&mut center, 7, 20));
+        assert_eq!(255, pixel_alpha(&mut center, 8, 20));
+        assert_eq!(255, pixel_alpha(&mut center, 11, 20));
+        assert_eq!(0, pixel_alpha(&mut center, 12, 20));
+    }
+
+    #[test]
+    fn test_render_profile() {
+        let image_str = r#"{
+  "width": 40,
+  "height": 20,
+  "unit-per-inch": 96,
+  "pens": [],
+  "brushes": [
+    { "pattern": { "type": "monochrome", "color": [0, 0, 1] } }
+  ],
+  "shapes": [
+    { "type": "region", "brush": 0, "data": [[[0, 0], ["L", [20, 0]], ["L", [20, 20]]]] },
+    { "type": "group", "content": [] },
+    { "type": "region", "brush": 0, "data": [[[20, 0], ["L", [40, 0]], ["L", [40, 20]]]] }
+  ]
+}"#;
+        let image: Image = serde_json::from_str(image_str).unwrap();
+
+        let (_surface, context) = create_surface(&image);
+        let options = RenderOptions { profile: true, ..RenderOptions::default() };
+        let report = render_with_options(&context, &image, 96.0, 1.0, &options).unwrap();
+        let indices: Vec<usize> = report.timings.iter().map(|(i, _)| *i).collect();
+        assert_eq!(vec![0, 1, 2], indices);
+
+        let report = render_with_options(&context, &image, 96.0, 1.0, &RenderOptions::default()).unwrap();
+        assert!(report.timings.is_empty());
+    }
+
+    #[test]
+    fn test_render_crop() {
+        let image_str = r#"{
+  "width": 40,
+  "height": 20,
+  "unit-per-inch": 96,
+  "pens": [],
+  "brushes": [
+    {
+      "pattern": {
+        "type": "linear-gradient",
+        "point-1": [0, 0],
+        "color-1": [1, 0, 0],
+        "point-2": [40, 0],
+        "color-2": [0, 0, 1]
+      }
+    }
+  ],
+  "shapes": [
+    { "type": "region", "brush": 0, "data": [[[0, 0], ["L", [40, 0]], ["L", [40, 20]], ["L", [0, 20]]]] }
+  ]
+}"#;
+        let image: Image = serde_json::from_str(image_str).unwrap();
+        let mut full = render_to_surface(&image);
+
+        let tile = |origin: Point| {
+            let surface = cairo::ImageSurface::create(cairo::Format::ARgb32, 20, 20).unwrap();
+            let context = Context::new(&surface).unwrap();
+            render_crop(&context, &image, 96.0, 1.0, origin).unwrap();
+            drop(context);
+            surface
+        };
+
+        let mut left = tile(Point { x: 0.0, y: 0.0 });
+        let mut right = tile(Point { x: 20.0, y: 0.0 });
+
+        assert_eq!(pixel(&mut full, 19, 10), pixel(&mut left, 19, 10));
+        assert_eq!(pixel(&mut full, 20, 10), pixel(&mut right, 0, 10));
+        assert_eq!(pixel(&mut full, 39, 10), pixel(&mut right, 19, 10));
+        assert_ne!(pixel(&mut left, 0, 10), pixel(&mut right, 0, 10));
+    }
+
+    #[test]
+    fn test_draw_list_round_trip() {
+        let image_str = include_str!("../samples/region.lison");
+        let image: Image = serde_json::from_str(image_str).unwrap();
+        let image2 = from_draw_list(&image.to_draw_list(), image.width, image.height, image.unit_per_inch);
+
+        let mut surface = render_to_surface(&image);
+        let mut surface2 = render_to_surface(&image2);
+        assert_eq!(&surface.data().unwrap()[..], &surface2.data().unwrap()[..]);
+    }
+
+    #[test]
+    fn test_default_cap() {
+        let image_str = r#"{
+  "width": 40,
+  "height": 20,
+  "unit-per-inch": 96,
+  "default-cap": "square",
+  "pens": [
+    { "pattern": { "type": "monochrome", "color": [0, 0, 0] }, "width": 4 }
+  ],
+  "brushes": [],
+  "shapes": [
+    { "type": "curve", "pen": 0, "data": [[10, 10], ["L", [30, 10]]] }
+  ]
+}"#;
+        let mut image: Image = serde_json::from_str(image_str).unwrap();
+
+        let mut square = render_to_surface(&image);
+        assert_eq!(255, pixel_alpha(&mut square, 8, 10));
+        assert_eq!(255, pixel_alpha(&mut square, 31, 10));
+
+        image.default_cap = None;
+
+        let mut butt = render_to_surface(&image);
+        assert_eq!(0, pixel_alpha(&mut butt, 8, 10));
+        assert_eq!(0, pixel_alpha(&mut butt, 31, 10));
+
+        image.pens[0].cap = Some(LineCap::Square);
+
+        let mut square = render_to_surface(&image);
+        assert_eq!(255, pixel_alpha(&mut square, 8, 10));
+    }
+
+    #[test]
+    fn test_check_backend() {
+        assert!(check_backend(Backend::Png).is_ok());
+
+        if cfg!(not(feature = "svg")) {
+            assert!(matches!(check_backend(Backend::Svg), Err(RenderError::BackendUnavailable("svg"))));
+        }
+
+        if cfg!(not(feature = "pdf")) {
+            assert!(matches!(check_backend(Backend::Pdf), Err(RenderError::BackendUnavailable("pdf"))));
+        }
+    }
+
+    #[test]
+    fn test_rasterize_supersample() {
+        let image_str = r#"{
+  "width": 20,
+  "height": 20,
+  "unit-per-inch": 96,
+  "pens": [],
+  "brushes": [
+    { "pattern": { "type": "monochrome", "color": [0, 0, 0] } }
+  ],
+  "shapes": [
+    { "type": "region", "brush": 0, "data": [[[0, 0], ["L", [20, 0]], ["L", [0, 20]]]] }
+  ]
+}"#;
+        let image: Image = serde_json::from_str(image_str).unwrap();
+
+        let options = RenderOptions { antialias: Some(cairo::Antialias::None), ..RenderOptions::default() };
+        let (mut aliased, _) = rasterize(&image, 96.0, 1.0, &options).unwrap();
+        assert_eq!(20, aliased.width());
+        assert!([0, 255].contains(&pixel_alpha(&mut aliased, 10, 9)));
+
+        let options = RenderOptions { supersample: 4, ..options };
+        let (mut supersampled, _) = rasterize(&image, 96.0, 1.0, &options).unwrap();
+        assert_eq!(20, supersampled.width());
+        assert_eq!(20, supersampled.height());
+
+        // The diagonal edge passes through the pixel, so it is partially covered.
+        let alpha = pixel_alpha(&mut supersampled, 10, 9);
+        assert!(alpha > 0 && alpha < 255);
+        assert_eq!(255, pixel_alpha(&mut supersampled, 2, 2));
+        assert_eq!(0, pixel_alpha(&mut supersampled, 17, 17));
+    }
+
+    #[test]
+    fn test_rasterize_viewport() {
+        let image_str = r#"{
+  "width": 20,
+  "height": 20,
+  "unit-per-inch": 96,
+  "pens": [],
+  "brushes": [
+    { "pattern": { "type": "monochrome", "color": [0, 0, 0] } }
+  ],
+  "shapes": [
+    { "type": "region", "brush": 0, "data": [[[-10, 0], ["L", [-5, 0]], ["L", [-5, 5]], ["L", [-10, 5]]]] }
+  ]
+}"#;
+        let image: Image = serde_json::from_str(image_str).unwrap();
+
+        let (mut clipped, _) = rasterize(&image, 96.0, 1.0, &RenderOptions::default()).unwrap();
+        assert_eq!(20, clipped.width());
+        assert!((0..20).all(|x| pixel_alpha(&mut clipped, x, 2) == 0));
+
+        let options = RenderOptions { viewport: Some(image.expanded_canvas()), ..RenderOptions::default() };
+        let (mut expanded, _) = rasterize(&image, 96.0, 1.0, &options).unwrap();
+        assert_eq!(30, expanded.width());
+        assert_eq!(20, expanded.height());
+        assert_eq!(255, pixel_alpha(&mut expanded, 2, 2));
+        assert_eq!(0, pixel_alpha(&mut expanded, 7, 2));
+    }
+
+    #[test]
+    fn test_clip_to_canvas() {
+        let image_str = r#"{
+  "width": 20,
+  "height": 20,
+  "unit-per-inch": 96,
+  "pens": [],
+  "brushes": [
+    { "pattern": { "type": "monochrome", "color": [0, 0, 0] } }
+  ],
+  "shapes": [
+    { "type": "region", "brush": 0, "data": [[[-10, 0], ["L", [5, 0]], ["L", [5, 5]], ["L", [-10, 5]]]] }
+  ]
+}"#;
+        let image: Image = serde_json::from_str(image_str).unwrap();
+        let viewport = Some(image.expanded_canvas());
+
+        let options = RenderOptions { viewport, ..RenderOptions::default() };
+        let (mut unclipped, _) = rasterize(&image, 96.0, 1.0, &options).unwrap();
+        assert_eq!(255, pixel_alpha(&mut unclipped, 2, 2));
+        assert_eq!(255, pixel_alpha(&mut unclipped, 12, 2));
+
+        let options = RenderOptions { viewport, clip_to_canvas: true, ..RenderOptions::default() };
+        let (mut clipped, _) = rasterize(&image, 96.0, 1.0, &options).unwrap();
+        assert_eq!(30, clipped.width());
+        assert_eq!(0, pixel_alpha(&mut clipped, 2, 2));
+        assert_eq!(255, pixel_alpha(&mut clipped, 12, 2));
+    }
+
+    #[test]
+    fn test_background() {
+        let image_str = r#"{
+  "width": 20,
+  "height": 20,
+  "unit-per-inch": 96,
+  "pens": [],
+  "brushes": [
+    { "pattern": { "type": "monochrome", "color": [0, 0, 1] } }
+  ],
+  "shapes": [
+    { "type": "region", "brush": 0, "data": [[[5, 5], ["L", [15, 5]], ["L", [15, 15]], ["L", [5, 15]]]] }
+  ]
+}"#;
+        let image: Image = serde_json::from_str(image_str).unwrap();
+
+        let (mut transparent, _) = rasterize(&image, 96.0, 1.0, &RenderOptions::default()).unwrap();
+        assert_eq!([0, 0, 0, 0], pixel(&mut transparent, 0, 0));
+
+        let background = Color::parse("#ff0000");
+        let options = RenderOptions { background, ..RenderOptions::default() };
+        let (mut surface, _) = rasterize(&image, 96.0, 1.0, &options).unwrap();
+        assert_eq!([255, 255, 0, 0], pixel(&mut surface, 0, 0));
+        assert_eq!([255, 255, 0, 0], pixel(&mut surface, 19, 19));
+        assert_eq!([255, 0, 0, 255], pixel(&mut surface, 10, 10));
+
+        // The background covers the margin outside the canvas as well.
+        let options = RenderOptions { background, viewport: Some((Point { x: -5.0, y: -5.0 }, Point { x: 25.0, y: 25.0 })), clip_to_canvas: true, ..RenderOptions::default() };
+        let (mut surface, _) = rasterize(&image, 96.0, 1.0, &options).unwrap();
+        assert_eq!([255, 255, 0, 0], pixel(&mut surface, 0, 0));
+    }
+
+    #[test]
+    fn test_region_to_pattern() {
+        let image_str = r#"{
+  "width": 40,
+  "height": 40,
+  "unit-per-inch": 48,
+  "pens": [
+    { "pattern": { "type": "monochrome", "color": [0, 0, 0] }, "width": 1 }
+  ],
+  "brushes": [
+    { "pattern": { "type": "monochrome", "color": [1, 0, 0] } }
+  ],
+  "shapes": [
+    { "type": "region", "brush": 0, "data": [[[5, 5], ["L", [15, 5]], ["L", [15, 10]], ["L", [5, 10]]]] },
+    { "type": "region", "pen": 0, "brush": 0, "data": [[[5, 5], ["L", [15, 5]], ["L", [15, 10]], ["L", [5, 10]]]] }
+  ]
+}"#;
+        let image: Image = serde_json::from_str(image_str).unwrap();
+
+        let region = match &image.shapes[0] {
+            Shape::Region(region) => region,
+            _ => panic!("unexpected shape type")
+        };
+        let pattern = region.to_pattern(&image, 96.0, 1.0).unwrap();
+        let mut surface = cairo::ImageSurface::try_from(pattern.surface().unwrap()).unwrap();
+        drop(pattern);
+        assert_eq!(20, surface.width());
+        assert_eq!(10, surface.height());
+        assert_eq!([255, 255, 0, 0], pixel(&mut surface, 10, 5));
+
+        let region = match &image.shapes[1] {
+            Shape::Region(region) => region,
+            _ => panic!("unexpected shape type")
+        };
+        let pattern = region.to_pattern(&image, 96.0, 1.0).unwrap();
+        let surface = cairo::ImageSurface::try_from(pattern.surface().unwrap()).unwrap();
+        assert_eq!(22, surface.width());
+        assert_eq!(12, surface.height());
+
+        // The pattern lines up with the region when painted on a full rendering.
+        let (mut target, context) = create_surface(&image);
+        context.set_source(&pattern).unwrap();
+        context.paint().unwrap();
+        drop(context);
+        assert_eq!(255, pixel_alpha(&mut target, 15, 15));
+        assert_eq!(0, pixel_alpha(&mut target, 5, 5));
+    }
+
+    #[test]
+    fn test_alpha_mode() {
+        let image_str = r#"{
+  "width": 10,
+  "height": 10,
+  "unit-per-inch": 96,
+  "alpha-mode": "premultiplied",
+  "pens": [],
+  "brushes": [
+    { "pattern": { "type": "monochrome", "color": [0.4, 0.2, 0, 0.5] } }
+  ],
+  "shapes": [
+    { "type": "region", "brush": 0, "data": [[[0, 0], ["L", [10, 0]], ["L", [10, 10]], ["L", [0, 10]]]] }
+  ]
+}"#;
+        let premultiplied: Image = serde_json::from_str(image_str).unwrap();
+
+        let mut straight = premultiplied.clone();
+        straight.alpha_mode = None;
+        straight.brushes[0].pattern = Pattern::Monochrome(MonochromePattern {
+            color: Color { red: 0.8, green: 0.4, blue: 0.0, alpha: 0.5 }
+        });
+
+        let mut premultiplied_surface = render_to_surface(&premultiplied);
+        let mut straight_surface = render_to_surface(&straight);
+        assert_eq!(pixel(&mut straight_surface, 5, 5), pixel(&mut premultiplied_surface, 5, 5));
+        assert_eq!(128, pixel_alpha(&mut premultiplied_surface, 5, 5));
+    }
+
+    #[test]
+    fn test_gradient_hsl() {
+        let image_str = r#"{
+  "width": 100,
+  "height": 10,
+  "unit-per-inch": 96,
+  "pens": [],
+  "brushes": [
+    {
+      "pattern": {
+        "type": "linear-gradient",
+        "point-1": [0, 0], "color-1": [1, 0, 0],
+        "point-2": [100, 0], "color-2": [0, 1, 0]
+      }
+    }
+  ],
+  "shapes": [
+    { "type": "region", "brush": 0, "data": [[[0, 0], ["L", [100, 0]], ["L", [100, 10]], ["L", [0, 10]]]] }
+  ]
+}"#;
+        let mut image: Image = serde_json::from_str(image_str).unwrap();
+
+        // Chroma, the spread of the color components, drops to half in RGB.
+        let chroma = |[_, r, g, b]: [u8; 4]| r.max(g).max(b) - r.min(g).min(b);
+
+        let mut rgb = render_to_surface(&image);
+        assert!(chroma(pixel(&mut rgb, 50, 5)) < 140);
+
+        if let Pattern::LinearGradient(grad) = &mut image.brushes[0].pattern {
+            grad.interpolate = Some(ColorInterp::Hsl);
+        }
+
+        let mut hsl = render_to_surface(&image);
+        let mid = pixel(&mut hsl, 50, 5);
+        assert!(chroma(mid) > 240);
+        assert!(mid[1] > 240 && mid[2] > 240);
+    }
+
+    #[test]
+    fn test_font_options() {
+        let image: Image = serde_json::from_str(include_str!("../samples/region.lison")).unwrap();
+
+        let mut font_options = cairo::FontOptions::new().unwrap();
+        font_options.set_hint_style(cairo::HintStyle::None);
+        font_options.set_hint_metrics(cairo::HintMetrics::Off);
+        let options = RenderOptions { font_options: Some(font_options), ..RenderOptions::default() };
+
+        let (_surface, context) = create_surface(&image);
+        render_with_options(&context, &image, 96.0, 1.0, &options).unwrap();
+        let applied = context.font_options().unwrap();
+        assert_eq!(cairo::HintStyle::None, applied.hint_style());
+        assert_eq!(cairo::HintMetrics::Off, applied.hint_metrics());
+
+        // The index labels are the only text, so they show the effect of the options.
+        let labels_with = |antialias: cairo::Antialias| {
+            let mut font_options = cairo::FontOptions::new().unwrap();
+            font_options.set_antialias(antialias);
+            let options = RenderOptions { font_options: Some(font_options), debug_index: true, ..RenderOptions::default() };
+            let (mut surface, _) = rasterize(&image, 96.0, 1.0, &options).unwrap();
+            surface.data().unwrap().to_vec()
+        };
+
+        assert_eq!(labels_with(cairo::Antialias::None), labels_with(cairo::Antialias::None));
+        assert_ne!(labels_with(cairo::Antialias::None), labels_with(cairo::Antialias::Gray));
+    }
+
+    #[test]
+    fn test_zero_area_region() {
+        let image_str = r#"{
+  "width": 40,
+  "height": 40,
+  "unit-per-inch": 96,
+  "pens": [
+    { "pattern": { "type": "monochrome", "color": [0, 0, 1] }, "width": 2 }
+  ],
+  "brushes": [
+    { "pattern": { "type": "monochrome", "color": [1, 0, 0] } }
+  ],
+  "shapes": [
+    {
+      "type": "region",
+      "brush": 0,
+      "data": [
+        [[5, 20], ["L", [35, 20]], ["L", [20, 20]]],
+        [[5, 30], ["L", [15, 30]], ["L", [15, 38]], ["L", [5, 38]]]
+      ]
+    }
+  ]
+}"#;
+        let mut image: Image = serde_json::from_str(image_str).unwrap();
+
+        let mut fill_only = render_to_surface(&image);
+        assert!((0..40).all(|x| (15..25).all(|y| pixel_alpha(&mut fill_only, x, y) == 0)));
+        assert_eq!([255, 255, 0, 0], pixel(&mut fill_only, 10, 34));
+
+        if let Shape::Region(region) = &mut image.shapes[0] {
+            region.pen = Some(0);
+        }
+
+        let mut stroked = render_to_surface(&image);
+        assert_eq!([255, 0, 0, 255], pixel(&mut stroked, 20, 19));
+        assert_eq!([255, 0, 0, 255], pixel(&mut stroked, 20, 20));
+        assert_eq!([255, 255, 0, 0], pixel(&mut stroked, 10, 34));
+    }
+
+    #[test]
+    fn test_max_pixels() {
+        let image: Image = serde_json::from_str(include_str!("../samples/region.lison")).unwrap();
+
+        let options = RenderOptions { max_pixels: Some(100 * 100), ..RenderOptions::default() };
+        let (surface, _) = rasterize(&image, 72.0, 1.0, &options).unwrap();
+        assert_eq!(100 * 100, surface.width() * surface.height());
+
+        // Far too large to allocate, so this only passes if the check comes first.
+        let result = rasterize(&image, 72.0, 400.0, &options);
+        assert!(matches!(result, Err(RenderError::TooManyPixels { width: 40000, height: 40000, max_pixels: 10000 })));
+
+        // Supersampling renders into a surface that many times larger on each axis first.
+        let supersampled = RenderOptions { supersample: 2, ..options };
+        let result = rasterize(&image, 72.0, 1.0, &supersampled);
+        assert!(matches!(result, Err(RenderError::TooManyPixels { width: 200, height: 200, max_pixels: 10000 })));
+    }
+
+    #[test]
+    fn test_pattern_origin() {
+        let image_str = r#"{
+  "width": 40,
+  "height": 10,
+  "unit-per-inch": 96,
+  "pens": [],
+  "brushes": [
+    {
+      "pattern": {
+        "type": "linear-gradient",
+        "point-1": [0, 0], "color-1": [0, 0, 0],
+        "point-2": [10, 0], "color-2": [1, 1, 1]
+      }
+    }
+  ],
+  "shapes": [
+    { "type": "region", "brush": 0, "pattern-origin": [0, 0], "data": [[[0, 0], ["L", [10, 0]], ["L", [10, 10]], ["L", [0, 10]]]] },
+    { "type": "region", "brush": 0, "pattern-origin": [20, 0], "data": [[[20, 0], ["L", [30, 0]], ["L", [30, 10]], ["L", [20, 10]]]] }
+  ]
+}"#;
+        let mut image: Image = serde_json::from_str(image_str).unwrap();
+
+        let mut aligned = render_to_surface(&image);
+        for x in 0..10 {
+            assert_eq!(pixel(&mut aligned, x, 5), pixel(&mut aligned, x + 20, 5));
+        }
+        assert!(pixel(&mut aligned, 2, 5)[1] < pixel(&mut aligned, 7, 5)[1]);
+
+        if let Shape::Region(region) = &mut image.shapes[1] {
+            region.pattern_origin = None;
+        }
+
+        let mut global = render_to_surface(&image);
+        assert_eq!([255, 255, 255, 255], pixel(&mut global, 22, 5));
+    }
+
+    #[test]
+    fn test_region_resolved_pen_brush() {
+        let image: Image = serde_json::from_str(include_str!("../samples/region.lison")).unwrap();
+        let mut region = RegionShape {
+            id: None,
+            pen: Some(0),
+            brush: None,
+            fill_opacity: None,
+            stroke_opacity: None,
+            pattern_origin: None,
+            fill_rule: None,
+            data: Vec::new()
+        };
+
+        assert!(region.resolved_pen(&image).unwrap().is_some());
+        assert!(region.resolved_brush(&image).unwrap().is_none());
+
+        region.brush = Some(image.brushes.len());
+        assert!(matches!(
+            region.resolved_brush(&image),
+            Err(RenderError::BrushOutOfRange { index, count }) if index == count
+        ));
+
+        region.pen = Some(image.pens.len() + 1);
+        assert!(matches!(region.resolved_pen(&image), Err(RenderError::PenOutOfRange { .. })));
+        assert!(region.to_pattern(&image, 96.0, 1.0).is_err());
+
+        // Rendering a region with a missing brush fails instead of panicking.
+        let mut broken = image.clone();
+        if let Shape::Region(shape) = &mut broken.shapes[0] {
+            shape.brush = Some(broken.brushes.len());
+        }
+        let result = rasterize(&broken, 96.0, 1.0, &RenderOptions::default());
+        assert!(matches!(result, Err(RenderError::BrushOutOfRange { index, count }) if index == count));
+        let (_surface, context) = create_surface(&broken);
+        assert!(matches!(render(&context, &broken, 96.0, 1.0), Err(cairo::Error::InvalidIndex)));
+    }
+
+    #[test]
+    fn test_curve_resolved_pen() {
+        let mut image: Image = serde_json::from_str(include_str!("../samples/curve.lison")).unwrap();
+        let Shape::Curve(mut curve) = image.shapes[1].clone() else { panic!("unexpected shape type") };
+        assert!(curve.resolved_pen(&image).is_ok());
+
+        // Rendering a curve with a missing pen fails instead of panicking.
+        curve.pen = image.pens.len();
+        assert!(matches!(curve.resolved_pen(&image), Err(RenderError::PenOutOfRange { index, count }) if index == count));
+        image.shapes[1] = Shape::Curve(curve);
+        let (_surface, context) = create_surface(&image);
+        assert!(matches!(render(&context, &image, 96.0, 1.0), Err(cairo::Error::InvalidIndex)));
+    }
+
+    #[test]
+    fn test_dump_paths() {
+        let image_str = r#"{
+  "width": 20,
+  "height": 20,
+  "unit-per-inch": 48,
+  "pens": [
+    { "pattern": { "type": "monochrome", "color": [0, 0, 0] }, "width": 1 }
+  ],
+  "brushes": [],
+  "shapes": [
+    { "type": "curve", "pen": 0, "data": [[1, 2], ["L", [10, 5]]] }
+  ]
+}"#;
+        let image: Image = serde_json::from_str(image_str).unwrap();
+        let (_surface, context) = create_surface(&image);
+
+        let report = render_with_options(&context, &image, 96.0, 1.0, &RenderOptions::default()).unwrap();
+        assert!(report.paths.is_empty());
+
+        let options = RenderOptions { dump_paths: true, ..RenderOptions::default() };
+        let report = render_with_options(&context, &image, 96.0, 1.0, &options).unwrap();
+        assert_eq!(vec![PathOp::MoveTo(2.0, 4.0), PathOp::LineTo(20.0, 10.0)], report.paths);
+    }
+
+    #[test]
+    fn test_arc_segment() {
+        let image_str = r#"{
+  "width": 20,
+  "height": 20,
+  "unit-per-inch": 96,
+  "pens": [],
+  "brushes": [
+    { "pattern": { "type": "monochrome", "color": [0, 0, 0] } }
+  ],
+  "shapes": [
+    { "type": "region", "brush": 0, "data": [[[0, 0], ["L", [16, 0]], ["A", 16, 16, 0, false, true, [0, 16]]]] }
+  ]
+}"#;
+        let image: Image = serde_json::from_str(image_str).unwrap();
+        let (mut surface, context) = create_surface(&image);
+
+        let options = RenderOptions { dump_paths: true, ..RenderOptions::default() };
+        let report = render_with_options(&context, &image, 96.0, 1.0, &options).unwrap();
+        drop(context);
+
+        let kappa = 16.0 * 0.5522847498;
+        match report.paths[..] {
+            [PathOp::MoveTo(..), PathOp::LineTo(..), PathOp::CurveTo(x2, y2, x3, y3, x4, y4), PathOp::ClosePath] => {
+                assert!((x2 - 16.0).abs() < 1e-9 && (y2 - kappa).abs() < 1e-9);
+                assert!((x3 - kappa).abs() < 1e-9 && (y3 - 16.0).abs() < 1e-9);
+                assert!(x4 == 0.0 && y4 == 16.0);
+            },
+            _ => panic!("unexpected path {:?}", report.paths)
+        }
+
+        // Inside and outside of the quarter disc near its rim.
+        assert_eq!(255, pixel_alpha(&mut surface, 10, 10));
+        assert_eq!(0, pixel_alpha(&mut surface, 13, 13));
+    }
+
+    #[test]
+    fn test_extract_shape() {
+        let image: Image = serde_json::from_str(include_str!("../samples/curve.lison")).unwrap();
+
+        for index in 0..image.shapes.len() {
+            let alone = Image { shapes: vec![image.shapes[index].clone()], ..image.clone() };
+            let extracted = image.extract_shape(index, false).unwrap();
+
+            let mut expect = render_to_surface(&alone);
+            let mut actual = render_to_surface(&extracted);
+            assert_eq!(expect.data().unwrap().to_vec(), actual.data().unwrap().to_vec());
+        }
+    }
+
+    #[test]
+    fn test_color_out_of_range() {
+        let image_str = r#"{
+  "width": 10,
+  "height": 10,
+  "unit-per-inch": 96,
+  "pens": [],
+  "brushes": [
+    { "pattern": { "type": "monochrome", "color": [2.0, -1.0, 0.5] } },
+    { "pattern": { "type": "monochrome", "color": [1, 0, 0.5, -0.5] } },
+    { "pattern": { "type": "linear-gradient", "point-1": [0, 0], "color-1": [3, 3, 3], "point-2": [10, 0], "color-2": [1, 1, 1] } }
+  ],
+  "shapes": [
+    { "type": "region", "brush": 0, "data": [[[0, 0], ["L", [10, 0]], ["L", [10, 3]], ["L", [0, 3]]]] },
+    { "type": "region", "brush": 1, "data": [[[0, 0], ["L", [10, 0]], ["L", [10, 3]], ["L", [0, 3]]]] },
+    { "type": "region", "brush": 2, "data": [[[0, 5], ["L", [10, 5]], ["L", [10, 8]], ["L", [0, 8]]]] }
+  ]
+}"#;
+        let image: Image = serde_json::from_str(image_str).unwrap();
+        let mut surface = render_to_surface(&image);
+
+        // The negative alpha of the second region makes it invisible.
+        assert_eq!([255, 255, 0, 128], pixel(&mut surface, 5, 1));
+        assert_eq!([255, 255, 255, 255], pixel(&mut surface, 5, 6));
+    }
+
+    #[test]
+    fn test_uniform_gradient() {
+        let image_str = r#"{
+  "width": 10,
+  "height": 10,
+  "unit-per-inch": 96,
+  "pens": [],
+  "brushes": [
+    { "pattern": { "type": "linear-gradient", "point-1": [0, 0], "color-1": [0.2, 0.4, 0.6], "point-2": [10, 0], "color-2": [0.2, 0.4, 0.6] } }
+  ],
+  "shapes": [
+    { "type": "region", "brush": 0, "data": [[[0, 0], ["L", [10, 0]], ["L", [10, 10]], ["L", [0, 10]]]] }
+  ]
+}"#;
+        let image: Image = serde_json::from_str(image_str).unwrap();
+        assert_eq!(vec![ValidationWarning::UniformBrushGradient { index: 0 }], image.warnings());
+
+        let mut surface = render_to_surface(&image);
+        let expected = [255, 51, 102, 153];
+
+        for (x, y) in [(0, 0), (5, 5), (9, 9), (9, 0)] {
+            assert_eq!(expected, pixel(&mut surface, x, y));
+        }
+    }
+
+    #[test]
+    fn test_miter_limit() {
+        let image_str = r#"{
+  "width": 50,
+  "height": 40,
+  "unit-per-inch": 96,
+  "pens": [
+    { "pattern": { "type": "monochrome", "color": [0, 0, 0] }, "width": 4, "join": "miter" },
+    { "pattern": { "type": "monochrome", "color": [0, 0, 0] }, "width": 4, "join": "miter", "miter-limit": 1.2 }
+  ],
+  "brushes": [],
+  "shapes": [
+    { "type": "curve", "pen": 0, "data": [[5, 30], ["L", [25, 10]], ["L", [45, 30]]] }
+  ]
+}"#;
+        let mut image: Image = serde_json::from_str(image_str).unwrap();
+        let mut miter = render_to_surface(&image);
+
+        // The right angle needs a limit of at least the square root of 2 for a miter.
+        if let Shape::Curve(curve) = &mut image.shapes[0] {
+            curve.pen = 1;
+        }
+        let mut bevel = render_to_surface(&image);
+
+        for x in [24, 25] {
+            assert!(pixel_alpha(&mut miter, x, 8) > 200);
+            assert!(pixel_alpha(&mut bevel, x, 8) < 128);
+        }
+
+        // Without a limit of its own, a pen does not inherit that of the previous one.
+        image.shapes.push(image.shapes[0].clone());
+        if let Shape::Curve(curve) = &mut image.shapes[1] {
+            curve.pen = 0;
+        }
+        let mut both = render_to_surface(&image);
+        assert!(pixel_alpha(&mut both, 24, 8) > 200);
+    }
+
+    #[test]
+    fn test_dash() {
+        let image_str = r#"{
+  "width": 40,
+  "height": 10,
+  "unit-per-inch": 96,
+  "pens": [
+    { "pattern": { "type": "monochrome", "color": [0, 0, 0] }, "width": 2, "dash": { "pattern": [4, 4] } },
+    { "pattern": { "type": "monochrome", "color": [0, 0, 0] }, "width": 2, "dash": { "pattern": [0, 0] } }
+  ],
+  "brushes": [],
+  "shapes": [
+    { "type": "curve", "pen": 0, "data": [[0, 3], ["L", [40, 3]]] },
+    { "type": "curve", "pen": 1, "data": [[0, 7], ["L", [40, 7]]] }
+  ]
+}"#;
+        let image: Image = serde_json::from_str(image_str).unwrap();
+        let mut surface = render_to_surface(&image);
+
+        // Dashes at [0, 4), [8, 12), ... with gaps between them.
+        for x in [1, 9, 17, 25, 33] {
+            assert_eq!(255, pixel_alpha(&mut surface, x, 2));
+            assert_eq!(0, pixel_alpha(&mut surface, x + 4, 2));
+        }
+
+        // The all-zero dash of the second pen draws a solid line.
+        for x in [1, 5, 9, 13] {
+            assert_eq!(255, pixel_alpha(&mut surface, x, 6));
+        }
+
+        // Dash lengths scale with the resolution.
+        let (mut doubled, _) = rasterize(&image, 192.0, 1.0, &RenderOptions::default()).unwrap();
+        assert_eq!(255, pixel_alpha(&mut doubled, 7, 5));
+        assert_eq!(0, pixel_alpha(&mut doubled, 9, 5));
+    }
+
+    #[test]
+    fn test_relative_dash() {
+        let image_str = r#"{
+  "width": 40,
+  "height": 30,
+  "unit-per-inch": 96,
+  "pens": [
+    { "pattern": { "type": "monochrome", "color": [0, 0, 0] }, "width": 2, "dash": { "pattern": [0.25, 0.25] }, "dash-mode": "relative" }
+  ],
+  "brushes": [],
+  "shapes": [
+    { "type": "curve", "pen": 0, "data": [[0, 3], ["L", [20, 3]]] },
+    { "type": "curve", "pen": 0, "data": [[0, 7], ["L", [40, 7]]] },
+    { "type": "region", "pen": 0, "data": [[[0, 12], ["L", [10, 12]], ["L", [10, 22]], ["L", [0, 22]]]] }
+  ]
+}"#;
+        let image: Image = serde_json::from_str(image_str).unwrap();
+        let mut surface = render_to_surface(&image);
+
+        // A quarter of 20 units: dashes at [0, 5) and [10, 15).
+        assert_eq!(255, pixel_alpha(&mut surface, 3, 2));
+        assert_eq!(0, pixel_alpha(&mut surface, 7, 2));
+        assert_eq!(255, pixel_alpha(&mut surface, 12, 2));
+
+        // A quarter of 40 units: dashes at [0, 10) and [20, 30).
+        assert_eq!(255, pixel_alpha(&mut surface, 7, 6));
+        assert_eq!(0, pixel_alpha(&mut surface, 12, 6));
+        assert_eq!(255, pixel_alpha(&mut surface, 22, 6));
+
+        // A quarter of the 40-unit perimeter covers the top side, then the right side is a gap.
+        assert_eq!(255, pixel_alpha(&mut surface, 5, 12));
+        assert_eq!(0, pixel_alpha(&mut surface, 10, 17));
+        assert_eq!(255, pixel_alpha(&mut surface, 5, 22));
+    }
+
+    #[test]
+    fn test_region_opacity_failure() {
+        let image_str = r#"{
+  "width": 10,
+  "height": 10,
+  "unit-per-inch": 96,
+  "pens": [],
+  "brushes": [ { "pattern": { "type": "image", "data": "" } } ],
+  "shapes": [
+    { "type": "region", "brush": 0, "fill-opacity": 0.5, "fill-rule": "nonzero", "data": [[[0, 0], ["L", [10, 0]], ["L", [10, 10]]]] }
+  ]
+}"#;
+        let image: Image = serde_json::from_str(image_str).unwrap();
+
+        // The empty bitmap is not a PNG file, so the fill fails. That leaves neither a pushed
+        // group nor the fill rule of the region behind.
+        let (mut surface, context) = create_surface(&image);
+        let Shape::Region(region) = &image.shapes[0] else { unreachable!() };
+        assert!(render_region(&context, region, &image, &Scaler::new(&image, 96.0, 1.0)).is_err());
+        assert_eq!(cairo::FillRule::EvenOdd, context.fill_rule());
+
+        assert!(with_opacity(&context, Some(0.5), || Err(cairo::Error::InvalidIndex)).is_err());
+        context.set_source_rgb(1.0, 0.0, 0.0);
+        context.paint().unwrap();
+        drop(context);
+        assert_eq!([255, 255, 0, 0], pixel(&mut surface, 5, 5));
+    }
+
+    #[test]
+    fn test_fill_rule() {
+        let image_str = r#"{
+  "width": 30,
+  "height": 20,
+  "unit-per-inch": 96,
+  "pens": [],
+  "brushes": [ { "pattern": { "type": "monochrome", "color": [0, 0, 0] } } ],
+  "shapes": [
+    { "type": "region", "brush": 0, "data": [
+      [[0, 0], ["L", [20, 0]], ["L", [20, 20]], ["L", [0, 20]]],
+      [[10, 0], ["L", [30, 0]], ["L", [30, 20]], ["L", [10, 20]]]
+    ] }
+  ]
+}"#;
+        let mut image: Image = serde_json::from_str(image_str).unwrap();
+
+        let mut even_odd = render_to_surface(&image);
+        assert_eq!(255, pixel_alpha(&mut even_odd, 5, 10));
+        assert_eq!(0, pixel_alpha(&mut even_odd, 15, 10));
+        assert_eq!(255, pixel_alpha(&mut even_odd, 25, 10));
+
+        if let Shape::Region(region) = &mut image.shapes[0] {
+            region.fill_rule = Some(FillRule::Nonzero);
+        }
+
+        let mut nonzero = render_to_surface(&image);
+        assert_eq!(255, pixel_alpha(&mut nonzero, 5, 10));
+        assert_eq!(255, pixel_alpha(&mut nonzero, 15, 10));
+        assert_eq!(255, pixel_alpha(&mut nonzero, 25, 10));
+    }
+
+    #[test]
+    fn test_group_opacity() {
+        let image_str = r#"{
+  "width": 30,
+  "height": 10,
+  "unit-per-inch": 96,
+  "pens": [],
+  "brushes": [ { "pattern": { "type": "monochrome", "color": [0, 0, 0, 0.5] } } ],
+  "shapes": [
+    { "type": "group", "opacity": 0.5, "content": [
+      { "type": "region", "brush": 0, "data": [[[0, 0], ["L", [20, 0]], ["L", [20, 10]], ["L", [0, 10]]]] },
+      { "type": "region", "brush": 0, "data": [[[10, 0], ["L", [30, 0]], ["L", [30, 10]], ["L", [10, 10]]]] }
+    ] }
+  ]
+}"#;
+        let mut image: Image = serde_json::from_str(image_str).unwrap();
+
+        // The children blend with each other first: 0.5, 0.75 and 0.5, then the whole at 50%.
+        let mut grouped = render_to_surface(&image);
+        assert!(pixel_alpha(&mut grouped, 5, 5).abs_diff(64) <= 1);
+        assert!(pixel_alpha(&mut grouped, 15, 5).abs_diff(96) <= 1);
+        assert!(pixel_alpha(&mut grouped, 25, 5).abs_diff(64) <= 1);
+
+        if let Shape::Group(group) = &mut image.shapes[0] {
+            group.opacity = None;
+        }
+
+        let mut plain = render_to_surface(&image);
+        assert!(pixel_alpha(&mut plain, 5, 5).abs_diff(128) <= 1);
+        assert!(pixel_alpha(&mut plain, 15, 5).abs_diff(191) <= 1);
+    }
+
+    #[test]
+    fn test_group_opacity_failure() {
+        let image_str = r#"{
+  "width": 10,
+  "height": 10,
+  "unit-per-inch": 96,
+  "pens": [],
+  "brushes": [ { "pattern": { "type": "monochrome", "color": [0, 0, 1] } } ],
+  "shapes": [
+    { "type": "group", "opacity": 0.5, "content": [
+      { "type": "region", "brush": 0, "data": [[[0, 0], ["L", [10, 0]], ["L", [10, 10]]]] },
+      { "type": "region", "brush": 1, "data": [[[0, 0], ["L", [10, 10]], ["L", [0, 10]]]] }
+    ] }
+  ]
+}"#;
+        let image: Image = serde_json::from_str(image_str).unwrap();
+
+        // The group is popped when a child fails, so later drawing reaches the surface.
+        let (mut surface, context) = create_surface(&image);
+        assert!(render(&context, &image, 96.0, 1.0).is_err());
+        context.set_source_rgb(1.0, 0.0, 0.0);
+        context.paint().unwrap();
+        drop(context);
+        assert_eq!([255, 255, 0, 0], pixel(&mut surface, 2, 8));
+    }
+
+    #[test]
+    fn test_group_operator() {
+        let image_str = r#"{
+  "width": 30,
+  "height": 10,
+  "unit-per-inch": 96,
+  "pens": [],
+  "brushes": [
+    { "pattern": { "type": "monochrome", "color": [1, 1, 0] } },
+    { "pattern": { "type": "monochrome", "color": [0, 1, 1] } }
+  ],
+  "shapes": [
+    { "type": "region", "brush": 0, "data": [[[0, 0], ["L", [20, 0]], ["L", [20, 10]], ["L", [0, 10]]]] },
+    { "type": "group", "operator": "multiply", "content": [
+      { "type": "group", "content": [
+        { "type": "region", "brush": 1, "data": [[[10, 0], ["L", [30, 0]], ["L", [30, 10]], ["L", [10, 10]]]] }
+      ] }
+    ] },
+    { "type": "region", "brush": 0, "data": [[[0, 0], ["L", [5, 0]], ["L", [5, 10]], ["L", [0, 10]]]] }
+  ]
+}"#;
+        let mut image: Image = serde_json::from_str(image_str).unwrap();
+
+        // Yellow times cyan is green where they overlap; over nothing, cyan stays cyan. The
+        // shape after the group is painted over again.
+        let mut multiplied = render_to_surface(&image);
+        assert_eq!([255, 0, 255, 0], pixel(&mut multiplied, 15, 5));
+        assert_eq!([255, 0, 255, 255], pixel(&mut multiplied, 25, 5));
+        assert_eq!([255, 255, 255, 0], pixel(&mut multiplied, 2, 5));
+
+        if let Shape::Group(group) = &mut image.shapes[1] {
+            group.operator = None;
+        }
+
+        let mut plain = render_to_surface(&image);
+        assert_eq!([255, 0, 255, 255], pixel(&mut plain, 15, 5));
+
+        // A failing child pops the nested groups, so later drawing reaches the surface.
+        if let Shape::Group(group) = &mut image.shapes[1] {
+            group.operator = Some(Operator::Multiply);
+
+            if let Shape::Group(inner) = &mut group.content[0] {
+                inner.operator = Some(Operator::Screen);
+
+                if let Shape::Region(region) = &mut inner.content[0] {
+                    region.brush = Some(2);
+                }
+            }
+        }
+
+        let (mut surface, context) = create_surface(&image);
+        assert!(render(&context, &image, 96.0, 1.0).is_err());
+        context.set_source_rgb(0.0, 0.0, 1.0);
+        context.paint().unwrap();
+        drop(context);
+        assert_eq!([255, 0, 0, 255], pixel(&mut surface, 25, 5));
+    }
+
+    #[test]
+    fn test_group_transform() {
+        let image_str = r#"{
+  "width": 40,
+  "height": 40,
+  "unit-per-inch": 48,
+  "pens": [],
+  "brushes": [ { "pattern": { "type": "monochrome", "color": [0, 0, 0] } } ],
+  "shapes": [
+    { "type": "group", "transform": [0, 1, -1, 0, 20, 0], "content": [
+      { "type": "region", "brush": 0, "data": [[[0, 0], ["L", [10, 0]], ["L", [10, 5]], ["L", [0, 5]]]] }
+    ] }
+  ]
+}"#;
+        let image: Image = serde_json::from_str(image_str).unwrap();
+
+        // Rendered at twice the unit size, the turned rectangle covers [15, 20] x [0, 10]
+        // in image units.
+        let (mut surface, _) = rasterize(&image, 96.0, 1.0, &RenderOptions::default()).unwrap();
+        assert_eq!(255, pixel_alpha(&mut surface, 35, 10));
+        assert_eq!(0, pixel_alpha(&mut surface, 25, 10));
+        assert_eq!(0, pixel_alpha(&mut surface, 35, 25));
+
+        let (min, max) = image.bounding_box().unwrap();
+        assert!(Point { x: 15.0, y: 0.0 } == min);
+        assert!(Point { x: 20.0, y: 10.0 } == max);
+
+        // A failure inside the group still restores the transform of the context.
+        let broken_str = image_str.replace(
+            r#"{ "type": "monochrome", "color": [0, 0, 0] }"#,
+            r#"{ "type": "image", "data": "bm90IGEgUE5HIGZpbGU=" }"#);
+        let broken: Image = serde_json::from_str(&broken_str).unwrap();
+        let (_surface, context) = create_surface(&broken);
+        assert!(render(&context, &broken, 96.0, 1.0).is_err());
+        assert!(context.matrix() == cairo::Matrix::identity());
+    }
+
+    #[test]
+    fn test_styles() {
+        let image_str = r#"{
+  "width": 30,
+  "height": 10,
+  "unit-per-inch": 96,
+  "pens": [],
+  "brushes": [
+    { "pattern": { "type": "monochrome", "color": [1, 0, 0] } },
+    { "pattern": { "type": "monochrome", "color": [0, 0, 1] } }
+  ],
+  "shapes": [
+    { "type": "region", "id": "left", "brush": 0, "data": [[[0, 0], ["L", [10, 0]], ["L", [10, 10]], ["L", [0, 10]]]] },
+    { "type": "group", "id": "rest", "content": [
+      { "type": "region", "brush": 0, "data": [[[10, 0], ["L", [20, 0]], ["L", [20, 10]], ["L", [10, 10]]]] },
+      { "type": "region", "id": "right", "brush": 0, "data": [[[20, 0], ["L", [30, 0]], ["L", [30, 10]], ["L", [20, 10]]]] }
+    ] }
+  ]
+}"#;
+        let image: Image = serde_json::from_str(image_str).unwrap();
+        let original = serde_json::to_string(&image).unwrap();
+
+        let mut styles = HashMap::new();
+        styles.insert(String::from("left"), StyleOverride {
+            fill_color: Some(Color { red: 0.0, green: 1.0, blue: 0.0, alpha: 1.0 }),
+            ..StyleOverride::default()
+        });
+        styles.insert(String::from("rest"), StyleOverride { brush: Some(1), ..StyleOverride::default() });
+        styles.insert(String::from("right"), StyleOverride::default());
+        let options = RenderOptions { styles, ..RenderOptions::default() };
+
+        let (mut surface, _) = rasterize(&image, 96.0, 1.0, &options).unwrap();
+        assert_eq!([255, 0, 255, 0], pixel(&mut surface, 5, 5));
+        assert_eq!([255, 0, 0, 255], pixel(&mut surface, 15, 5));
+        assert_eq!([255, 255, 0, 0], pixel(&mut surface, 25, 5));
+
+        assert_eq!(original, serde_json::to_string(&image).unwrap());
+        assert_eq!(2, image.brushes.len());
+
+        let (mut surface, _) = rasterize(&image, 96.0, 1.0, &RenderOptions::default()).unwrap();
+        assert_eq!([255, 255, 0, 0], pixel(&mut surface, 5, 5));
+
+        // Overrides refer to pens and brushes of the image, which must exist.
+        let mut styles = HashMap::new();
+        styles.insert(String::from("left"), StyleOverride { pen: Some(0), ..StyleOverride::default() });
+        let options = RenderOptions { styles, ..RenderOptions::default() };
+        let result = rasterize(&image, 96.0, 1.0, &options);
+        assert!(matches!(result, Err(RenderError::PenOutOfRange { index: 0, count: 0 })));
+        let (_surface, context) = create_surface(&image);
+        assert!(render_with_options(&context, &image, 96.0, 1.0, &options).is_err());
+
+        let mut styles = HashMap::new();
+        styles.insert(String::from("right"), StyleOverride { brush: Some(2), ..StyleOverride::default() });
+        let options = RenderOptions { styles, ..RenderOptions::default() };
+        let result = rasterize(&image, 96.0, 1.0, &options);
+        assert!(matches!(result, Err(RenderError::BrushOutOfRange { index: 2, count: 2 })));
+    }
+
+    #[test]
+    fn test_debug_index() {
+        let image_str = r#"{
+  "width": 40,
+  "height": 40,
+  "unit-per-inch": 96,
+  "pens": [
+    { "pattern": { "type": "monochrome", "color": [0, 0, 0] }, "width": 1 }
+  ],
+  "brushes": [],
+  "shapes": [
+    { "type": "curve", "pen": 0, "data": [[1, 2], ["L", [10, 5]]] },
+    { "type": "group", "content": [] },
+    { "type": "region", "pen": 0, "data": [[[20, 20], ["L", [30, 20]], ["L", [30, 30]]]] }
+  ]
+}"#;
+        let image: Image = serde_json::from_str(image_str).unwrap();
+        let (mut plain, context) = create_surface(&image);
+        let report = render_with_options(&context, &image, 96.0, 1.0, &RenderOptions::default()).unwrap();
+        assert!(report.labels.is_empty());
+        drop(context);
+
+        let (mut labelled, context) = create_surface(&image);
+        let options = RenderOptions { debug_index: true, ..RenderOptions::default() };
+        let report = render_with_options(&context, &image, 96.0, 1.0, &options).unwrap();
+        assert_eq!(vec![0, 2], report.labels);
+        drop(context);
+
+        assert_ne!(plain.data().unwrap().to_vec(), labelled.data().unwrap().to_vec());
+    }
+
+    #[test]
+    fn test_conic_gradient() {
+        let image_str = r#"{
+  "width": 40,
+  "height": 40,
+  "unit-per-inch": 96,
+  "pens": [],
+  "brushes": [
+    { "pattern": {
+      "type": "conic-gradient",
+      "center": [20, 20],
+      "start-angle": 0,
+      "stops": [[0, [1, 0, 0]], [0.5, [0, 0, 1]], [1, [1, 0, 0]]]
+    } }
+  ],
+  "shapes": [
+    { "type": "region", "brush": 0, "data": [[[0, 0], ["L", [40, 0]], ["L", [40, 40]], ["L", [0, 40]]]] }
+  ]
+}"#;
+        let image: Image = serde_json::from_str(image_str).unwrap();
+        let (mut surface, context) = create_surface(&image);
+        render(&context, &image, 96.0, 1.0).unwrap();
+        drop(context);
+
+        let right = pixel(&mut surface, 38, 20);
+        assert_eq!(255, right[0]);
+        assert!(right[1] > 240 && right[3] < 16);
+
+        let left = pixel(&mut surface, 1, 20);
+        assert!(left[1] < 16 && left[3] > 240);
+
+        let below = pixel(&mut surface, 20, 38);
+        assert!(below[1] > 96 && below[1] < 160);
+        assert!(below[3] > 96 && below[3] < 160);
+
+        // Both sides of the seam at the start angle are red.
+        let above_seam = pixel(&mut surface, 38, 19);
+        let below_seam = pixel(&mut surface, 38, 21);
+        assert!(above_seam[1] > 240 && below_seam[1] > 240);
+        assert_eq!(255, above_seam[0]);
+        assert_eq!(255, below_seam[0]);
+    }
+
+    #[test]
+    fn test_image_pattern() {
+        use base64::Engine;
+
+        let texture = cairo::ImageSurface::create(cairo::Format::ARgb32, 2, 1).unwrap();
+        let context = Context::new(&texture).unwrap();
+        context.set_source_rgb(1.0, 0.0, 0.0);
+        context.rectangle(0.0, 0.0, 1.0, 1.0);
+        context.fill().unwrap();
+        context.set_source_rgb(0.0, 0.0, 1.0);
+        context.rectangle(1.0, 0.0, 1.0, 1.0);
+        context.fill().unwrap();
+        drop(context);
+
+        let mut png = Vec::new();
+        texture.write_to_png(&mut png).unwrap();
+        let data = base64::engine::general_purpose::STANDARD.encode(&png);
+
+        let image_str = format!(r#"{{
+  "width": 16,
+  "height": 8,
+  "unit-per-inch": 48,
+  "pens": [],
+  "brushes": [
+    {{ "pattern": {{ "type": "image", "data": "{}", "pixel-size": 1.5 }} }}
+  ],
+  "shapes": [
+    {{ "type": "region", "brush": 0, "data": [[[0, 0], ["L", [16, 0]], ["L", [16, 8]], ["L", [0, 8]]]] }}
+  ]
+}}"#, data);
+        let mut image: Image = serde_json::from_str(&image_str).unwrap();
+
+        // Each bitmap pixel covers 3 device pixels at 96 ppi; sample at the texel centers.
+        let (mut surface, _) = rasterize(&image, 96.0, 1.0, &RenderOptions::default()).unwrap();
+        assert_eq!([255, 255, 0, 0], pixel(&mut surface, 1, 1));
+        assert_eq!([255, 0, 0, 255], pixel(&mut surface, 4, 1));
+        assert_eq!([255, 255, 0, 0], pixel(&mut surface, 7, 10));
+
+        let bad_base64 = image_str.replace(&data, "not base64!");
+        assert!(serde_json::from_str::<Image>(&bad_base64).is_err());
+
+        if let Pattern::Image(pat) = &mut image.brushes[0].pattern {
+            pat.data = base64::engine::general_purpose::STANDARD.encode(b"not a PNG file");
+        }
+
+        let result = rasterize(&image, 96.0, 1.0, &RenderOptions::default());
+        assert!(matches!(result, Err(RenderError::ImagePng(_))));
+
+        if let Pattern::Image(pat) = &mut image.brushes[0].pattern {
+            pat.data = String::from("not base64!");
+        }
+
+        let result = rasterize(&image, 96.0, 1.0, &RenderOptions::default());
+        assert!(matches!(result, Err(RenderError::ImageData(LisonError::InvalidBase64))));
+        assert!(matches!(render_swatch(&image.brushes[0].pattern, 4, 4), Err(RenderError::ImageData(_))));
+    }
+
+    #[test]
+    fn test_render_swatch() {
+        let monochrome: Pattern = serde_json::from_str(r#"{"type": "monochrome", "color": [1, 0, 0, 0.5]}"#).unwrap();
+        let swatch = render_swatch(&monochrome, 8, 4).unwrap();
+
+        assert_eq!(8 * 4 * 4, swatch.len());
+        assert!(swatch.chunks_exact(4).all(|pixel| pixel == swatch[..4].as_ref()));
+        assert_eq!(255, swatch[0]);
+        assert_eq!(0, swatch[1]);
+
+        let gradient: Pattern = serde_json::from_str(r#"{
+  "type": "linear-gradient",
+  "point-1": [0, 0], "color-1": [0, 0, 0, 1],
+  "point-2": [100, 0], "color-2": [1, 1, 1, 1]
+}"#).unwrap();
+        let swatch = render_swatch(&gradient, 16, 4).unwrap();
+        let left = &swatch[..4];
+        let right = &swatch[15 * 4..16 * 4];
+
+        assert!(left[0] < 32);
+        assert!(right[0] > 224);
+        assert_eq!(255, left[3]);
+
+        let stops: Pattern = serde_json::from_str(r#"{
+  "type": "linear-gradient",
+  "point-1": [0, 0], "color-1": [0, 0, 0, 1],
+  "point-2": [100, 0], "color-2": [0, 0, 0, 1],
+  "stops": [[0.5, [1, 0, 0]]]
+}"#).unwrap();
+        let swatch = render_swatch(&stops, 16, 4).unwrap();
+        assert!(swatch[..4][0] < 32);
+        assert!(swatch[8 * 4] > 224);
+        assert!(swatch[15 * 4] < 32);
+    }
+
+    #[test]
+    fn test_gradient_extend() {
+        let image_str = r#"{
+  "width": 32,
+  "height": 4,
+  "unit-per-inch": 96,
+  "pens": [],
+  "brushes": [
+    { "pattern": { "type": "linear-gradient", "point-1": [0, 0], "color-1": [0, 0, 0], "point-2": [8, 0], "color-2": [1, 1, 1] } }
+  ],
+  "shapes": [
+    { "type": "region", "brush": 0, "data": [[[0, 0], ["L", [32, 0]], ["L", [32, 4]], ["L", [0, 4]]]] }
+  ]
+}"#;
+        let mut image: Image = serde_json::from_str(image_str).unwrap();
+
+        let mut surface = render_to_surface(&image);
+        assert!(pixel(&mut surface, 1, 1)[1] < 64);
+        assert!(pixel(&mut surface, 9, 1)[1] > 224);
+        assert!(pixel(&mut surface, 30, 1)[1] > 224);
+
+        let Pattern::LinearGradient(grad) = &mut image.brushes[0].pattern else { panic!() };
+        grad.extend = Some(Extend::Repeat);
+
+        let mut surface = render_to_surface(&image);
+        assert!(pixel(&mut surface, 9, 1)[1] < 64);
+        assert!(pixel(&mut surface, 15, 1)[1] > 192);
+
+        let Pattern::LinearGradient(grad) = &mut image.brushes[0].pattern else { panic!() };
+        grad.extend = Some(Extend::Reflect);
+
+        let mut surface = render_to_surface(&image);
+        assert!(pixel(&mut surface, 9, 1)[1] > 192);
+        assert!(pixel(&mut surface, 15, 1)[1] < 64);
+
+        let Pattern::LinearGradient(grad) = &mut image.brushes[0].pattern else { panic!() };
+        grad.extend = Some(Extend::None);
+
+        let mut surface = render_to_surface(&image);
+        assert_eq!(0, pixel_alpha(&mut surface, 20, 1));
+    }
+
+    #[test]
+    fn test_radial_gradient_aspect() {
+        let image_str = r#"{
+  "width": 40,
+  "height": 40,
+  "unit-per-inch": 96,
+  "pens": [],
+  "brushes": [
+    { "pattern": {
+      "type": "radial-gradient",
+      "center-1": [20, 20], "radius-1": 0, "color-1": [1, 1, 1],
+      "center-2": [20, 20], "radius-2": 10, "color-2": [0, 0, 0]
+    } }
+  ],
+  "shapes": [
+    { "type": "region", "brush": 0, "data": [[[0, 0], ["L", [40, 0]], ["L", [40, 40]], ["L", [0, 40]]]] }
+  ]
+}"#;
+        let mut image: Image = serde_json::from_str(image_str).unwrap();
+
+        let mut surface = render_to_surface(&image);
+        assert!(pixel(&mut surface, 35, 20)[1] < 16);
+        assert!(pixel(&mut surface, 20, 35)[1] < 16);
+
+        let Pattern::RadialGradient(grad) = &mut image.brushes[0].pattern else { panic!() };
+        grad.aspect = Some(2.0);
+
+        // 15 units to the right is 7.5 units away in gradient space, while 15 units down is
+        // still beyond the outer circle.
+        let mut surface = render_to_surface(&image);
+        let right = pixel(&mut surface, 35, 20)[1];
+        assert!(right > 32 && right < 96);
+        assert!(pixel(&mut surface, 20, 35)[1] < 16);
+        assert!(pixel(&mut surface, 5, 20)[1] > 32);
+    }
+
+    #[test]
+    fn test_render_streaming() {
+        for sample in [
+            include_str!("../samples/curve.lison"),
+            include_str!("../samples/pattern.lison"),
+            include_str!("../samples/region.lison")
+        ] {
+            let image: Image = serde_json::from_str(sample).unwrap();
+
+            let mut buffered = render_to_surface(&image);
+
+            let (mut streamed, context) = create_surface(&image);
+            render_streaming(sample.as_bytes(), &context, image.unit_per_inch, 1.0).unwrap();
+            drop(context);
+
+            assert_eq!(buffered.data().unwrap().to_vec(), streamed.data().unwrap().to_vec());
+        }
+
+        let (_surface, context) = create_surface(&serde_json::from_str(include_str!("../samples/region.lison")).unwrap());
+        let late_brushes = r#"{ "width": 10, "height": 10, "unit-per-inch": 96, "pens": [], "shapes": [], "brushes": [] }"#;
+        assert!(matches!(render_streaming(late_brushes.as_bytes(), &context, 96.0, 1.0), Err(RenderError::Json(_))));
+
+        let missing = r#"{ "width": 10, "height": 10, "unit-per-inch": 96, "pens": [], "brushes": [] }"#;
+        assert!(matches!(render_streaming(missing.as_bytes(), &context, 96.0, 1.0), Err(RenderError::Json(_))));
+
+        let bad_pen = r#"{ "width": 10, "height": 10, "unit-per-inch": 96, "pens": [], "brushes": [],
+            "shapes": [{ "type": "curve", "pen": 0, "data": [[0, 0], ["L", [5, 5]]] }] }"#;
+        assert!(matches!(render_streaming(bad_pen.as_bytes(), &context, 96.0, 1.0), Err(RenderError::PenOutOfRange { index: 0, count: 0 })));
+
+        let bad_brush = r#"{ "width": 10, "height": 10, "unit-per-inch": 96, "pens": [], "brushes": [],
+            "shapes": [{ "type": "group", "content": [{ "type": "region", "brush": 2, "data": [] }] }] }"#;
+        assert!(matches!(render_streaming(bad_brush.as_bytes(), &context, 96.0, 1.0), Err(RenderError::BrushOutOfRange { index: 2, count: 0 })));
+    }
+}
@@ -0,0 +1,28 @@
+#![cfg(feature = "pdf")]
+
+use lison::image::*;
+use lison::render::*;
+
+#[test]
+fn test_render_pdf() {
+    let image_str = r#"{
+  "width": 96,
+  "height": 48,
+  "unit-per-inch": 96,
+  "pens": [],
+  "brushes": [
+    { "pattern": { "type": "monochrome", "color": [1, 0, 0] } }
+  ],
+  "shapes": [
+    { "type": "region", "brush": 0, "data": [[[8, 8], ["L", [88, 8]], ["L", [88, 40]], ["L", [8, 40]]]] }
+  ]
+}"#;
+    let image: Image = serde_json::from_str(image_str).unwrap();
+
+    let (pdf, _) = render_pdf(&image, 1.0, &RenderOptions::default(), Vec::new()).unwrap();
+    assert!(pdf.starts_with(b"%PDF"));
+
+    // One inch by half an inch.
+    let pdf = String::from_utf8_lossy(&pdf);
+    assert!(pdf.contains("/MediaBox [ 0 0 72 36 ]"));
+}
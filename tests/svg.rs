@@ -0,0 +1,33 @@
+#![cfg(feature = "svg")]
+
+use lison::image::*;
+use lison::render::*;
+
+#[test]
+fn test_render_svg() {
+    let image_str = r#"{
+  "width": 40,
+  "height": 20,
+  "unit-per-inch": 96,
+  "pens": [
+    { "pattern": { "type": "monochrome", "color": [0, 0, 0] }, "width": 2, "cap": "round", "join": "bevel" }
+  ],
+  "brushes": [
+    { "pattern": { "type": "linear-gradient", "point-1": [0, 0], "color-1": [1, 0, 0], "point-2": [40, 0], "color-2": [0, 0, 1] } }
+  ],
+  "shapes": [
+    { "type": "region", "pen": 0, "brush": 0, "data": [[[5, 5], ["L", [35, 5]], ["L", [35, 15]], ["L", [5, 15]]]] }
+  ]
+}"#;
+    let image: Image = serde_json::from_str(image_str).unwrap();
+
+    let (svg, _) = render_svg(&image, 192.0, 1.0, &RenderOptions::default(), Vec::new()).unwrap();
+    let svg = String::from_utf8(svg).unwrap();
+
+    assert!(svg.contains(r#"width="60pt""#));
+    assert!(svg.contains(r#"height="30pt""#));
+    assert!(svg.contains("<path"));
+    assert!(svg.contains("<linearGradient"));
+    assert!(svg.contains("stroke-linecap:round") || svg.contains(r#"stroke-linecap="round""#));
+    assert!(svg.contains("stroke-linejoin:bevel") || svg.contains(r#"stroke-linejoin="bevel""#));
+}